@@ -0,0 +1,354 @@
+//! # Ledger Arithmetic
+//!
+//! Fixed-point quantities shared by the ledger core, its services and the pallets:
+//! - `Energy`: kWh with three decimals (1 Wh resolution)
+//! - `Price`: tokens per kWh with four decimals
+//! - `Amount`: token amounts with eighteen decimals
+//!
+//! All arithmetic is checked. Operations that lose precision take an explicit
+//! `Rounding`, so every conversion states which way it rounds. Values
+//! serialize as decimal strings such as `"12.500"`, which survive JSON and
+//! TOML without passing through floating point.
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// How to round a result that falls between two representable values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rounding {
+    /// Towards zero
+    Down,
+    /// Away from zero
+    Up,
+    /// To the nearest value, halves away from zero
+    Nearest,
+}
+
+/// Why a decimal string could not be read as a fixed-point value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseFixedError {
+    /// Not a plain non-negative decimal number
+    Invalid,
+    /// More decimal places than the type holds
+    TooPrecise,
+    /// Larger than the type holds
+    Overflow,
+}
+
+impl fmt::Display for ParseFixedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseFixedError::Invalid => write!(f, "not a non-negative decimal number"),
+            ParseFixedError::TooPrecise => write!(f, "too many decimal places"),
+            ParseFixedError::Overflow => write!(f, "value out of range"),
+        }
+    }
+}
+
+impl std::error::Error for ParseFixedError {}
+
+/// `value * numerator / denominator` with the given rounding; `None` on overflow or a zero denominator
+pub fn mul_div(value: u128, numerator: u128, denominator: u128, rounding: Rounding) -> Option<u128> {
+    if denominator == 0 {
+        return None;
+    }
+    let product = value.checked_mul(numerator)?;
+    let (quotient, remainder) = (product / denominator, product % denominator);
+    let round_up = match rounding {
+        Rounding::Down => false,
+        Rounding::Up => remainder > 0,
+        Rounding::Nearest => remainder > 0 && remainder >= denominator - remainder,
+    };
+    if round_up {
+        quotient.checked_add(1)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Read a decimal string into units of `10^-decimals`.
+///
+/// Extra decimal places are an error without a rounding mode and rounded with one.
+fn parse_decimal(text: &str, decimals: u32, rounding: Option<Rounding>) -> Result<u128, ParseFixedError> {
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) || (text.contains('.') && fraction.is_empty()) {
+        return Err(ParseFixedError::Invalid);
+    }
+
+    let kept = fraction.len().min(decimals as usize);
+    let (fraction, dropped) = fraction.split_at(kept);
+    let round_up = match rounding {
+        _ if dropped.bytes().all(|byte| byte == b'0') => false,
+        None => return Err(ParseFixedError::TooPrecise),
+        Some(Rounding::Down) => false,
+        Some(Rounding::Up) => true,
+        Some(Rounding::Nearest) => dropped.as_bytes()[0] >= b'5',
+    };
+
+    let scale = 10u128.pow(decimals);
+    let whole: u128 = whole.parse().map_err(|_| ParseFixedError::Overflow)?;
+    let fraction: u128 = if fraction.is_empty() { 0 } else { fraction.parse().map_err(|_| ParseFixedError::Overflow)? };
+    whole.checked_mul(scale)
+        .and_then(|value| value.checked_add(fraction * 10u128.pow(decimals - kept as u32)))
+        .and_then(|value| value.checked_add(round_up as u128))
+        .ok_or(ParseFixedError::Overflow)
+}
+
+macro_rules! fixed_point {
+    ($(#[$meta:meta])* $name:ident, $inner:ty, $decimals:expr, $expecting:expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name($inner);
+
+        impl $name {
+            /// Decimal places
+            pub const DECIMALS: u32 = $decimals;
+            /// Raw units in one whole unit
+            pub const SCALE: $inner = (10 as $inner).pow($decimals);
+            /// Zero
+            pub const ZERO: Self = Self(0);
+            /// Largest representable value
+            pub const MAX: Self = Self(<$inner>::MAX);
+
+            /// Value from raw units of `10^-DECIMALS`
+            pub const fn from_raw(raw: $inner) -> Self {
+                Self(raw)
+            }
+
+            /// Raw units of `10^-DECIMALS`
+            pub const fn raw(self) -> $inner {
+                self.0
+            }
+
+            /// Value of `units` whole units; `None` on overflow
+            pub const fn from_units(units: $inner) -> Option<Self> {
+                match units.checked_mul(Self::SCALE) {
+                    Some(raw) => Some(Self(raw)),
+                    None => None,
+                }
+            }
+
+            /// Read a decimal string, rounding extra decimal places
+            pub fn parse_rounded(text: &str, rounding: Rounding) -> Result<Self, ParseFixedError> {
+                let raw = parse_decimal(text, Self::DECIMALS, Some(rounding))?;
+                <$inner>::try_from(raw).map(Self).map_err(|_| ParseFixedError::Overflow)
+            }
+
+            /// Whether the value is zero
+            pub fn is_zero(self) -> bool {
+                self.0 == 0
+            }
+
+            /// Sum; `None` on overflow
+            pub fn checked_add(self, other: Self) -> Option<Self> {
+                self.0.checked_add(other.0).map(Self)
+            }
+
+            /// Difference; `None` if `other` is larger
+            pub fn checked_sub(self, other: Self) -> Option<Self> {
+                self.0.checked_sub(other.0).map(Self)
+            }
+
+            /// Sum, capped at the largest value
+            pub fn saturating_add(self, other: Self) -> Self {
+                Self(self.0.saturating_add(other.0))
+            }
+
+            /// Difference, floored at zero
+            pub fn saturating_sub(self, other: Self) -> Self {
+                Self(self.0.saturating_sub(other.0))
+            }
+
+            /// Value times an integer; `None` on overflow
+            pub fn checked_mul_int(self, factor: $inner) -> Option<Self> {
+                self.0.checked_mul(factor).map(Self)
+            }
+
+            /// Value divided by an integer; `None` for a zero divisor
+            pub fn checked_div_int(self, divisor: $inner, rounding: Rounding) -> Option<Self> {
+                self.checked_mul_ratio(1, divisor as u128, rounding)
+            }
+
+            /// `self * numerator / denominator`, e.g. a fee in basis points; `None` on overflow or a zero denominator
+            pub fn checked_mul_ratio(self, numerator: u128, denominator: u128, rounding: Rounding) -> Option<Self> {
+                let raw = mul_div(self.0 as u128, numerator, denominator, rounding)?;
+                <$inner>::try_from(raw).ok().map(Self)
+            }
+
+            /// Sum of all values; `None` on overflow
+            pub fn checked_sum(values: impl IntoIterator<Item = Self>) -> Option<Self> {
+                values.into_iter().try_fold(Self::ZERO, Self::checked_add)
+            }
+
+            /// Nearest `f64`, for interfaces still taking floats; never compute further with it
+            pub fn to_f64(self) -> f64 {
+                self.0 as f64 / Self::SCALE as f64
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let (whole, fraction) = (self.0 / Self::SCALE, self.0 % Self::SCALE);
+                write!(f, "{}.{:0width$}", whole, fraction, width = Self::DECIMALS as usize)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseFixedError;
+
+            /// Read a decimal string; extra decimal places are an error
+            fn from_str(text: &str) -> Result<Self, Self::Err> {
+                let raw = parse_decimal(text, Self::DECIMALS, None)?;
+                <$inner>::try_from(raw).map(Self).map_err(|_| ParseFixedError::Overflow)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            /// Accepts decimal strings, and numbers in whole units
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct FixedVisitor;
+
+                impl Visitor<'_> for FixedVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                        write!(f, "{} as a decimal string", $expecting)
+                    }
+
+                    fn visit_str<E: de::Error>(self, text: &str) -> Result<$name, E> {
+                        text.parse().map_err(E::custom)
+                    }
+
+                    fn visit_u64<E: de::Error>(self, units: u64) -> Result<$name, E> {
+                        self.visit_str(&units.to_string())
+                    }
+
+                    fn visit_u128<E: de::Error>(self, units: u128) -> Result<$name, E> {
+                        self.visit_str(&units.to_string())
+                    }
+
+                    fn visit_i64<E: de::Error>(self, units: i64) -> Result<$name, E> {
+                        self.visit_str(&units.to_string())
+                    }
+
+                    /// Read through the shortest decimal form of the number, so `0.1` is exactly one tenth
+                    fn visit_f64<E: de::Error>(self, value: f64) -> Result<$name, E> {
+                        self.visit_str(&value.to_string())
+                    }
+                }
+
+                deserializer.deserialize_any(FixedVisitor)
+            }
+        }
+    };
+}
+
+fixed_point!(
+    /// Energy in kWh, with a resolution of 1 Wh
+    Energy, u64, 3, "energy in kWh"
+);
+
+fixed_point!(
+    /// Price of one kWh in tokens, with four decimals
+    Price, u64, 4, "a price per kWh"
+);
+
+fixed_point!(
+    /// Token amount with eighteen decimals; the raw value is the balance in the smallest unit
+    Amount, u128, 18, "a token amount"
+);
+
+/// Raw amount units in one raw energy unit at a raw price unit
+const COST_SCALE: u128 = 10u128.pow(Amount::DECIMALS - Energy::DECIMALS - Price::DECIMALS);
+
+impl Price {
+    /// Cost of `energy` at this price, which is exact; `None` on overflow
+    pub fn checked_cost(self, energy: Energy) -> Option<Amount> {
+        (energy.raw() as u128)
+            .checked_mul(self.raw() as u128)?
+            .checked_mul(COST_SCALE)
+            .map(Amount::from_raw)
+    }
+
+    /// Energy `amount` buys at this price; `None` for a zero price or on overflow
+    pub fn checked_energy(self, amount: Amount, rounding: Rounding) -> Option<Energy> {
+        let denominator = (self.raw() as u128).checked_mul(COST_SCALE)?;
+        let raw = mul_div(amount.raw(), 1, denominator, rounding)?;
+        u64::try_from(raw).ok().map(Energy::from_raw)
+    }
+
+    /// Price at which `energy` costs `amount`; `None` for zero energy or on overflow
+    pub fn per_kwh(amount: Amount, energy: Energy, rounding: Rounding) -> Option<Self> {
+        let denominator = (energy.raw() as u128).checked_mul(COST_SCALE)?;
+        let raw = mul_div(amount.raw(), 1, denominator, rounding)?;
+        u64::try_from(raw).ok().map(Self::from_raw)
+    }
+
+    /// Price halfway between two prices
+    pub fn midpoint(self, other: Self, rounding: Rounding) -> Self {
+        let sum = self.raw() as u128 + other.raw() as u128;
+        // Half of the sum of two u64 values always fits a u64
+        Self::from_raw(mul_div(sum, 1, 2, rounding).unwrap_or_default() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decimal_strings_round_trip_exactly() {
+        let energy: Energy = "12.5".parse().unwrap();
+        assert_eq!(energy, Energy::from_raw(12_500));
+        assert_eq!(energy.to_string(), "12.500");
+        assert_eq!("0.0001".parse::<Energy>(), Err(ParseFixedError::TooPrecise));
+        assert_eq!(Energy::parse_rounded("0.0005", Rounding::Nearest), Ok(Energy::from_raw(1)));
+        assert_eq!(Energy::parse_rounded("0.0004", Rounding::Nearest), Ok(Energy::ZERO));
+        assert_eq!(Energy::parse_rounded("0.0001", Rounding::Up), Ok(Energy::from_raw(1)));
+        for invalid in ["", ".5", "5.", "-1", "1e3", "1.2.3"] {
+            assert_eq!(invalid.parse::<Price>(), Err(ParseFixedError::Invalid), "{}", invalid);
+        }
+        assert_eq!("18446744073709552".parse::<Energy>(), Err(ParseFixedError::Overflow));
+
+        // Strings, whole numbers and floats all read exactly
+        let prices: Vec<Price> = serde_json::from_str(r#"["4.5", 4, 0.1]"#).unwrap();
+        assert_eq!(prices, [Price::from_raw(45_000), Price::from_raw(40_000), Price::from_raw(1_000)]);
+        assert_eq!(serde_json::to_string(&prices[0]).unwrap(), r#""4.5000""#);
+        assert!(serde_json::from_str::<Price>("0.00001").is_err());
+    }
+
+    #[test]
+    fn test_checked_arithmetic_and_rounding() {
+        let energy = Energy::from_units(3).unwrap();
+        let price: Price = "4.5001".parse().unwrap();
+        let cost = price.checked_cost(energy).unwrap();
+        assert_eq!(cost.to_string(), "13.500300000000000000");
+        assert_eq!(Price::per_kwh(cost, energy, Rounding::Down), Some(price));
+        assert_eq!(Price::per_kwh(cost, Energy::ZERO, Rounding::Down), None);
+        assert_eq!(price.checked_energy(cost, Rounding::Down), Some(energy));
+        assert_eq!(Price::ZERO.checked_energy(cost, Rounding::Down), None);
+
+        // A 1% fee on 0.000000000000000150 rounds one way or the other, never silently
+        let small = Amount::from_raw(150);
+        assert_eq!(small.checked_mul_ratio(100, 10_000, Rounding::Down), Some(Amount::from_raw(1)));
+        assert_eq!(small.checked_mul_ratio(100, 10_000, Rounding::Up), Some(Amount::from_raw(2)));
+        assert_eq!(small.checked_mul_ratio(100, 10_000, Rounding::Nearest), Some(Amount::from_raw(2)));
+        assert_eq!(small.checked_mul_ratio(1, 0, Rounding::Down), None);
+
+        assert_eq!(Price::from_raw(3).midpoint(Price::from_raw(4), Rounding::Down), Price::from_raw(3));
+        assert_eq!(Price::MAX.midpoint(Price::MAX, Rounding::Up), Price::MAX);
+        assert_eq!(Energy::MAX.checked_add(Energy::from_raw(1)), None);
+        assert_eq!(Energy::ZERO.checked_sub(Energy::from_raw(1)), None);
+        assert_eq!(Amount::checked_sum([Amount::MAX, Amount::from_raw(1)]), None);
+        assert_eq!(Energy::from_units(u64::MAX), None);
+    }
+}
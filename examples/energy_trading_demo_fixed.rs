@@ -3,8 +3,10 @@
 /// This example demonstrates the complete integration of the Energy Trading Pallet
 /// with the Token System Pallet.
 
-use pallet_token_system::{TokenSystem, TokenSystemConfig};
-use pallet_energy_trading::{EnergyTradingSystem, EnergyTradingConfig, OrderType, ProsumerType};
+use pallet_token_system::{TokenSystem, TokenSystemConfig, UNIT};
+use pallet_energy_trading::{
+    calculate_energy_cost, Amount, Energy, EnergyTradingConfig, EnergyTradingSystem, OrderType, Price, ProsumerType,
+};
 
 fn main() {
     println!("🌟 Energy Trading Ecosystem Demo");
@@ -23,8 +25,8 @@ fn main() {
     println!("1. Setting up the ecosystem...");
     
     // Setup grid operator with governance privileges
-    token_system.mint_grid(&grid_operator, 1_000_000).unwrap();
-    token_system.mint_watt(&grid_operator, 10_000_000).unwrap();
+    token_system.mint_grid(&grid_operator, 1_000_000 * UNIT).unwrap();
+    token_system.mint_watt(&grid_operator, 10_000_000 * UNIT).unwrap();
     
     // Create governance proposal for grid fees
     let proposal_id = token_system.create_proposal(
@@ -46,28 +48,28 @@ fn main() {
 
     println!("\n3. Energy generation and consumption...");
     
-    // Alice generates 100 kWh of solar energy (convert to centi-kWh: 100 * 100 = 10000)
-    energy_trading.generate_energy(&alice, 10000).unwrap();
+    // Alice generates 100 kWh of solar energy
+    energy_trading.generate_energy(&alice, kwh("100")).unwrap();
     println!("   ✓ Alice generated 100 kWh of solar energy");
     
-    // Bob consumes 75 kWh (convert to centi-kWh: 75 * 100 = 7500)
-    energy_trading.consume_energy(&bob, 7500).unwrap();
+    // Bob consumes 75 kWh
+    energy_trading.consume_energy(&bob, kwh("75")).unwrap();
     println!("   ✓ Bob consumed 75 kWh");
     
     // Charlie generates 50 kWh and consumes 30 kWh
-    energy_trading.generate_energy(&charlie, 5000).unwrap();
-    energy_trading.consume_energy(&charlie, 3000).unwrap();
+    energy_trading.generate_energy(&charlie, kwh("50")).unwrap();
+    energy_trading.consume_energy(&charlie, kwh("30")).unwrap();
     println!("   ✓ Charlie generated 50 kWh and consumed 30 kWh");
 
     println!("\n4. Creating energy trading orders...");
     
     // Alice places a sell order (has excess energy)
-    // 80 kWh at 0.95 WATT per kWh (convert: 80*100=8000 centi-kWh, 0.95*10000=9500 deci-milliwatts)
+    // 80 kWh at 0.95 WATT per kWh
     let _sell_order_id = energy_trading.place_order(
         alice.clone(),
         OrderType::Sell,
-        8000, // 80 kWh in centi-kWh
-        9500, // 0.95 WATT per kWh in deci-milliwatts
+        kwh("80"),
+        watt_per_kwh("0.95"),
         &mut token_system
     ).unwrap();
     println!("   ✓ Alice placed sell order: 80 kWh @ 0.95 WATT/kWh");
@@ -77,8 +79,8 @@ fn main() {
     let _buy_order_id = energy_trading.place_order(
         bob.clone(),
         OrderType::Buy,
-        7000, // 70 kWh in centi-kWh
-        10000, // 1.0 WATT per kWh in deci-milliwatts
+        kwh("70"),
+        watt_per_kwh("1.0"),
         &mut token_system
     ).unwrap();
     println!("   ✓ Bob placed buy order: 70 kWh @ 1.0 WATT/kWh");
@@ -88,8 +90,8 @@ fn main() {
     let _charlie_sell_id = energy_trading.place_order(
         charlie.clone(),
         OrderType::Sell,
-        2000, // 20 kWh in centi-kWh
-        9800, // 0.98 WATT per kWh in deci-milliwatts
+        kwh("20"),
+        watt_per_kwh("0.98"),
         &mut token_system
     ).unwrap();
     println!("   ✓ Charlie placed sell order: 20 kWh @ 0.98 WATT/kWh");
@@ -102,35 +104,33 @@ fn main() {
 
     println!("\n7. Market statistics:");
     let stats = &energy_trading.statistics;
-    println!("   • Total energy traded: {:.2} kWh", stats.total_energy_traded as f64 / 100.0);
-    println!("   • Total volume: {} WATT tokens", stats.total_volume);
+    println!("   • Total energy traded: {} kWh", stats.total_energy_traded);
+    println!("   • Total volume: {} WATT tokens", Amount::from_raw(stats.total_volume));
     println!("   • Total trades: {}", stats.trades_count);
     println!("   • Active buy orders: {}", stats.buy_orders_count);
     println!("   • Active sell orders: {}", stats.sell_orders_count);
     if let Some(price) = stats.market_price {
-        println!("   • Current market price: {:.4} WATT/kWh", price as f64 / 10000.0);
+        println!("   • Current market price: {} WATT/kWh", price);
     }
 
     println!("\n8. Prosumer performance:");
     for prosumer_id in [&alice, &bob, &charlie] {
         if let Some(prosumer) = energy_trading.get_prosumer(prosumer_id) {
-            let net_energy = prosumer.energy_generated as i64 - prosumer.energy_consumed as i64;
-            println!("   • {}: Generated {:.1} kWh, Consumed {:.1} kWh, Net: {:.1} kWh",
+            let net_wh = energy_trading.get_net_energy(prosumer_id).unwrap();
+            println!("   • {}: Generated {} kWh, Consumed {} kWh, Net: {:.3} kWh",
                      prosumer_id, 
-                     prosumer.energy_generated as f64 / 100.0, 
-                     prosumer.energy_consumed as f64 / 100.0, 
-                     net_energy as f64 / 100.0);
+                     prosumer.energy_generated, 
+                     prosumer.energy_consumed, 
+                     net_wh as f64 / Energy::SCALE as f64);
         }
     }
 
     println!("\n9. Governance and grid fees:");
     // Grid fee calculation (5% of 10 kWh * price)
     let grid_fee_rate = energy_trading.config.grid_fee_rate; // in basis points
-    let example_energy = 1000; // 10 kWh in centi-kWh
-    let example_price = 10000; // 1.0 WATT per kWh
-    let grid_fee = (example_energy * example_price * grid_fee_rate as u64) / 1_000_000;
+    let (_, grid_fee, _) = calculate_energy_cost(kwh("10"), watt_per_kwh("1.0"), grid_fee_rate).unwrap();
     println!("   • Grid fee rate: {}% ({} basis points)", grid_fee_rate as f64 / 100.0, grid_fee_rate);
-    println!("   • Example grid fee for 10 kWh @ 1.0 WATT: {} WATT tokens", grid_fee);
+    println!("   • Example grid fee for 10 kWh @ 1.0 WATT: {} WATT tokens", Amount::from_raw(grid_fee));
     
     if let Some(proposal) = token_system.get_proposal(proposal_id) {
         println!("   • Governance proposal status: {:?}", proposal.status);
@@ -146,6 +146,14 @@ fn main() {
     println!("   - Real-time market statistics");
 }
 
+fn kwh(text: &str) -> Energy {
+    text.parse().unwrap()
+}
+
+fn watt_per_kwh(text: &str) -> Price {
+    text.parse().unwrap()
+}
+
 fn setup_prosumer(
     token_system: &mut TokenSystem,
     energy_trading: &mut EnergyTradingSystem,
@@ -153,7 +161,7 @@ fn setup_prosumer(
     prosumer_type: ProsumerType,
 ) {
     // Give users initial WATT tokens for trading
-    token_system.mint_watt(&user_id.to_string(), 10_000).unwrap();
+    token_system.mint_watt(&user_id.to_string(), 10_000 * UNIT).unwrap();
     
     // Register as prosumer (needs name, address, prosumer_type)
     energy_trading.register_prosumer(
@@ -176,7 +184,7 @@ fn print_balances(token_system: &TokenSystem, users: &[&str]) {
     for user in users {
         let grid_balance = token_system.grid_balance(&user.to_string());
         let watt_balance = token_system.watt_balance(&user.to_string());
-        println!("   • {}: {} GRID, {} WATT", user, grid_balance, watt_balance);
+        println!("   • {}: {} GRID, {} WATT", user, Amount::from_raw(grid_balance), Amount::from_raw(watt_balance));
     }
 }
 
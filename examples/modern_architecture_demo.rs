@@ -56,8 +56,8 @@ fn demo_token_operations(system: &mut ThaiEnergyTradingSystem) -> CoreResult<()>
     
     println!("   🔄 Token transfer executed:");
     println!("      - Transaction: {}", format_hash_short(&result.transaction_hash));
-    println!("      - Success: {}", result.success);
-    println!("      - Gas used: {}", result.gas_used);
+    println!("      - Success: {}", result.is_success());
+    println!("      - Fee: {}", result.fee.total());
     
     // Check balances
     let alice_info = system.runtime.get_account_info(&alice);
@@ -95,7 +95,7 @@ fn demo_energy_trading(system: &mut ThaiEnergyTradingSystem) -> CoreResult<()> {
     
     println!("   ⚡ Energy order placed:");
     println!("      - Transaction: {}", format_hash_short(&result.transaction_hash));
-    println!("      - Success: {}", result.success);
+    println!("      - Success: {}", result.is_success());
     println!("      - Energy: {} kWh", 100.0);
     println!("      - Price: {} THB/kWh", 4.50);
     
@@ -123,7 +123,7 @@ fn demo_governance(system: &mut ThaiEnergyTradingSystem) -> CoreResult<()> {
     
     println!("   🔒 Tokens staked:");
     println!("      - Amount: {}", format_balance(constants::MIN_VALIDATOR_STAKE, constants::TOKEN_DECIMALS));
-    println!("      - Success: {}", stake_result.success);
+    println!("      - Success: {}", stake_result.is_success());
     
     // Create governance proposal
     let proposal = GovernanceProposal {
@@ -147,7 +147,7 @@ fn demo_governance(system: &mut ThaiEnergyTradingSystem) -> CoreResult<()> {
     
     println!("   📋 Governance proposal created:");
     println!("      - Proposal ID: {}", format_hash_short(&proposal_id));
-    println!("      - Success: {}", result.success);
+    println!("      - Success: {}", result.is_success());
     
     // Vote on proposal
     let vote_result = system.runtime.execute_call(
@@ -159,7 +159,7 @@ fn demo_governance(system: &mut ThaiEnergyTradingSystem) -> CoreResult<()> {
     )?;
     
     println!("   🗳️ Vote cast:");
-    println!("      - Success: {}", vote_result.success);
+    println!("      - Success: {}", vote_result.is_success());
     println!("      - Vote: For");
     
     Ok(())
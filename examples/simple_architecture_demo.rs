@@ -105,7 +105,7 @@ fn main() -> CoreResult<()> {
         participant_type: ParticipantType::Producer,
         registered_at: chrono::Utc::now().timestamp() as u64,
         is_active: true,
-        energy_balance: 1000.0,
+        energy_balance: Energy::from_raw(1_000_000),
     };
     
     let bob_participant = Participant {
@@ -113,7 +113,7 @@ fn main() -> CoreResult<()> {
         participant_type: ParticipantType::Consumer,
         registered_at: chrono::Utc::now().timestamp() as u64,
         is_active: true,
-        energy_balance: 0.0,
+        energy_balance: Energy::ZERO,
     };
     
     system.runtime.energy_trading_service.register_participant(alice_participant)?;
@@ -121,7 +121,7 @@ fn main() -> CoreResult<()> {
     println!("      - kWh to Token ratio: {}", constants::KWH_TO_TOKEN_RATIO);
     println!("      - Min energy trade: {} kWh", constants::MIN_ENERGY_TRADE);
     println!("      - Max energy trade: {} kWh", constants::MAX_ENERGY_TRADE);
-    println!("      - Grid fee: {}%", constants::GRID_FEE_PERCENTAGE * 100.0);
+    println!("      - Grid fee: {}%", constants::GRID_FEE_BPS as f64 / 100.0);
     
     println!("\n🎉 Demo completed successfully!");
     println!("✨ The new architecture is working properly with:");
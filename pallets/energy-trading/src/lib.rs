@@ -43,12 +43,13 @@ use uuid::Uuid;
 
 // Import from token system
 pub use pallet_token_system::{TokenSystem, TokenType, Balance, AccountId, BlockNumber};
+pub use ledger_arithmetic::{Amount, Energy, Price, Rounding};
 
-/// Energy amount in kWh (stored as u64 with 2 decimal places)
-pub type EnergyAmount = u64; // Energy in centi-kWh (100 = 1 kWh)
+/// Energy amount in kWh, with a resolution of 1 Wh
+pub type EnergyAmount = Energy;
 
-/// Price per kWh in WATT tokens (stored as u64 with 4 decimal places)
-pub type PricePerKwh = u64; // Price in deci-milliwatts (10000 = 1 WATT)
+/// Price per kWh in WATT tokens, with 4 decimal places
+pub type PricePerKwh = Price;
 
 /// Trade ID type
 pub type TradeId = String;
@@ -74,9 +75,9 @@ pub struct EnergyOrder {
     pub trader: AccountId,
     /// Order type (Buy/Sell)
     pub order_type: OrderType,
-    /// Energy amount in kWh (with 2 decimal precision)
+    /// Energy amount in kWh
     pub energy_amount: EnergyAmount,
-    /// Price per kWh in WATT tokens (with 4 decimal precision)  
+    /// Price per kWh in WATT tokens
     pub price_per_kwh: PricePerKwh,
     /// Timestamp when order was created
     pub timestamp: DateTime<Utc>,
@@ -222,6 +223,8 @@ pub enum EnergyTradingError {
     MarketClosed,
     #[error("Token system error: {0}")]
     TokenSystemError(String),
+    #[error("Arithmetic overflow")]
+    Overflow,
 }
 
 /// Energy trading system configuration
@@ -229,13 +232,13 @@ pub enum EnergyTradingError {
 pub struct EnergyTradingConfig {
     /// Grid fee rate (in basis points, 10000 = 100%)
     pub grid_fee_rate: u32,
-    /// Minimum order size (in centi-kWh)
+    /// Minimum order size
     pub min_order_size: EnergyAmount,
-    /// Maximum order size (in centi-kWh)
+    /// Maximum order size
     pub max_order_size: EnergyAmount,
-    /// Minimum price per kWh (in deci-milliwatts)
+    /// Minimum price per kWh
     pub min_price_per_kwh: PricePerKwh,
-    /// Maximum price per kWh (in deci-milliwatts)
+    /// Maximum price per kWh
     pub max_price_per_kwh: PricePerKwh,
     /// Order expiry time (in blocks)
     pub order_expiry_blocks: BlockNumber,
@@ -247,10 +250,10 @@ impl Default for EnergyTradingConfig {
     fn default() -> Self {
         Self {
             grid_fee_rate: 500,            // 5%
-            min_order_size: 100,           // 1 kWh
-            max_order_size: 100000,        // 1000 kWh
-            min_price_per_kwh: 100,        // 0.01 WATT
-            max_price_per_kwh: 100000,     // 10 WATT
+            min_order_size: Energy::from_raw(1_000),       // 1 kWh
+            max_order_size: Energy::from_raw(1_000_000),   // 1000 kWh
+            min_price_per_kwh: Price::from_raw(100),       // 0.01 WATT
+            max_price_per_kwh: Price::from_raw(100_000),   // 10 WATT
             order_expiry_blocks: 86400,    // ~1 day assuming 1 block per second
             market_open: true,
         }
@@ -296,7 +299,7 @@ impl Default for EnergyTradingSystem {
                 buy_orders_count: 0,
                 sell_orders_count: 0,
                 trades_count: 0,
-                total_energy_traded: 0,
+                total_energy_traded: Energy::ZERO,
                 total_volume: 0,
                 market_price: None,
                 last_updated: Utc::now(),
@@ -357,8 +360,8 @@ impl EnergyTradingSystem {
         let prosumer = Prosumer {
             address: address.clone(),
             name: name.clone(),
-            energy_generated: 0,
-            energy_consumed: 0,
+            energy_generated: Energy::ZERO,
+            energy_consumed: Energy::ZERO,
             is_active: true,
             registered_at: Utc::now(),
             prosumer_type: prosumer_type.clone(),
@@ -378,7 +381,7 @@ impl EnergyTradingSystem {
     pub fn generate_energy(&mut self, address: &AccountId, amount: EnergyAmount) -> Result<(), EnergyTradingError> {
         let prosumer = self.prosumers.get_mut(address).ok_or(EnergyTradingError::ProsumerNotFound)?;
         
-        prosumer.energy_generated += amount;
+        prosumer.energy_generated = prosumer.energy_generated.checked_add(amount).ok_or(EnergyTradingError::Overflow)?;
         self.emit_event(Event::EnergyGenerated {
             address: address.clone(),
             amount,
@@ -391,7 +394,7 @@ impl EnergyTradingSystem {
     pub fn consume_energy(&mut self, address: &AccountId, amount: EnergyAmount) -> Result<(), EnergyTradingError> {
         let prosumer = self.prosumers.get_mut(address).ok_or(EnergyTradingError::ProsumerNotFound)?;
         
-        prosumer.energy_consumed += amount;
+        prosumer.energy_consumed = prosumer.energy_consumed.checked_add(amount).ok_or(EnergyTradingError::Overflow)?;
         self.emit_event(Event::EnergyConsumed {
             address: address.clone(),
             amount,
@@ -400,22 +403,22 @@ impl EnergyTradingSystem {
         Ok(())
     }
 
-    /// Get net energy for a prosumer (generated - consumed)
-    pub fn get_net_energy(&self, address: &AccountId) -> Result<i64, EnergyTradingError> {
+    /// Get net energy for a prosumer (generated - consumed), in Wh
+    pub fn get_net_energy(&self, address: &AccountId) -> Result<i128, EnergyTradingError> {
         let prosumer = self.prosumers.get(address).ok_or(EnergyTradingError::ProsumerNotFound)?;
-        Ok(prosumer.energy_generated as i64 - prosumer.energy_consumed as i64)
+        Ok(prosumer.energy_generated.raw() as i128 - prosumer.energy_consumed.raw() as i128)
     }
 
     /// Get available energy for sale (positive net energy)
     pub fn get_sellable_energy(&self, address: &AccountId) -> Result<EnergyAmount, EnergyTradingError> {
-        let net_energy = self.get_net_energy(address)?;
-        Ok(if net_energy > 0 { net_energy as EnergyAmount } else { 0 })
+        let prosumer = self.prosumers.get(address).ok_or(EnergyTradingError::ProsumerNotFound)?;
+        Ok(prosumer.energy_generated.saturating_sub(prosumer.energy_consumed))
     }
 
     /// Get required energy to buy (negative net energy)
     pub fn get_required_energy(&self, address: &AccountId) -> Result<EnergyAmount, EnergyTradingError> {
-        let net_energy = self.get_net_energy(address)?;
-        Ok(if net_energy < 0 { (-net_energy) as EnergyAmount } else { 0 })
+        let prosumer = self.prosumers.get(address).ok_or(EnergyTradingError::ProsumerNotFound)?;
+        Ok(prosumer.energy_consumed.saturating_sub(prosumer.energy_generated))
     }
 
     /// Validate order parameters
@@ -466,7 +469,7 @@ impl EnergyTradingSystem {
             price_per_kwh,
             timestamp: Utc::now(),
             is_active: true,
-            filled_amount: 0,
+            filled_amount: Energy::ZERO,
         };
 
         // Validate order
@@ -483,7 +486,7 @@ impl EnergyTradingSystem {
             }
             OrderType::Buy => {
                 // Check if buyer has enough WATT tokens for the maximum possible cost
-                let max_cost = self.calculate_max_order_cost(energy_amount, price_per_kwh);
+                let max_cost = self.calculate_max_order_cost(energy_amount, price_per_kwh)?;
                 let watt_balance = token_system.watt_balance(&trader);
                 if watt_balance < max_cost {
                     return Err(EnergyTradingError::InsufficientWattBalance);
@@ -541,10 +544,9 @@ impl EnergyTradingSystem {
     }
 
     /// Calculate maximum cost for an order (including grid fee)
-    fn calculate_max_order_cost(&self, energy_amount: EnergyAmount, price_per_kwh: PricePerKwh) -> Balance {
-        let base_cost = (energy_amount as u128 * price_per_kwh as u128) / 10000; // Convert from deci-milliwatts
-        let grid_fee = (base_cost * self.config.grid_fee_rate as u128) / 10000;
-        (base_cost + grid_fee) as Balance
+    fn calculate_max_order_cost(&self, energy_amount: EnergyAmount, price_per_kwh: PricePerKwh) -> Result<Balance, EnergyTradingError> {
+        let (_, _, final_cost) = calculate_energy_cost(energy_amount, price_per_kwh, self.config.grid_fee_rate)?;
+        Ok(final_cost)
     }
 
     /// Match buy and sell orders
@@ -566,27 +568,25 @@ impl EnergyTradingSystem {
             }
 
             // Calculate trade details
-            let remaining_buy = buy_order.energy_amount - buy_order.filled_amount;
-            let remaining_sell = sell_order.energy_amount - sell_order.filled_amount;
+            let remaining_buy = buy_order.energy_amount.saturating_sub(buy_order.filled_amount);
+            let remaining_sell = sell_order.energy_amount.saturating_sub(sell_order.filled_amount);
             let trade_amount = remaining_buy.min(remaining_sell);
             let trade_price = sell_order.price_per_kwh; // Use ask price
 
             // Calculate costs
-            let base_cost = (trade_amount as u128 * trade_price as u128) / 10000; // Convert precision
-            let grid_fee = (base_cost * self.config.grid_fee_rate as u128) / 10000;
-            let final_cost = base_cost + grid_fee;
+            let (base_cost, grid_fee, final_cost) = calculate_energy_cost(trade_amount, trade_price, self.config.grid_fee_rate)?;
 
             // Execute trade through token system
             let buyer = buy_order.trader.clone();
             let seller = sell_order.trader.clone();
 
             // Transfer WATT tokens from buyer to seller
-            token_system.transfer_watt(&buyer, &seller, base_cost as Balance)
+            token_system.transfer_watt(&buyer, &seller, base_cost)
                 .map_err(|e| EnergyTradingError::TokenSystemError(format!("{:?}", e)))?;
 
             // Collect grid fee (transfer to system or burn)
             if grid_fee > 0 {
-                token_system.burn_watt(&buyer, grid_fee as Balance)
+                token_system.burn_watt(&buyer, grid_fee)
                     .map_err(|e| EnergyTradingError::TokenSystemError(format!("{:?}", e)))?;
             }
 
@@ -598,9 +598,9 @@ impl EnergyTradingSystem {
                 seller: seller.clone(),
                 energy_amount: trade_amount,
                 price_per_kwh: trade_price,
-                total_cost: base_cost as Balance,
-                grid_fee: grid_fee as Balance,
-                final_cost: final_cost as Balance,
+                total_cost: base_cost,
+                grid_fee,
+                final_cost,
                 timestamp: Utc::now(),
                 buy_order_id: buy_order.id.clone(),
                 sell_order_id: sell_order.id.clone(),
@@ -611,9 +611,9 @@ impl EnergyTradingSystem {
             executed_trades.push(trade_id.clone());
 
             // Update statistics
-            self.statistics.total_energy_traded += trade_amount;
-            self.statistics.total_volume += base_cost as Balance;
-            self.total_grid_fees += grid_fee as Balance;
+            self.statistics.total_energy_traded = self.statistics.total_energy_traded.saturating_add(trade_amount);
+            self.statistics.total_volume = self.statistics.total_volume.saturating_add(base_cost);
+            self.total_grid_fees = self.total_grid_fees.saturating_add(grid_fee);
 
             // Emit events
             self.emit_event(Event::TradeExecuted {
@@ -622,13 +622,13 @@ impl EnergyTradingSystem {
                 seller,
                 energy_amount: trade_amount,
                 price_per_kwh: trade_price,
-                total_cost: base_cost as Balance,
+                total_cost: base_cost,
             });
 
             if grid_fee > 0 {
                 self.emit_event(Event::GridFeeCollected {
                     trade_id,
-                    fee_amount: grid_fee as Balance,
+                    fee_amount: grid_fee,
                 });
             }
 
@@ -636,8 +636,8 @@ impl EnergyTradingSystem {
             let mut buy_order = self.buy_orders.pop_front().unwrap();
             let mut sell_order = self.sell_orders.pop_front().unwrap();
 
-            buy_order.filled_amount += trade_amount;
-            sell_order.filled_amount += trade_amount;
+            buy_order.filled_amount = buy_order.filled_amount.saturating_add(trade_amount);
+            sell_order.filled_amount = sell_order.filled_amount.saturating_add(trade_amount);
 
             // Check if orders are completely filled
             if buy_order.filled_amount < buy_order.energy_amount {
@@ -646,7 +646,7 @@ impl EnergyTradingSystem {
                 self.emit_event(Event::OrderPartiallyFilled {
                     order_id: buy_order.id.clone(),
                     filled_amount: buy_order.filled_amount,
-                    remaining: buy_order.energy_amount - buy_order.filled_amount,
+                    remaining: buy_order.energy_amount.saturating_sub(buy_order.filled_amount),
                 });
             } else {
                 // Completely filled
@@ -662,7 +662,7 @@ impl EnergyTradingSystem {
                 self.emit_event(Event::OrderPartiallyFilled {
                     order_id: sell_order.id.clone(),
                     filled_amount: sell_order.filled_amount,
-                    remaining: sell_order.energy_amount - sell_order.filled_amount,
+                    remaining: sell_order.energy_amount.saturating_sub(sell_order.filled_amount),
                 });
             } else {
                 // Completely filled
@@ -781,6 +781,22 @@ impl EnergyTradingSystem {
     }
 }
 
+/// Cost of `energy_amount` at `price_per_kwh`, as (base cost, grid fee, final cost).
+///
+/// The grid fee rate is in basis points and the fee rounds up, so a
+/// non-zero rate never yields a free trade.
+pub fn calculate_energy_cost(
+    energy_amount: EnergyAmount,
+    price_per_kwh: PricePerKwh,
+    grid_fee_rate: u32,
+) -> Result<(Balance, Balance, Balance), EnergyTradingError> {
+    let base_cost = price_per_kwh.checked_cost(energy_amount).ok_or(EnergyTradingError::Overflow)?;
+    let grid_fee = base_cost.checked_mul_ratio(grid_fee_rate as u128, 10000, Rounding::Up)
+        .ok_or(EnergyTradingError::Overflow)?;
+    let final_cost = base_cost.checked_add(grid_fee).ok_or(EnergyTradingError::Overflow)?;
+    Ok((base_cost.raw(), grid_fee.raw(), final_cost.raw()))
+}
+
 // ================================================================================
 // FEATURE-GATED IMPLEMENTATIONS
 // ================================================================================
@@ -865,11 +881,11 @@ impl EnergyTradingSystem {
     ) -> Result<TradeId, EnergyTradingError> {
         // Calculate trade amount (minimum of buy and sell amounts)
         let trade_amount = std::cmp::min(
-            buy_order.energy_amount - buy_order.filled_amount,
-            sell_order.energy_amount - sell_order.filled_amount,
+            buy_order.energy_amount.saturating_sub(buy_order.filled_amount),
+            sell_order.energy_amount.saturating_sub(sell_order.filled_amount),
         );
 
-        if trade_amount == 0 {
+        if trade_amount.is_zero() {
             return Err(EnergyTradingError::InvalidEnergyAmount);
         }
 
@@ -881,7 +897,7 @@ impl EnergyTradingSystem {
             trade_amount,
             trade_price,
             self.config.grid_fee_rate,
-        );
+        )?;
 
         // Execute token transfers
         token_system.transfer_watt(&buy_order.trader, &sell_order.trader, base_cost)
@@ -911,9 +927,9 @@ impl EnergyTradingSystem {
         self.trades.insert(trade_id.clone(), trade);
 
         // Update statistics
-        self.statistics.total_energy_traded += trade_amount;
-        self.statistics.total_volume += base_cost;
-        self.total_grid_fees += grid_fee;
+        self.statistics.total_energy_traded = self.statistics.total_energy_traded.saturating_add(trade_amount);
+        self.statistics.total_volume = self.statistics.total_volume.saturating_add(base_cost);
+        self.total_grid_fees = self.total_grid_fees.saturating_add(grid_fee);
 
         // Emit events
         self.emit_event(Event::TradeExecuted {
@@ -949,7 +965,7 @@ impl EnergyTradingSystem {
         distance_km: Option<f64>,
         congestion_factor: Option<f64>,
     ) -> Balance {
-        let base_cost = (energy_amount as u128 * price_per_kwh as u128) / 10000;
+        let base_cost = price_per_kwh.checked_cost(energy_amount).unwrap_or(Amount::MAX);
         let mut fee_rate = self.config.grid_fee_rate;
         
         // Apply distance-based pricing if provided
@@ -967,7 +983,7 @@ impl EnergyTradingSystem {
         // Cap at 50% max fee rate
         fee_rate = fee_rate.min(5000);
         
-        ((base_cost * fee_rate as u128) / 10000) as Balance
+        base_cost.checked_mul_ratio(fee_rate as u128, 10000, Rounding::Up).unwrap_or(Amount::MAX).raw()
     }
 }
 
@@ -994,7 +1010,7 @@ impl EnergyTradingSystem {
             
             // Enhanced statistics
             average_trade_size: if base_stats.trades_count > 0 {
-                Some(base_stats.total_energy_traded / base_stats.trades_count as u64)
+                base_stats.total_energy_traded.checked_div_int(base_stats.trades_count as u64, Rounding::Nearest)
             } else {
                 None
             },
@@ -1071,7 +1087,7 @@ impl EnergyTradingSystem {
     pub fn set_market_operator(&mut self, operator: AccountId) -> Result<(), EnergyTradingError> {
         // In a real implementation, this would set up operator roles
         // For now, we'll just emit an event
-        self.emit_event(Event::MarketPriceUpdated { new_price: 0 });
+        self.emit_event(Event::MarketPriceUpdated { new_price: Price::ZERO });
         Ok(())
     }
     
@@ -1096,7 +1112,7 @@ pub struct EnhancedMarketStatistics {
     pub market_price: Option<PricePerKwh>,
     pub last_updated: DateTime<Utc>,
     
-    // Enhanced statistics (using u64 for Eq trait compatibility)
+    // Enhanced statistics
     pub average_trade_size: Option<EnergyAmount>,
     pub price_volatility_percent: Option<u32>, // In basis points (100 = 1%)
     pub trade_velocity_per_hour: Option<u32>, // Trades per hour * 100
@@ -1214,3 +1230,102 @@ impl FeatureCapabilities {
         features
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pallet_token_system::UNIT;
+
+    fn kwh(text: &str) -> EnergyAmount {
+        text.parse().unwrap()
+    }
+
+    fn watt_per_kwh(text: &str) -> PricePerKwh {
+        text.parse().unwrap()
+    }
+
+    /// Alice has 20 kWh to sell, Bob holds 100 WATT
+    fn create_test_market() -> (EnergyTradingSystem, TokenSystem) {
+        let mut market = EnergyTradingSystem::default();
+        let mut tokens = TokenSystem::default();
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+
+        market.register_prosumer(alice.clone(), "Alice".to_string(), ProsumerType::Residential).unwrap();
+        market.register_prosumer(bob.clone(), "Bob".to_string(), ProsumerType::Consumer).unwrap();
+        market.generate_energy(&alice, kwh("20")).unwrap();
+        tokens.mint_watt(&bob, 100 * UNIT).unwrap();
+        (market, tokens)
+    }
+
+    #[test]
+    fn test_matched_orders_settle_in_watt() {
+        let (mut market, mut tokens) = create_test_market();
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+
+        let sell_id = market.place_order(alice.clone(), OrderType::Sell, kwh("10"), watt_per_kwh("4.5"), &mut tokens).unwrap();
+        assert_eq!(market.get_market_price(), Some(watt_per_kwh("4.5")));
+        let buy_id = market.place_order(bob.clone(), OrderType::Buy, kwh("10"), watt_per_kwh("5"), &mut tokens).unwrap();
+
+        // The trade clears at the ask: 45 WATT to Alice plus a 5% grid fee burned from Bob
+        assert_eq!(market.trades.len(), 1);
+        let trade = market.trades.values().next().unwrap();
+        assert_eq!((trade.buy_order_id.as_str(), trade.sell_order_id.as_str()), (buy_id.as_str(), sell_id.as_str()));
+        assert_eq!(trade.price_per_kwh, watt_per_kwh("4.5"));
+        assert_eq!(trade.total_cost, 45 * UNIT);
+        assert_eq!(trade.grid_fee, 225 * UNIT / 100);
+        assert_eq!(trade.final_cost, 4725 * UNIT / 100);
+
+        assert_eq!(tokens.watt_balance(&alice), 45 * UNIT);
+        assert_eq!(tokens.watt_balance(&bob), 5275 * UNIT / 100);
+        assert_eq!(market.get_total_grid_fees(), 225 * UNIT / 100);
+
+        let (buy_orders, sell_orders) = market.get_order_book();
+        assert!(buy_orders.is_empty() && sell_orders.is_empty());
+        let stats = market.get_statistics();
+        assert_eq!(stats.trades_count, 1);
+        assert_eq!(stats.total_energy_traded, kwh("10"));
+        assert_eq!(stats.total_volume, 45 * UNIT);
+    }
+
+    #[test]
+    fn test_partially_filled_order_stays_on_the_book() {
+        let (mut market, mut tokens) = create_test_market();
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+
+        let sell_id = market.place_order(alice, OrderType::Sell, kwh("10"), watt_per_kwh("4.5"), &mut tokens).unwrap();
+        market.place_order(bob.clone(), OrderType::Buy, kwh("4"), watt_per_kwh("4.5"), &mut tokens).unwrap();
+
+        let (buy_orders, sell_orders) = market.get_order_book();
+        assert!(buy_orders.is_empty());
+        assert_eq!(sell_orders.len(), 1);
+        assert_eq!(sell_orders[0].filled_amount, kwh("4"));
+        assert_eq!(market.get_order(&sell_id).unwrap().filled_amount, kwh("4"));
+        assert!(market.get_events().contains(&Event::OrderPartiallyFilled {
+            order_id: sell_id,
+            filled_amount: kwh("4"),
+            remaining: kwh("6"),
+        }));
+        assert_eq!(tokens.watt_balance(&bob), 100 * UNIT - 189 * UNIT / 10);
+    }
+
+    #[test]
+    fn test_orders_need_funds_and_energy() {
+        let (mut market, mut tokens) = create_test_market();
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+
+        // 10 kWh at 10 WATT costs 100 WATT plus the grid fee
+        assert_eq!(
+            market.place_order(bob.clone(), OrderType::Buy, kwh("10"), watt_per_kwh("10"), &mut tokens),
+            Err(EnergyTradingError::InsufficientWattBalance)
+        );
+        assert_eq!(
+            market.place_order(alice, OrderType::Sell, kwh("20.001"), watt_per_kwh("4.5"), &mut tokens),
+            Err(EnergyTradingError::InsufficientEnergyAvailable)
+        );
+        assert_eq!(
+            market.place_order(bob, OrderType::Buy, kwh("0.5"), watt_per_kwh("4.5"), &mut tokens),
+            Err(EnergyTradingError::InvalidEnergyAmount)
+        );
+        assert!(market.orders.is_empty());
+    }
+}
@@ -67,6 +67,11 @@ use uuid::Uuid;
 // Import from related pallets
 pub use pallet_token_system::{TokenSystem, TokenType, Balance, AccountId, BlockNumber};
 pub use pallet_energy_trading::{EnergyTradingSystem, EnergyTrade, EnergyOrder};
+pub use ledger_arithmetic::Amount;
+
+/// Multi-signature accounts and their pending calls
+#[cfg(feature = "multi-signature")]
+pub mod multisig;
 
 /// Transaction ID type
 pub type TransactionId = String;
@@ -117,7 +122,7 @@ pub struct CrossChainTransaction {
     /// Transaction type
     pub transaction_type: TransactionType,
     /// Transaction amount (if applicable)
-    pub amount: f64,
+    pub amount: Amount,
     /// Transaction data payload
     pub data: serde_json::Value,
     /// Transaction timestamp
@@ -203,7 +208,7 @@ pub enum Event {
         source_chain: ChainType,
         target_chain: ChainType,
         transaction_type: TransactionType,
-        amount: f64,
+        amount: Amount,
     },
     /// Cross-chain transaction confirmed
     CrossChainTransactionConfirmed {
@@ -231,6 +236,12 @@ pub enum Event {
         chain_type: ChainType,
         validator_id: ValidatorId,
     },
+    /// Validator stake slashed for an offence
+    ValidatorSlashed {
+        chain_type: ChainType,
+        validator_id: ValidatorId,
+        amount: Balance,
+    },
     /// Compliance report submitted
     ComplianceReportSubmitted {
         report_id: String,
@@ -286,6 +297,14 @@ pub enum HybridArchitectureError {
     OracleDataError(String),
     #[error("System configuration error: {0}")]
     SystemConfigurationError(String),
+    #[error("Invalid multisig: {0}")]
+    InvalidMultisig(String),
+    #[error("Not a signatory: {0}")]
+    NotSignatory(AccountId),
+    #[error("Call already approved by {0}")]
+    AlreadyApproved(AccountId),
+    #[error("No pending multisig call: {0}")]
+    MultisigCallNotFound(String),
 }
 
 /// Hybrid architecture system configuration
@@ -650,6 +669,29 @@ impl HybridArchitectureSystem {
     pub fn clear_events(&mut self) {
         self.events.clear();
     }
+
+    /// Record a slash applied to a validator's stake
+    pub fn record_slash(
+        &mut self,
+        chain_type: ChainType,
+        validator_id: &str,
+        amount: Balance,
+    ) -> Result<Balance, HybridArchitectureError> {
+        let validator = self.validators.get_mut(&chain_type)
+            .and_then(|validators| validators.iter_mut().find(|v| v.id == validator_id))
+            .ok_or_else(|| HybridArchitectureError::ValidatorNotFound(validator_id.to_string()))?;
+
+        let slashed = amount.min(validator.stake);
+        validator.stake -= slashed;
+        validator.performance_metrics.slashing_events += 1;
+
+        self.emit_event(Event::ValidatorSlashed {
+            chain_type,
+            validator_id: validator_id.to_string(),
+            amount: slashed,
+        });
+        Ok(slashed)
+    }
 }
 
 // ================================================================================
@@ -774,13 +816,6 @@ impl HybridArchitectureSystem {
             &transaction.target_chain,
         )?;
 
-        // Validate amount
-        if transaction.amount < 0.0 {
-            return Err(HybridArchitectureError::InvalidTransactionType(
-                "Amount cannot be negative".to_string()
-            ));
-        }
-
         Ok(())
     }
 
@@ -1133,6 +1168,11 @@ impl FeatureCapabilities {
         cfg!(feature = "performance-optimized")
     }
 
+    /// Check if multi-signature accounts are enabled
+    pub fn has_multi_signature() -> bool {
+        cfg!(feature = "multi-signature")
+    }
+
     /// Get all enabled features
     pub fn enabled_features() -> Vec<&'static str> {
         let mut features = Vec::new();
@@ -1145,6 +1185,7 @@ impl FeatureCapabilities {
         if cfg!(feature = "metrics") { features.push("metrics"); }
         if cfg!(feature = "health-checks") { features.push("health-checks"); }
         if cfg!(feature = "performance-optimized") { features.push("performance-optimized"); }
+        if cfg!(feature = "multi-signature") { features.push("multi-signature"); }
         
         features
     }
@@ -1167,6 +1208,31 @@ mod tests {
         assert_eq!(system.chain_status.len(), 3);
     }
 
+    #[test]
+    fn test_record_slash_updates_validator_metrics() {
+        let mut system = create_test_system();
+        system.validators.insert(ChainType::Public, vec![ValidatorInfo {
+            id: "validator-1".to_string(),
+            address: "validator-address".to_string(),
+            name: "Validator 1".to_string(),
+            stake: 1_000,
+            is_active: true,
+            performance_metrics: ValidatorMetrics {
+                blocks_produced: 0,
+                blocks_missed: 0,
+                uptime_percentage: 100.0,
+                slashing_events: 0,
+            },
+        }]);
+
+        assert_eq!(system.record_slash(ChainType::Public, "validator-1", 50), Ok(50));
+        let validator = &system.validators[&ChainType::Public][0];
+        assert_eq!(validator.stake, 950);
+        assert_eq!(validator.performance_metrics.slashing_events, 1);
+        assert!(matches!(system.get_events().last(), Some(Event::ValidatorSlashed { amount: 50, .. })));
+        assert!(system.record_slash(ChainType::Consortium, "validator-1", 50).is_err());
+    }
+
     #[test]
     fn test_chain_status() {
         let system = create_test_system();
@@ -1194,7 +1260,7 @@ mod tests {
             source_chain: ChainType::Public,
             target_chain: ChainType::Consortium,
             transaction_type: TransactionType::TokenTransfer,
-            amount: 1000.0,
+            amount: Amount::from_units(1000).unwrap(),
             data: serde_json::json!({"amount": 1000}),
             timestamp: Utc::now(),
             status: TransactionStatus::Pending,
@@ -0,0 +1,250 @@
+//! Multi-signature accounts
+//!
+//! A multisig account is controlled by a set of signatories, `threshold` of
+//! whom must approve a call before it is dispatched as the account. The
+//! account id is derived by the runtime from [`Multisig::seed`], which is the
+//! same for every order of the same signatories. Approvals are collected over
+//! several transactions and lapse after a timeout. The calls themselves are
+//! opaque here: the runtime hands them in encoded, together with their hash.
+
+use crate::{AccountId, BlockNumber, HybridArchitectureError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Blocks a pending call collects approvals for (one day of six-second blocks)
+pub const DEFAULT_APPROVAL_TIMEOUT: BlockNumber = 14_400;
+
+/// Largest signatory set of a multisig account
+pub const DEFAULT_MAX_SIGNATORIES: usize = 100;
+
+/// Multisig configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MultisigConfig {
+    /// Largest signatory set
+    pub max_signatories: usize,
+    /// Blocks after the first approval during which a call can be approved
+    pub approval_timeout: BlockNumber,
+}
+
+impl Default for MultisigConfig {
+    fn default() -> Self {
+        Self {
+            max_signatories: DEFAULT_MAX_SIGNATORIES,
+            approval_timeout: DEFAULT_APPROVAL_TIMEOUT,
+        }
+    }
+}
+
+/// Signatory set and threshold of a multisig account
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Multisig {
+    /// Signatories, sorted and without duplicates
+    signatories: Vec<AccountId>,
+    /// Approvals needed to dispatch a call
+    threshold: u16,
+}
+
+impl Multisig {
+    /// Multisig of the given signatories in any order.
+    ///
+    /// At least two approvals must be required, so that no single key
+    /// controls the account.
+    pub fn new(mut signatories: Vec<AccountId>, threshold: u16) -> Result<Self, HybridArchitectureError> {
+        signatories.sort();
+        signatories.dedup();
+        if threshold < 2 {
+            return Err(HybridArchitectureError::InvalidMultisig("threshold must be at least 2".to_string()));
+        }
+        if usize::from(threshold) > signatories.len() {
+            return Err(HybridArchitectureError::InvalidMultisig(format!(
+                "threshold {} exceeds {} signatories", threshold, signatories.len()
+            )));
+        }
+        Ok(Self { signatories, threshold })
+    }
+
+    /// Signatories, sorted
+    pub fn signatories(&self) -> &[AccountId] {
+        &self.signatories
+    }
+
+    /// Approvals needed to dispatch a call
+    pub fn threshold(&self) -> u16 {
+        self.threshold
+    }
+
+    /// Whether `who` is one of the signatories
+    pub fn is_signatory(&self, who: &AccountId) -> bool {
+        self.signatories.binary_search(who).is_ok()
+    }
+
+    /// Seed the account id is derived from
+    pub fn seed(&self) -> String {
+        format!("multisig/{}/{}", self.threshold, self.signatories.join(","))
+    }
+}
+
+/// Call of a multisig account waiting for approvals
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PendingCall {
+    /// Signatories and threshold of the account
+    pub multisig: Multisig,
+    /// Hash of the call
+    pub call_hash: String,
+    /// Encoded call
+    pub call: Vec<u8>,
+    /// Signatory who approved first; the only one who may cancel
+    pub depositor: AccountId,
+    /// Signatories who approved
+    pub approvals: BTreeSet<AccountId>,
+    /// Last block the call can be approved in
+    pub expires_at: BlockNumber,
+}
+
+/// Outcome of an approval
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Approval {
+    /// More approvals are needed
+    Pending {
+        /// Approvals so far
+        approvals: usize,
+    },
+    /// The threshold is reached and the call is no longer pending
+    Ready(PendingCall),
+}
+
+/// Pending calls of all multisig accounts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MultisigRegistry {
+    /// Multisig configuration
+    pub config: MultisigConfig,
+    /// Pending calls by multisig account and call hash
+    pub pending: BTreeMap<AccountId, BTreeMap<String, PendingCall>>,
+}
+
+impl MultisigRegistry {
+    /// Create a registry with no pending calls
+    pub fn new(config: MultisigConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Approve a call of the multisig `account` as signatory `who` at block `now`.
+    ///
+    /// The first approval makes the call pending. A call whose timeout has
+    /// passed is dropped, so its next approval starts over.
+    pub fn approve(
+        &mut self,
+        account: &AccountId,
+        multisig: Multisig,
+        who: &AccountId,
+        call_hash: String,
+        call: Vec<u8>,
+        now: BlockNumber,
+    ) -> Result<Approval, HybridArchitectureError> {
+        if multisig.signatories.len() > self.config.max_signatories {
+            return Err(HybridArchitectureError::InvalidMultisig(format!(
+                "more than {} signatories", self.config.max_signatories
+            )));
+        }
+        if !multisig.is_signatory(who) {
+            return Err(HybridArchitectureError::NotSignatory(who.clone()));
+        }
+
+        let calls = self.pending.entry(account.clone()).or_default();
+        calls.retain(|_, pending| pending.expires_at >= now);
+        let pending = calls.entry(call_hash.clone()).or_insert_with(|| PendingCall {
+            multisig,
+            call_hash: call_hash.clone(),
+            call,
+            depositor: who.clone(),
+            approvals: BTreeSet::new(),
+            expires_at: now.saturating_add(self.config.approval_timeout),
+        });
+        if !pending.approvals.insert(who.clone()) {
+            return Err(HybridArchitectureError::AlreadyApproved(who.clone()));
+        }
+
+        let approvals = pending.approvals.len();
+        let ready = approvals >= usize::from(pending.multisig.threshold);
+        let outcome = if ready {
+            calls.remove(&call_hash).map(Approval::Ready).expect("pending call exists")
+        } else {
+            Approval::Pending { approvals }
+        };
+        if calls.is_empty() {
+            self.pending.remove(account);
+        }
+        Ok(outcome)
+    }
+
+    /// Withdraw a pending call on behalf of its depositor
+    pub fn cancel(
+        &mut self,
+        account: &AccountId,
+        call_hash: &str,
+        who: &AccountId,
+    ) -> Result<PendingCall, HybridArchitectureError> {
+        let calls = self.pending.get_mut(account)
+            .ok_or_else(|| HybridArchitectureError::MultisigCallNotFound(call_hash.to_string()))?;
+        let pending = calls.get(call_hash)
+            .ok_or_else(|| HybridArchitectureError::MultisigCallNotFound(call_hash.to_string()))?;
+        if &pending.depositor != who {
+            return Err(HybridArchitectureError::AccessDenied("only the depositor can cancel".to_string()));
+        }
+        let pending = calls.remove(call_hash).expect("pending call exists");
+        if calls.is_empty() {
+            self.pending.remove(account);
+        }
+        Ok(pending)
+    }
+
+    /// Drop the calls that can no longer be approved at block `now`
+    pub fn expire(&mut self, now: BlockNumber) -> Vec<PendingCall> {
+        let mut expired = Vec::new();
+        for calls in self.pending.values_mut() {
+            let (lapsed, kept) = std::mem::take(calls).into_iter().partition(|(_, pending)| pending.expires_at < now);
+            *calls = kept;
+            expired.extend(lapsed.into_values());
+        }
+        self.pending.retain(|_, calls| !calls.is_empty());
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_approvals_reach_threshold_or_lapse() {
+        let [alice, bob, charlie] = ["alice", "bob", "charlie"].map(String::from);
+        let multisig = Multisig::new(vec![charlie.clone(), alice.clone(), bob.clone()], 2).unwrap();
+        assert_eq!(multisig.seed(), Multisig::new(vec![bob.clone(), charlie.clone(), alice.clone()], 2).unwrap().seed());
+        assert!(Multisig::new(vec![alice.clone(), bob.clone()], 1).is_err());
+        assert!(Multisig::new(vec![alice.clone(), alice.clone()], 2).is_err());
+
+        let mut registry = MultisigRegistry::new(MultisigConfig { approval_timeout: 10, ..MultisigConfig::default() });
+        let account = "multisig-account".to_string();
+        let approve = |registry: &mut MultisigRegistry, who: &AccountId, now| {
+            registry.approve(&account, multisig.clone(), who, "hash".to_string(), vec![1], now)
+        };
+        assert_eq!(approve(&mut registry, &alice, 1), Ok(Approval::Pending { approvals: 1 }));
+        assert_eq!(approve(&mut registry, &alice, 2), Err(HybridArchitectureError::AlreadyApproved(alice.clone())));
+        assert!(approve(&mut registry, &"mallory".to_string(), 2).is_err());
+        let Ok(Approval::Ready(call)) = approve(&mut registry, &bob, 3) else { panic!("threshold reached") };
+        assert_eq!((call.call, call.depositor), (vec![1], alice.clone()));
+        assert!(registry.pending.is_empty());
+
+        // Only the depositor cancels, and approvals lapse after the timeout
+        approve(&mut registry, &alice, 20).unwrap();
+        assert!(registry.cancel(&account, "hash", &bob).is_err());
+        assert!(registry.cancel(&account, "hash", &alice).is_ok());
+        approve(&mut registry, &alice, 20).unwrap();
+        assert_eq!(approve(&mut registry, &bob, 31), Ok(Approval::Pending { approvals: 1 }));
+        assert_eq!(registry.expire(42).len(), 1);
+        assert!(registry.pending.is_empty());
+    }
+}
@@ -10,8 +10,13 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-/// Token balance type
-pub type Balance = u64;
+/// Token balance in the smallest unit, the raw value of an [`Amount`]
+pub type Balance = u128;
+
+pub use ledger_arithmetic::{Amount, Rounding};
+
+/// One whole token in the smallest unit
+pub const UNIT: Balance = Amount::SCALE;
 
 /// Token ID type
 pub type TokenId = u32;
@@ -41,8 +46,8 @@ pub struct TokenInfo {
     pub token_type: TokenType,
     /// Total supply
     pub total_supply: Balance,
-    /// Current price (in basis points, 1 USD = 10000)
-    pub price: u32,
+    /// Current price in USD, in the smallest unit (1 USD = `UNIT`)
+    pub price: Balance,
     /// Whether the token is active
     pub is_active: bool,
 }
@@ -135,7 +140,7 @@ pub enum Event {
     /// Proposal was finalized
     ProposalFinalized { id: ProposalId, status: ProposalStatus },
     /// Token price was updated
-    PriceUpdated { token_type: TokenType, new_price: u32 },
+    PriceUpdated { token_type: TokenType, new_price: Balance },
 }
 
 /// Errors that can occur in the token system
@@ -187,12 +192,12 @@ pub struct TokenSystemConfig {
 impl Default for TokenSystemConfig {
     fn default() -> Self {
         Self {
-            min_stake_amount: 1000,
+            min_stake_amount: 1_000 * UNIT,
             staking_reward_rate: 800, // 8% annual
             voting_period: 100,
-            min_proposal_balance: 10000,
-            grid_initial_supply: 1_000_000_000,
-            watt_initial_supply: 1_000_000_000,
+            min_proposal_balance: 10_000 * UNIT,
+            grid_initial_supply: 1_000_000_000 * UNIT,
+            watt_initial_supply: 1_000_000_000 * UNIT,
         }
     }
 }
@@ -233,7 +238,7 @@ impl Default for TokenSystem {
         token_info.insert(TokenType::Grid, TokenInfo {
             token_type: TokenType::Grid,
             total_supply: config.grid_initial_supply,
-            price: 10000, // 1 USD = 10000 basis points
+            price: UNIT, // 1 USD
             is_active: true,
         });
         
@@ -241,7 +246,7 @@ impl Default for TokenSystem {
         token_info.insert(TokenType::Watt, TokenInfo {
             token_type: TokenType::Watt,
             total_supply: config.watt_initial_supply,
-            price: 10000, // 1 USD = 10000 basis points
+            price: UNIT, // 1 USD
             is_active: true,
         });
         
@@ -477,8 +482,11 @@ impl TokenSystem {
             if blocks_elapsed > 0 {
                 // Simple calculation: reward = stake * rate * blocks / (blocks_per_year)
                 // Assuming 6000 blocks per year as an example
-                let reward = stake_info.amount * self.config.staking_reward_rate as u64 * blocks_elapsed / (6000 * 10000);
-                return reward + stake_info.unclaimed_rewards;
+                let rate = self.config.staking_reward_rate as u128 * blocks_elapsed as u128;
+                let reward = Amount::from_raw(stake_info.amount)
+                    .checked_mul_ratio(rate, 6000 * 10000, Rounding::Down)
+                    .unwrap_or(Amount::MAX);
+                return reward.raw().saturating_add(stake_info.unclaimed_rewards);
             }
         }
         0
@@ -636,7 +644,7 @@ impl TokenSystem {
     }
 
     /// Update token price (for price stability mechanisms)
-    pub fn update_token_price(&mut self, token_type: TokenType, new_price: u32) -> Result<(), TokenError> {
+    pub fn update_token_price(&mut self, token_type: TokenType, new_price: Balance) -> Result<(), TokenError> {
         if let Some(info) = self.token_info.get_mut(&token_type) {
             info.price = new_price;
             self.emit_event(Event::PriceUpdated { token_type, new_price });
@@ -674,10 +682,10 @@ mod tests {
     fn create_test_system() -> TokenSystem {
         let mut system = TokenSystem::default();
         // Give some initial tokens for testing
-        system.mint_grid(&"alice".to_string(), 100000).unwrap();
-        system.mint_grid(&"bob".to_string(), 50000).unwrap();
-        system.mint_watt(&"alice".to_string(), 10000).unwrap();
-        system.mint_watt(&"bob".to_string(), 5000).unwrap();
+        system.mint_grid(&"alice".to_string(), 100000 * UNIT).unwrap();
+        system.mint_grid(&"bob".to_string(), 50000 * UNIT).unwrap();
+        system.mint_watt(&"alice".to_string(), 10000 * UNIT).unwrap();
+        system.mint_watt(&"bob".to_string(), 5000 * UNIT).unwrap();
         system.clear_events();
         system
     }
@@ -687,12 +695,12 @@ mod tests {
         let mut system = TokenSystem::default();
         
         // Mint GRID tokens
-        system.mint_grid(&"alice".to_string(), 1000).unwrap();
-        assert_eq!(system.grid_balance(&"alice".to_string()), 1000);
+        system.mint_grid(&"alice".to_string(), 1000 * UNIT).unwrap();
+        assert_eq!(system.grid_balance(&"alice".to_string()), 1000 * UNIT);
         
         // Mint WATT tokens
-        system.mint_watt(&"alice".to_string(), 500).unwrap();
-        assert_eq!(system.watt_balance(&"alice".to_string()), 500);
+        system.mint_watt(&"alice".to_string(), 500 * UNIT).unwrap();
+        assert_eq!(system.watt_balance(&"alice".to_string()), 500 * UNIT);
         
         // Check events
         assert_eq!(system.events.len(), 2);
@@ -703,12 +711,12 @@ mod tests {
         let mut system = create_test_system();
         
         // Burn GRID tokens
-        system.burn_grid(&"alice".to_string(), 1000).unwrap();
-        assert_eq!(system.grid_balance(&"alice".to_string()), 99000);
+        system.burn_grid(&"alice".to_string(), 1000 * UNIT).unwrap();
+        assert_eq!(system.grid_balance(&"alice".to_string()), 99000 * UNIT);
         
         // Test insufficient balance
         assert_eq!(
-            system.burn_grid(&"alice".to_string(), 200000),
+            system.burn_grid(&"alice".to_string(), 200000 * UNIT),
             Err(TokenError::InsufficientBalance)
         );
     }
@@ -718,13 +726,13 @@ mod tests {
         let mut system = create_test_system();
         
         // Transfer GRID tokens
-        system.transfer_grid(&"alice".to_string(), &"bob".to_string(), 1000).unwrap();
-        assert_eq!(system.grid_balance(&"alice".to_string()), 99000);
-        assert_eq!(system.grid_balance(&"bob".to_string()), 51000);
+        system.transfer_grid(&"alice".to_string(), &"bob".to_string(), 1000 * UNIT).unwrap();
+        assert_eq!(system.grid_balance(&"alice".to_string()), 99000 * UNIT);
+        assert_eq!(system.grid_balance(&"bob".to_string()), 51000 * UNIT);
         
         // Test insufficient balance
         assert_eq!(
-            system.transfer_grid(&"alice".to_string(), &"bob".to_string(), 200000),
+            system.transfer_grid(&"alice".to_string(), &"bob".to_string(), 200000 * UNIT),
             Err(TokenError::InsufficientBalance)
         );
     }
@@ -734,14 +742,14 @@ mod tests {
         let mut system = create_test_system();
         
         // Stake tokens
-        system.stake(&"alice".to_string(), 10000).unwrap();
-        assert_eq!(system.grid_balance(&"alice".to_string()), 90000);
-        assert_eq!(system.get_stake_info(&"alice".to_string()).unwrap().amount, 10000);
-        assert_eq!(system.total_staked(), 10000);
+        system.stake(&"alice".to_string(), 10000 * UNIT).unwrap();
+        assert_eq!(system.grid_balance(&"alice".to_string()), 90000 * UNIT);
+        assert_eq!(system.get_stake_info(&"alice".to_string()).unwrap().amount, 10000 * UNIT);
+        assert_eq!(system.total_staked(), 10000 * UNIT);
         
         // Test minimum stake amount
         assert_eq!(
-            system.stake(&"alice".to_string(), 500),
+            system.stake(&"alice".to_string(), 500 * UNIT),
             Err(TokenError::MinimumStakeNotMet)
         );
     }
@@ -751,12 +759,12 @@ mod tests {
         let mut system = create_test_system();
         
         // Stake and then unstake
-        system.stake(&"alice".to_string(), 10000).unwrap();
-        system.unstake(&"alice".to_string(), 5000).unwrap();
+        system.stake(&"alice".to_string(), 10000 * UNIT).unwrap();
+        system.unstake(&"alice".to_string(), 5000 * UNIT).unwrap();
         
-        assert_eq!(system.grid_balance(&"alice".to_string()), 95000);
-        assert_eq!(system.get_stake_info(&"alice".to_string()).unwrap().amount, 5000);
-        assert_eq!(system.total_staked(), 5000);
+        assert_eq!(system.grid_balance(&"alice".to_string()), 95000 * UNIT);
+        assert_eq!(system.get_stake_info(&"alice".to_string()).unwrap().amount, 5000 * UNIT);
+        assert_eq!(system.total_staked(), 5000 * UNIT);
     }
 
     #[test]
@@ -764,7 +772,7 @@ mod tests {
         let mut system = create_test_system();
         
         // Stake tokens
-        system.stake(&"alice".to_string(), 10000).unwrap();
+        system.stake(&"alice".to_string(), 10000 * UNIT).unwrap();
         
         // Advance some blocks
         system.set_block(100);
@@ -801,8 +809,8 @@ mod tests {
         let mut system = create_test_system();
         
         // Stake tokens for voting power
-        system.stake(&"alice".to_string(), 10000).unwrap();
-        system.stake(&"bob".to_string(), 5000).unwrap();
+        system.stake(&"alice".to_string(), 10000 * UNIT).unwrap();
+        system.stake(&"bob".to_string(), 5000 * UNIT).unwrap();
         
         // Create proposal
         let proposal_id = system.create_proposal(
@@ -821,7 +829,7 @@ mod tests {
         system.vote(proposal_id, &"bob".to_string(), true).unwrap();
         
         let proposal = system.get_proposal(proposal_id).unwrap();
-        assert_eq!(proposal.votes_for, 5000);
+        assert_eq!(proposal.votes_for, 5000 * UNIT);
         assert_eq!(proposal.votes_against, 0);
     }
 
@@ -830,8 +838,8 @@ mod tests {
         let mut system = create_test_system();
         
         // Setup for voting
-        system.stake(&"alice".to_string(), 10000).unwrap();
-        system.stake(&"bob".to_string(), 5000).unwrap();
+        system.stake(&"alice".to_string(), 10000 * UNIT).unwrap();
+        system.stake(&"bob".to_string(), 5000 * UNIT).unwrap();
         
         let proposal_id = system.create_proposal(
             &"alice".to_string(),
@@ -863,16 +871,16 @@ mod tests {
         let mut system = create_test_system();
         
         // Update GRID token price
-        system.update_token_price(TokenType::Grid, 15000).unwrap();
+        system.update_token_price(TokenType::Grid, 3 * UNIT / 2).unwrap();
         
         let grid_info = system.get_token_info(&TokenType::Grid).unwrap();
-        assert_eq!(grid_info.price, 15000);
+        assert_eq!(grid_info.price, 3 * UNIT / 2);
         
         // Check event
         assert_eq!(system.events.len(), 1);
         if let Event::PriceUpdated { token_type, new_price } = &system.events[0] {
             assert_eq!(*token_type, TokenType::Grid);
-            assert_eq!(*new_price, 15000);
+            assert_eq!(*new_price, 3 * UNIT / 2);
         } else {
             panic!("Expected PriceUpdated event");
         }
@@ -883,8 +891,8 @@ mod tests {
         let system = create_test_system();
         
         // Check initial total supplies
-        assert_eq!(system.total_supply(&TokenType::Grid), 1_000_000_000 + 150000); // Initial + minted
-        assert_eq!(system.total_supply(&TokenType::Watt), 1_000_000_000 + 15000); // Initial + minted
+        assert_eq!(system.total_supply(&TokenType::Grid), (1_000_000_000 + 150000) * UNIT); // Initial + minted
+        assert_eq!(system.total_supply(&TokenType::Watt), (1_000_000_000 + 15000) * UNIT); // Initial + minted
     }
 
     #[test]
@@ -893,7 +901,7 @@ mod tests {
         
         // Test various error conditions
         assert_eq!(
-            system.transfer_grid(&"alice".to_string(), &"bob".to_string(), 200000),
+            system.transfer_grid(&"alice".to_string(), &"bob".to_string(), 200000 * UNIT),
             Err(TokenError::InsufficientBalance)
         );
         
@@ -903,7 +911,7 @@ mod tests {
         );
         
         assert_eq!(
-            system.unstake(&"charlie".to_string(), 1000),
+            system.unstake(&"charlie".to_string(), 1000 * UNIT),
             Err(TokenError::NotStaking)
         );
     }
@@ -913,8 +921,8 @@ mod tests {
         let mut system = create_test_system();
         
         // 1. Users stake tokens
-        system.stake(&"alice".to_string(), 20000).unwrap();
-        system.stake(&"bob".to_string(), 10000).unwrap();
+        system.stake(&"alice".to_string(), 20000 * UNIT).unwrap();
+        system.stake(&"bob".to_string(), 10000 * UNIT).unwrap();
         
         // 2. Create a proposal
         let proposal_id = system.create_proposal(
@@ -935,15 +943,15 @@ mod tests {
         system.finalize_proposal(proposal_id).unwrap();
         
         // 6. Update token price (simulate market activity)
-        system.update_token_price(TokenType::Watt, 9500).unwrap();
+        system.update_token_price(TokenType::Watt, 95 * UNIT / 100).unwrap();
         
         // 7. Transfer tokens
-        system.transfer_watt(&"alice".to_string(), &"bob".to_string(), 1000).unwrap();
+        system.transfer_watt(&"alice".to_string(), &"bob".to_string(), 1000 * UNIT).unwrap();
         
         // Verify final state
-        assert!(system.grid_balance(&"alice".to_string()) > 80000); // Original + rewards
-        assert_eq!(system.watt_balance(&"alice".to_string()), 9000); // 10000 - 1000 transferred
-        assert_eq!(system.watt_balance(&"bob".to_string()), 6000); // 5000 + 1000 received
+        assert!(system.grid_balance(&"alice".to_string()) > 80000 * UNIT); // Original + rewards
+        assert_eq!(system.watt_balance(&"alice".to_string()), 9000 * UNIT); // 10000 - 1000 transferred
+        assert_eq!(system.watt_balance(&"bob".to_string()), 6000 * UNIT); // 5000 + 1000 received
         
         let proposal = system.get_proposal(proposal_id).unwrap();
         assert_eq!(proposal.status, ProposalStatus::Passed);
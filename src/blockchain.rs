@@ -1,5 +1,6 @@
 use crate::block::{Block, Transaction};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Blockchain {
@@ -7,6 +8,9 @@ pub struct Blockchain {
     pub difficulty: usize,
     pub pending_transactions: Vec<Transaction>,
     pub mining_reward: f64,
+    /// Net energy traded per address, updated as blocks are mined
+    #[serde(default)]
+    pub energy_balances: HashMap<String, f64>,
 }
 
 impl Blockchain {
@@ -16,6 +20,7 @@ impl Blockchain {
             difficulty: 4,
             pending_transactions: Vec::new(),
             mining_reward: 10.0,
+            energy_balances: HashMap::new(),
         };
         blockchain.create_genesis_block();
         blockchain
@@ -46,7 +51,13 @@ impl Blockchain {
             self.get_latest_block().hash.clone(),
         );
         block.mine_block(self.difficulty);
-        
+
+        for transaction in &block.transactions {
+            if let crate::block::TransactionType::EnergyTrade = transaction.transaction_type {
+                *self.energy_balances.entry(transaction.from.clone()).or_insert(0.0) -= transaction.energy_amount;
+                *self.energy_balances.entry(transaction.to.clone()).or_insert(0.0) += transaction.energy_amount;
+            }
+        }
         self.chain.push(block);
         self.pending_transactions.clear();
     }
@@ -56,25 +67,7 @@ impl Blockchain {
     }
 
     pub fn get_energy_balance(&self, address: &str) -> f64 {
-        let mut energy_balance = 0.0;
-
-        for block in &self.chain {
-            for transaction in &block.transactions {
-                match transaction.transaction_type {
-                    crate::block::TransactionType::EnergyTrade => {
-                        if transaction.from == address {
-                            energy_balance -= transaction.energy_amount;
-                        }
-                        if transaction.to == address {
-                            energy_balance += transaction.energy_amount;
-                        }
-                    }
-                    _ => {}
-                }
-            }
-        }
-
-        energy_balance
+        self.energy_balances.get(address).copied().unwrap_or(0.0)
     }
 
     pub fn is_chain_valid(&self) -> bool {
@@ -2,8 +2,19 @@
 // This module contains the fundamental blockchain operations
 
 use crate::primitives::*;
+use crate::core::codec::*;
+use crate::core::finality::*;
+use crate::core::fork_choice::*;
+use crate::core::index::*;
+use crate::core::merkle::*;
+use crate::core::offences::*;
+use crate::core::poa::*;
+use crate::core::snapshot::*;
+use crate::core::state_trie::*;
+use crate::core::storage::*;
+use crate::core::transaction_pool::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// Block header structure
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -24,6 +35,9 @@ pub struct BlockHeader {
     pub difficulty: u64,
     /// Nonce
     pub nonce: u64,
+    /// Finality authorities that take over once this block is final
+    #[serde(default)]
+    pub authority_change: Option<AuthoritySet>,
 }
 
 /// Block body structure
@@ -33,6 +47,18 @@ pub struct BlockBody {
     pub transactions: Vec<Transaction>,
     /// Justifications/proofs
     pub justifications: Vec<Justification>,
+    /// Runtime calls executed for the block, in execution order
+    #[serde(default)]
+    pub calls: Vec<Extrinsic>,
+}
+
+impl BlockBody {
+    /// Merkle leaves under the transactions root: the transactions, then the calls
+    pub fn leaves(&self) -> Vec<[u8; 32]> {
+        self.transactions.iter().map(Transaction::leaf_hash)
+            .chain(self.calls.iter().map(Extrinsic::leaf_hash))
+            .collect()
+    }
 }
 
 /// Complete block structure
@@ -61,6 +87,8 @@ pub struct Transaction {
     pub data: Vec<u8>,
     /// Transaction fee
     pub fee: Balance,
+    /// Sender nonce, equal to the number of transactions the sender has had included before
+    pub nonce: u64,
     /// Block number when included
     pub block_number: Option<BlockNumber>,
     /// Transaction timestamp
@@ -69,8 +97,24 @@ pub struct Transaction {
     pub signature: Option<String>,
 }
 
-/// Transaction types
+/// Runtime call recorded in a block, so that the block can be re-executed
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Extrinsic {
+    /// Account the call was executed for
+    pub sender: AccountId,
+    /// JSON encoding of the call
+    pub call: Vec<u8>,
+}
+
+impl Extrinsic {
+    /// Merkle leaf hash of the call
+    pub fn leaf_hash(&self) -> [u8; 32] {
+        leaf_hash(&self.encode())
+    }
+}
+
+/// Transaction types
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum TransactionType {
     /// Token transfer
     Transfer,
@@ -119,6 +163,44 @@ pub struct ValidatorSignature {
     pub timestamp: Timestamp,
 }
 
+/// Proof that a transaction is included in a block
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransactionProof {
+    /// Transaction hash
+    pub transaction_hash: Hash,
+    /// Hash of the including block
+    pub block_hash: Hash,
+    /// Number of the including block
+    pub block_number: BlockNumber,
+    /// Transactions root of the including block
+    pub transactions_root: Hash,
+    /// Merkle path from the transaction leaf to the root
+    pub proof: MerkleProof,
+}
+
+/// Account states a block overwrote, used to revert it during a reorg
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct BlockUndo {
+    /// State of each touched account before the block, `None` if it did not exist
+    pub accounts: BTreeMap<AccountId, Option<AccountState>>,
+}
+
+/// Outcome of importing a block
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ImportResult {
+    /// Block was already known
+    AlreadyKnown,
+    /// Block is valid but extends a chain that is not the best chain
+    SideChain,
+    /// Block became the new best block
+    NewBest {
+        /// Blocks removed from the best chain, newest first
+        retracted: Vec<Hash>,
+        /// Blocks added to the best chain, oldest first
+        enacted: Vec<Hash>,
+    },
+}
+
 /// Blockchain state
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockchainState {
@@ -132,8 +214,8 @@ pub struct BlockchainState {
     pub transactions: HashMap<Hash, Transaction>,
     /// Account states
     pub accounts: HashMap<AccountId, AccountState>,
-    /// Pending transactions
-    pub pending_transactions: Vec<Transaction>,
+    /// Undo records of best-chain blocks
+    pub undo: HashMap<Hash, BlockUndo>,
 }
 
 /// Account state
@@ -148,7 +230,7 @@ pub struct AccountState {
     /// Account type
     pub account_type: AccountType,
     /// Energy balance
-    pub energy_balance: EnergyAmount,
+    pub energy_balance: Energy,
     /// Last activity timestamp
     pub last_activity: Timestamp,
 }
@@ -176,62 +258,511 @@ pub struct Blockchain {
     pub state: BlockchainState,
     /// System configuration
     pub config: SystemConfig,
+    /// Storage backend
+    pub storage: Box<dyn StorageBackend>,
+    /// Authenticated account state
+    pub state_trie: StateTrie,
+    /// Pending transactions
+    pub pool: TransactionPool,
+    /// Rule choosing the best chain among forks
+    pub fork_choice: Box<dyn ForkChoiceRule>,
+    /// Finality vote collection
+    pub finality: FinalityGadget,
+    /// Slot schedule, set when running Proof-of-Authority
+    pub authorship: Option<PoaAuthorship>,
+    /// Private key this node seals its PoA blocks with
+    authority_key: Option<String>,
+    /// Offences detected and not yet reported
+    offences: Vec<Offence>,
+    /// Secondary indexes over the best chain
+    index: ChainIndex,
+    /// Pruning horizon and finalized height of the last pruning pass
+    pruned_at: Option<(BlockNumber, BlockNumber)>,
+    /// Block the runtime last saved its service state at; pruning keeps the bodies after it
+    state_checkpoint: Option<BlockNumber>,
+    /// Authorities to announce in the next mined block
+    scheduled_authorities: Option<Vec<Authority>>,
+    /// Runtime calls to record in the next mined block
+    pending_calls: Vec<Extrinsic>,
 }
 
 impl Blockchain {
-    /// Create new blockchain
+    /// Create new in-memory blockchain
     pub fn new(config: SystemConfig) -> Self {
-        let genesis_block = Self::create_genesis_block(&config);
-        
-        let mut blocks = HashMap::new();
-        blocks.insert(genesis_block.header.parent_hash.clone(), genesis_block.clone());
-        
+        Self::with_storage(config, Box::new(MemoryStorage::new()))
+            .expect("in-memory storage cannot fail")
+    }
+
+    /// Open the blockchain stored under `node_config.data_dir`, creating genesis on first start
+    pub fn open(config: SystemConfig) -> CoreResult<Self> {
+        let path = std::path::Path::new(&config.node_config.data_dir).join("chain");
+        let storage = FileStorage::open(path)?;
+        Self::with_storage(config, Box::new(storage))
+    }
+
+    /// Create blockchain on top of the given storage backend
+    pub fn with_storage(config: SystemConfig, storage: Box<dyn StorageBackend>) -> CoreResult<Self> {
+        if storage.get(Column::Meta, META_BEST_BLOCK)?.is_some() {
+            let stored_genesis: Option<Hash> = read_value(storage.as_ref(), Column::Meta, META_GENESIS_BLOCK)?;
+            if stored_genesis.as_ref() != Some(&Self::genesis_hash(&config.chain_spec.genesis)) {
+                return Err(CoreError::StorageError("Stored chain was created from a different chain spec".to_string()));
+            }
+            let state = Self::load_state(storage.as_ref())?;
+            let state_trie = StateTrie::from_accounts(&state.accounts);
+            if state_trie.root_hash() != state.best_block.header.state_root {
+                return Err(CoreError::StorageError("Stored accounts do not match best block state root".to_string()));
+            }
+            let index = Self::build_index(&state)?;
+            let mut finality = Self::genesis_finality(&config);
+            if let Some(authorities) = read_value(storage.as_ref(), Column::Meta, META_AUTHORITY_SET)? {
+                finality.authorities = authorities;
+            }
+            return Ok(Self {
+                state,
+                storage,
+                state_trie,
+                pool: TransactionPool::default(),
+                fork_choice: Box::new(LongestChain),
+                finality,
+                authorship: Self::poa_authorship(&config),
+                authority_key: None,
+                offences: Vec::new(),
+                index,
+                pruned_at: None,
+            state_checkpoint: None,
+                scheduled_authorities: None,
+                pending_calls: Vec::new(),
+                config,
+            });
+        }
+
+        let accounts = Self::genesis_accounts(&config.chain_spec.genesis);
+        let state_trie = StateTrie::from_accounts(&accounts);
+        let genesis_block = Self::create_genesis_block(&config.chain_spec.genesis, state_trie.root_hash());
+
         let state = BlockchainState {
             best_block: genesis_block.clone(),
-            finalized_block: genesis_block,
-            blocks,
+            finalized_block: genesis_block.clone(),
+            blocks: HashMap::new(),
             transactions: HashMap::new(),
-            accounts: HashMap::new(),
-            pending_transactions: Vec::new(),
+            accounts,
+            undo: HashMap::new(),
+        };
+
+        let mut blockchain = Self {
+            state,
+            storage,
+            state_trie,
+            pool: TransactionPool::default(),
+            fork_choice: Box::new(LongestChain),
+            finality: Self::genesis_finality(&config),
+            authorship: Self::poa_authorship(&config),
+            authority_key: None,
+            offences: Vec::new(),
+            index: ChainIndex::default(),
+            pruned_at: None,
+            state_checkpoint: None,
+            scheduled_authorities: None,
+            pending_calls: Vec::new(),
+            config,
         };
+        let genesis_hash = genesis_block.hash();
+        blockchain.state.blocks.insert(genesis_hash.clone(), genesis_block.clone());
+        blockchain.index.enact(&genesis_block);
+
+        let mut batch = WriteBatch::new();
+        batch.put(Column::Meta, META_GENESIS_BLOCK, &genesis_hash)?;
+        batch.put(Column::Blocks, &genesis_hash, &genesis_block)?;
+        blockchain.stage_head(&mut batch)?;
+        for (account_id, account) in &blockchain.state.accounts {
+            batch.put(Column::Accounts, account_id, account)?;
+        }
+        blockchain.storage.write(batch)?;
+
+        Ok(blockchain)
+    }
 
-        Self { state, config }
+    /// Restore the blockchain under `node_config.data_dir` from a snapshot
+    pub fn restore(config: SystemConfig, snapshot: ChainSnapshot) -> CoreResult<Self> {
+        let path = std::path::Path::new(&config.node_config.data_dir).join("chain");
+        let storage = FileStorage::open(path)?;
+        Self::from_snapshot(config, Box::new(storage), snapshot)
     }
 
-    /// Create genesis block
-    fn create_genesis_block(_config: &SystemConfig) -> Block {
+    /// Restore a node from a snapshot into empty storage.
+    ///
+    /// The snapshot is verified against the genesis of `config` first; the
+    /// snapshot block becomes the best and finalized block.
+    pub fn from_snapshot(
+        config: SystemConfig,
+        storage: Box<dyn StorageBackend>,
+        snapshot: ChainSnapshot,
+    ) -> CoreResult<Self> {
+        if storage.get(Column::Meta, META_BEST_BLOCK)?.is_some() {
+            return Err(CoreError::StorageError("Cannot restore a snapshot over an existing chain".to_string()));
+        }
+        let mut finality = Self::genesis_finality(&config);
+        snapshot.verify(&Self::genesis_hash(&config.chain_spec.genesis), &finality.authorities, finality.threshold)?;
+        finality.authorities = snapshot.authorities;
+
+        let block = snapshot.block;
+        let accounts: HashMap<AccountId, AccountState> = snapshot.accounts.into_iter().collect();
+        let transactions = block.body.transactions.iter()
+            .map(|transaction| (transaction.hash.clone(), transaction.clone()))
+            .collect();
+        let state = BlockchainState {
+            best_block: block.clone(),
+            finalized_block: block.clone(),
+            blocks: HashMap::from([(block.hash(), block.clone())]),
+            transactions,
+            accounts,
+            undo: HashMap::new(),
+        };
+        let mut index = ChainIndex::default();
+        index.enact(&block);
+
+        let mut blockchain = Self {
+            state_trie: StateTrie::from_accounts(&state.accounts),
+            state,
+            storage,
+            pool: TransactionPool::default(),
+            fork_choice: Box::new(LongestChain),
+            finality,
+            authorship: Self::poa_authorship(&config),
+            authority_key: None,
+            offences: Vec::new(),
+            index,
+            pruned_at: None,
+            state_checkpoint: None,
+            scheduled_authorities: None,
+            pending_calls: Vec::new(),
+            config,
+        };
+
+        let mut batch = WriteBatch::new();
+        batch.put(Column::Meta, META_GENESIS_BLOCK, &snapshot.genesis_hash)?;
+        batch.put(Column::Blocks, &block.hash(), &block)?;
+        for (hash, transaction) in &blockchain.state.transactions {
+            batch.put(Column::Transactions, hash, transaction)?;
+        }
+        for (account_id, account) in &blockchain.state.accounts {
+            batch.put(Column::Accounts, account_id, account)?;
+        }
+        blockchain.stage_head(&mut batch)?;
+        blockchain.storage.write(batch)?;
+        Ok(blockchain)
+    }
+
+    /// Snapshot of the accounts at the finalized block
+    pub fn export_snapshot(&self) -> CoreResult<ChainSnapshot> {
+        let block = self.state.finalized_block.clone();
+        if block.header.number > 0 && block.header.authority_change.is_some() {
+            return Err(CoreError::ValidationFailed("Finalized block changes the authority set; wait for the next one".to_string()));
+        }
+        let accounts = if block.hash() == self.state.best_block.hash() {
+            self.state.accounts.iter().map(|(id, account)| (id.clone(), account.clone())).collect()
+        } else {
+            let root = crate::utils::crypto::hash_from_hex(&block.header.state_root)
+                .ok_or_else(|| CoreError::ValidationFailed("Malformed state root".to_string()))?;
+            self.state_trie.accounts_at(&root)
+                .ok_or_else(|| CoreError::ValidationFailed("State for the finalized block is not available".to_string()))?
+        };
+        Ok(ChainSnapshot {
+            version: SNAPSHOT_VERSION,
+            genesis_hash: Self::genesis_hash(&self.config.chain_spec.genesis),
+            block,
+            authorities: self.finality.authorities.clone(),
+            accounts,
+        })
+    }
+
+    /// Hash of the genesis block a genesis config produces
+    pub fn genesis_hash(genesis: &GenesisConfig) -> Hash {
+        let state_root = StateTrie::from_accounts(&Self::genesis_accounts(genesis)).root_hash();
+        Self::create_genesis_block(genesis, state_root).hash()
+    }
+
+    /// Accounts funded in the genesis spec; genesis validators get validator accounts
+    fn genesis_accounts(genesis: &GenesisConfig) -> HashMap<AccountId, AccountState> {
+        genesis.accounts.iter()
+            .map(|(account_id, balance)| {
+                let account_type = if genesis.validators.contains(account_id) {
+                    AccountType::Validator
+                } else {
+                    AccountType::User
+                };
+                (account_id.clone(), AccountState {
+                    balance: *balance,
+                    account_type,
+                    ..AccountState::default()
+                })
+            })
+            .collect()
+    }
+
+    /// Finality gadget voting with the genesis validators
+    fn genesis_finality(config: &SystemConfig) -> FinalityGadget {
+        let mut authorities = AuthoritySet::from_genesis(&config.chain_spec.genesis);
+        // PBFT counts replicas, not stake
+        if config.consensus_config.algorithm == ConsensusAlgorithm::PBFT {
+            for authority in &mut authorities.authorities {
+                authority.weight = 1;
+            }
+        }
+        FinalityGadget::new(authorities, config.consensus_config.finality_threshold)
+    }
+
+    /// Round-robin slot schedule if the chain runs Proof-of-Authority
+    fn poa_authorship(config: &SystemConfig) -> Option<PoaAuthorship> {
+        (config.consensus_config.algorithm == ConsensusAlgorithm::PoA).then(|| PoaAuthorship::from_config(config))
+    }
+
+    /// Set the private key used to seal blocks this node authors
+    pub fn set_authority_key(&mut self, private_key: String) {
+        self.authority_key = Some(private_key);
+    }
+
+    /// Load blockchain state from storage
+    fn load_state(storage: &dyn StorageBackend) -> CoreResult<BlockchainState> {
+        let best_hash: Hash = read_value(storage, Column::Meta, META_BEST_BLOCK)?
+            .ok_or_else(|| CoreError::StorageError("Missing best block pointer".to_string()))?;
+        let finalized_hash: Hash = read_value(storage, Column::Meta, META_FINALIZED_BLOCK)?
+            .unwrap_or_else(|| best_hash.clone());
+
+        let blocks: HashMap<Hash, Block> = read_column(storage, Column::Blocks)?.into_iter().collect();
+        let transactions: HashMap<Hash, Transaction> =
+            read_column(storage, Column::Transactions)?.into_iter().collect();
+        let accounts: HashMap<AccountId, AccountState> =
+            read_column(storage, Column::Accounts)?.into_iter().collect();
+        let undo: HashMap<Hash, BlockUndo> = read_column(storage, Column::Undo)?.into_iter().collect();
+
+        let best_block = blocks.get(&best_hash).cloned()
+            .ok_or_else(|| CoreError::StorageError(format!("Best block {} not found", best_hash)))?;
+        let finalized_block = blocks.get(&finalized_hash).cloned()
+            .ok_or_else(|| CoreError::StorageError(format!("Finalized block {} not found", finalized_hash)))?;
+
+        Ok(BlockchainState {
+            best_block,
+            finalized_block,
+            blocks,
+            transactions,
+            accounts,
+            undo,
+        })
+    }
+
+    /// Index the stored best chain, walking back from the best block.
+    ///
+    /// The walk ends at genesis, or at the snapshot block a restored node
+    /// started from.
+    fn build_index(state: &BlockchainState) -> CoreResult<ChainIndex> {
+        let mut index = ChainIndex::default();
+        let mut current = Some(&state.best_block);
+        while let Some(block) = current {
+            index.enact(block);
+            current = state.blocks.get(&block.header.parent_hash);
+            if current.is_none() && block.header.number > state.finalized_block.header.number {
+                return Err(CoreError::BlockNotFound);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Queue the best and finalized block pointers into a write batch
+    fn stage_head(&self, batch: &mut WriteBatch) -> CoreResult<()> {
+        batch.put(Column::Meta, META_BEST_BLOCK, &self.state.best_block.hash())?;
+        batch.put(Column::Meta, META_FINALIZED_BLOCK, &self.state.finalized_block.hash())?;
+        batch.put(Column::Meta, META_AUTHORITY_SET, &self.finality.authorities)?;
+        Ok(())
+    }
+
+    /// Persist new blocks and a change of best chain.
+    ///
+    /// `retracted` blocks left the best chain and `enacted` ones joined it; their
+    /// transactions, undo records and touched accounts are written or removed.
+    fn persist_changes(&mut self, stored: &[Hash], retracted: &[Hash], enacted: &[Hash]) -> CoreResult<()> {
+        self.persist_changes_with(WriteBatch::new(), stored, retracted, enacted)
+    }
+
+    /// Persist changes like [`Self::persist_changes`], together with the writes already in `batch`
+    fn persist_changes_with(
+        &mut self,
+        mut batch: WriteBatch,
+        stored: &[Hash],
+        retracted: &[Hash],
+        enacted: &[Hash],
+    ) -> CoreResult<()> {
+        for hash in stored {
+            if let Some(block) = self.state.blocks.get(hash) {
+                batch.put(Column::Blocks, hash, block)?;
+            }
+        }
+
+        let mut touched: Vec<&AccountId> = Vec::new();
+        for (hash, is_enacted) in retracted.iter().map(|h| (h, false)).chain(enacted.iter().map(|h| (h, true))) {
+            let block = self.state.blocks.get(hash).ok_or(CoreError::BlockNotFound)?;
+            if is_enacted {
+                self.index.enact(block);
+            } else {
+                self.index.retract(block);
+            }
+            match self.state.undo.get(hash) {
+                Some(undo) => batch.put(Column::Undo, hash, undo)?,
+                None => batch.delete(Column::Undo, hash),
+            }
+            for transaction in &block.body.transactions {
+                match self.state.transactions.get(&transaction.hash) {
+                    Some(stored) => batch.put(Column::Transactions, &transaction.hash, stored)?,
+                    None => batch.delete(Column::Transactions, &transaction.hash),
+                }
+                touched.push(&transaction.from);
+                touched.push(&transaction.to);
+            }
+        }
+        touched.sort();
+        touched.dedup();
+        for account_id in touched {
+            match self.state.accounts.get(account_id) {
+                Some(account) => batch.put(Column::Accounts, account_id, account)?,
+                None => batch.delete(Column::Accounts, account_id),
+            }
+        }
+
+        self.stage_pruning(&mut batch)?;
+        self.stage_head(&mut batch)?;
+        self.storage.write(batch)
+    }
+
+    /// Drop the finalized history the pruning mode does not keep, queueing the deletions.
+    ///
+    /// Forks that lost to the finalized chain and undo records of final blocks
+    /// go in every mode but archive. Canonical blocks below the horizon keep
+    /// their header and justifications but lose their transactions and calls,
+    /// and state roots older than the finalized block are dropped from the
+    /// trie. The horizon never passes the block after the state checkpoint.
+    fn stage_pruning(&mut self, batch: &mut WriteBatch) -> CoreResult<()> {
+        let finalized_number = self.state.finalized_block.header.number;
+        let best_number = self.state.best_block.header.number;
+        let horizon = match self.config.node_config.pruning {
+            PruningMode::Archive => return Ok(()),
+            PruningMode::KeepBlocks(keep) => finalized_number.min((best_number + 1).saturating_sub(keep)),
+            PruningMode::HeadersOnly => finalized_number,
+        };
+        // Blocks after the saved service state are replayed on restart
+        let horizon = self.state_checkpoint.map_or(horizon, |checkpoint| horizon.min(checkpoint + 1));
+        if self.pruned_at == Some((horizon, finalized_number)) {
+            return Ok(());
+        }
+        self.pruned_at = Some((horizon, finalized_number));
+
+        let mut stale = Vec::new();
+        let mut bodies = Vec::new();
+        let mut undo = Vec::new();
+        for (hash, block) in &self.state.blocks {
+            let number = block.header.number;
+            if number > finalized_number {
+                continue;
+            }
+            if self.index.block_hash(number) != Some(hash) {
+                stale.push(hash.clone());
+                continue;
+            }
+            if self.state.undo.contains_key(hash) {
+                undo.push(hash.clone());
+            }
+            if number < horizon && !(block.body.transactions.is_empty() && block.body.calls.is_empty()) {
+                bodies.push(hash.clone());
+            }
+        }
+
+        for hash in stale.iter().chain(&undo) {
+            self.state.undo.remove(hash);
+            batch.delete(Column::Undo, hash);
+        }
+        for hash in &stale {
+            self.state.blocks.remove(hash);
+            batch.delete(Column::Blocks, hash);
+        }
+        for hash in &bodies {
+            let block = self.state.blocks.get_mut(hash).ok_or(CoreError::BlockNotFound)?;
+            self.index.forget_transactions(block);
+            for transaction in block.body.transactions.drain(..) {
+                self.state.transactions.remove(&transaction.hash);
+                batch.delete(Column::Transactions, &transaction.hash);
+            }
+            block.body.calls.clear();
+            batch.put(Column::Blocks, hash, block)?;
+        }
+
+        let roots: Vec<[u8; 32]> = self.state.blocks.values()
+            .filter(|block| block.header.number >= finalized_number)
+            .filter_map(|block| crate::utils::crypto::hash_from_hex(&block.header.state_root))
+            .collect();
+        self.state_trie.retain_roots(&roots);
+        Ok(())
+    }
+
+    /// Create genesis block.
+    ///
+    /// The header announces the genesis authorities, so the genesis hash
+    /// commits to the validator set as well as to the balances.
+    fn create_genesis_block(genesis: &GenesisConfig, state_root: Hash) -> Block {
         let header = BlockHeader {
             number: 0,
             parent_hash: "0x0000000000000000000000000000000000000000000000000000000000000000".to_string(),
-            state_root: "0x0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+            state_root,
             transactions_root: "0x0000000000000000000000000000000000000000000000000000000000000000".to_string(),
-            timestamp: chrono::Utc::now().timestamp() as u64,
+            // Fixed so that every node derives the same genesis hash from the same spec
+            timestamp: 0,
             author: "genesis".to_string(),
             difficulty: 0,
             nonce: 0,
+            authority_change: Some(AuthoritySet::from_genesis(genesis)),
         };
 
         let body = BlockBody {
             transactions: Vec::new(),
             justifications: Vec::new(),
+            calls: Vec::new(),
         };
 
         Block { header, body }
     }
 
-    /// Add transaction to pending pool
+    /// Add transaction to pending pool.
+    ///
+    /// A transaction carrying the sender's next nonce becomes ready for
+    /// inclusion; one whose nonce is further ahead waits in the future queue
+    /// until the gap is filled.
     pub fn add_transaction(&mut self, transaction: Transaction) -> CoreResult<()> {
         // Validate transaction
         self.validate_transaction(&transaction)?;
-        
-        // Add to pending pool
-        self.state.pending_transactions.push(transaction);
-        
-        Ok(())
+
+        let account = self.state.accounts.get(&transaction.from).cloned().unwrap_or_default();
+        self.pool.submit(transaction, &account)
+    }
+
+    /// Next nonce the sender should use, counting transactions already in the pending pool
+    pub fn next_nonce(&self, account_id: &AccountId) -> u64 {
+        let account_nonce = self.state.accounts.get(account_id).map(|a| a.nonce).unwrap_or(0);
+        self.pool.next_nonce(account_id, account_nonce)
+    }
+
+    /// Future-queued transactions of an account, in nonce order
+    pub fn get_future_transactions(&self, account_id: &AccountId) -> Vec<&Transaction> {
+        self.pool.future_transactions(account_id)
+    }
+
+    /// Transaction pool statistics
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
     }
 
     /// Validate transaction
     fn validate_transaction(&self, transaction: &Transaction) -> CoreResult<()> {
+        // Only the holder of the sender's key may spend from it
+        transaction.verify_signature()?;
+
         // Check sender exists
         if !self.state.accounts.contains_key(&transaction.from) {
             return Err(CoreError::InvalidAccount);
@@ -251,48 +782,606 @@ impl Blockchain {
         Ok(())
     }
 
+    /// Check that a transaction can be applied on top of the current state
+    fn check_inclusion(&self, transaction: &Transaction) -> CoreResult<()> {
+        let account = self.state.accounts.get(&transaction.from).ok_or(CoreError::InvalidAccount)?;
+        if transaction.nonce != account.nonce {
+            return Err(CoreError::InvalidNonce { expected: account.nonce, got: transaction.nonce });
+        }
+        let total = transaction.amount.checked_add(transaction.fee).ok_or(CoreError::InvalidAmount)?;
+        if account.balance < total {
+            return Err(CoreError::InsufficientBalance);
+        }
+        Ok(())
+    }
+
     /// Mine new block
     pub fn mine_block(&mut self, author: AccountId) -> CoreResult<Block> {
-        let pending_transactions = self.state.pending_transactions.clone();
-        self.state.pending_transactions.clear();
+        self.mine_block_at(author, crate::utils::time::current_timestamp())
+    }
+
+    /// Mine new block with the given timestamp.
+    ///
+    /// Under Proof-of-Authority the timestamp's slot must belong to `author`,
+    /// and the block is sealed with the node's authority key.
+    pub fn mine_block_at(&mut self, author: AccountId, timestamp: Timestamp) -> CoreResult<Block> {
+        self.mine_block_with(author, timestamp, |_, _| Ok(()))
+    }
+
+    /// Mine new block with the given timestamp, writing what `stage` queues for it in the same batch as the block
+    pub fn mine_block_with(
+        &mut self,
+        author: AccountId,
+        timestamp: Timestamp,
+        stage: impl FnOnce(&Block, &mut WriteBatch) -> CoreResult<()>,
+    ) -> CoreResult<Block> {
+        let parent_header = self.state.best_block.header.clone();
+        let seal_key = match &self.authorship {
+            Some(authorship) => {
+                authorship.check_slot(&author, &parent_header, timestamp)?;
+                let key = self.authority_key.clone()
+                    .filter(|key| crate::utils::crypto::public_key_from_private(key).as_ref() == Some(&author))
+                    .ok_or_else(|| CoreError::ValidationFailed(format!("No authority key for {}", author)))?;
+                Some(key)
+            }
+            None => None,
+        };
+
+        let authority_change = self.scheduled_authorities.take().map(|authorities| AuthoritySet {
+            set_id: self.latest_set_id(&parent_header.hash()) + 1,
+            authorities,
+        });
+        let candidates = self.pool.select_for_block();
+
+        // Execute first so the header commits to the post-state. Transactions
+        // that no longer apply (out of nonce order or underfunded) are left out.
+        let mut undo = BlockUndo::default();
+        let mut pending_transactions = Vec::new();
+        for transaction in candidates {
+            if self.apply_transaction(&transaction, &mut undo).is_ok() {
+                pending_transactions.push(transaction);
+            }
+        }
+
+        // Drop included transactions and re-check the rest against the new balances
+        self.pool.revalidate(&self.state.accounts);
+
+        let body = BlockBody {
+            transactions: pending_transactions,
+            justifications: Vec::new(),
+            calls: std::mem::take(&mut self.pending_calls),
+        };
 
         let header = BlockHeader {
             number: self.state.best_block.header.number + 1,
-            parent_hash: self.calculate_block_hash(&self.state.best_block),
-            state_root: self.calculate_state_root(),
-            transactions_root: self.calculate_transactions_root(&pending_transactions),
-            timestamp: chrono::Utc::now().timestamp() as u64,
+            parent_hash: self.state.best_block.hash(),
+            state_root: self.state_trie.root_hash(),
+            transactions_root: Self::calculate_transactions_root(&body),
+            timestamp,
             author,
             difficulty: 1,
             nonce: 0,
+            authority_change,
         };
 
-        let body = BlockBody {
-            transactions: pending_transactions,
-            justifications: Vec::new(),
-        };
+        let mut block = Block { header, body };
+        if let Some(key) = seal_key {
+            block.body.justifications.push(seal_header(&block.header, &key)?);
+        }
+        let mut batch = WriteBatch::new();
+        stage(&block, &mut batch)?;
+        if let Some(authorship) = &mut self.authorship {
+            self.offences.extend(authorship.note_block(&block.header, &parent_header));
+        }
 
-        let block = Block { header, body };
-        
         // Add block to chain
-        let block_hash = self.calculate_block_hash(&block);
-        self.state.blocks.insert(block_hash, block.clone());
+        let block_hash = block.hash();
+        self.state.blocks.insert(block_hash.clone(), block.clone());
+        self.state.undo.insert(block_hash.clone(), undo);
         self.state.best_block = block.clone();
 
-        // Process transactions
-        self.process_block_transactions(&block)?;
+        // Persist block and updated state
+        let hashes = std::slice::from_ref(&block_hash);
+        self.persist_changes_with(batch, hashes, &[], hashes)?;
 
         Ok(block)
     }
 
-    /// Process transactions in a block
-    fn process_block_transactions(&mut self, block: &Block) -> CoreResult<()> {
+    /// Import a block produced elsewhere.
+    ///
+    /// The block is checked against its parent and executed on the parent's
+    /// state. It is kept even if it loses fork choice, so that a later block on
+    /// the same fork can still trigger a reorg. Blocks that do not descend from
+    /// the finalized block are rejected.
+    pub fn import_block(&mut self, block: Block) -> CoreResult<ImportResult> {
+        let hash = block.hash();
+        if self.state.blocks.contains_key(&hash) {
+            // The same block may come back carrying a justification
+            if let Some(justification) = finality_justification(&block) {
+                if !self.is_finalized(&hash) {
+                    self.import_justification(&hash, justification.clone())?;
+                }
+            }
+            return Ok(ImportResult::AlreadyKnown);
+        }
+        self.check_block(&block)?;
+
+        // Execute on top of the parent, switching forks if needed
+        let previous_best = self.state.best_block.hash();
+        self.set_head(&block.header.parent_hash)?;
+        if let Err(error) = self.execute_block(&block) {
+            self.set_head(&previous_best)?;
+            return Err(error);
+        }
+        self.state.blocks.insert(hash.clone(), block.clone());
+        if let Some(authorship) = &mut self.authorship {
+            let parent = &self.state.blocks[&block.header.parent_hash].header;
+            self.offences.extend(authorship.note_block(&block.header, parent));
+        }
+
+        let previous_header = self.state.blocks[&previous_best].header.clone();
+        let result = if self.fork_choice.prefer(&block.header, &previous_header, &self.state.blocks) {
+            let (retracted, enacted) = self.tree_route(&previous_best, &hash)?;
+            self.persist_changes(std::slice::from_ref(&hash), &retracted, &enacted)?;
+            self.requeue_retracted(&retracted);
+            ImportResult::NewBest { retracted, enacted }
+        } else {
+            self.set_head(&previous_best)?;
+            self.persist_changes(std::slice::from_ref(&hash), &[], &[])?;
+            ImportResult::SideChain
+        };
+
+        // Justification was verified in `check_block`
+        if let Some(justification) = finality_justification(&block) {
+            self.finality.note_justification(&hash, block.header.number, justification);
+            self.finalize_block(&hash, justification.clone())?;
+        }
+        Ok(result)
+    }
+
+    /// Return transactions of abandoned blocks to the pool if they are still valid
+    fn requeue_retracted(&mut self, retracted: &[Hash]) {
+        for retracted_hash in retracted {
+            for transaction in self.state.blocks[retracted_hash].body.transactions.clone() {
+                if !self.state.transactions.contains_key(&transaction.hash) {
+                    let account = self.state.accounts.get(&transaction.from).cloned().unwrap_or_default();
+                    let _ = self.pool.submit(transaction, &account);
+                }
+            }
+        }
+        self.pool.revalidate(&self.state.accounts);
+    }
+
+    /// Record a validator's finality vote for a block.
+    ///
+    /// Returns `true` once the block is final. A vote for an already final
+    /// block is accepted without effect.
+    pub fn submit_finality_vote(&mut self, block_hash: &Hash, vote: ValidatorSignature) -> CoreResult<bool> {
+        if self.is_finalized(block_hash) {
+            return Ok(true);
+        }
+        let number = self.finalizable_number(block_hash)?;
+
+        match self.finality.add_vote(block_hash, number, vote)? {
+            Some(justification) => {
+                self.finalize_block(block_hash, justification)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Finalize a block with a justification received from another node
+    pub fn import_justification(&mut self, block_hash: &Hash, justification: Justification) -> CoreResult<()> {
+        let number = self.finalizable_number(block_hash)?;
+        self.finality.verify_justification(&justification, block_hash, number)?;
+        self.finality.note_justification(block_hash, number, &justification);
+        self.finalize_block(block_hash, justification)
+    }
+
+    /// Report PoA authorities that missed more than `max_missed_slots` since the last call.
+    ///
+    /// Missed-slot counters start over afterwards, so calling this once per
+    /// era bounds the downtime allowed per era.
+    pub fn note_unresponsive(&mut self, max_missed_slots: u64) {
+        if let Some(authorship) = &mut self.authorship {
+            self.offences.extend(authorship.take_unresponsive(max_missed_slots));
+        }
+    }
+
+    /// Offences detected since the last call: equivocating seals and votes, and downtime
+    pub fn take_offences(&mut self) -> Vec<Offence> {
+        let mut offences = std::mem::take(&mut self.offences);
+        offences.extend(self.finality.take_offences());
+        offences
+    }
+
+    /// Move the best block back to the finalized block.
+    ///
+    /// The reverted blocks stay known and can still be finalized later; their
+    /// transactions go back to the pool.
+    pub fn revert_to_finalized(&mut self) -> CoreResult<()> {
+        let previous_best = self.state.best_block.hash();
+        let finalized = self.state.finalized_block.hash();
+        if previous_best == finalized {
+            return Ok(());
+        }
+        let (retracted, enacted) = self.tree_route(&previous_best, &finalized)?;
+        self.set_head(&finalized)?;
+        self.persist_changes(&[], &retracted, &enacted)?;
+        self.requeue_retracted(&retracted);
+        Ok(())
+    }
+
+    /// Whether a block is on the best chain at or below the finalized block
+    pub fn is_finalized(&self, block_hash: &Hash) -> bool {
+        let finalized = &self.state.finalized_block.header;
+        match self.state.blocks.get(block_hash) {
+            Some(block) if block.header.number <= finalized.number => {
+                self.ancestor_at(&self.state.finalized_block.hash(), block.header.number).as_ref() == Some(block_hash)
+            }
+            _ => false,
+        }
+    }
+
+    /// Height of a block that may still become final, i.e. a descendant of the finalized block.
+    ///
+    /// A block announcing an authority set change must be final before any
+    /// of its descendants, which are signed by the new set.
+    fn finalizable_number(&self, block_hash: &Hash) -> CoreResult<BlockNumber> {
+        let block = self.state.blocks.get(block_hash).ok_or(CoreError::BlockNotFound)?;
+        let finalized = &self.state.finalized_block;
+        if block.header.number <= finalized.header.number
+            || self.ancestor_at(block_hash, finalized.header.number) != Some(finalized.hash())
+        {
+            return Err(CoreError::ValidationFailed("Block does not descend from the finalized block".to_string()));
+        }
+        if let Some((number, _)) = self.pending_authority_change(&block.header.parent_hash) {
+            return Err(CoreError::ValidationFailed(format!("Authority set change in block {} must be finalized first", number)));
+        }
+        Ok(block.header.number)
+    }
+
+    /// Announce a new finality authority set in the next mined block.
+    ///
+    /// The set takes over once that block is final.
+    pub fn schedule_authority_change(&mut self, authorities: Vec<Authority>) {
+        self.scheduled_authorities = Some(authorities);
+    }
+
+    /// Block the runtime's service state was last saved at, if the runtime saves it
+    pub fn state_checkpoint(&self) -> Option<BlockNumber> {
+        self.state_checkpoint
+    }
+
+    /// Note that the runtime saved its service state at block `number`.
+    ///
+    /// The runtime rebuilds its state from there on restart, so pruning keeps
+    /// the bodies of the blocks after it.
+    pub fn set_state_checkpoint(&mut self, number: BlockNumber) {
+        self.state_checkpoint = Some(number);
+    }
+
+    /// Record a runtime call in the next mined block
+    pub fn record_call(&mut self, extrinsic: Extrinsic) {
+        self.pending_calls.push(extrinsic);
+    }
+
+    /// Remove and return the calls recorded for the next mined block
+    pub fn take_pending_calls(&mut self) -> Vec<Extrinsic> {
+        std::mem::take(&mut self.pending_calls)
+    }
+
+    /// Newest authority set change announced after the finalized block, up to and including `hash`
+    fn pending_authority_change(&self, hash: &Hash) -> Option<(BlockNumber, &AuthoritySet)> {
+        let finalized_number = self.state.finalized_block.header.number;
+        let mut header = &self.state.blocks.get(hash)?.header;
+        while header.number > finalized_number {
+            if let Some(set) = &header.authority_change {
+                return Some((header.number, set));
+            }
+            header = &self.state.blocks.get(&header.parent_hash)?.header;
+        }
+        None
+    }
+
+    /// Identifier of the authority set in force after block `hash`, counting unfinalized announcements
+    fn latest_set_id(&self, hash: &Hash) -> u64 {
+        self.pending_authority_change(hash)
+            .map(|(_, set)| set.set_id)
+            .unwrap_or(self.finality.authorities.set_id)
+    }
+
+    /// Make a block final, switching the best chain to it if needed
+    fn finalize_block(&mut self, block_hash: &Hash, justification: Justification) -> CoreResult<()> {
+        let number = self.finalizable_number(block_hash)?;
+        let previous_best = self.state.best_block.hash();
+        if self.canonical_hash(number).as_ref() != Some(block_hash) {
+            self.set_head(block_hash)?;
+        }
+        let (retracted, enacted) = self.tree_route(&previous_best, &self.state.best_block.hash())?;
+
+        let block = self.state.blocks.get_mut(block_hash).ok_or(CoreError::BlockNotFound)?;
+        block.body.justifications.retain(|j| j.justification_type != JustificationType::Finality);
+        block.body.justifications.push(justification);
+        let block = block.clone();
+        if &self.state.best_block.hash() == block_hash {
+            self.state.best_block = block.clone();
+        }
+        self.finality.prune(number);
+        if let Some(set) = &block.header.authority_change {
+            self.finality.change_authorities(set.authorities.clone());
+        }
+        if let Some(authorship) = &mut self.authorship {
+            authorship.prune(block.header.timestamp);
+        }
+        self.state.finalized_block = block;
+
+        self.persist_changes(std::slice::from_ref(block_hash), &retracted, &enacted)?;
+        self.requeue_retracted(&retracted);
+        Ok(())
+    }
+
+    /// Replace the fork choice rule
+    pub fn set_fork_choice(&mut self, rule: Box<dyn ForkChoiceRule>) {
+        self.fork_choice = rule;
+    }
+
+    /// Checks of a block that do not need its parent state
+    fn check_block(&self, block: &Block) -> CoreResult<()> {
+        let header = &block.header;
+        let parent = self.state.blocks.get(&header.parent_hash)
+            .ok_or_else(|| CoreError::ValidationFailed(format!("Unknown parent block {}", header.parent_hash)))?;
+        if header.number != parent.header.number + 1 {
+            return Err(CoreError::ValidationFailed("Block number does not follow its parent".to_string()));
+        }
+        if header.timestamp < parent.header.timestamp {
+            return Err(CoreError::ValidationFailed("Block timestamp is before its parent".to_string()));
+        }
+        if let Some(set) = &header.authority_change {
+            if set.set_id != self.latest_set_id(&header.parent_hash) + 1 || set.total_weight() == 0 {
+                return Err(CoreError::ValidationFailed("Invalid authority set change".to_string()));
+            }
+        }
+        if let Some(authorship) = &self.authorship {
+            let now = crate::utils::time::current_timestamp();
+            authorship.verify_header(header, &parent.header, &block.body.justifications, now)?;
+        }
+
+        let finalized_number = self.state.finalized_block.header.number;
+        let finalized_hash = self.state.finalized_block.hash();
+        if self.ancestor_at(&header.parent_hash, finalized_number) != Some(finalized_hash) {
+            return Err(CoreError::ValidationFailed("Block does not descend from the finalized block".to_string()));
+        }
+
+        if Self::calculate_transactions_root(&block.body) != header.transactions_root {
+            return Err(CoreError::ValidationFailed("Transactions root mismatch".to_string()));
+        }
+        for transaction in &block.body.transactions {
+            transaction.verify_signature()?;
+        }
+        if let Some(justification) = finality_justification(block) {
+            self.finality.verify_justification(justification, &block.hash(), header.number)?;
+        }
+        Ok(())
+    }
+
+    /// Execute a block on top of the best block and make it the best block.
+    ///
+    /// On failure the state is left as it was.
+    fn execute_block(&mut self, block: &Block) -> CoreResult<()> {
+        let parent_root = self.state.best_block.header.state_root.clone();
+        let mut undo = BlockUndo::default();
+
+        let mut result = Ok(());
+        for transaction in &block.body.transactions {
+            result = self.apply_transaction(transaction, &mut undo);
+            if result.is_err() {
+                break;
+            }
+        }
+        if result.is_ok() && self.state_trie.root_hash() != block.header.state_root {
+            result = Err(CoreError::ValidationFailed("State root mismatch".to_string()));
+        }
+
+        if let Err(error) = result {
+            self.rollback(block, &undo, &parent_root)?;
+            return Err(error);
+        }
+
+        self.state.undo.insert(block.hash(), undo);
+        self.state.best_block = block.clone();
+        Ok(())
+    }
+
+    /// Execute the transactions of a child of the best block without importing it.
+    ///
+    /// Returns the state root they produce and the resulting state of every
+    /// account they touch, `None` for accounts that do not exist. The chain is
+    /// left as it was.
+    pub fn dry_run_block(&mut self, block: &Block) -> CoreResult<(Hash, BTreeMap<AccountId, Option<AccountState>>)> {
+        if block.header.parent_hash != self.state.best_block.hash() {
+            return Err(CoreError::ValidationFailed("Block does not extend the best block".to_string()));
+        }
+        let parent_root = self.state.best_block.header.state_root.clone();
+        let mut undo = BlockUndo::default();
+
+        let mut result = Ok(());
+        for transaction in &block.body.transactions {
+            result = self.apply_transaction(transaction, &mut undo);
+            if result.is_err() {
+                break;
+            }
+        }
+        let root = self.state_trie.root_hash();
+        let touched = undo.accounts.keys()
+            .map(|account_id| (account_id.clone(), self.state.accounts.get(account_id).cloned()))
+            .collect();
+
+        self.rollback(block, &undo, &parent_root)?;
+        result.map(|()| (root, touched))
+    }
+
+    /// Move the best block to `target`, reverting and re-executing blocks as needed
+    fn set_head(&mut self, target: &Hash) -> CoreResult<()> {
+        let (retracted, enacted) = self.tree_route(&self.state.best_block.hash(), target)?;
+
+        // The common ancestor must not be older than the finalized block
+        let ancestor_number = match retracted.last() {
+            Some(oldest) => self.state.blocks[oldest].header.number - 1,
+            None => self.state.best_block.header.number,
+        };
+        if ancestor_number < self.state.finalized_block.header.number {
+            return Err(CoreError::ValidationFailed("Reorg would revert the finalized block".to_string()));
+        }
+
+        for _ in &retracted {
+            self.revert_best_block()?;
+        }
+        for hash in &enacted {
+            let block = self.state.blocks.get(hash).cloned().ok_or(CoreError::BlockNotFound)?;
+            self.execute_block(&block)?;
+        }
+        Ok(())
+    }
+
+    /// Undo the best block, making its parent the best block
+    fn revert_best_block(&mut self) -> CoreResult<()> {
+        let block = self.state.best_block.clone();
+        let hash = block.hash();
+        let parent = self.state.blocks.get(&block.header.parent_hash).cloned().ok_or(CoreError::BlockNotFound)?;
+        let undo = self.state.undo.remove(&hash)
+            .ok_or_else(|| CoreError::StorageError(format!("Missing undo record for block {}", hash)))?;
+
+        self.rollback(&block, &undo, &parent.header.state_root)?;
+        self.state.best_block = parent;
+        Ok(())
+    }
+
+    /// Restore the accounts and transactions a block changed
+    fn rollback(&mut self, block: &Block, undo: &BlockUndo, state_root: &Hash) -> CoreResult<()> {
+        for (account_id, previous) in &undo.accounts {
+            match previous {
+                Some(account) => self.state.accounts.insert(account_id.clone(), account.clone()),
+                None => self.state.accounts.remove(account_id),
+            };
+        }
         for transaction in &block.body.transactions {
-            self.process_transaction(transaction)?;
+            self.state.transactions.remove(&transaction.hash);
+        }
+
+        let root = crate::utils::crypto::hash_from_hex(state_root)
+            .ok_or_else(|| CoreError::ValidationFailed("Malformed state root".to_string()))?;
+        if !self.state_trie.reset_to(root, &self.state.accounts) {
+            return Err(CoreError::StorageError("Reverted accounts do not match the parent state root".to_string()));
         }
         Ok(())
     }
 
+    /// Blocks to retract (newest first) and enact (oldest first) to move from `from` to `to`
+    fn tree_route(&self, from: &Hash, to: &Hash) -> CoreResult<(Vec<Hash>, Vec<Hash>)> {
+        let mut retracted = Vec::new();
+        let mut enacted = Vec::new();
+        let mut from = from.clone();
+        let mut to = to.clone();
+
+        while from != to {
+            let from_header = &self.state.blocks.get(&from).ok_or(CoreError::BlockNotFound)?.header;
+            let to_header = &self.state.blocks.get(&to).ok_or(CoreError::BlockNotFound)?.header;
+            let (from_number, to_number) = (from_header.number, to_header.number);
+            let (from_parent, to_parent) = (from_header.parent_hash.clone(), to_header.parent_hash.clone());
+
+            if from_number >= to_number {
+                retracted.push(std::mem::replace(&mut from, from_parent));
+            }
+            if to_number >= from_number {
+                enacted.push(std::mem::replace(&mut to, to_parent));
+            }
+        }
+
+        enacted.reverse();
+        Ok((retracted, enacted))
+    }
+
+    /// Hash of the ancestor of `hash` (or `hash` itself) at height `number`
+    pub fn ancestor_at(&self, hash: &Hash, number: BlockNumber) -> Option<Hash> {
+        let mut current = hash.clone();
+        loop {
+            let header = &self.state.blocks.get(&current)?.header;
+            if header.number == number {
+                return Some(current);
+            }
+            if header.number < number {
+                return None;
+            }
+            current = header.parent_hash.clone();
+        }
+    }
+
+    /// Best-chain block at height `number`
+    pub fn get_block_by_number(&self, number: BlockNumber) -> Option<&Block> {
+        self.index.block_hash(number).and_then(|hash| self.state.blocks.get(hash))
+    }
+
+    /// Best-chain transactions sent by an account, newest first
+    pub fn get_transactions_by_sender(
+        &self,
+        account_id: &AccountId,
+        filter: &TransactionFilter,
+        page: &PageRequest,
+    ) -> Page<&Transaction> {
+        self.query_transactions(self.index.sent_by(account_id), filter, page)
+    }
+
+    /// Best-chain transactions received by an account, newest first
+    pub fn get_transactions_by_receiver(
+        &self,
+        account_id: &AccountId,
+        filter: &TransactionFilter,
+        page: &PageRequest,
+    ) -> Page<&Transaction> {
+        self.query_transactions(self.index.received_by(account_id), filter, page)
+    }
+
+    /// Best-chain transactions of a type, newest first
+    pub fn get_transactions_by_type(
+        &self,
+        transaction_type: &TransactionType,
+        filter: &TransactionFilter,
+        page: &PageRequest,
+    ) -> Page<&Transaction> {
+        self.query_transactions(self.index.of_type(transaction_type), filter, page)
+    }
+
+    fn query_transactions<'a>(
+        &'a self,
+        locations: impl Iterator<Item = &'a TransactionLocation>,
+        filter: &TransactionFilter,
+        page: &PageRequest,
+    ) -> Page<&'a Transaction> {
+        let matches = locations
+            .filter(|location| filter.in_range(location))
+            .filter_map(|location| self.state.transactions.get(&location.hash))
+            .filter(|transaction| filter.transaction_type.as_ref().is_none_or(|t| &transaction.transaction_type == t));
+        Page::paginate(matches, page)
+    }
+
+    /// Hash of the best-chain block at height `number`
+    pub fn canonical_hash(&self, number: BlockNumber) -> Option<Hash> {
+        self.ancestor_at(&self.state.best_block.hash(), number)
+    }
+
+    /// Apply a transaction on top of the current state, recording what it overwrites in `undo`
+    fn apply_transaction(&mut self, transaction: &Transaction, undo: &mut BlockUndo) -> CoreResult<()> {
+        self.check_inclusion(transaction)?;
+        for account_id in [&transaction.from, &transaction.to] {
+            if !undo.accounts.contains_key(account_id) {
+                undo.accounts.insert(account_id.clone(), self.state.accounts.get(account_id).cloned());
+            }
+        }
+        self.process_transaction(transaction)
+    }
+
     /// Process individual transaction
     fn process_transaction(&mut self, transaction: &Transaction) -> CoreResult<()> {
         // Update sender balance
@@ -311,40 +1400,75 @@ impl Blockchain {
                 nonce: 0,
                 staked: 0,
                 account_type: AccountType::User,
-                energy_balance: 0.0,
+                energy_balance: Energy::ZERO,
                 last_activity: transaction.timestamp,
             };
             self.state.accounts.insert(transaction.to.clone(), account);
         }
 
+        // Move the state root forward
+        for account_id in [&transaction.from, &transaction.to] {
+            if let Some(account) = self.state.accounts.get(account_id) {
+                self.state_trie.update(account_id, account);
+            }
+        }
+
         // Store transaction
         self.state.transactions.insert(transaction.hash.clone(), transaction.clone());
 
         Ok(())
     }
 
-    /// Calculate block hash
-    fn calculate_block_hash(&self, block: &Block) -> Hash {
-        use std::hash::{Hash, Hasher};
-        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-        block.header.hash(&mut hasher);
-        format!("0x{:x}", hasher.finish())
+    /// Calculate transactions root
+    fn calculate_transactions_root(body: &BlockBody) -> Hash {
+        crate::utils::crypto::hash_to_hex(&merkle_root(&body.leaves()))
     }
 
-    /// Calculate state root
-    fn calculate_state_root(&self) -> Hash {
-        use std::hash::{Hash, Hasher};
-        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-        format!("{:?}", self.state.accounts).hash(&mut hasher);
-        format!("0x{:x}", hasher.finish())
+    /// Build a proof that a best-chain transaction is included in its block
+    pub fn get_transaction_proof(&self, hash: &Hash) -> CoreResult<TransactionProof> {
+        let location = self.index.location(hash).ok_or(CoreError::TransactionNotFound)?;
+        let block = self.get_block_by_number(location.block_number).ok_or(CoreError::BlockNotFound)?;
+
+        let proof = merkle_proof(&block.body.leaves(), location.index as usize)
+            .ok_or_else(|| CoreError::SystemError("Failed to build Merkle proof".to_string()))?;
+
+        Ok(TransactionProof {
+            transaction_hash: hash.clone(),
+            block_hash: block.hash(),
+            block_number: block.header.number,
+            transactions_root: block.header.transactions_root.clone(),
+            proof,
+        })
     }
 
-    /// Calculate transactions root
-    fn calculate_transactions_root(&self, transactions: &[Transaction]) -> Hash {
-        use std::hash::{Hash, Hasher};
-        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-        format!("{:?}", transactions).hash(&mut hasher);
-        format!("0x{:x}", hasher.finish())
+    /// Verify that `transaction` is included under `transactions_root` (taken from a trusted header)
+    pub fn verify_transaction_proof(
+        transaction: &Transaction,
+        proof: &TransactionProof,
+        transactions_root: &Hash,
+    ) -> bool {
+        if transaction.hash != proof.transaction_hash || &proof.transactions_root != transactions_root {
+            return false;
+        }
+        match crate::utils::crypto::hash_from_hex(transactions_root) {
+            Some(root) => verify_merkle_proof(&transaction.leaf_hash(), &proof.proof, &root),
+            None => false,
+        }
+    }
+
+    /// Prove an account's current state against the best block's state root
+    pub fn prove_account(&self, account_id: &AccountId) -> CoreResult<AccountProof> {
+        self.state_trie.prove(&self.state_trie.root, account_id)
+            .ok_or_else(|| CoreError::SystemError("State trie is missing the current root".to_string()))
+    }
+
+    /// Prove an account's state as of the given block
+    pub fn prove_account_at(&self, account_id: &AccountId, block_hash: &Hash) -> CoreResult<AccountProof> {
+        let block = self.state.blocks.get(block_hash).ok_or(CoreError::BlockNotFound)?;
+        let root = crate::utils::crypto::hash_from_hex(&block.header.state_root)
+            .ok_or_else(|| CoreError::ValidationFailed("Malformed state root".to_string()))?;
+        self.state_trie.prove(&root, account_id)
+            .ok_or_else(|| CoreError::ValidationFailed("State for this block is not available".to_string()))
     }
 
     /// Get account state
@@ -375,6 +1499,11 @@ impl Blockchain {
     }
 }
 
+/// Finality justification carried in a block body, if any
+pub fn finality_justification(block: &Block) -> Option<&Justification> {
+    block.body.justifications.iter().find(|j| j.justification_type == JustificationType::Finality)
+}
+
 impl Default for AccountState {
     fn default() -> Self {
         Self {
@@ -382,23 +1511,851 @@ impl Default for AccountState {
             nonce: 0,
             staked: 0,
             account_type: AccountType::User,
-            energy_balance: 0.0,
+            energy_balance: Energy::ZERO,
             last_activity: 0,
         }
     }
 }
 
-use std::hash::{Hash as StdHash, Hasher};
-
-impl StdHash for BlockHeader {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.number.hash(state);
-        self.parent_hash.hash(state);
-        self.state_root.hash(state);
-        self.transactions_root.hash(state);
-        self.timestamp.hash(state);
-        self.author.hash(state);
-        self.difficulty.hash(state);
-        self.nonce.hash(state);
+impl BlockHeader {
+    /// SHA-256 hash of the canonical header encoding
+    pub fn hash(&self) -> Hash {
+        hash_encoded(self)
+    }
+}
+
+impl Block {
+    /// Block hash (the hash of its header)
+    pub fn hash(&self) -> Hash {
+        self.header.hash()
+    }
+
+    /// Whether pruning dropped the transactions and calls the header commits to
+    pub fn is_pruned(&self) -> bool {
+        self.body.transactions.is_empty()
+            && self.body.calls.is_empty()
+            && self.header.transactions_root != crate::utils::crypto::hash_to_hex(&merkle_root(&[]))
+    }
+}
+
+impl Transaction {
+    /// Canonical payload covered by the sender's signature and the transaction hash
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.from.encode_to(&mut out);
+        self.to.encode_to(&mut out);
+        self.amount.encode_to(&mut out);
+        self.transaction_type.encode_to(&mut out);
+        self.data.encode_to(&mut out);
+        self.fee.encode_to(&mut out);
+        self.nonce.encode_to(&mut out);
+        self.timestamp.encode_to(&mut out);
+        out
+    }
+
+    /// Hash of the signing payload
+    pub fn compute_hash(&self) -> Hash {
+        crate::utils::crypto::sha256_hash(&self.signing_payload())
+    }
+
+    /// Set the hash and sign with the sender's hex-encoded private key
+    pub fn sign(&mut self, private_key: &str) -> CoreResult<()> {
+        self.hash = self.compute_hash();
+        self.signature = Some(crate::utils::crypto::sign_message(&self.signing_payload(), private_key)?);
+        Ok(())
+    }
+
+    /// Check the hash and that the signature was made by the key behind `from`
+    pub fn verify_signature(&self) -> CoreResult<()> {
+        let signature = self.signature.as_ref().ok_or(CoreError::InvalidSignature)?;
+        if self.hash != self.compute_hash() {
+            return Err(CoreError::ValidationFailed("Transaction hash does not match its contents".to_string()));
+        }
+        if !crate::utils::crypto::verify_signature(&self.signing_payload(), signature, &self.from) {
+            return Err(CoreError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    /// Merkle leaf hash of the transaction
+    pub fn leaf_hash(&self) -> [u8; 32] {
+        leaf_hash(&self.encode())
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.number.encode_to(out);
+        self.parent_hash.encode_to(out);
+        self.state_root.encode_to(out);
+        self.transactions_root.encode_to(out);
+        self.timestamp.encode_to(out);
+        self.author.encode_to(out);
+        self.difficulty.encode_to(out);
+        self.nonce.encode_to(out);
+        self.authority_change.encode_to(out);
+    }
+}
+
+impl Encode for TransactionType {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        let index: u8 = match self {
+            TransactionType::Transfer => 0,
+            TransactionType::EnergyTrade => 1,
+            TransactionType::Staking => 2,
+            TransactionType::GovernanceVote => 3,
+            TransactionType::ContractDeploy => 4,
+            TransactionType::ContractCall => 5,
+        };
+        index.encode_to(out);
+    }
+}
+
+/// Block number is left out: it is assigned on inclusion and not part of the signed content
+impl Encode for Transaction {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.hash.encode_to(out);
+        self.from.encode_to(out);
+        self.to.encode_to(out);
+        self.amount.encode_to(out);
+        self.transaction_type.encode_to(out);
+        self.data.encode_to(out);
+        self.fee.encode_to(out);
+        self.nonce.encode_to(out);
+        self.timestamp.encode_to(out);
+        self.signature.encode_to(out);
+    }
+}
+
+/// Tagged so that a call cannot be passed off as a transaction leaf
+impl Encode for Extrinsic {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        "extrinsic".encode_to(out);
+        self.sender.encode_to(out);
+        self.call.encode_to(out);
+    }
+}
+
+impl Encode for AccountType {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        let index: u8 = match self {
+            AccountType::User => 0,
+            AccountType::Producer => 1,
+            AccountType::Consumer => 2,
+            AccountType::Prosumer => 3,
+            AccountType::Validator => 4,
+            AccountType::Authority => 5,
+        };
+        index.encode_to(out);
+    }
+}
+
+impl Encode for AccountState {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.balance.encode_to(out);
+        self.nonce.encode_to(out);
+        self.staked.encode_to(out);
+        self.account_type.encode_to(out);
+        self.energy_balance.encode_to(out);
+        self.last_activity.encode_to(out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(name: &str) -> AccountId {
+        crate::utils::crypto::dev_account_id(name)
+    }
+
+    fn test_config(data_dir: &std::path::Path) -> SystemConfig {
+        let mut config = SystemConfig::default();
+        config.node_config.data_dir = data_dir.to_string_lossy().to_string();
+        config.chain_spec.genesis.accounts.insert(account("alice"), 1_000);
+        config
+    }
+
+    fn unsigned_transfer(from: &str, to: &str, amount: Balance, nonce: u64) -> Transaction {
+        Transaction {
+            hash: String::new(),
+            from: account(from),
+            to: account(to),
+            amount,
+            transaction_type: TransactionType::Transfer,
+            data: Vec::new(),
+            fee: 1,
+            nonce,
+            block_number: None,
+            timestamp: 0,
+            signature: None,
+        }
+    }
+
+    fn transfer(from: &str, to: &str, amount: Balance, nonce: u64) -> Transaction {
+        let mut tx = unsigned_transfer(from, to, amount, nonce);
+        tx.sign(&crate::utils::crypto::keypair_from_seed(from).0).unwrap();
+        tx
+    }
+
+    #[test]
+    fn test_blockchain_reloads_from_data_dir() {
+        let dir = std::env::temp_dir().join(format!("ledger-chain-{}", uuid::Uuid::new_v4()));
+        let config = test_config(&dir);
+
+        let (tx_hash, best_block) = {
+            let mut blockchain = Blockchain::open(config.clone()).unwrap();
+            let tx = transfer("alice", "bob", 100, 0);
+            let tx_hash = tx.hash.clone();
+            blockchain.add_transaction(tx).unwrap();
+            let block = blockchain.mine_block("validator".to_string()).unwrap();
+            (tx_hash, block)
+        };
+
+        let blockchain = Blockchain::open(config).unwrap();
+        assert_eq!(blockchain.get_block_height(), 1);
+        assert_eq!(blockchain.state.best_block, best_block);
+        assert_eq!(blockchain.state.finalized_block.header.number, 0);
+        assert_eq!(blockchain.get_balance(&account("alice")), 899);
+        assert_eq!(blockchain.get_balance(&account("bob")), 100);
+        assert!(blockchain.get_transaction(&tx_hash).is_some());
+        assert_eq!(blockchain.state.blocks.len(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_transaction_inclusion_proof() {
+        let dir = std::env::temp_dir();
+        let mut blockchain = Blockchain::new(test_config(&dir));
+        let transactions: Vec<Transaction> = (0..3u64).map(|i| transfer("alice", "bob", 10, i)).collect();
+        for tx in &transactions {
+            blockchain.add_transaction(tx.clone()).unwrap();
+        }
+        let block = blockchain.mine_block("validator".to_string()).unwrap();
+        assert_eq!(block.header.transactions_root.len(), 66);
+
+        for tx in &transactions {
+            let proof = blockchain.get_transaction_proof(&tx.hash).unwrap();
+            assert_eq!(proof.block_hash, block.hash());
+            assert!(Blockchain::verify_transaction_proof(tx, &proof, &block.header.transactions_root));
+
+            let mut forged = tx.clone();
+            forged.amount += 1;
+            assert!(!Blockchain::verify_transaction_proof(&forged, &proof, &block.header.transactions_root));
+        }
+
+        assert_eq!(
+            blockchain.get_transaction_proof(&"0xmissing".to_string()),
+            Err(CoreError::TransactionNotFound)
+        );
+    }
+
+    #[test]
+    fn test_account_proof_against_block_state_root() {
+        let mut blockchain = Blockchain::new(test_config(&std::env::temp_dir()));
+        let genesis = blockchain.state.best_block.clone();
+
+        blockchain.add_transaction(transfer("alice", "bob", 250, 0)).unwrap();
+        let block = blockchain.mine_block("validator".to_string()).unwrap();
+
+        let proof = blockchain.prove_account(&account("bob")).unwrap();
+        assert_eq!(proof.account.as_ref().map(|a| a.balance), Some(250));
+        assert!(proof.verify(&block.header.state_root));
+        assert!(!proof.verify(&genesis.header.state_root));
+
+        // Bob did not exist at genesis
+        let absent = blockchain.prove_account_at(&account("bob"), &genesis.hash()).unwrap();
+        assert_eq!(absent.account, None);
+        assert!(absent.verify(&genesis.header.state_root));
+    }
+
+    #[test]
+    fn test_rejects_unsigned_and_forged_transactions() {
+        let mut blockchain = Blockchain::new(test_config(&std::env::temp_dir()));
+
+        let unsigned = unsigned_transfer("alice", "bob", 10, 0);
+        assert_eq!(blockchain.add_transaction(unsigned), Err(CoreError::InvalidSignature));
+
+        // Bob signs a transfer out of Alice's account
+        let mut stolen = unsigned_transfer("alice", "bob", 10, 0);
+        stolen.sign(&crate::utils::crypto::keypair_from_seed("bob").0).unwrap();
+        assert_eq!(blockchain.add_transaction(stolen), Err(CoreError::InvalidSignature));
+
+        // Amount changed after signing
+        let mut tampered = transfer("alice", "bob", 10, 0);
+        tampered.amount = 900;
+        tampered.hash = tampered.compute_hash();
+        assert_eq!(blockchain.add_transaction(tampered), Err(CoreError::InvalidSignature));
+
+        assert!(blockchain.add_transaction(transfer("alice", "bob", 10, 0)).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_malformed_signatures_and_forged_blocks() {
+        let mut blockchain = Blockchain::new(test_config(&std::env::temp_dir()));
+
+        // Signatures that are not 64 hex-encoded bytes
+        let mut truncated = transfer("alice", "bob", 10, 0);
+        truncated.signature = truncated.signature.map(|signature| signature[..64].to_string());
+        assert_eq!(blockchain.add_transaction(truncated), Err(CoreError::InvalidSignature));
+        let mut not_hex = transfer("alice", "bob", 10, 0);
+        not_hex.signature = Some("zz".repeat(64));
+        assert_eq!(blockchain.add_transaction(not_hex), Err(CoreError::InvalidSignature));
+
+        // A sender that is not a public key cannot have signed anything
+        let mut keyless = unsigned_transfer("alice", "bob", 10, 0);
+        keyless.from = "treasury".to_string();
+        keyless.sign(&crate::utils::crypto::keypair_from_seed("alice").0).unwrap();
+        assert_eq!(blockchain.add_transaction(keyless), Err(CoreError::InvalidSignature));
+        assert!(unsigned_transfer("alice", "bob", 10, 0).sign("not-a-key").is_err());
+
+        // A block carrying a transaction altered after signing is not imported, even with a matching root
+        let mut producer = Blockchain::new(test_config(&std::env::temp_dir()));
+        producer.add_transaction(transfer("alice", "bob", 10, 0)).unwrap();
+        let mut forged = producer.mine_block("validator".to_string()).unwrap();
+        let transaction = &mut forged.body.transactions[0];
+        transaction.amount = 900;
+        transaction.hash = transaction.compute_hash();
+        forged.header.transactions_root = Blockchain::calculate_transactions_root(&forged.body);
+        assert_eq!(blockchain.import_block(forged), Err(CoreError::InvalidSignature));
+        assert_eq!(blockchain.get_block_height(), 0);
+    }
+
+    #[test]
+    fn test_nonce_replay_and_future_queue() {
+        let mut blockchain = Blockchain::new(test_config(&std::env::temp_dir()));
+        let alice = account("alice");
+
+        let first = transfer("alice", "bob", 10, 0);
+        blockchain.add_transaction(first.clone()).unwrap();
+        assert_eq!(
+            blockchain.add_transaction(first.clone()),
+            Err(CoreError::InvalidNonce { expected: 1, got: 0 })
+        );
+
+        // Nonces 2 and 3 wait until 1 arrives
+        blockchain.add_transaction(transfer("alice", "bob", 30, 3)).unwrap();
+        blockchain.add_transaction(transfer("alice", "bob", 20, 2)).unwrap();
+        assert_eq!(blockchain.pool.ready_transactions(&alice).len(), 1);
+        assert_eq!(blockchain.get_future_transactions(&alice).len(), 2);
+
+        blockchain.add_transaction(transfer("alice", "bob", 15, 1)).unwrap();
+        assert!(blockchain.get_future_transactions(&alice).is_empty());
+        let nonces: Vec<u64> = blockchain.pool.ready_transactions(&alice).iter().map(|tx| tx.nonce).collect();
+        assert_eq!(nonces, vec![0, 1, 2, 3]);
+        assert_eq!(blockchain.next_nonce(&alice), 4);
+
+        let block = blockchain.mine_block("validator".to_string()).unwrap();
+        assert_eq!(block.body.transactions.len(), 4);
+        assert_eq!(blockchain.get_account(&alice).unwrap().nonce, 4);
+
+        // The included transfer cannot be replayed
+        assert_eq!(
+            blockchain.add_transaction(first),
+            Err(CoreError::InvalidNonce { expected: 4, got: 0 })
+        );
+    }
+
+    #[test]
+    fn test_pool_keeps_transactions_the_sender_cannot_fund() {
+        let mut blockchain = Blockchain::new(test_config(&std::env::temp_dir()));
+        let alice = account("alice");
+
+        // Both are individually affordable, but together they overdraw Alice
+        blockchain.add_transaction(transfer("alice", "bob", 600, 0)).unwrap();
+        assert_eq!(
+            blockchain.add_transaction(transfer("alice", "bob", 600, 1)),
+            Err(CoreError::InsufficientBalance)
+        );
+        blockchain.add_transaction(transfer("alice", "bob", 100, 2)).unwrap();
+        assert_eq!(blockchain.get_future_transactions(&alice).len(), 1);
+
+        let block = blockchain.mine_block("validator".to_string()).unwrap();
+        let nonces: Vec<u64> = block.body.transactions.iter().map(|tx| tx.nonce).collect();
+        assert_eq!(nonces, vec![0]);
+        assert_eq!(blockchain.get_balance(&alice), 399);
+        assert_eq!(blockchain.next_nonce(&alice), 1);
+
+        let stats = blockchain.pool_stats();
+        assert_eq!((stats.ready, stats.future), (0, 1));
+    }
+
+    #[test]
+    fn test_block_respects_transaction_limit() {
+        let mut blockchain = Blockchain::new(test_config(&std::env::temp_dir()));
+        blockchain.pool.config.max_block_transactions = 2;
+        for nonce in 0..3 {
+            blockchain.add_transaction(transfer("alice", "bob", 10, nonce)).unwrap();
+        }
+
+        assert_eq!(blockchain.mine_block("validator".to_string()).unwrap().body.transactions.len(), 2);
+        assert_eq!(blockchain.pool_stats().ready, 1);
+        assert_eq!(blockchain.mine_block("validator".to_string()).unwrap().body.transactions.len(), 1);
+        assert!(blockchain.pool.is_empty());
+    }
+
+    #[test]
+    fn test_import_blocks_from_another_node() {
+        let mut producer = Blockchain::new(test_config(&std::env::temp_dir()));
+        let mut follower = Blockchain::new(test_config(&std::env::temp_dir()));
+        let genesis_hash = follower.state.best_block.hash();
+        assert_eq!(producer.state.best_block.hash(), genesis_hash);
+        assert!(follower.get_block(&genesis_hash).is_some());
+
+        producer.add_transaction(transfer("alice", "bob", 100, 0)).unwrap();
+        let first = producer.mine_block("producer".to_string()).unwrap();
+        producer.add_transaction(transfer("alice", "bob", 50, 1)).unwrap();
+        let second = producer.mine_block("producer".to_string()).unwrap();
+
+        assert!(matches!(follower.import_block(second.clone()), Err(CoreError::ValidationFailed(_))));
+        assert_eq!(
+            follower.import_block(first.clone()),
+            Ok(ImportResult::NewBest { retracted: vec![], enacted: vec![first.hash()] })
+        );
+        follower.import_block(second).unwrap();
+        assert_eq!(follower.import_block(first), Ok(ImportResult::AlreadyKnown));
+
+        assert_eq!(follower.state.best_block, producer.state.best_block);
+        assert_eq!(follower.get_balance(&account("bob")), 150);
+        assert_eq!(follower.next_nonce(&account("alice")), 2);
+    }
+
+    #[test]
+    fn test_import_rejects_invalid_blocks() {
+        let mut producer = Blockchain::new(test_config(&std::env::temp_dir()));
+        let mut follower = Blockchain::new(test_config(&std::env::temp_dir()));
+        producer.add_transaction(transfer("alice", "bob", 100, 0)).unwrap();
+        let block = producer.mine_block("producer".to_string()).unwrap();
+        let genesis = follower.state.best_block.clone();
+
+        let mut wrong_root = block.clone();
+        wrong_root.header.state_root = genesis.header.state_root.clone();
+        assert_eq!(
+            follower.import_block(wrong_root),
+            Err(CoreError::ValidationFailed("State root mismatch".to_string()))
+        );
+
+        let mut tampered = block.clone();
+        tampered.body.transactions[0].amount = 900;
+        assert!(follower.import_block(tampered).is_err());
+
+        let mut wrong_number = block.clone();
+        wrong_number.header.number = 5;
+        assert!(follower.import_block(wrong_number).is_err());
+
+        // Failed imports leave no trace
+        assert_eq!(follower.state.best_block, genesis);
+        assert_eq!(follower.get_balance(&account("alice")), 1_000);
+        assert_eq!(follower.prove_account(&account("bob")).unwrap().account, None);
+        assert!(follower.import_block(block).is_ok());
+    }
+
+    #[test]
+    fn test_reorg_to_longer_fork_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("ledger-chain-{}", uuid::Uuid::new_v4()));
+        let mut other = Blockchain::new(test_config(&dir));
+        other.add_transaction(transfer("alice", "charlie", 300, 0)).unwrap();
+        let b1 = other.mine_block("other".to_string()).unwrap();
+        let b2 = other.mine_block("other".to_string()).unwrap();
+
+        let a1 = {
+            let mut node = Blockchain::open(test_config(&dir)).unwrap();
+            node.add_transaction(transfer("alice", "bob", 100, 0)).unwrap();
+            node.mine_block("node".to_string()).unwrap()
+        };
+
+        // Undo records come back from disk, so the reorg also works after a restart
+        let mut node = Blockchain::open(test_config(&dir)).unwrap();
+        assert_eq!(node.import_block(b1.clone()), Ok(ImportResult::SideChain));
+        assert_eq!(node.state.best_block.hash(), a1.hash());
+        assert_eq!(node.get_balance(&account("bob")), 100);
+
+        assert_eq!(
+            node.import_block(b2.clone()),
+            Ok(ImportResult::NewBest { retracted: vec![a1.hash()], enacted: vec![b1.hash(), b2.hash()] })
+        );
+        assert_eq!(node.get_account(&account("bob")), None);
+        assert_eq!(node.get_balance(&account("charlie")), 300);
+        assert!(node.get_transaction(&a1.body.transactions[0].hash).is_none());
+        assert_eq!(node.get_transaction_proof(&a1.body.transactions[0].hash), Err(CoreError::TransactionNotFound));
+        let page = PageRequest::default();
+        assert_eq!(node.get_transactions_by_receiver(&account("bob"), &TransactionFilter::default(), &page).total, 0);
+        assert_eq!(node.prove_account(&account("alice")).unwrap().account, other.get_account(&account("alice")).cloned());
+        // Alice's retracted transfer reused nonce 0, so it does not return to the pool
+        assert!(node.pool.is_empty());
+        drop(node);
+
+        let node = Blockchain::open(test_config(&dir)).unwrap();
+        assert_eq!(node.state.best_block, b2);
+        assert_eq!(node.get_account(&account("bob")), None);
+        assert_eq!(node.canonical_hash(1), Some(b1.hash()));
+        assert_eq!(node.get_block_by_number(2), Some(&b2));
+        let received = node.get_transactions_by_receiver(&account("charlie"), &TransactionFilter::default(), &page);
+        assert_eq!(received.items, vec![&b1.body.transactions[0]]);
+        let proof = node.get_transaction_proof(&b1.body.transactions[0].hash).unwrap();
+        assert_eq!((proof.block_hash, proof.block_number), (b1.hash(), 1));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_history_queries_filter_and_paginate() {
+        let mut blockchain = Blockchain::new(test_config(&std::env::temp_dir()));
+        let mut trade = unsigned_transfer("alice", "meter", 5, 0);
+        trade.transaction_type = TransactionType::EnergyTrade;
+        trade.sign(&crate::utils::crypto::keypair_from_seed("alice").0).unwrap();
+        blockchain.add_transaction(trade.clone()).unwrap();
+        blockchain.mine_block_at("validator".to_string(), 1_000).unwrap();
+        for nonce in 1..4 {
+            blockchain.add_transaction(transfer("alice", "meter", 10, nonce)).unwrap();
+            blockchain.mine_block_at("validator".to_string(), 2_000 + nonce).unwrap();
+        }
+
+        let everything = TransactionFilter::default();
+        let first = blockchain.get_transactions_by_sender(&account("alice"), &everything, &PageRequest { offset: 0, limit: 3 });
+        assert_eq!(first.total, 4);
+        assert_eq!(first.items[0].nonce, 3);
+        assert_eq!(first.next_offset, Some(3));
+        let last = blockchain.get_transactions_by_sender(&account("alice"), &everything, &PageRequest { offset: 3, limit: 3 });
+        assert_eq!(last.items, vec![&trade]);
+        assert_eq!(last.next_offset, None);
+
+        let trades = TransactionFilter { transaction_type: Some(TransactionType::EnergyTrade), ..Default::default() };
+        let page = PageRequest::default();
+        assert_eq!(blockchain.get_transactions_by_receiver(&account("meter"), &trades, &page).items, vec![&trade]);
+        assert_eq!(blockchain.get_transactions_by_type(&TransactionType::Transfer, &everything, &page).total, 3);
+
+        let recent = TransactionFilter { since: Some(2_002), until: Some(2_004), ..Default::default() };
+        let recent = blockchain.get_transactions_by_receiver(&account("meter"), &recent, &page);
+        assert_eq!(recent.items.iter().map(|t| t.nonce).collect::<Vec<_>>(), vec![3, 2]);
+        assert_eq!(blockchain.get_block_by_number(1).unwrap().header.timestamp, 1_000);
+    }
+
+    #[test]
+    fn test_history_pages_stop_at_their_bounds() {
+        let mut blockchain = Blockchain::new(test_config(&std::env::temp_dir()));
+        for nonce in 0..4 {
+            blockchain.add_transaction(transfer("alice", "meter", 10, nonce)).unwrap();
+        }
+        blockchain.mine_block_at("validator".to_string(), 1_000).unwrap();
+        let everything = TransactionFilter::default();
+        let sent = |offset, limit| blockchain.get_transactions_by_sender(&account("alice"), &everything, &PageRequest { offset, limit });
+
+        // A page ending exactly at the last match is the last page
+        let page = sent(0, 4);
+        assert_eq!((page.items.len(), page.total, page.next_offset), (4, 4, None));
+        let page = sent(1, 3);
+        assert_eq!((page.items.len(), page.next_offset), (3, None));
+        let page = sent(2, 1);
+        assert_eq!((page.items[0].nonce, page.next_offset), (1, Some(3)));
+
+        // Offsets at or past the end return nothing but still count every match
+        for offset in [4, 5, usize::MAX] {
+            let page = sent(offset, 10);
+            assert!(page.items.is_empty());
+            assert_eq!((page.total, page.next_offset), (4, None));
+        }
+        let nobody = blockchain.get_transactions_by_sender(&account("bob"), &everything, &PageRequest::default());
+        assert_eq!(nobody, Page { items: Vec::new(), total: 0, next_offset: None });
+
+        // Limits are capped at the largest page
+        let page = Page::paginate(0..MAX_PAGE_SIZE * 2 + 1, &PageRequest { offset: 0, limit: usize::MAX });
+        assert_eq!(page.items.len(), MAX_PAGE_SIZE);
+        assert_eq!((page.total, page.next_offset), (MAX_PAGE_SIZE * 2 + 1, Some(MAX_PAGE_SIZE)));
+        let page = Page::paginate(0..MAX_PAGE_SIZE * 2 + 1, &PageRequest { offset: MAX_PAGE_SIZE * 2, limit: usize::MAX });
+        assert_eq!((page.items, page.next_offset), (vec![MAX_PAGE_SIZE * 2], None));
+    }
+
+    #[test]
+    fn test_reorg_never_reverts_finalized_block() {
+        let mut node = Blockchain::new(test_config(&std::env::temp_dir()));
+        let mut other = Blockchain::new(test_config(&std::env::temp_dir()));
+        node.add_transaction(transfer("alice", "bob", 100, 0)).unwrap();
+        let a1 = node.mine_block("node".to_string()).unwrap();
+        node.state.finalized_block = a1.clone();
+
+        let b1 = other.mine_block("other".to_string()).unwrap();
+        let b2 = other.mine_block("other".to_string()).unwrap();
+        assert!(node.import_block(b1).is_err());
+        assert!(node.import_block(b2).is_err());
+        assert_eq!(node.state.best_block, a1);
+        assert_eq!(node.get_balance(&account("bob")), 100);
+    }
+
+    fn validator_config() -> SystemConfig {
+        let mut config = test_config(&std::env::temp_dir());
+        for name in ["validator1", "validator2", "validator3"] {
+            config.chain_spec.genesis.accounts.insert(account(name), 100);
+            config.chain_spec.genesis.validators.push(account(name));
+        }
+        config
+    }
+
+    fn vote(blockchain: &Blockchain, name: &str, block: &Block) -> ValidatorSignature {
+        let (private_key, public_key) = crate::utils::crypto::keypair_from_seed(name);
+        let set_id = blockchain.finality.authorities.set_id;
+        sign_finality_vote(&block.hash(), block.header.number, set_id, &private_key, &public_key).unwrap()
+    }
+
+    #[test]
+    fn test_block_finalized_by_validator_votes() {
+        let mut blockchain = Blockchain::new(validator_config());
+        blockchain.add_transaction(transfer("alice", "bob", 100, 0)).unwrap();
+        let block = blockchain.mine_block(account("validator1")).unwrap();
+        let hash = block.hash();
+
+        // Outsiders and forged votes do not count
+        let outsider = vote(&blockchain, "mallory", &block);
+        assert!(blockchain.submit_finality_vote(&hash, outsider).is_err());
+        let mut forged = vote(&blockchain, "validator1", &block);
+        forged.validator = account("validator2");
+        assert_eq!(blockchain.submit_finality_vote(&hash, forged), Err(CoreError::InvalidSignature));
+
+        // A third of the stake is not enough, and repeating a vote does not count twice
+        let first = vote(&blockchain, "validator1", &block);
+        assert_eq!(blockchain.submit_finality_vote(&hash, first.clone()), Ok(false));
+        assert_eq!(blockchain.submit_finality_vote(&hash, first), Ok(false));
+        assert!(!blockchain.is_finalized(&hash));
+
+        assert_eq!(blockchain.submit_finality_vote(&hash, vote(&blockchain, "validator2", &block)), Ok(true));
+        assert!(blockchain.is_finalized(&hash));
+        assert_eq!(blockchain.state.finalized_block.hash(), hash);
+        let justification = finality_justification(blockchain.get_block(&hash).unwrap()).unwrap();
+        assert_eq!(justification.signatures.len(), 2);
+        assert!(blockchain.finality.verify_justification(justification, &hash, 1).is_ok());
+    }
+
+    #[test]
+    fn test_imported_justification_finalizes_and_blocks_reorg() {
+        let mut producer = Blockchain::new(validator_config());
+        let mut follower = Blockchain::new(validator_config());
+        let mut rival = Blockchain::new(validator_config());
+
+        let block = producer.mine_block(account("validator1")).unwrap();
+        for name in ["validator1", "validator3"] {
+            producer.submit_finality_vote(&block.hash(), vote(&producer, name, &block)).unwrap();
+        }
+        let justified = producer.get_block(&block.hash()).unwrap().clone();
+        assert_eq!(justified.body.justifications.len(), 1);
+
+        // A justification signed by too little stake is rejected with the block
+        let mut weak = justified.clone();
+        weak.body.justifications[0].signatures.truncate(1);
+        assert!(follower.import_block(weak).is_err());
+
+        follower.import_block(justified).unwrap();
+        assert!(follower.is_finalized(&block.hash()));
+
+        rival.mine_block(account("validator2")).unwrap();
+        let rival_tip = rival.mine_block(account("validator2")).unwrap();
+        let rival_first = rival.get_block(&rival_tip.header.parent_hash).unwrap().clone();
+        assert!(follower.import_block(rival_first).is_err());
+        assert_eq!(follower.state.best_block.hash(), block.hash());
+    }
+
+    #[test]
+    fn test_equivocating_votes_and_seals_are_offences() {
+        // validator1 votes for two different blocks at height 1
+        let mut node = Blockchain::new(validator_config());
+        let mut other = Blockchain::new(validator_config());
+        let block = node.mine_block(account("validator1")).unwrap();
+        let rival = other.mine_block(account("validator2")).unwrap();
+        node.import_block(rival.clone()).unwrap();
+        node.submit_finality_vote(&block.hash(), vote(&node, "validator1", &block)).unwrap();
+        node.submit_finality_vote(&rival.hash(), vote(&node, "validator1", &rival)).unwrap();
+        let offences = node.take_offences();
+        assert!(matches!(
+            offences.as_slice(),
+            [Offence::VoteEquivocation { offender, number: 1, .. }] if offender == &account("validator1")
+        ));
+        assert!(node.take_offences().is_empty());
+
+        // validator2 seals two different blocks for slot 10
+        let mut first = poa_producer("validator2");
+        let mut second = poa_producer("validator2");
+        let mut follower = Blockchain::new(poa_config());
+        second.add_transaction(transfer("alice", "bob", 100, 0)).unwrap();
+        follower.import_block(first.mine_block_at(account("validator2"), 60).unwrap()).unwrap();
+        follower.import_block(second.mine_block_at(account("validator2"), 61).unwrap()).unwrap();
+        assert!(matches!(
+            follower.take_offences().as_slice(),
+            [Offence::BlockEquivocation { offender, slot: 10, .. }] if offender == &account("validator2")
+        ));
+    }
+
+    #[test]
+    fn test_snapshot_restores_finalized_state() {
+        let mut producer = Blockchain::new(validator_config());
+        producer.add_transaction(transfer("alice", "bob", 100, 0)).unwrap();
+        let block = producer.mine_block(account("validator1")).unwrap();
+        for name in ["validator1", "validator2"] {
+            producer.submit_finality_vote(&block.hash(), vote(&producer, name, &block)).unwrap();
+        }
+        let snapshot = producer.export_snapshot().unwrap();
+        assert_eq!(snapshot.block.hash(), block.hash());
+
+        // Edited balances and snapshots of another chain are refused
+        let mut edited = snapshot.clone();
+        edited.accounts.get_mut(&account("bob")).unwrap().balance = 1_000_000;
+        assert!(Blockchain::from_snapshot(validator_config(), Box::new(MemoryStorage::new()), edited).is_err());
+        let other_chain = test_config(&std::env::temp_dir());
+        assert!(Blockchain::from_snapshot(other_chain, Box::new(MemoryStorage::new()), snapshot.clone()).is_err());
+
+        let dir = std::env::temp_dir().join(format!("ledger-chain-{}", uuid::Uuid::new_v4()));
+        let mut config = validator_config();
+        config.node_config.data_dir = dir.to_string_lossy().to_string();
+        let mut restored = Blockchain::restore(config.clone(), snapshot).unwrap();
+        assert_eq!(restored.get_balance(&account("bob")), 100);
+        assert!(restored.is_finalized(&block.hash()));
+
+        // The restored node follows the chain and survives a restart
+        producer.add_transaction(transfer("alice", "bob", 50, 1)).unwrap();
+        let next = producer.mine_block(account("validator2")).unwrap();
+        restored.import_block(next.clone()).unwrap();
+        drop(restored);
+        let reopened = Blockchain::open(config).unwrap();
+        assert_eq!(reopened.state.best_block.hash(), next.hash());
+        assert_eq!(reopened.get_balance(&account("bob")), 150);
+        assert_eq!(reopened.get_block_by_number(1).map(Block::hash), Some(block.hash()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pruning_drops_finalized_history() {
+        for (mode, kept_bodies) in [(PruningMode::HeadersOnly, vec![3]), (PruningMode::KeepBlocks(2), vec![2, 3])] {
+            let mut config = validator_config();
+            config.node_config.pruning = mode;
+            let mut blockchain = Blockchain::new(config);
+            let mut blocks = Vec::new();
+            for nonce in 0..3 {
+                blockchain.add_transaction(transfer("alice", "bob", 10, nonce)).unwrap();
+                blocks.push(blockchain.mine_block(account("validator1")).unwrap());
+            }
+            let stale = Blockchain::new(validator_config()).mine_block(account("validator2")).unwrap();
+            assert_eq!(blockchain.import_block(stale.clone()), Ok(ImportResult::SideChain));
+            let old_root = crate::utils::crypto::hash_from_hex(&blocks[0].header.state_root).unwrap();
+            assert!(blockchain.state_trie.accounts_at(&old_root).is_some());
+
+            let head = blocks[2].clone();
+            for name in ["validator1", "validator2"] {
+                blockchain.submit_finality_vote(&head.hash(), vote(&blockchain, name, &head)).unwrap();
+            }
+
+            assert!(blockchain.get_block(&stale.hash()).is_none());
+            assert!(blockchain.state.undo.is_empty());
+            for block in &blocks {
+                let number = block.header.number;
+                let stored = blockchain.get_block_by_number(number).unwrap();
+                assert_eq!(stored.header, block.header);
+                assert_eq!(stored.body.transactions.is_empty(), !kept_bodies.contains(&number), "{:?} block {}", mode, number);
+            }
+            let history = blockchain.get_transactions_by_sender(&account("alice"), &TransactionFilter::default(), &PageRequest::default());
+            assert_eq!(history.total, kept_bodies.len());
+
+            // Current state stays provable, older roots are gone
+            assert_eq!(blockchain.get_balance(&account("bob")), 30);
+            assert!(blockchain.prove_account(&account("bob")).unwrap().verify(&head.header.state_root));
+            assert!(blockchain.state_trie.accounts_at(&old_root).is_none());
+        }
+    }
+
+    #[test]
+    fn test_pruned_history_stays_pruned_after_a_restart() {
+        let dir = std::env::temp_dir().join(format!("ledger-chain-{}", uuid::Uuid::new_v4()));
+        let mut config = validator_config();
+        config.node_config.data_dir = dir.to_string_lossy().to_string();
+        config.node_config.pruning = PruningMode::HeadersOnly;
+        let mut blockchain = Blockchain::open(config.clone()).unwrap();
+        let mut blocks = Vec::new();
+        for nonce in 0..3 {
+            blockchain.add_transaction(transfer("alice", "bob", 10, nonce)).unwrap();
+            blockchain.record_call(Extrinsic { sender: account("alice"), call: b"{}".to_vec() });
+            blocks.push(blockchain.mine_block(account("validator1")).unwrap());
+        }
+        for name in ["validator1", "validator2"] {
+            blockchain.submit_finality_vote(&blocks[1].hash(), vote(&blockchain, name, &blocks[1])).unwrap();
+        }
+        drop(blockchain);
+
+        // Block 1 lost its transactions and calls, blocks 2 and 3 still prove theirs
+        let reopened = Blockchain::open(config).unwrap();
+        let pruned = reopened.get_block_by_number(1).unwrap();
+        assert!(pruned.is_pruned());
+        assert!(pruned.body.calls.is_empty());
+        assert_eq!(pruned.header, blocks[0].header);
+        let pruned_hash = &blocks[0].body.transactions[0].hash;
+        assert_eq!(reopened.get_transaction_proof(pruned_hash), Err(CoreError::TransactionNotFound));
+        for block in &blocks[1..] {
+            assert!(!reopened.get_block_by_number(block.header.number).unwrap().is_pruned());
+            let transaction = &block.body.transactions[0];
+            let proof = reopened.get_transaction_proof(&transaction.hash).unwrap();
+            assert_eq!(proof.block_hash, block.hash());
+            assert!(Blockchain::verify_transaction_proof(transaction, &proof, &block.header.transactions_root));
+        }
+        assert!(!Blockchain::new(validator_config()).state.best_block.is_pruned());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn poa_config() -> SystemConfig {
+        let mut config = validator_config();
+        config.consensus_config.algorithm = ConsensusAlgorithm::PoA;
+        config.consensus_config.block_time = 6;
+        config
+    }
+
+    fn poa_producer(name: &str) -> Blockchain {
+        let mut blockchain = Blockchain::new(poa_config());
+        blockchain.set_authority_key(crate::utils::crypto::keypair_from_seed(name).0);
+        blockchain
+    }
+
+    #[test]
+    fn test_poa_rejects_wrong_author_and_slot() {
+        // Slot 10 belongs to validator2 and slot 11 to validator3
+        let mut producer = poa_producer("validator2");
+        assert!(producer.mine_block_at(account("validator1"), 60).is_err());
+        assert!(producer.mine_block_at(account("validator3"), 66).is_err());
+        let block = producer.mine_block_at(account("validator2"), 60).unwrap();
+
+        // A later block cannot reuse the parent's slot
+        assert!(producer.mine_block_at(account("validator2"), 65).is_err());
+        assert_eq!(producer.state.best_block.hash(), block.hash());
+    }
+
+    #[test]
+    fn test_poa_verifies_seals_and_tracks_missed_slots() {
+        let mut first = poa_producer("validator2");
+        let mut second = poa_producer("validator3");
+        let mut follower = Blockchain::new(poa_config());
+
+        let block = first.mine_block_at(account("validator2"), 60).unwrap();
+        assert!(block.body.justifications.iter().any(|j| j.justification_type == JustificationType::Authority));
+
+        // Unsealed blocks and seals by someone else are rejected
+        let mut unsealed = block.clone();
+        unsealed.body.justifications.clear();
+        assert!(follower.import_block(unsealed).is_err());
+        let mut forged = block.clone();
+        forged.body.justifications = vec![seal_header(&block.header, &crate::utils::crypto::keypair_from_seed("validator1").0).unwrap()];
+        assert!(follower.import_block(forged).is_err());
+        follower.import_block(block.clone()).unwrap();
+        second.import_block(block).unwrap();
+
+        // validator3 skips slot 11 and validator1 slot 12; validator2 authors slot 13
+        let next = first.mine_block_at(account("validator2"), 78).unwrap();
+        follower.import_block(next).unwrap();
+        let authorship = follower.authorship.as_ref().unwrap();
+        assert_eq!(authorship.missed_slots_of(&account("validator3")), 1);
+        assert_eq!(authorship.missed_slots_of(&account("validator1")), 1);
+        assert_eq!(authorship.missed_slots_of(&account("validator2")), 0);
+
+        // A block for a slot that has not started yet is refused
+        let mut slot = crate::utils::time::current_timestamp() / 6 + 100;
+        slot += (2 + 3 - slot % 3) % 3;
+        let early = second.mine_block_at(account("validator3"), slot * 6).unwrap();
+        assert!(follower.import_block(early).is_err());
     }
 }
@@ -0,0 +1,276 @@
+// Chain specifications
+// This module provides the built-in chain presets, reads and writes chain specs
+// as JSON or TOML, and checks that two nodes run the same chain before peering
+
+use crate::core::blockchain::Blockchain;
+use crate::primitives::*;
+use crate::utils::crypto;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Names of the built-in chain specs
+pub const PRESETS: [&str; 3] = ["dev", "local-testnet", "consortium"];
+
+/// File format of a chain spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecFormat {
+    /// JSON document
+    Json,
+    /// TOML document
+    Toml,
+}
+
+impl SpecFormat {
+    /// Format given by a file extension; anything but `.toml` is read as JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => SpecFormat::Toml,
+            _ => SpecFormat::Json,
+        }
+    }
+
+    /// Format by name, `json` or `toml`
+    pub fn from_name(name: &str) -> CoreResult<Self> {
+        match name {
+            "json" => Ok(SpecFormat::Json),
+            "toml" => Ok(SpecFormat::Toml),
+            _ => Err(CoreError::InvalidInput(format!("Unknown chain spec format {}", name))),
+        }
+    }
+
+    /// Write a value in this format
+    pub fn encode<T: Serialize>(&self, value: &T) -> CoreResult<String> {
+        match self {
+            SpecFormat::Json => serde_json::to_string_pretty(value)
+                .map_err(|e| CoreError::InvalidInput(format!("Failed to encode chain spec: {}", e))),
+            SpecFormat::Toml => toml::to_string_pretty(value)
+                .map_err(|e| CoreError::InvalidInput(format!("Failed to encode chain spec: {}", e))),
+        }
+    }
+
+    /// Read a value in this format
+    pub fn decode<T: DeserializeOwned>(&self, text: &str) -> CoreResult<T> {
+        match self {
+            SpecFormat::Json => serde_json::from_str(text)
+                .map_err(|e| CoreError::InvalidInput(format!("Malformed chain spec: {}", e))),
+            SpecFormat::Toml => toml::from_str(text)
+                .map_err(|e| CoreError::InvalidInput(format!("Malformed chain spec: {}", e))),
+        }
+    }
+}
+
+/// Chain spec with the genesis hash it produces, as distributed to node operators
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawChainSpec {
+    /// Chain spec
+    #[serde(flatten)]
+    pub spec: ChainSpec,
+    /// Hash of the genesis block the spec produces
+    pub genesis_hash: Hash,
+}
+
+/// Chain spec file contents; the genesis hash is only present in raw specs
+#[derive(Deserialize)]
+struct SpecFile {
+    #[serde(flatten)]
+    spec: ChainSpec,
+    #[serde(default)]
+    genesis_hash: Option<Hash>,
+}
+
+impl ChainSpec {
+    /// Built-in chain spec by name, one of [`PRESETS`]
+    pub fn preset(name: &str) -> CoreResult<Self> {
+        match name {
+            "dev" => Ok(Self::dev()),
+            "local-testnet" => Ok(Self::local_testnet()),
+            "consortium" => Ok(Self::consortium()),
+            _ => Err(CoreError::InvalidInput(format!(
+                "Unknown chain spec {}; expected a file or one of {}", name, PRESETS.join(", ")
+            ))),
+        }
+    }
+
+    /// Single validator chain for development: alice validates, alice and bob are funded
+    pub fn dev() -> Self {
+        Self::from_dev_seeds("Development", "dev", &["alice"], &["alice", "bob"])
+    }
+
+    /// Three validator test network of alice, bob and charlie
+    pub fn local_testnet() -> Self {
+        let seeds = ["alice", "bob", "charlie"];
+        Self::from_dev_seeds("Thai Energy Trading Local Testnet", "local-testnet", &seeds, &seeds)
+    }
+
+    /// Network of the EGAT, MEA and PEA utilities.
+    ///
+    /// The validator keys are derived from public dev seeds as placeholders;
+    /// a real deployment must replace them in an exported spec.
+    pub fn consortium() -> Self {
+        let seeds = ["egat", "mea", "pea"];
+        Self::from_dev_seeds("Thai Energy Trading Consortium", "thai-energy-consortium", &seeds, &seeds)
+    }
+
+    fn from_dev_seeds(name: &str, id: &str, validators: &[&str], funded: &[&str]) -> Self {
+        let balance = 1_000_000 * 10_u128.pow(constants::TOKEN_DECIMALS as u32);
+        Self {
+            name: name.to_string(),
+            id: id.to_string(),
+            genesis: GenesisConfig {
+                accounts: funded.iter().map(|seed| (crypto::dev_account_id(seed), balance)).collect(),
+                validators: validators.iter().map(|seed| crypto::dev_account_id(seed)).collect(),
+                energy_market: EnergyMarketConfig::default(),
+            },
+            boot_nodes: Vec::new(),
+        }
+    }
+
+    /// Preset name or path of a chain spec file
+    pub fn from_name_or_path(chain: &str) -> CoreResult<Self> {
+        if PRESETS.contains(&chain) {
+            Self::preset(chain)
+        } else {
+            Self::load(Path::new(chain))
+        }
+    }
+
+    /// Read a chain spec from a JSON or TOML file.
+    ///
+    /// A raw spec's genesis hash must match the genesis the spec produces.
+    pub fn load(path: &Path) -> CoreResult<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| CoreError::StorageError(format!("Failed to read {}: {}", path.display(), e)))?;
+        let file: SpecFile = SpecFormat::from_path(path).decode(&text)?;
+        if let Some(genesis_hash) = file.genesis_hash {
+            if genesis_hash != file.spec.genesis_hash() {
+                return Err(CoreError::ValidationFailed(format!(
+                    "Chain spec {} does not produce its genesis hash {}", path.display(), genesis_hash
+                )));
+            }
+        }
+        Ok(file.spec)
+    }
+
+    /// Write the chain spec to a JSON or TOML file
+    pub fn save(&self, path: &Path) -> CoreResult<()> {
+        let text = SpecFormat::from_path(path).encode(self)?;
+        std::fs::write(path, text)
+            .map_err(|e| CoreError::StorageError(format!("Failed to write {}: {}", path.display(), e)))
+    }
+
+    /// Hash of the genesis block the spec produces
+    pub fn genesis_hash(&self) -> Hash {
+        Blockchain::genesis_hash(&self.genesis)
+    }
+
+    /// Spec with its genesis hash, for distribution
+    pub fn to_raw(&self) -> RawChainSpec {
+        RawChainSpec {
+            spec: self.clone(),
+            genesis_hash: self.genesis_hash(),
+        }
+    }
+}
+
+/// What a node announces about its chain when connecting to a peer
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Handshake {
+    /// Chain ID from the chain spec
+    pub chain_id: String,
+    /// Genesis block hash
+    pub genesis_hash: Hash,
+    /// Best block number
+    pub best_number: BlockNumber,
+    /// Best block hash
+    pub best_hash: Hash,
+}
+
+impl Handshake {
+    /// Refuse a peer that runs another chain or another genesis of the same chain
+    pub fn check(&self, remote: &Handshake) -> CoreResult<()> {
+        if self.chain_id != remote.chain_id {
+            return Err(CoreError::ValidationFailed(format!(
+                "Peer runs chain {}, not {}", remote.chain_id, self.chain_id
+            )));
+        }
+        if self.genesis_hash != remote.genesis_hash {
+            return Err(CoreError::ValidationFailed(format!(
+                "Peer genesis {} does not match {}", remote.genesis_hash, self.genesis_hash
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Blockchain {
+    /// Handshake announcing this node's chain and best block
+    pub fn handshake(&self) -> Handshake {
+        let best = &self.state.best_block;
+        Handshake {
+            chain_id: self.config.chain_spec.id.clone(),
+            genesis_hash: self.config.chain_spec.genesis_hash(),
+            best_number: best.header.number,
+            best_hash: best.hash(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_spec_round_trip_and_genesis_hash() {
+        let dir = std::env::temp_dir().join(format!("chain-spec-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let spec = ChainSpec::preset("local-testnet").unwrap();
+        for file in ["spec.json", "spec.toml"] {
+            let path = dir.join(file);
+            let format = SpecFormat::from_path(&path);
+            std::fs::write(&path, format.encode(&spec.to_raw()).unwrap()).unwrap();
+            let loaded = ChainSpec::load(&path).unwrap();
+            assert_eq!(loaded.genesis_hash(), spec.genesis_hash());
+            assert_eq!(loaded.genesis.accounts, spec.genesis.accounts);
+
+            // An edited balance no longer matches the distributed genesis hash
+            let mut raw = spec.to_raw();
+            *raw.spec.genesis.accounts.values_mut().next().unwrap() += 1;
+            std::fs::write(&path, format.encode(&raw).unwrap()).unwrap();
+            assert!(ChainSpec::load(&path).is_err());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let hashes: std::collections::HashSet<Hash> = PRESETS.iter()
+            .map(|name| ChainSpec::preset(name).unwrap().genesis_hash())
+            .collect();
+        assert_eq!(hashes.len(), PRESETS.len());
+        assert!(ChainSpec::preset("mainnet").is_err());
+    }
+
+    #[test]
+    fn test_mismatched_specs_refuse_to_peer() {
+        let dir = std::env::temp_dir().join(format!("ledger-chain-{}", uuid::Uuid::new_v4()));
+        let config = |spec: ChainSpec| {
+            let mut config = SystemConfig { chain_spec: spec, ..SystemConfig::default() };
+            config.node_config.data_dir = dir.to_string_lossy().to_string();
+            config
+        };
+        let local = Blockchain::new(config(ChainSpec::local_testnet()));
+        let same = Blockchain::new(config(ChainSpec::local_testnet()));
+        assert_eq!(local.handshake().check(&same.handshake()), Ok(()));
+
+        // Same chain ID and balances, different validators
+        let mut forked = ChainSpec::local_testnet();
+        forked.genesis.validators.pop();
+        let forked = Blockchain::new(config(forked));
+        assert!(local.handshake().check(&forked.handshake()).is_err());
+
+        // A node refuses to reopen its data directory with another spec
+        drop(Blockchain::open(config(ChainSpec::dev())).unwrap());
+        assert!(Blockchain::open(config(ChainSpec::local_testnet())).is_err());
+        assert!(Blockchain::open(config(ChainSpec::dev())).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+// Canonical binary encoding
+// This module defines the deterministic byte encoding that block, transaction
+// and state hashes are computed over
+
+use crate::primitives::*;
+
+/// Deterministic binary encoding.
+///
+/// Integers are big-endian, fixed-point values are encoded by their raw
+/// integer, and variable-length values are prefixed with their length as a `u64`. The
+/// encoding never depends on `Debug` output or hash map iteration order.
+pub trait Encode {
+    /// Append the encoding of `self` to `out`
+    fn encode_to(&self, out: &mut Vec<u8>);
+
+    /// Encode `self` into a new buffer
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_to(&mut out);
+        out
+    }
+}
+
+impl Encode for u8 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+}
+
+impl Encode for bool {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl Encode for u32 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Encode for u64 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Encode for u128 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Encode for Energy {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.raw().encode_to(out);
+    }
+}
+
+impl Encode for Price {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.raw().encode_to(out);
+    }
+}
+
+impl Encode for Amount {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.raw().encode_to(out);
+    }
+}
+
+impl Encode for str {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode_to(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_str().encode_to(out);
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                out.push(1);
+                value.encode_to(out);
+            }
+            None => out.push(0),
+        }
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode_to(out);
+        for item in self {
+            item.encode_to(out);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_to(out);
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (**self).encode_to(out);
+    }
+}
+
+/// Hash a value's canonical encoding with SHA-256
+pub fn hash_encoded<T: Encode + ?Sized>(value: &T) -> Hash {
+    crate::utils::crypto::sha256_hash(&value.encode())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_is_length_prefixed() {
+        // "ab" + "c" must not collide with "a" + "bc"
+        let left = ("ab".encode(), "c".encode());
+        let right = ("a".encode(), "bc".encode());
+        assert_ne!([left.0, left.1].concat(), [right.0, right.1].concat());
+        assert_eq!(7u64.encode(), vec![0, 0, 0, 0, 0, 0, 0, 7]);
+        assert_eq!(Some(1u8).encode(), vec![1, 1]);
+    }
+}
@@ -0,0 +1,255 @@
+// Finality gadget
+// This module collects validator signatures on block hashes and produces the
+// justifications that make a block final
+
+use crate::core::blockchain::{Justification, JustificationType, ValidatorSignature};
+use crate::core::codec::Encode;
+use crate::core::offences::Offence;
+use crate::primitives::*;
+use crate::utils::crypto;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Domain separation prefix of finality votes
+const FINALITY_CONTEXT: &str = "ledger-finality";
+
+/// Validator allowed to vote on finality
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Authority {
+    /// Validator account, which is also its public key
+    pub id: AccountId,
+    /// Voting weight (stake)
+    pub weight: Balance,
+}
+
+/// Set of finality authorities
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AuthoritySet {
+    /// Set identifier, incremented on every change so old votes cannot be replayed
+    pub set_id: u64,
+    /// Authorities and their weights
+    pub authorities: Vec<Authority>,
+}
+
+impl AuthoritySet {
+    /// Authority set of the genesis validators.
+    ///
+    /// Validators are weighted by their genesis balance; if none of them has a
+    /// balance they all get the same weight.
+    pub fn from_genesis(genesis: &GenesisConfig) -> Self {
+        let mut authorities: Vec<Authority> = genesis.validators.iter()
+            .map(|id| Authority {
+                id: id.clone(),
+                weight: genesis.accounts.get(id).copied().unwrap_or(0),
+            })
+            .collect();
+        if authorities.iter().all(|a| a.weight == 0) {
+            for authority in &mut authorities {
+                authority.weight = 1;
+            }
+        }
+        Self { set_id: 0, authorities }
+    }
+
+    /// Weight of an authority, `None` if it is not in the set
+    pub fn weight_of(&self, id: &AccountId) -> Option<Balance> {
+        self.authorities.iter().find(|a| &a.id == id).map(|a| a.weight)
+    }
+
+    /// Sum of all weights
+    pub fn total_weight(&self) -> Balance {
+        self.authorities.iter().map(|a| a.weight).sum()
+    }
+}
+
+impl Encode for Authority {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.id.encode_to(out);
+        self.weight.encode_to(out);
+    }
+}
+
+impl Encode for AuthoritySet {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.set_id.encode_to(out);
+        self.authorities.encode_to(out);
+    }
+}
+
+/// Message a validator signs to vote for a block
+pub fn finality_payload(block_hash: &Hash, number: BlockNumber, set_id: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    FINALITY_CONTEXT.encode_to(&mut out);
+    block_hash.encode_to(&mut out);
+    number.encode_to(&mut out);
+    set_id.encode_to(&mut out);
+    out
+}
+
+/// Sign a finality vote with a validator's hex-encoded private key
+pub fn sign_finality_vote(
+    block_hash: &Hash,
+    number: BlockNumber,
+    set_id: u64,
+    private_key: &str,
+    public_key: &str,
+) -> CoreResult<ValidatorSignature> {
+    let signature = crypto::sign_message(&finality_payload(block_hash, number, set_id), private_key)?;
+    Ok(ValidatorSignature {
+        validator: public_key.to_string(),
+        signature,
+        timestamp: crate::utils::time::current_timestamp(),
+    })
+}
+
+/// Collects finality votes until a block is signed by enough stake
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FinalityGadget {
+    /// Current authority set
+    pub authorities: AuthoritySet,
+    /// Share of total weight, in percent, that must be exceeded for finality
+    pub threshold: u32,
+    /// Votes collected so far by block hash
+    votes: HashMap<Hash, (BlockNumber, Vec<ValidatorSignature>)>,
+    /// First vote of each authority per height, to catch equivocation
+    seen_votes: HashMap<BlockNumber, HashMap<AccountId, (Hash, ValidatorSignature)>>,
+    /// Equivocations found and not yet taken
+    offences: Vec<Offence>,
+}
+
+impl FinalityGadget {
+    /// Create a gadget for an authority set
+    pub fn new(authorities: AuthoritySet, threshold: u32) -> Self {
+        Self {
+            authorities,
+            threshold,
+            votes: HashMap::new(),
+            seen_votes: HashMap::new(),
+            offences: Vec::new(),
+        }
+    }
+
+    /// Check a vote and return the voter's weight
+    pub fn check_vote(&self, block_hash: &Hash, number: BlockNumber, vote: &ValidatorSignature) -> CoreResult<Balance> {
+        let weight = self.authorities.weight_of(&vote.validator)
+            .ok_or_else(|| CoreError::ValidationFailed(format!("{} is not a finality authority", vote.validator)))?;
+        let payload = finality_payload(block_hash, number, self.authorities.set_id);
+        if !crypto::verify_signature(&payload, &vote.signature, &vote.validator) {
+            return Err(CoreError::InvalidSignature);
+        }
+        Ok(weight)
+    }
+
+    /// Record a vote, returning the justification once the block is final
+    pub fn add_vote(
+        &mut self,
+        block_hash: &Hash,
+        number: BlockNumber,
+        vote: ValidatorSignature,
+    ) -> CoreResult<Option<Justification>> {
+        self.check_vote(block_hash, number, &vote)?;
+        self.note_vote(block_hash, number, &vote);
+
+        let (_, votes) = self.votes.entry(block_hash.clone()).or_insert_with(|| (number, Vec::new()));
+        if !votes.iter().any(|v| v.validator == vote.validator) {
+            votes.push(vote);
+        }
+        let votes = votes.clone();
+
+        let weight: Balance = votes.iter().filter_map(|v| self.authorities.weight_of(&v.validator)).sum();
+        if !self.exceeds_threshold(weight) {
+            return Ok(None);
+        }
+
+        Ok(Some(Justification {
+            justification_type: JustificationType::Finality,
+            proof: finality_payload(block_hash, number, self.authorities.set_id),
+            signatures: votes,
+        }))
+    }
+
+    /// Verify a finality justification received with a block
+    pub fn verify_justification(
+        &self,
+        justification: &Justification,
+        block_hash: &Hash,
+        number: BlockNumber,
+    ) -> CoreResult<()> {
+        if justification.justification_type != JustificationType::Finality {
+            return Err(CoreError::ValidationFailed("Not a finality justification".to_string()));
+        }
+
+        let mut voters: Vec<&AccountId> = Vec::new();
+        let mut weight: Balance = 0;
+        for vote in &justification.signatures {
+            if voters.contains(&&vote.validator) {
+                return Err(CoreError::ValidationFailed("Duplicate vote in justification".to_string()));
+            }
+            weight += self.check_vote(block_hash, number, vote)?;
+            voters.push(&vote.validator);
+        }
+
+        if !self.exceeds_threshold(weight) {
+            return Err(CoreError::ValidationFailed("Justification lacks enough signed stake".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Record the votes of a verified justification for equivocation checks
+    pub fn note_justification(&mut self, block_hash: &Hash, number: BlockNumber, justification: &Justification) {
+        for vote in &justification.signatures {
+            self.note_vote(block_hash, number, vote);
+        }
+    }
+
+    /// Remember a checked vote; a second vote for another block at the same height is an offence
+    fn note_vote(&mut self, block_hash: &Hash, number: BlockNumber, vote: &ValidatorSignature) {
+        let seen = self.seen_votes.entry(number).or_default();
+        match seen.get(&vote.validator) {
+            Some((first_hash, first_vote)) if first_hash != block_hash => {
+                self.offences.push(Offence::VoteEquivocation {
+                    offender: vote.validator.clone(),
+                    set_id: self.authorities.set_id,
+                    number,
+                    first: (first_hash.clone(), first_vote.clone()),
+                    second: (block_hash.clone(), vote.clone()),
+                });
+            }
+            Some(_) => {}
+            None => {
+                seen.insert(vote.validator.clone(), (block_hash.clone(), vote.clone()));
+            }
+        }
+    }
+
+    /// Equivocations found since the last call
+    pub fn take_offences(&mut self) -> Vec<Offence> {
+        std::mem::take(&mut self.offences)
+    }
+
+    /// Switch to a new authority set; pending votes of the old set are dropped
+    pub fn change_authorities(&mut self, authorities: Vec<Authority>) {
+        self.authorities = AuthoritySet {
+            set_id: self.authorities.set_id + 1,
+            authorities,
+        };
+        self.votes.clear();
+        self.seen_votes.clear();
+    }
+
+    /// Forget votes for blocks at or below the finalized height
+    pub fn prune(&mut self, finalized_number: BlockNumber) {
+        self.votes.retain(|_, (number, _)| *number > finalized_number);
+        self.seen_votes.retain(|number, _| *number > finalized_number);
+    }
+
+    /// Votes collected for a block
+    pub fn votes_for(&self, block_hash: &Hash) -> &[ValidatorSignature] {
+        self.votes.get(block_hash).map(|(_, votes)| votes.as_slice()).unwrap_or(&[])
+    }
+
+    fn exceeds_threshold(&self, weight: Balance) -> bool {
+        let total = self.authorities.total_weight();
+        total > 0 && weight.saturating_mul(100) > total.saturating_mul(self.threshold as Balance)
+    }
+}
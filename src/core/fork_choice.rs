@@ -0,0 +1,45 @@
+// Fork choice rules
+// This module decides which of several competing chains is the best chain
+
+use crate::core::blockchain::{Block, BlockHeader};
+use crate::primitives::*;
+use std::collections::HashMap;
+
+/// Rule selecting the best chain among known blocks
+pub trait ForkChoiceRule: Send {
+    /// Whether the chain ending at `candidate` should replace the one ending at `current`
+    fn prefer(&self, candidate: &BlockHeader, current: &BlockHeader, blocks: &HashMap<Hash, Block>) -> bool;
+}
+
+/// Longest chain wins; on equal length the chain seen first is kept
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LongestChain;
+
+impl ForkChoiceRule for LongestChain {
+    fn prefer(&self, candidate: &BlockHeader, current: &BlockHeader, _blocks: &HashMap<Hash, Block>) -> bool {
+        candidate.number > current.number
+    }
+}
+
+/// Chain with the highest total difficulty wins; on a tie the chain seen first is kept
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeaviestChain;
+
+impl HeaviestChain {
+    /// Sum of difficulties from genesis up to `header`
+    pub fn total_difficulty(header: &BlockHeader, blocks: &HashMap<Hash, Block>) -> u128 {
+        let mut total = header.difficulty as u128;
+        let mut parent = blocks.get(&header.parent_hash);
+        while let Some(block) = parent {
+            total += block.header.difficulty as u128;
+            parent = blocks.get(&block.header.parent_hash);
+        }
+        total
+    }
+}
+
+impl ForkChoiceRule for HeaviestChain {
+    fn prefer(&self, candidate: &BlockHeader, current: &BlockHeader, blocks: &HashMap<Hash, Block>) -> bool {
+        Self::total_difficulty(candidate, blocks) > Self::total_difficulty(current, blocks)
+    }
+}
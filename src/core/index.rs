@@ -0,0 +1,188 @@
+// Secondary chain indexes
+// This module indexes the best chain by block number, transaction hash,
+// sender, receiver and type, so history queries do not scan every block
+
+use crate::core::blockchain::{Block, Transaction, TransactionType};
+use crate::primitives::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Largest page a query returns
+pub const MAX_PAGE_SIZE: usize = 1_000;
+
+/// Slice of a query result to return
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PageRequest {
+    /// Matching items to skip
+    pub offset: usize,
+    /// Items to return, capped at [`MAX_PAGE_SIZE`]
+    pub limit: usize,
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self { offset: 0, limit: 100 }
+    }
+}
+
+/// One page of a query result
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Page<T> {
+    /// Items of this page
+    pub items: Vec<T>,
+    /// Number of matching items across all pages
+    pub total: usize,
+    /// Offset of the next page, `None` on the last page
+    pub next_offset: Option<usize>,
+}
+
+impl<T> Page<T> {
+    /// Cut a page out of all matching items
+    pub fn paginate(matches: impl Iterator<Item = T>, request: &PageRequest) -> Self {
+        let limit = request.limit.min(MAX_PAGE_SIZE);
+        let mut total = 0;
+        let mut items = Vec::new();
+        for item in matches {
+            if total >= request.offset && items.len() < limit {
+                items.push(item);
+            }
+            total += 1;
+        }
+        let end = request.offset + items.len();
+        Self {
+            items,
+            total,
+            next_offset: (end < total).then_some(end),
+        }
+    }
+}
+
+/// Restriction of a transaction history query
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TransactionFilter {
+    /// Only transactions of this type
+    pub transaction_type: Option<TransactionType>,
+    /// Only transactions in blocks with a timestamp at or after this
+    pub since: Option<Timestamp>,
+    /// Only transactions in blocks with a timestamp before this
+    pub until: Option<Timestamp>,
+}
+
+impl TransactionFilter {
+    /// Whether the block time of a location is inside the filter's time range
+    pub fn in_range(&self, location: &TransactionLocation) -> bool {
+        self.since.is_none_or(|since| location.block_timestamp >= since)
+            && self.until.is_none_or(|until| location.block_timestamp < until)
+    }
+}
+
+/// Position of a transaction on the best chain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TransactionLocation {
+    /// Number of the including block
+    pub block_number: BlockNumber,
+    /// Index within the block
+    pub index: u32,
+    /// Timestamp of the including block
+    pub block_timestamp: Timestamp,
+    /// Transaction hash
+    pub hash: Hash,
+}
+
+/// Indexes over the best chain, updated as blocks are enacted and retracted
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChainIndex {
+    /// Block hash by number
+    block_hashes: BTreeMap<BlockNumber, Hash>,
+    /// Transactions by hash
+    by_hash: HashMap<Hash, TransactionLocation>,
+    /// Transactions by sender
+    by_sender: HashMap<AccountId, BTreeSet<TransactionLocation>>,
+    /// Transactions by receiver
+    by_receiver: HashMap<AccountId, BTreeSet<TransactionLocation>>,
+    /// Transactions by type
+    by_type: HashMap<TransactionType, BTreeSet<TransactionLocation>>,
+}
+
+impl ChainIndex {
+    /// Add a block that joined the best chain
+    pub fn enact(&mut self, block: &Block) {
+        self.block_hashes.insert(block.header.number, block.hash());
+        for (location, transaction) in Self::locations(block) {
+            self.by_hash.insert(transaction.hash.clone(), location.clone());
+            self.by_sender.entry(transaction.from.clone()).or_default().insert(location.clone());
+            self.by_receiver.entry(transaction.to.clone()).or_default().insert(location.clone());
+            self.by_type.entry(transaction.transaction_type.clone()).or_default().insert(location);
+        }
+    }
+
+    /// Remove a block that left the best chain
+    pub fn retract(&mut self, block: &Block) {
+        if self.block_hashes.get(&block.header.number) == Some(&block.hash()) {
+            self.block_hashes.remove(&block.header.number);
+        }
+        self.forget_transactions(block);
+    }
+
+    /// Remove the transactions of a block whose body was pruned; its number stays indexed
+    pub fn forget_transactions(&mut self, block: &Block) {
+        for (location, transaction) in Self::locations(block) {
+            if self.by_hash.get(&transaction.hash) == Some(&location) {
+                self.by_hash.remove(&transaction.hash);
+            }
+            Self::remove(&mut self.by_sender, &transaction.from, &location);
+            Self::remove(&mut self.by_receiver, &transaction.to, &location);
+            Self::remove(&mut self.by_type, &transaction.transaction_type, &location);
+        }
+    }
+
+    /// Hash of the best-chain block at a height
+    pub fn block_hash(&self, number: BlockNumber) -> Option<&Hash> {
+        self.block_hashes.get(&number)
+    }
+
+    /// Position of a best-chain transaction
+    pub fn location(&self, hash: &Hash) -> Option<&TransactionLocation> {
+        self.by_hash.get(hash)
+    }
+
+    /// Transactions sent by an account, newest first
+    pub fn sent_by(&self, account: &AccountId) -> impl Iterator<Item = &TransactionLocation> {
+        self.by_sender.get(account).into_iter().flat_map(|set| set.iter().rev())
+    }
+
+    /// Transactions received by an account, newest first
+    pub fn received_by(&self, account: &AccountId) -> impl Iterator<Item = &TransactionLocation> {
+        self.by_receiver.get(account).into_iter().flat_map(|set| set.iter().rev())
+    }
+
+    /// Transactions of a type, newest first
+    pub fn of_type(&self, transaction_type: &TransactionType) -> impl Iterator<Item = &TransactionLocation> {
+        self.by_type.get(transaction_type).into_iter().flat_map(|set| set.iter().rev())
+    }
+
+    fn locations(block: &Block) -> impl Iterator<Item = (TransactionLocation, &Transaction)> {
+        block.body.transactions.iter().enumerate().map(|(index, transaction)| {
+            let location = TransactionLocation {
+                block_number: block.header.number,
+                index: index as u32,
+                block_timestamp: block.header.timestamp,
+                hash: transaction.hash.clone(),
+            };
+            (location, transaction)
+        })
+    }
+
+    fn remove<K: std::hash::Hash + Eq>(
+        index: &mut HashMap<K, BTreeSet<TransactionLocation>>,
+        key: &K,
+        location: &TransactionLocation,
+    ) {
+        if let Some(set) = index.get_mut(key) {
+            set.remove(location);
+            if set.is_empty() {
+                index.remove(key);
+            }
+        }
+    }
+}
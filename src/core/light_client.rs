@@ -0,0 +1,303 @@
+// Light client
+// This module follows the chain by block headers and finality justifications
+// alone, and checks account and transaction proofs against the synced headers
+
+use crate::core::blockchain::{AccountState, Blockchain, BlockHeader, Justification, Transaction, TransactionProof};
+use crate::core::finality::{AuthoritySet, FinalityGadget};
+use crate::core::state_trie::AccountProof;
+use crate::primitives::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Header-only view of the chain, trusting nothing but its checkpoint
+#[derive(Debug, Clone)]
+pub struct LightClient {
+    /// Authorities that sign justifications for blocks after the finalized header
+    pub authorities: AuthoritySet,
+    /// Share of total weight, in percent, a justification must exceed
+    pub threshold: u32,
+    /// Headers of the finalized chain and of the forks above it, by hash
+    headers: HashMap<Hash, BlockHeader>,
+    /// Hash of the finalized chain's header by height, from the checkpoint on
+    finalized_chain: BTreeMap<BlockNumber, Hash>,
+    /// Hash of the highest header descending from the finalized header
+    best: Hash,
+}
+
+impl LightClient {
+    /// Start from a trusted checkpoint header, usually genesis, and the authorities after it
+    pub fn new(checkpoint: BlockHeader, authorities: AuthoritySet, threshold: u32) -> Self {
+        let hash = checkpoint.hash();
+        Self {
+            authorities,
+            threshold,
+            finalized_chain: BTreeMap::from([(checkpoint.number, hash.clone())]),
+            headers: HashMap::from([(hash.clone(), checkpoint)]),
+            best: hash,
+        }
+    }
+
+    /// Add a header on top of a known one.
+    ///
+    /// Headers are only linked, not trusted, until a justification finalizes
+    /// them or a descendant.
+    pub fn import_header(&mut self, header: BlockHeader) -> CoreResult<()> {
+        let hash = header.hash();
+        if self.headers.contains_key(&hash) {
+            return Ok(());
+        }
+        let parent = self.headers.get(&header.parent_hash)
+            .ok_or_else(|| CoreError::ValidationFailed(format!("Unknown parent header {}", header.parent_hash)))?;
+        if header.number != parent.number + 1 {
+            return Err(CoreError::ValidationFailed("Header number does not follow its parent".to_string()));
+        }
+        if header.timestamp < parent.timestamp {
+            return Err(CoreError::ValidationFailed("Header timestamp is before its parent".to_string()));
+        }
+        if let Some(set) = &header.authority_change {
+            if set.set_id != self.latest_set_id(&header.parent_hash) + 1 || set.total_weight() == 0 {
+                return Err(CoreError::ValidationFailed("Invalid authority set change".to_string()));
+            }
+        }
+
+        if header.number > self.best_header().number {
+            self.best = hash.clone();
+        }
+        self.headers.insert(hash, header);
+        Ok(())
+    }
+
+    /// Finalize a synced header with a justification from the current authorities.
+    ///
+    /// A header announcing a new authority set must be finalized before its
+    /// descendants; the new set is trusted from then on. Forks that do not
+    /// contain the header are dropped.
+    pub fn import_justification(&mut self, block_hash: &Hash, justification: &Justification) -> CoreResult<()> {
+        let header = self.headers.get(block_hash).ok_or(CoreError::BlockNotFound)?;
+        let finalized = self.finalized_header();
+        if header.number <= finalized.number || self.ancestor_at(block_hash, finalized.number).as_ref() != Some(self.finalized_hash()) {
+            return Err(CoreError::ValidationFailed("Header does not descend from the finalized header".to_string()));
+        }
+        if let Some((number, _)) = self.pending_authority_change(&header.parent_hash) {
+            return Err(CoreError::ValidationFailed(format!("Authority set change in block {} must be finalized first", number)));
+        }
+        FinalityGadget::new(self.authorities.clone(), self.threshold)
+            .verify_justification(justification, block_hash, header.number)?;
+
+        let number = header.number;
+        let authority_change = header.authority_change.clone();
+        let previous = self.finalized_hash().clone();
+        let mut current = block_hash.clone();
+        while current != previous {
+            let header = &self.headers[&current];
+            let parent = header.parent_hash.clone();
+            self.finalized_chain.insert(header.number, std::mem::replace(&mut current, parent));
+        }
+
+        let finalized_chain = &self.finalized_chain;
+        let headers = &self.headers;
+        let keep: HashSet<Hash> = headers.iter()
+            .filter(|(hash, header)| {
+                if header.number <= number {
+                    finalized_chain.get(&header.number) == Some(*hash)
+                } else {
+                    Self::ancestor_in(headers, hash, number).as_ref() == Some(block_hash)
+                }
+            })
+            .map(|(hash, _)| hash.clone())
+            .collect();
+        self.headers.retain(|hash, _| keep.contains(hash));
+        if !self.headers.contains_key(&self.best) || self.best_header().number < number {
+            self.best = self.headers.iter()
+                .max_by_key(|(_, header)| header.number)
+                .map(|(hash, _)| hash.clone())
+                .unwrap_or_else(|| block_hash.clone());
+        }
+
+        if let Some(set) = authority_change {
+            self.authorities = set;
+        }
+        Ok(())
+    }
+
+    /// Check an account proof against the state root of a finalized header
+    pub fn verify_account(&self, proof: &AccountProof, block_hash: &Hash) -> CoreResult<Option<AccountState>> {
+        let header = self.finalized(block_hash)?;
+        if !proof.verify(&header.state_root) {
+            return Err(CoreError::ValidationFailed("Account proof does not match the state root".to_string()));
+        }
+        Ok(proof.account.clone())
+    }
+
+    /// Balance of an account proven against a finalized header; zero if the account does not exist
+    pub fn verify_balance(&self, proof: &AccountProof, block_hash: &Hash) -> CoreResult<Balance> {
+        Ok(self.verify_account(proof, block_hash)?.map(|account| account.balance).unwrap_or(0))
+    }
+
+    /// Check that a transaction, such as an energy trade, is included in a finalized block
+    pub fn verify_transaction(&self, transaction: &Transaction, proof: &TransactionProof) -> CoreResult<()> {
+        let header = self.finalized(&proof.block_hash)?;
+        if header.number != proof.block_number
+            || !Blockchain::verify_transaction_proof(transaction, proof, &header.transactions_root)
+        {
+            return Err(CoreError::ValidationFailed("Transaction proof does not match the block".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Latest finalized header
+    pub fn finalized_header(&self) -> &BlockHeader {
+        &self.headers[self.finalized_hash()]
+    }
+
+    /// Highest synced header on top of the finalized one
+    pub fn best_header(&self) -> &BlockHeader {
+        &self.headers[&self.best]
+    }
+
+    /// Synced header by hash
+    pub fn header(&self, hash: &Hash) -> Option<&BlockHeader> {
+        self.headers.get(hash)
+    }
+
+    /// Whether a header is on the finalized chain
+    pub fn is_finalized(&self, hash: &Hash) -> bool {
+        self.headers.get(hash)
+            .is_some_and(|header| self.finalized_chain.get(&header.number) == Some(hash))
+    }
+
+    fn finalized(&self, hash: &Hash) -> CoreResult<&BlockHeader> {
+        if !self.is_finalized(hash) {
+            return Err(CoreError::ValidationFailed(format!("Header {} is not finalized", hash)));
+        }
+        Ok(&self.headers[hash])
+    }
+
+    fn finalized_hash(&self) -> &Hash {
+        self.finalized_chain.values().next_back().expect("checkpoint is finalized")
+    }
+
+    fn ancestor_at(&self, hash: &Hash, number: BlockNumber) -> Option<Hash> {
+        Self::ancestor_in(&self.headers, hash, number)
+    }
+
+    fn ancestor_in(headers: &HashMap<Hash, BlockHeader>, hash: &Hash, number: BlockNumber) -> Option<Hash> {
+        let mut current = hash.clone();
+        loop {
+            let header = headers.get(&current)?;
+            if header.number == number {
+                return Some(current);
+            }
+            if header.number < number {
+                return None;
+            }
+            current = header.parent_hash.clone();
+        }
+    }
+
+    /// Newest authority set change announced after the finalized header, up to and including `hash`
+    fn pending_authority_change(&self, hash: &Hash) -> Option<(BlockNumber, &AuthoritySet)> {
+        let finalized_number = self.finalized_header().number;
+        let mut header = self.headers.get(hash)?;
+        while header.number > finalized_number {
+            if let Some(set) = &header.authority_change {
+                return Some((header.number, set));
+            }
+            header = self.headers.get(&header.parent_hash)?;
+        }
+        None
+    }
+
+    fn latest_set_id(&self, hash: &Hash) -> u64 {
+        self.pending_authority_change(hash)
+            .map(|(_, set)| set.set_id)
+            .unwrap_or(self.authorities.set_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::blockchain::{finality_justification, Block, JustificationType, TransactionType, ValidatorSignature};
+    use crate::core::finality::{finality_payload, sign_finality_vote, Authority};
+    use crate::utils::crypto;
+
+    fn account(name: &str) -> AccountId {
+        crypto::dev_account_id(name)
+    }
+
+    fn vote(block: &Block, set_id: u64, name: &str) -> ValidatorSignature {
+        let (private_key, public_key) = crypto::keypair_from_seed(name);
+        sign_finality_vote(&block.hash(), block.header.number, set_id, &private_key, &public_key).unwrap()
+    }
+
+    #[test]
+    fn test_light_client_follows_finality_and_authority_changes() {
+        let mut config = SystemConfig::default();
+        config.chain_spec.genesis.accounts.insert(account("alice"), 1_000);
+        for name in ["validator1", "validator2", "validator3"] {
+            config.chain_spec.genesis.accounts.insert(account(name), 100);
+            config.chain_spec.genesis.validators.push(account(name));
+        }
+        let mut full = Blockchain::new(config.clone());
+        let genesis = full.get_block_by_number(0).unwrap().header.clone();
+        let mut light = LightClient::new(genesis, AuthoritySet::from_genesis(&config.chain_spec.genesis), full.finality.threshold);
+
+        // Block 1 carries a trade and hands finality to validator4
+        let mut trade = Transaction {
+            hash: String::new(),
+            from: account("alice"),
+            to: account("bob"),
+            amount: 100,
+            transaction_type: TransactionType::EnergyTrade,
+            data: Vec::new(),
+            fee: 1,
+            nonce: 0,
+            block_number: None,
+            timestamp: 0,
+            signature: None,
+        };
+        trade.sign(&crypto::keypair_from_seed("alice").0).unwrap();
+        full.add_transaction(trade.clone()).unwrap();
+        full.schedule_authority_change(vec![Authority { id: account("validator4"), weight: 10 }]);
+        let first = full.mine_block(account("validator1")).unwrap();
+        let second = full.mine_block(account("validator2")).unwrap();
+        light.import_header(first.header.clone()).unwrap();
+        light.import_header(second.header.clone()).unwrap();
+        assert_eq!(light.best_header().number, 2);
+
+        // The outgoing set cannot finalize past the change
+        let stale = Justification {
+            justification_type: JustificationType::Finality,
+            proof: finality_payload(&second.hash(), 2, 0),
+            signatures: ["validator1", "validator2", "validator3"].map(|name| vote(&second, 0, name)).to_vec(),
+        };
+        assert!(light.import_justification(&second.hash(), &stale).is_err());
+
+        for name in ["validator1", "validator2"] {
+            full.submit_finality_vote(&first.hash(), vote(&first, 0, name)).unwrap();
+        }
+        let justification = finality_justification(full.get_block(&first.hash()).unwrap()).unwrap();
+        light.import_justification(&first.hash(), justification).unwrap();
+        assert_eq!(light.authorities, full.finality.authorities);
+        assert_eq!(light.authorities.set_id, 1);
+
+        // From now on only the new set finalizes
+        assert!(light.import_justification(&second.hash(), &stale).is_err());
+        assert_eq!(full.submit_finality_vote(&second.hash(), vote(&second, 1, "validator4")), Ok(true));
+        let justification = finality_justification(full.get_block(&second.hash()).unwrap()).unwrap();
+        light.import_justification(&second.hash(), justification).unwrap();
+        assert_eq!(light.finalized_header(), &second.header);
+
+        // Balances and trade inclusion are checked against finalized headers only
+        let mut proof = full.prove_account(&account("bob")).unwrap();
+        assert_eq!(light.verify_balance(&proof, &second.hash()), Ok(100));
+        proof.account.as_mut().unwrap().balance = 1_000;
+        assert!(light.verify_balance(&proof, &second.hash()).is_err());
+
+        let inclusion = full.get_transaction_proof(&trade.hash).unwrap();
+        assert_eq!(light.verify_transaction(&trade, &inclusion), Ok(()));
+        let mut forged = trade.clone();
+        forged.amount = 1_000;
+        assert!(light.verify_transaction(&forged, &inclusion).is_err());
+    }
+}
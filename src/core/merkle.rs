@@ -0,0 +1,158 @@
+// Binary Merkle tree
+// This module builds transaction roots and inclusion proofs
+
+use crate::primitives::*;
+use crate::utils::crypto::{hash_from_hex, hash_to_hex, sha256};
+use serde::{Deserialize, Serialize};
+
+/// Domain separation prefix for leaf hashes
+const LEAF_PREFIX: u8 = 0x00;
+
+/// Domain separation prefix for inner node hashes
+const NODE_PREFIX: u8 = 0x01;
+
+/// Root of a tree without leaves
+pub const EMPTY_ROOT: [u8; 32] = [0u8; 32];
+
+/// Sibling hash on the path from a leaf to the root
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProofStep {
+    /// Sibling hash
+    pub hash: Hash,
+    /// Whether the sibling is the left child
+    pub is_left: bool,
+}
+
+/// Merkle inclusion proof
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MerkleProof {
+    /// Index of the leaf
+    pub leaf_index: u64,
+    /// Number of leaves in the tree
+    pub leaf_count: u64,
+    /// Sibling hashes from the leaf up to the root
+    pub path: Vec<ProofStep>,
+}
+
+/// Hash raw leaf data
+pub fn leaf_hash(data: &[u8]) -> [u8; 32] {
+    let mut bytes = Vec::with_capacity(data.len() + 1);
+    bytes.push(LEAF_PREFIX);
+    bytes.extend_from_slice(data);
+    sha256(&bytes)
+}
+
+/// Hash two child nodes
+pub fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut bytes = Vec::with_capacity(65);
+    bytes.push(NODE_PREFIX);
+    bytes.extend_from_slice(left);
+    bytes.extend_from_slice(right);
+    sha256(&bytes)
+}
+
+/// Compute the root over already hashed leaves.
+///
+/// An unpaired node at the end of a level is carried up unchanged rather than
+/// duplicated, so two different leaf lists never share a root.
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return EMPTY_ROOT;
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Build an inclusion proof for the leaf at `index`
+pub fn merkle_proof(leaves: &[[u8; 32]], index: usize) -> Option<MerkleProof> {
+    if index >= leaves.len() {
+        return None;
+    }
+
+    let mut path = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut position = index;
+    while level.len() > 1 {
+        let sibling = position ^ 1;
+        if sibling < level.len() {
+            path.push(ProofStep {
+                hash: hash_to_hex(&level[sibling]),
+                is_left: sibling < position,
+            });
+        }
+        level = next_level(&level);
+        position /= 2;
+    }
+
+    Some(MerkleProof {
+        leaf_index: index as u64,
+        leaf_count: leaves.len() as u64,
+        path,
+    })
+}
+
+/// Check that `leaf` is included under `root`
+pub fn verify_merkle_proof(leaf: &[u8; 32], proof: &MerkleProof, root: &[u8; 32]) -> bool {
+    if proof.leaf_index >= proof.leaf_count {
+        return false;
+    }
+
+    let mut current = *leaf;
+    for step in &proof.path {
+        let sibling = match hash_from_hex(&step.hash) {
+            Some(sibling) => sibling,
+            None => return false,
+        };
+        current = if step.is_left {
+            node_hash(&sibling, &current)
+        } else {
+            node_hash(&current, &sibling)
+        };
+    }
+    current == *root
+}
+
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: u8) -> Vec<[u8; 32]> {
+        (0..count).map(|i| leaf_hash(&[i])).collect()
+    }
+
+    #[test]
+    fn test_every_leaf_has_valid_proof() {
+        for count in 1..=9 {
+            let leaves = leaves(count);
+            let root = merkle_root(&leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_proof(&leaves, index).unwrap();
+                assert!(verify_merkle_proof(leaf, &proof, &root), "count {} index {}", count, index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_proof_rejects_wrong_leaf() {
+        let leaves = leaves(5);
+        let root = merkle_root(&leaves);
+        let proof = merkle_proof(&leaves, 2).unwrap();
+        assert!(!verify_merkle_proof(&leaves[3], &proof, &root));
+        assert_eq!(merkle_root(&[]), EMPTY_ROOT);
+    }
+}
@@ -2,6 +2,34 @@
 // This module contains the fundamental blockchain operations and structures
 
 pub mod blockchain;
+pub mod chain_spec;
+pub mod codec;
+pub mod finality;
+pub mod fork_choice;
+pub mod index;
+pub mod light_client;
+pub mod merkle;
+pub mod offences;
+pub mod pbft;
+pub mod poa;
+pub mod snapshot;
+pub mod state_trie;
+pub mod storage;
+pub mod transaction_pool;
 
 // Re-export core types
 pub use blockchain::*;
+pub use chain_spec::*;
+pub use codec::*;
+pub use finality::*;
+pub use fork_choice::*;
+pub use index::*;
+pub use light_client::*;
+pub use merkle::*;
+pub use offences::*;
+pub use pbft::*;
+pub use poa::*;
+pub use snapshot::*;
+pub use state_trie::*;
+pub use storage::*;
+pub use transaction_pool::*;
@@ -0,0 +1,71 @@
+// Validator offences
+// This module describes the misbehaviour the chain detects on its own:
+// equivocating block seals or finality votes, and missing too many slots
+
+use crate::core::blockchain::{BlockHeader, ValidatorSignature};
+use crate::primitives::*;
+use serde::{Deserialize, Serialize};
+
+/// Kind of an offence, which decides the slashing penalty
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OffenceKind {
+    /// Signed two conflicting blocks or votes
+    Equivocation,
+    /// Missed too many assigned slots
+    Unresponsiveness,
+}
+
+/// Misbehaviour of a validator, with the evidence that proves it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Offence {
+    /// Two different blocks sealed by the same author for the same slot
+    BlockEquivocation {
+        /// Author of both blocks
+        offender: AccountId,
+        /// Slot both blocks claim
+        slot: u64,
+        /// Header seen first
+        first: BlockHeader,
+        /// Conflicting header
+        second: BlockHeader,
+    },
+    /// Finality votes for two different blocks at the same height
+    VoteEquivocation {
+        /// Authority that cast both votes
+        offender: AccountId,
+        /// Authority set the votes were cast in
+        set_id: u64,
+        /// Height of both blocks
+        number: BlockNumber,
+        /// Vote seen first and the block it is for
+        first: (Hash, ValidatorSignature),
+        /// Conflicting vote and the block it is for
+        second: (Hash, ValidatorSignature),
+    },
+    /// More slots missed in an era than allowed
+    Unresponsive {
+        /// Authority that missed the slots
+        offender: AccountId,
+        /// Slots missed
+        missed_slots: u64,
+    },
+}
+
+impl Offence {
+    /// Validator that committed the offence
+    pub fn offender(&self) -> &AccountId {
+        match self {
+            Offence::BlockEquivocation { offender, .. }
+            | Offence::VoteEquivocation { offender, .. }
+            | Offence::Unresponsive { offender, .. } => offender,
+        }
+    }
+
+    /// Kind of the offence
+    pub fn kind(&self) -> OffenceKind {
+        match self {
+            Offence::BlockEquivocation { .. } | Offence::VoteEquivocation { .. } => OffenceKind::Equivocation,
+            Offence::Unresponsive { .. } => OffenceKind::Unresponsiveness,
+        }
+    }
+}
@@ -0,0 +1,1034 @@
+// PBFT consensus engine
+// This module runs practical Byzantine fault tolerant agreement on blocks
+// among the validators, over a pluggable message transport
+
+use crate::core::blockchain::{Block, Blockchain, Justification, JustificationType, ValidatorSignature};
+use crate::core::codec::Encode;
+use crate::core::finality::{finality_payload, sign_finality_vote};
+use crate::primitives::*;
+use crate::utils::crypto;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Domain separation prefix of PBFT messages
+const PBFT_CONTEXT: &str = "ledger-pbft";
+
+/// Most finalized blocks returned for one catch-up request
+const MAX_BLOCKS_PER_RESPONSE: u64 = 64;
+
+/// Tuning of the PBFT engine, in ticks of the driving clock
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PbftConfig {
+    /// Ticks without progress before a replica asks for a new primary
+    pub view_timeout: u64,
+    /// Sequence numbers between checkpoints
+    pub checkpoint_interval: u64,
+    /// Sequence numbers accepted above the stable checkpoint
+    pub log_window: u64,
+}
+
+impl Default for PbftConfig {
+    fn default() -> Self {
+        Self {
+            view_timeout: constants::PBFT_VIEW_TIMEOUT,
+            checkpoint_interval: constants::PBFT_CHECKPOINT_INTERVAL,
+            log_window: constants::PBFT_LOG_WINDOW,
+        }
+    }
+}
+
+/// Content of a PBFT message. Sequence numbers are block numbers.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum PbftPayload {
+    /// Primary proposes a block for a sequence number
+    PrePrepare {
+        view: u64,
+        sequence: BlockNumber,
+        digest: Hash,
+        block: Block,
+    },
+    /// Backup accepted the primary's proposal
+    Prepare {
+        view: u64,
+        sequence: BlockNumber,
+        digest: Hash,
+    },
+    /// Replica saw the proposal prepared by a quorum; carries its finality vote
+    Commit {
+        view: u64,
+        sequence: BlockNumber,
+        digest: Hash,
+        vote: ValidatorSignature,
+    },
+    /// State root a replica reached after finalizing a sequence number
+    Checkpoint {
+        sequence: BlockNumber,
+        state_root: Hash,
+    },
+    /// Replica asks to move to `new_view`
+    ViewChange {
+        new_view: u64,
+        stable_checkpoint: BlockNumber,
+        prepared: Option<PreparedCertificate>,
+    },
+    /// New primary starts `view`, proving it with a quorum of view changes
+    NewView {
+        view: u64,
+        view_changes: Vec<PbftMessage>,
+        pre_prepare: Option<Box<PbftMessage>>,
+    },
+    /// Lagging replica asks for finalized blocks from a height on
+    BlockRequest { from: BlockNumber },
+    /// Finalized blocks with their justifications
+    BlockResponse { blocks: Vec<Block> },
+}
+
+/// Proof that a block prepared: its pre-prepare and a quorum of prepares
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PreparedCertificate {
+    /// Primary's proposal
+    pub pre_prepare: Box<PbftMessage>,
+    /// Matching prepares of the backups
+    pub prepares: Vec<PbftMessage>,
+}
+
+/// PBFT message signed by its sender
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PbftMessage {
+    /// Sending replica
+    pub sender: AccountId,
+    /// Message content
+    pub payload: PbftPayload,
+    /// Sender's signature over the signing payload
+    pub signature: String,
+}
+
+impl PbftPayload {
+    /// Canonical bytes covered by the sender's signature.
+    ///
+    /// Nested messages are covered through their own signatures and blocks
+    /// through their hashes.
+    pub fn signing_payload(&self, sender: &AccountId) -> Vec<u8> {
+        let mut out = Vec::new();
+        PBFT_CONTEXT.encode_to(&mut out);
+        sender.encode_to(&mut out);
+        match self {
+            PbftPayload::PrePrepare { view, sequence, digest, .. } => {
+                0u8.encode_to(&mut out);
+                view.encode_to(&mut out);
+                sequence.encode_to(&mut out);
+                digest.encode_to(&mut out);
+            }
+            PbftPayload::Prepare { view, sequence, digest } => {
+                1u8.encode_to(&mut out);
+                view.encode_to(&mut out);
+                sequence.encode_to(&mut out);
+                digest.encode_to(&mut out);
+            }
+            PbftPayload::Commit { view, sequence, digest, vote } => {
+                2u8.encode_to(&mut out);
+                view.encode_to(&mut out);
+                sequence.encode_to(&mut out);
+                digest.encode_to(&mut out);
+                vote.signature.encode_to(&mut out);
+            }
+            PbftPayload::Checkpoint { sequence, state_root } => {
+                3u8.encode_to(&mut out);
+                sequence.encode_to(&mut out);
+                state_root.encode_to(&mut out);
+            }
+            PbftPayload::ViewChange { new_view, stable_checkpoint, prepared } => {
+                4u8.encode_to(&mut out);
+                new_view.encode_to(&mut out);
+                stable_checkpoint.encode_to(&mut out);
+                prepared.as_ref().map(|c| &c.pre_prepare.signature).encode_to(&mut out);
+                let prepares: Vec<&String> = prepared.iter().flat_map(|c| &c.prepares).map(|m| &m.signature).collect();
+                prepares.encode_to(&mut out);
+            }
+            PbftPayload::NewView { view, view_changes, pre_prepare } => {
+                5u8.encode_to(&mut out);
+                view.encode_to(&mut out);
+                let signatures: Vec<&String> = view_changes.iter().map(|m| &m.signature).collect();
+                signatures.encode_to(&mut out);
+                pre_prepare.as_ref().map(|m| &m.signature).encode_to(&mut out);
+            }
+            PbftPayload::BlockRequest { from } => {
+                6u8.encode_to(&mut out);
+                from.encode_to(&mut out);
+            }
+            PbftPayload::BlockResponse { blocks } => {
+                7u8.encode_to(&mut out);
+                let hashes: Vec<Hash> = blocks.iter().map(|b| b.hash()).collect();
+                hashes.encode_to(&mut out);
+            }
+        }
+        out
+    }
+
+    /// View, sequence number and digest of a pre-prepare, prepare or commit
+    fn position(&self) -> Option<(u64, BlockNumber, &Hash)> {
+        match self {
+            PbftPayload::PrePrepare { view, sequence, digest, .. }
+            | PbftPayload::Prepare { view, sequence, digest }
+            | PbftPayload::Commit { view, sequence, digest, .. } => Some((*view, *sequence, digest)),
+            _ => None,
+        }
+    }
+}
+
+impl PbftMessage {
+    /// Sign a payload with the sender's hex-encoded private key
+    pub fn sign(sender: AccountId, payload: PbftPayload, private_key: &str) -> CoreResult<Self> {
+        let signature = crypto::sign_message(&payload.signing_payload(&sender), private_key)?;
+        Ok(Self { sender, payload, signature })
+    }
+
+    /// Check the sender's signature
+    pub fn verify(&self) -> bool {
+        crypto::verify_signature(&self.payload.signing_payload(&self.sender), &self.signature, &self.sender)
+    }
+}
+
+/// Delivers PBFT messages between replicas
+pub trait PbftTransport {
+    /// Send a message to one replica
+    fn send(&mut self, to: &AccountId, message: PbftMessage);
+    /// Send a message to every replica except its sender
+    fn broadcast(&mut self, message: PbftMessage);
+}
+
+/// In-process transport for running several replicas together.
+///
+/// Messages are handed out when the network is advanced. Links can lose
+/// every message or hold messages back for a number of ticks.
+#[derive(Debug, Clone, Default)]
+pub struct LocalNetwork {
+    /// Replicas reachable through the network
+    pub replicas: Vec<AccountId>,
+    /// Current tick
+    pub now: u64,
+    /// Messages lost on dropping links so far
+    pub dropped: u64,
+    /// Undelivered messages with their delivery tick and recipient
+    in_flight: Vec<(u64, AccountId, PbftMessage)>,
+    /// Links (sender, recipient) that lose every message
+    dropped_links: HashSet<(AccountId, AccountId)>,
+    /// Extra ticks messages spend on a link
+    delays: HashMap<(AccountId, AccountId), u64>,
+}
+
+impl LocalNetwork {
+    /// Create a network connecting `replicas`
+    pub fn new(replicas: Vec<AccountId>) -> Self {
+        Self {
+            replicas,
+            ..Self::default()
+        }
+    }
+
+    /// Lose every message sent from `from` to `to`
+    pub fn drop_link(&mut self, from: &AccountId, to: &AccountId) {
+        self.dropped_links.insert((from.clone(), to.clone()));
+    }
+
+    /// Cut a replica off from all others in both directions
+    pub fn isolate(&mut self, replica: &AccountId) {
+        for other in self.replicas.clone() {
+            if &other != replica {
+                self.drop_link(replica, &other);
+                self.drop_link(&other, replica);
+            }
+        }
+    }
+
+    /// Delay every message sent from `from` to `to` by `ticks`
+    pub fn set_delay(&mut self, from: &AccountId, to: &AccountId, ticks: u64) {
+        self.delays.insert((from.clone(), to.clone()), ticks);
+    }
+
+    /// Remove all drops and delays
+    pub fn heal(&mut self) {
+        self.dropped_links.clear();
+        self.delays.clear();
+    }
+
+    /// Move to the next tick and return the messages due, in sending order
+    pub fn advance(&mut self) -> Vec<(AccountId, PbftMessage)> {
+        self.now += 1;
+        let now = self.now;
+        let (due, pending) = std::mem::take(&mut self.in_flight).into_iter().partition(|(at, _, _)| *at <= now);
+        self.in_flight = pending;
+        due.into_iter().map(|(_, to, message)| (to, message)).collect()
+    }
+
+    /// Number of messages still travelling
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    fn enqueue(&mut self, to: &AccountId, message: PbftMessage) {
+        let link = (message.sender.clone(), to.clone());
+        if self.dropped_links.contains(&link) {
+            self.dropped += 1;
+            return;
+        }
+        let delay = self.delays.get(&link).copied().unwrap_or(0);
+        self.in_flight.push((self.now + 1 + delay, to.clone(), message));
+    }
+}
+
+impl PbftTransport for LocalNetwork {
+    fn send(&mut self, to: &AccountId, message: PbftMessage) {
+        self.enqueue(to, message);
+    }
+
+    fn broadcast(&mut self, message: PbftMessage) {
+        for to in self.replicas.clone() {
+            if to != message.sender {
+                self.enqueue(&to, message.clone());
+            }
+        }
+    }
+}
+
+/// Messages collected for one sequence number
+#[derive(Debug, Clone, Default)]
+struct SequenceLog {
+    /// Accepted proposal, from the latest view that proposed this sequence
+    pre_prepare: Option<PbftMessage>,
+    /// Prepares of any view
+    prepares: Vec<PbftMessage>,
+    /// Commits of any view
+    commits: Vec<PbftMessage>,
+    /// View in which this replica sent its own commit
+    committed_view: Option<u64>,
+}
+
+/// PBFT replica driving the finalization of its blockchain.
+///
+/// The primary of view `v` is `validators[v % n]`. One block is agreed at a
+/// time: the primary proposes block `finalized + 1` once the previous one
+/// is final. A block is finalized with a justification made of the
+/// finality votes carried by a quorum of commits, so followers can verify
+/// it like any other finality justification.
+pub struct PbftReplica {
+    /// Replica identity, which is also its validator account
+    pub id: AccountId,
+    /// Private key signing this replica's messages
+    private_key: String,
+    /// Chain the replicas agree on
+    pub blockchain: Blockchain,
+    /// Replicas in primary rotation order
+    pub validators: Vec<AccountId>,
+    /// Engine tuning
+    pub config: PbftConfig,
+    /// Current view
+    pub view: u64,
+    /// View this replica is moving to while a view change is under way
+    pub pending_view: Option<u64>,
+    /// Highest sequence number with a quorum of matching checkpoints
+    pub stable_checkpoint: BlockNumber,
+    /// Protocol messages by sequence number, above the stable checkpoint
+    log: BTreeMap<BlockNumber, SequenceLog>,
+    /// Checkpoint messages by sequence number
+    checkpoints: BTreeMap<BlockNumber, Vec<PbftMessage>>,
+    /// View change messages by target view
+    view_changes: BTreeMap<u64, Vec<PbftMessage>>,
+    /// New view message that started the current view, replayed to laggards
+    last_new_view: Option<PbftMessage>,
+    /// Ticks since the last finalized block or view change
+    idle_ticks: u64,
+}
+
+impl PbftReplica {
+    /// Create a replica for a chain configured with `ConsensusAlgorithm::PBFT`
+    pub fn new(blockchain: Blockchain, private_key: String, config: PbftConfig) -> CoreResult<Self> {
+        if blockchain.config.consensus_config.algorithm != ConsensusAlgorithm::PBFT {
+            return Err(CoreError::ValidationFailed("Chain is not configured for PBFT".to_string()));
+        }
+        let id = crypto::public_key_from_private(&private_key)
+            .ok_or_else(|| CoreError::ValidationFailed("Malformed private key".to_string()))?;
+        let validators = blockchain.config.chain_spec.genesis.validators.clone();
+        if !validators.contains(&id) {
+            return Err(CoreError::ValidationFailed(format!("{} is not a validator", id)));
+        }
+
+        Ok(Self {
+            id,
+            private_key,
+            stable_checkpoint: blockchain.state.finalized_block.header.number,
+            blockchain,
+            validators,
+            config,
+            view: 0,
+            pending_view: None,
+            log: BTreeMap::new(),
+            checkpoints: BTreeMap::new(),
+            view_changes: BTreeMap::new(),
+            last_new_view: None,
+            idle_ticks: 0,
+        })
+    }
+
+    /// Number of faulty replicas tolerated
+    pub fn max_faulty(&self) -> usize {
+        (self.validators.len() - 1) / 3
+    }
+
+    /// Number of matching messages forming a quorum
+    pub fn quorum(&self) -> usize {
+        self.validators.len() - self.max_faulty()
+    }
+
+    /// Primary of a view
+    pub fn primary(&self, view: u64) -> &AccountId {
+        &self.validators[(view % self.validators.len() as u64) as usize]
+    }
+
+    /// Whether this replica is the primary of the view it is in
+    pub fn is_primary(&self) -> bool {
+        self.pending_view.is_none() && self.primary(self.view) == &self.id
+    }
+
+    /// Height of the last finalized block
+    pub fn finalized_number(&self) -> BlockNumber {
+        self.blockchain.state.finalized_block.header.number
+    }
+
+    /// Advance the replica's clock by one tick.
+    ///
+    /// The primary proposes a block when it has transactions and nothing is
+    /// in flight. Any replica that waits too long for progress starts a view
+    /// change, escalating to later views if that stalls too.
+    pub fn tick(&mut self, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        self.idle_ticks += 1;
+        let next = self.finalized_number() + 1;
+        let in_flight = self.log.get(&next)
+            .and_then(|entry| entry.pre_prepare.as_ref())
+            .and_then(|message| message.payload.position())
+            .is_some_and(|(view, _, _)| view == self.view);
+        let has_transactions = self.blockchain.pool.stats().ready > 0;
+
+        if self.is_primary() && !in_flight && has_transactions && self.in_window(next) {
+            return self.propose(transport);
+        }
+        let waiting = self.pending_view.is_some() || in_flight || has_transactions;
+        if waiting && self.idle_ticks >= self.config.view_timeout {
+            let target = self.pending_view.unwrap_or(self.view) + 1;
+            self.start_view_change(target, transport)?;
+        }
+        Ok(())
+    }
+
+    /// Process a message delivered by the transport
+    pub fn handle_message(&mut self, message: PbftMessage, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        self.check_message(&message)?;
+        match &message.payload {
+            PbftPayload::PrePrepare { .. } => self.on_pre_prepare(message, transport),
+            PbftPayload::Prepare { .. } | PbftPayload::Commit { .. } => self.on_vote(message, transport),
+            PbftPayload::Checkpoint { .. } => self.on_checkpoint(message, transport),
+            PbftPayload::ViewChange { .. } => self.on_view_change(message, transport),
+            PbftPayload::NewView { .. } => self.on_new_view(message, transport),
+            PbftPayload::BlockRequest { from } => {
+                self.on_block_request(&message.sender, *from, transport);
+                Ok(())
+            }
+            PbftPayload::BlockResponse { blocks } => self.on_block_response(blocks.clone()),
+        }
+    }
+
+    /// Check that a message comes from a replica and carries its signature
+    fn check_message(&self, message: &PbftMessage) -> CoreResult<()> {
+        if !self.validators.contains(&message.sender) {
+            return Err(CoreError::ValidationFailed(format!("{} is not a replica", message.sender)));
+        }
+        if !message.verify() {
+            return Err(CoreError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    /// Sign a message as this replica
+    fn sign(&self, payload: PbftPayload) -> CoreResult<PbftMessage> {
+        PbftMessage::sign(self.id.clone(), payload, &self.private_key)
+    }
+
+    /// Whether a sequence number lies between the watermarks
+    fn in_window(&self, sequence: BlockNumber) -> bool {
+        sequence > self.stable_checkpoint && sequence <= self.stable_checkpoint + self.config.log_window
+    }
+
+    /// Build the next block on the finalized block and propose it
+    fn propose(&mut self, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        self.blockchain.revert_to_finalized()?;
+        let block = self.blockchain.mine_block(self.id.clone())?;
+        let sequence = block.header.number;
+        let message = self.sign(PbftPayload::PrePrepare {
+            view: self.view,
+            sequence,
+            digest: block.hash(),
+            block,
+        })?;
+        self.log.entry(sequence).or_default().pre_prepare = Some(message.clone());
+        transport.broadcast(message);
+        self.advance(sequence, transport)
+    }
+
+    /// Make a proposed block part of the local chain.
+    ///
+    /// Returns `false` if the replica is behind and cannot execute it yet.
+    fn accept_block(&mut self, block: &Block) -> CoreResult<bool> {
+        let number = block.header.number;
+        if number <= self.finalized_number() {
+            return Ok(self.blockchain.canonical_hash(number) == Some(block.hash()));
+        }
+        if number > self.finalized_number() + 1 {
+            return Ok(false);
+        }
+        self.blockchain.import_block(block.clone())?;
+        Ok(true)
+    }
+
+    fn on_pre_prepare(&mut self, message: PbftMessage, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        let PbftPayload::PrePrepare { view, sequence, digest, block } = &message.payload else {
+            return Ok(());
+        };
+        let (view, sequence, digest) = (*view, *sequence, digest.clone());
+        if self.pending_view.is_some() || view != self.view || !self.in_window(sequence) {
+            return Ok(());
+        }
+        if &message.sender != self.primary(view) {
+            return Err(CoreError::ValidationFailed("Pre-prepare from a replica that is not the primary".to_string()));
+        }
+        if block.hash() != digest || block.header.number != sequence {
+            return Err(CoreError::ValidationFailed("Pre-prepare digest does not match its block".to_string()));
+        }
+
+        // Only the first proposal of a view counts for a sequence number
+        let proposed_view = self.log.get(&sequence)
+            .and_then(|entry| entry.pre_prepare.as_ref())
+            .and_then(|existing| existing.payload.position())
+            .map(|(existing_view, _, _)| existing_view);
+        if proposed_view == Some(view) {
+            return Ok(());
+        }
+        if !self.accept_block(block)? {
+            let primary = message.sender.clone();
+            self.request_blocks(&primary, transport);
+            return Ok(());
+        }
+
+        let prepare = self.sign(PbftPayload::Prepare { view, sequence, digest })?;
+        let entry = self.log.entry(sequence).or_default();
+        entry.pre_prepare = Some(message);
+        entry.prepares.push(prepare.clone());
+        transport.broadcast(prepare);
+        self.advance(sequence, transport)
+    }
+
+    fn on_vote(&mut self, message: PbftMessage, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        let Some((view, sequence, digest)) = message.payload.position() else {
+            return Ok(());
+        };
+        if !self.in_window(sequence) {
+            return Ok(());
+        }
+        match &message.payload {
+            PbftPayload::Prepare { .. } if &message.sender == self.primary(view) => {
+                return Err(CoreError::ValidationFailed("The primary does not send prepares".to_string()));
+            }
+            PbftPayload::Commit { vote, .. } => {
+                if vote.validator != message.sender {
+                    return Err(CoreError::InvalidSignature);
+                }
+                self.blockchain.finality.check_vote(digest, sequence, vote)?;
+            }
+            _ => {}
+        }
+
+        let entry = self.log.entry(sequence).or_default();
+        let votes = match message.payload {
+            PbftPayload::Prepare { .. } => &mut entry.prepares,
+            _ => &mut entry.commits,
+        };
+        let seen = votes.iter().any(|v| {
+            v.sender == message.sender && v.payload.position().map(|(v, _, _)| v) == Some(view)
+        });
+        if !seen {
+            votes.push(message);
+        }
+        self.advance(sequence, transport)
+    }
+
+    /// Move a sequence number through prepared and committed as far as its messages allow
+    fn advance(&mut self, sequence: BlockNumber, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        let Some(entry) = self.log.get(&sequence) else {
+            return Ok(());
+        };
+        let Some((view, _, digest)) = entry.pre_prepare.as_ref().and_then(|m| m.payload.position()) else {
+            return Ok(());
+        };
+        let digest = digest.clone();
+        let matches = |message: &PbftMessage| message.payload.position() == Some((view, sequence, &digest));
+
+        // Prepared: the pre-prepare plus matching prepares form a quorum
+        if entry.prepares.iter().filter(|m| matches(m)).count() + 1 < self.quorum() {
+            return Ok(());
+        }
+        if entry.committed_view != Some(view) && self.pending_view.is_none() {
+            let set_id = self.blockchain.finality.authorities.set_id;
+            let vote = sign_finality_vote(&digest, sequence, set_id, &self.private_key, &self.id)?;
+            let commit = self.sign(PbftPayload::Commit { view, sequence, digest: digest.clone(), vote })?;
+            let entry = self.log.entry(sequence).or_default();
+            entry.committed_view = Some(view);
+            entry.commits.push(commit.clone());
+            transport.broadcast(commit);
+        }
+
+        // Committed: a quorum of matching commits
+        let votes: Vec<ValidatorSignature> = self.log[&sequence].commits.iter()
+            .filter(|m| matches(m))
+            .filter_map(|m| match &m.payload {
+                PbftPayload::Commit { vote, .. } => Some(vote.clone()),
+                _ => None,
+            })
+            .collect();
+        if votes.len() < self.quorum() || self.blockchain.is_finalized(&digest) {
+            return Ok(());
+        }
+        let set_id = self.blockchain.finality.authorities.set_id;
+        let justification = Justification {
+            justification_type: JustificationType::Finality,
+            proof: finality_payload(&digest, sequence, set_id),
+            signatures: votes,
+        };
+        self.blockchain.import_justification(&digest, justification)?;
+        self.idle_ticks = 0;
+
+        if sequence.is_multiple_of(self.config.checkpoint_interval.max(1)) {
+            let state_root = self.blockchain.state.finalized_block.header.state_root.clone();
+            let checkpoint = self.sign(PbftPayload::Checkpoint { sequence, state_root })?;
+            transport.broadcast(checkpoint.clone());
+            self.on_checkpoint(checkpoint, transport)?;
+        }
+        Ok(())
+    }
+
+    fn on_checkpoint(&mut self, message: PbftMessage, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        let PbftPayload::Checkpoint { sequence, .. } = message.payload else {
+            return Ok(());
+        };
+        if sequence <= self.stable_checkpoint {
+            return Ok(());
+        }
+        let sender = message.sender.clone();
+        let quorum = self.quorum();
+        let checkpoints = self.checkpoints.entry(sequence).or_default();
+        if checkpoints.iter().any(|c| c.sender == sender) {
+            return Ok(());
+        }
+        checkpoints.push(message);
+
+        let mut roots: HashMap<&Hash, usize> = HashMap::new();
+        for checkpoint in checkpoints.iter() {
+            if let PbftPayload::Checkpoint { state_root, .. } = &checkpoint.payload {
+                *roots.entry(state_root).or_insert(0) += 1;
+            }
+        }
+        if roots.values().all(|count| *count < quorum) {
+            return Ok(());
+        }
+
+        // Stable: forget everything at or below it and move the watermarks
+        self.stable_checkpoint = sequence;
+        self.log.retain(|s, _| *s > sequence);
+        self.checkpoints.retain(|s, _| *s > sequence);
+        if sequence > self.finalized_number() {
+            self.request_blocks(&sender, transport);
+        }
+        Ok(())
+    }
+
+    /// Ask another replica for the finalized blocks this replica is missing
+    fn request_blocks(&self, from: &AccountId, transport: &mut dyn PbftTransport) {
+        if from == &self.id {
+            return;
+        }
+        if let Ok(request) = self.sign(PbftPayload::BlockRequest { from: self.finalized_number() + 1 }) {
+            transport.send(from, request);
+        }
+    }
+
+    fn on_block_request(&self, requester: &AccountId, from: BlockNumber, transport: &mut dyn PbftTransport) {
+        let to = self.finalized_number().min(from.saturating_add(MAX_BLOCKS_PER_RESPONSE - 1));
+        let blocks: Vec<Block> = (from.max(1)..=to)
+            .filter_map(|number| self.blockchain.canonical_hash(number))
+            .filter_map(|hash| self.blockchain.get_block(&hash).cloned())
+            .collect();
+        if blocks.is_empty() {
+            return;
+        }
+        if let Ok(response) = self.sign(PbftPayload::BlockResponse { blocks }) {
+            transport.send(requester, response);
+        }
+    }
+
+    /// Import finalized blocks; their justifications finalize them
+    fn on_block_response(&mut self, blocks: Vec<Block>) -> CoreResult<()> {
+        for block in blocks {
+            if block.header.number > self.finalized_number() {
+                self.blockchain.import_block(block)?;
+                self.idle_ticks = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Highest prepared proposal above the stable checkpoint, with its proof
+    fn prepared_certificate(&self) -> Option<PreparedCertificate> {
+        self.log.values().rev().find_map(|entry| {
+            let pre_prepare = entry.pre_prepare.as_ref()?;
+            let position = pre_prepare.payload.position()?;
+            let prepares: Vec<PbftMessage> = entry.prepares.iter()
+                .filter(|m| m.payload.position() == Some(position))
+                .take(self.quorum() - 1)
+                .cloned()
+                .collect();
+            (prepares.len() + 1 >= self.quorum()).then(|| PreparedCertificate {
+                pre_prepare: Box::new(pre_prepare.clone()),
+                prepares,
+            })
+        })
+    }
+
+    /// Check that a certificate proves its block prepared
+    fn check_certificate(&self, certificate: &PreparedCertificate) -> CoreResult<()> {
+        let pre_prepare = &certificate.pre_prepare;
+        self.check_message(pre_prepare)?;
+        let PbftPayload::PrePrepare { view, digest, block, .. } = &pre_prepare.payload else {
+            return Err(CoreError::ValidationFailed("Certificate without a pre-prepare".to_string()));
+        };
+        if &pre_prepare.sender != self.primary(*view) || &block.hash() != digest {
+            return Err(CoreError::ValidationFailed("Malformed pre-prepare in certificate".to_string()));
+        }
+
+        let position = pre_prepare.payload.position();
+        let mut senders = HashSet::new();
+        for prepare in &certificate.prepares {
+            self.check_message(prepare)?;
+            let is_prepare = matches!(prepare.payload, PbftPayload::Prepare { .. });
+            if !is_prepare || prepare.payload.position() != position || prepare.sender == pre_prepare.sender {
+                return Err(CoreError::ValidationFailed("Prepare does not match the certificate".to_string()));
+            }
+            senders.insert(&prepare.sender);
+        }
+        if senders.len() + 1 < self.quorum() {
+            return Err(CoreError::ValidationFailed("Certificate lacks a quorum of prepares".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Proposal a new primary must carry over: the prepared one with the highest
+    /// sequence number, then view, above every reported stable checkpoint
+    fn reproposal(view_changes: &[PbftMessage]) -> Option<(BlockNumber, Block)> {
+        let mut stable = 0;
+        let mut best: Option<(BlockNumber, u64, &Block)> = None;
+        for message in view_changes {
+            let PbftPayload::ViewChange { stable_checkpoint, prepared, .. } = &message.payload else {
+                continue;
+            };
+            stable = stable.max(*stable_checkpoint);
+            if let Some(PbftPayload::PrePrepare { view, sequence, block, .. }) =
+                prepared.as_ref().map(|c| &c.pre_prepare.payload)
+            {
+                if best.is_none_or(|(s, v, _)| (*sequence, *view) > (s, v)) {
+                    best = Some((*sequence, *view, block));
+                }
+            }
+        }
+        best.filter(|(sequence, _, _)| *sequence > stable)
+            .map(|(sequence, _, block)| (sequence, block.clone()))
+    }
+
+    /// Stop accepting proposals of the current view and ask for `new_view`
+    fn start_view_change(&mut self, new_view: u64, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        self.pending_view = Some(new_view);
+        self.idle_ticks = 0;
+        let message = self.sign(PbftPayload::ViewChange {
+            new_view,
+            stable_checkpoint: self.stable_checkpoint,
+            prepared: self.prepared_certificate(),
+        })?;
+        self.view_changes.entry(new_view).or_default().push(message.clone());
+        transport.broadcast(message);
+        self.try_new_view(new_view, transport)
+    }
+
+    fn on_view_change(&mut self, message: PbftMessage, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        let PbftPayload::ViewChange { new_view, stable_checkpoint, prepared } = &message.payload else {
+            return Ok(());
+        };
+        let new_view = *new_view;
+        if self.pending_view.is_none() {
+            // The sender may just be behind: send it the blocks finalized since
+            // its stable checkpoint and the message that started this view
+            self.on_block_request(&message.sender, stable_checkpoint + 1, transport);
+            if let Some(new_view_message) = self.last_new_view.clone() {
+                transport.send(&message.sender, new_view_message);
+            }
+        }
+        if new_view <= self.view {
+            return Ok(());
+        }
+        if let Some(certificate) = prepared {
+            self.check_certificate(certificate)?;
+        }
+
+        let view_changes = self.view_changes.entry(new_view).or_default();
+        if view_changes.iter().any(|m| m.sender == message.sender) {
+            return Ok(());
+        }
+        view_changes.push(message);
+
+        // f + 1 replicas include an honest one that timed out, so join them
+        let joined = self.view_changes[&new_view].len() > self.max_faulty();
+        if joined && new_view > self.pending_view.unwrap_or(self.view) {
+            return self.start_view_change(new_view, transport);
+        }
+        self.try_new_view(new_view, transport)
+    }
+
+    /// As primary of `new_view`, start it once a quorum asked for it
+    fn try_new_view(&mut self, new_view: u64, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        if self.primary(new_view) != &self.id || new_view <= self.view {
+            return Ok(());
+        }
+        let view_changes = self.view_changes.get(&new_view).cloned().unwrap_or_default();
+        if view_changes.len() < self.quorum() {
+            return Ok(());
+        }
+
+        let pre_prepare = match Self::reproposal(&view_changes) {
+            Some((sequence, block)) => Some(Box::new(self.sign(PbftPayload::PrePrepare {
+                view: new_view,
+                sequence,
+                digest: block.hash(),
+                block,
+            })?)),
+            None => None,
+        };
+        let message = self.sign(PbftPayload::NewView {
+            view: new_view,
+            view_changes,
+            pre_prepare: pre_prepare.clone(),
+        })?;
+        transport.broadcast(message.clone());
+        self.enter_view(new_view, message);
+
+        if let Some(pre_prepare) = pre_prepare {
+            if let PbftPayload::PrePrepare { sequence, block, .. } = &pre_prepare.payload {
+                let sequence = *sequence;
+                if self.in_window(sequence) && self.accept_block(block)? {
+                    self.log.entry(sequence).or_default().pre_prepare = Some(*pre_prepare);
+                    self.advance(sequence, transport)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn on_new_view(&mut self, message: PbftMessage, transport: &mut dyn PbftTransport) -> CoreResult<()> {
+        let PbftPayload::NewView { view, view_changes, pre_prepare } = &message.payload else {
+            return Ok(());
+        };
+        let view = *view;
+        // A replica that wanted to leave its view may rejoin it if the others stayed
+        if view < self.view || (view == self.view && self.pending_view.is_none()) {
+            return Ok(());
+        }
+        if &message.sender != self.primary(view) {
+            return Err(CoreError::ValidationFailed("New view from a replica that is not its primary".to_string()));
+        }
+
+        let mut senders = HashSet::new();
+        for view_change in view_changes {
+            self.check_message(view_change)?;
+            let PbftPayload::ViewChange { new_view, prepared, .. } = &view_change.payload else {
+                return Err(CoreError::ValidationFailed("New view carries a message that is not a view change".to_string()));
+            };
+            if *new_view != view {
+                return Err(CoreError::ValidationFailed("View change is for another view".to_string()));
+            }
+            if let Some(certificate) = prepared {
+                self.check_certificate(certificate)?;
+            }
+            senders.insert(&view_change.sender);
+        }
+        if senders.len() < self.quorum() {
+            return Err(CoreError::ValidationFailed("New view lacks a quorum of view changes".to_string()));
+        }
+
+        let expected = Self::reproposal(view_changes).map(|(sequence, block)| (view, sequence, block.hash()));
+        let carried = pre_prepare.as_ref()
+            .filter(|m| m.sender == message.sender && matches!(m.payload, PbftPayload::PrePrepare { .. }))
+            .and_then(|m| m.payload.position())
+            .map(|(view, sequence, digest)| (view, sequence, digest.clone()));
+        if expected != carried {
+            return Err(CoreError::ValidationFailed("New view does not carry over the prepared block".to_string()));
+        }
+
+        let pre_prepare = pre_prepare.clone();
+        self.enter_view(view, message);
+        match pre_prepare {
+            Some(pre_prepare) => self.handle_message(*pre_prepare, transport),
+            None => Ok(()),
+        }
+    }
+
+    /// Switch to a view, dropping blocks that were proposed but not finalized
+    fn enter_view(&mut self, view: u64, new_view_message: PbftMessage) {
+        self.view = view;
+        self.pending_view = None;
+        self.idle_ticks = 0;
+        self.view_changes.retain(|v, _| *v > view);
+        self.last_new_view = Some(new_view_message);
+        // Only the primary builds blocks, so a failed revert is retried when it next proposes
+        let _ = self.blockchain.revert_to_finalized();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::blockchain::{Transaction, TransactionType};
+
+    const REPLICAS: [&str; 4] = ["validator1", "validator2", "validator3", "validator4"];
+
+    fn account(name: &str) -> AccountId {
+        crypto::dev_account_id(name)
+    }
+
+    struct Cluster {
+        replicas: Vec<PbftReplica>,
+        network: LocalNetwork,
+    }
+
+    impl Cluster {
+        fn new(config: PbftConfig) -> Self {
+            let mut chain_config = SystemConfig::default();
+            chain_config.consensus_config.algorithm = ConsensusAlgorithm::PBFT;
+            chain_config.chain_spec.genesis.accounts.insert(account("alice"), 1_000);
+            chain_config.chain_spec.genesis.validators = REPLICAS.iter().map(|name| account(name)).collect();
+
+            let replicas: Vec<PbftReplica> = REPLICAS.iter()
+                .map(|name| {
+                    let blockchain = Blockchain::new(chain_config.clone());
+                    let private_key = crypto::keypair_from_seed(name).0;
+                    PbftReplica::new(blockchain, private_key, config.clone()).unwrap()
+                })
+                .collect();
+            let network = LocalNetwork::new(replicas.iter().map(|r| r.id.clone()).collect());
+            Self { replicas, network }
+        }
+
+        /// Clients send their transactions to every replica
+        fn submit(&mut self, nonce: u64) {
+            let mut tx = Transaction {
+                hash: String::new(),
+                from: account("alice"),
+                to: account("bob"),
+                amount: 10,
+                transaction_type: TransactionType::Transfer,
+                data: Vec::new(),
+                fee: 1,
+                nonce,
+                block_number: None,
+                timestamp: 0,
+                signature: None,
+            };
+            tx.sign(&crypto::keypair_from_seed("alice").0).unwrap();
+            for replica in &mut self.replicas {
+                replica.blockchain.add_transaction(tx.clone()).unwrap();
+            }
+        }
+
+        fn run(&mut self, ticks: u64) {
+            for _ in 0..ticks {
+                for (to, message) in self.network.advance() {
+                    let replica = self.replicas.iter_mut().find(|r| r.id == to).unwrap();
+                    let _ = replica.handle_message(message, &mut self.network);
+                }
+                for replica in &mut self.replicas {
+                    let _ = replica.tick(&mut self.network);
+                }
+            }
+        }
+
+        fn finalized(&self, index: usize) -> (BlockNumber, Hash) {
+            let block = &self.replicas[index].blockchain.state.finalized_block;
+            (block.header.number, block.hash())
+        }
+    }
+
+    #[test]
+    fn test_replicas_finalize_blocks_and_checkpoint() {
+        let mut cluster = Cluster::new(PbftConfig { checkpoint_interval: 2, ..PbftConfig::default() });
+        // A slow link does not stop the quorum
+        let (first, last) = (cluster.replicas[0].id.clone(), cluster.replicas[3].id.clone());
+        cluster.network.set_delay(&first, &last, 3);
+
+        cluster.submit(0);
+        cluster.run(10);
+        cluster.submit(1);
+        cluster.run(10);
+
+        let (number, hash) = cluster.finalized(0);
+        assert_eq!(number, 2);
+        for (index, replica) in cluster.replicas.iter().enumerate() {
+            assert_eq!(cluster.finalized(index), (number, hash.clone()));
+            assert_eq!(replica.view, 0);
+            assert_eq!(replica.stable_checkpoint, 2);
+            assert_eq!(replica.blockchain.get_balance(&account("bob")), 20);
+        }
+    }
+
+    #[test]
+    fn test_view_change_replaces_silent_primary() {
+        let mut cluster = Cluster::new(PbftConfig { view_timeout: 5, ..PbftConfig::default() });
+        let primary = cluster.replicas[0].id.clone();
+        cluster.network.isolate(&primary);
+
+        cluster.submit(0);
+        cluster.run(20);
+        for index in 1..4 {
+            assert_eq!(cluster.replicas[index].view, 1);
+            assert_eq!(cluster.finalized(index).0, 1);
+        }
+        assert_eq!(cluster.finalized(0).0, 0);
+
+        // Once reconnected, the old primary learns the new view and catches up
+        cluster.network.heal();
+        cluster.submit(1);
+        cluster.run(30);
+        let expected = cluster.finalized(1);
+        assert_eq!(expected.0, 2);
+        assert_eq!(cluster.finalized(0), expected);
+        assert_eq!(cluster.replicas[0].view, cluster.replicas[1].view);
+    }
+
+    #[test]
+    fn test_rejects_proposals_not_from_the_primary() {
+        let mut cluster = Cluster::new(PbftConfig::default());
+        let mut network = LocalNetwork::new(Vec::new());
+        let block = cluster.replicas[1].blockchain.state.best_block.clone();
+        let payload = PbftPayload::PrePrepare { view: 0, sequence: 1, digest: block.hash(), block };
+
+        let backup = cluster.replicas[1].sign(payload.clone()).unwrap();
+        assert!(cluster.replicas[2].handle_message(backup, &mut network).is_err());
+
+        let mut forged = cluster.replicas[1].sign(payload).unwrap();
+        forged.sender = cluster.replicas[0].id.clone();
+        assert_eq!(cluster.replicas[2].handle_message(forged, &mut network), Err(CoreError::InvalidSignature));
+        assert_eq!(network.in_flight(), 0);
+    }
+}
@@ -0,0 +1,262 @@
+// Proof-of-Authority block authorship
+// This module assigns block production slots to the genesis validators in
+// round-robin order and checks that blocks were sealed by the slot's author
+
+use crate::core::blockchain::{BlockHeader, Justification, JustificationType, ValidatorSignature};
+use crate::core::codec::Encode;
+use crate::core::offences::Offence;
+use crate::primitives::*;
+use crate::utils::crypto;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Domain separation prefix of block seals
+const SEAL_CONTEXT: &str = "ledger-seal";
+
+/// Round-robin slot schedule over a fixed authority set.
+///
+/// Time is divided into slots of `slot_duration` seconds and slot `n` belongs
+/// to `authorities[n % len]`. A block's slot is derived from its timestamp.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PoaAuthorship {
+    /// Authorities in rotation order
+    pub authorities: Vec<AccountId>,
+    /// Slot length in seconds
+    pub slot_duration: u64,
+    /// Slots each authority let pass without a block
+    pub missed_slots: HashMap<AccountId, u64>,
+    /// First header seen for each slot above the finalized block, to catch equivocation
+    pub sealed_slots: HashMap<u64, BlockHeader>,
+}
+
+impl PoaAuthorship {
+    /// Create a schedule; a zero slot duration is treated as one second
+    pub fn new(authorities: Vec<AccountId>, slot_duration: u64) -> Self {
+        Self {
+            authorities,
+            slot_duration: slot_duration.max(1),
+            missed_slots: HashMap::new(),
+            sealed_slots: HashMap::new(),
+        }
+    }
+
+    /// Schedule of the genesis validators with `block_time` slots
+    pub fn from_config(config: &SystemConfig) -> Self {
+        Self::new(
+            config.chain_spec.genesis.validators.clone(),
+            config.consensus_config.block_time,
+        )
+    }
+
+    /// Slot containing a timestamp
+    pub fn slot_at(&self, timestamp: Timestamp) -> u64 {
+        timestamp / self.slot_duration
+    }
+
+    /// Authority allowed to author a block in `slot`
+    pub fn expected_author(&self, slot: u64) -> Option<&AccountId> {
+        if self.authorities.is_empty() {
+            return None;
+        }
+        self.authorities.get((slot % self.authorities.len() as u64) as usize)
+    }
+
+    /// Check that `author` may build on `parent` at `timestamp`
+    pub fn check_slot(&self, author: &AccountId, parent: &BlockHeader, timestamp: Timestamp) -> CoreResult<()> {
+        let slot = self.slot_at(timestamp);
+        // Genesis has no author, so the first block may use any slot
+        if parent.number > 0 && slot <= self.slot_at(parent.timestamp) {
+            return Err(CoreError::ValidationFailed(format!("Slot {} is not after the parent's slot", slot)));
+        }
+        match self.expected_author(slot) {
+            Some(expected) if expected == author => Ok(()),
+            Some(expected) => Err(CoreError::ValidationFailed(format!(
+                "Slot {} belongs to {}, not {}",
+                slot, expected, author
+            ))),
+            None => Err(CoreError::ValidationFailed("No PoA authorities configured".to_string())),
+        }
+    }
+
+    /// Verify an imported header: slot, author and seal
+    pub fn verify_header(
+        &self,
+        header: &BlockHeader,
+        parent: &BlockHeader,
+        justifications: &[Justification],
+        now: Timestamp,
+    ) -> CoreResult<()> {
+        if self.slot_at(header.timestamp) > self.slot_at(now) {
+            return Err(CoreError::ValidationFailed("Block is from a future slot".to_string()));
+        }
+        self.check_slot(&header.author, parent, header.timestamp)?;
+
+        let seal = justifications.iter()
+            .find(|j| j.justification_type == JustificationType::Authority)
+            .and_then(|j| j.signatures.first())
+            .ok_or_else(|| CoreError::ValidationFailed("Block is not sealed".to_string()))?;
+        if seal.validator != header.author
+            || !crypto::verify_signature(&seal_payload(&header.hash()), &seal.signature, &header.author)
+        {
+            return Err(CoreError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    /// Record a block that passed `verify_header`.
+    ///
+    /// Counts the slots skipped between `parent` and `header` against their
+    /// authorities and returns an equivocation if the slot already has a
+    /// different block.
+    pub fn note_block(&mut self, header: &BlockHeader, parent: &BlockHeader) -> Option<Offence> {
+        let slot = self.slot_at(header.timestamp);
+        let offence = match self.sealed_slots.get(&slot) {
+            Some(first) if first.hash() != header.hash() => Some(Offence::BlockEquivocation {
+                offender: header.author.clone(),
+                slot,
+                first: first.clone(),
+                second: header.clone(),
+            }),
+            Some(_) => None,
+            None => {
+                self.sealed_slots.insert(slot, header.clone());
+                None
+            }
+        };
+        // A sibling of an earlier block must not count the same gap twice
+        if offence.is_none() {
+            self.note_skipped_slots(header, parent);
+        }
+        offence
+    }
+
+    fn note_skipped_slots(&mut self, header: &BlockHeader, parent: &BlockHeader) {
+        if parent.number == 0 {
+            return;
+        }
+        let len = self.authorities.len() as u64;
+        if len == 0 {
+            return;
+        }
+        let first = self.slot_at(parent.timestamp) + 1;
+        let skipped = self.slot_at(header.timestamp).saturating_sub(first);
+
+        // Count per authority rather than per slot, so a long outage stays cheap
+        let (full_rounds, remainder) = (skipped / len, skipped % len);
+        for offset in 0..len.min(skipped) {
+            if let Some(author) = self.expected_author(first + offset).cloned() {
+                let missed = full_rounds + u64::from(offset < remainder);
+                *self.missed_slots.entry(author).or_insert(0) += missed;
+            }
+        }
+    }
+
+    /// Slots an authority missed so far
+    pub fn missed_slots_of(&self, authority: &AccountId) -> u64 {
+        self.missed_slots.get(authority).copied().unwrap_or(0)
+    }
+
+    /// Report authorities that missed more than `max_missed_slots` and reset all counters
+    pub fn take_unresponsive(&mut self, max_missed_slots: u64) -> Vec<Offence> {
+        let mut offences: Vec<Offence> = self.missed_slots.drain()
+            .filter(|(_, missed)| *missed > max_missed_slots)
+            .map(|(offender, missed_slots)| Offence::Unresponsive { offender, missed_slots })
+            .collect();
+        offences.sort_by(|a, b| a.offender().cmp(b.offender()));
+        offences
+    }
+
+    /// Forget seen headers up to the slot of the finalized block
+    pub fn prune(&mut self, finalized_timestamp: Timestamp) {
+        let finalized_slot = self.slot_at(finalized_timestamp);
+        self.sealed_slots.retain(|slot, _| *slot > finalized_slot);
+    }
+}
+
+/// Message an author signs to seal a block
+pub fn seal_payload(header_hash: &Hash) -> Vec<u8> {
+    let mut out = Vec::new();
+    SEAL_CONTEXT.encode_to(&mut out);
+    header_hash.encode_to(&mut out);
+    out
+}
+
+/// Seal a header with the author's hex-encoded private key
+pub fn seal_header(header: &BlockHeader, private_key: &str) -> CoreResult<Justification> {
+    let hash = header.hash();
+    let signature = crypto::sign_message(&seal_payload(&hash), private_key)?;
+    Ok(Justification {
+        justification_type: JustificationType::Authority,
+        proof: hash.into_bytes(),
+        signatures: vec![ValidatorSignature {
+            validator: header.author.clone(),
+            signature,
+            timestamp: header.timestamp,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(number: BlockNumber, timestamp: Timestamp, author: &str) -> BlockHeader {
+        BlockHeader {
+            number,
+            parent_hash: String::new(),
+            state_root: String::new(),
+            transactions_root: String::new(),
+            timestamp,
+            author: author.to_string(),
+            difficulty: 1,
+            nonce: 0,
+            authority_change: None,
+        }
+    }
+
+    #[test]
+    fn test_round_robin_slots_and_missed_slots() {
+        let names = ["a", "b", "c"].map(String::from).to_vec();
+        let mut poa = PoaAuthorship::new(names, 6);
+        assert_eq!(poa.expected_author(poa.slot_at(61)), Some(&"b".to_string()));
+
+        let parent = header(1, 60, "b");
+        assert!(poa.check_slot(&"c".to_string(), &parent, 66).is_ok());
+        assert!(poa.check_slot(&"a".to_string(), &parent, 66).is_err());
+        // Same slot as the parent
+        assert!(poa.check_slot(&"b".to_string(), &parent, 65).is_err());
+
+        // Slots 11 (c) and 12 (a) pass without a block before b authors slot 13
+        poa.note_block(&header(2, 78, "b"), &parent);
+        assert_eq!(poa.missed_slots_of(&"c".to_string()), 1);
+        assert_eq!(poa.missed_slots_of(&"a".to_string()), 1);
+        assert_eq!(poa.missed_slots_of(&"b".to_string()), 0);
+
+        let offences = poa.take_unresponsive(0);
+        assert_eq!(offences.len(), 2);
+        assert_eq!(offences[0], Offence::Unresponsive { offender: "a".to_string(), missed_slots: 1 });
+        assert_eq!(poa.missed_slots_of(&"a".to_string()), 0);
+    }
+
+    #[test]
+    fn test_second_block_in_slot_is_equivocation() {
+        let mut poa = PoaAuthorship::new(["a", "b"].map(String::from).to_vec(), 6);
+        let parent = header(1, 60, "a");
+        let first = header(2, 66, "b");
+        let mut second = first.clone();
+        second.nonce = 1;
+
+        assert!(poa.note_block(&first, &parent).is_none());
+        assert!(poa.note_block(&first, &parent).is_none());
+        match poa.note_block(&second, &parent) {
+            Some(Offence::BlockEquivocation { offender, slot, .. }) => {
+                assert_eq!(offender, "b");
+                assert_eq!(slot, 11);
+            }
+            other => panic!("expected equivocation, got {:?}", other),
+        }
+
+        poa.prune(66);
+        assert!(poa.note_block(&second, &parent).is_none());
+    }
+}
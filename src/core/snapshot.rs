@@ -0,0 +1,73 @@
+// Chain state snapshots
+// This module defines the account state snapshot taken at a finalized block,
+// which bootstraps a new node without replaying the chain
+
+use crate::core::blockchain::{finality_justification, AccountState, Block};
+use crate::core::finality::{AuthoritySet, FinalityGadget};
+use crate::core::merkle::merkle_root;
+use crate::core::state_trie::StateTrie;
+use crate::primitives::*;
+use crate::utils::crypto;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Version of the snapshot format
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Account state of the chain at a finalized block
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChainSnapshot {
+    /// Snapshot format version
+    pub version: u32,
+    /// Genesis block hash of the chain the snapshot belongs to
+    pub genesis_hash: Hash,
+    /// Finalized block the snapshot was taken at, with its justification
+    pub block: Block,
+    /// Finality authorities at that block
+    pub authorities: AuthoritySet,
+    /// All accounts at that block
+    pub accounts: BTreeMap<AccountId, AccountState>,
+}
+
+impl ChainSnapshot {
+    /// Check the snapshot against a node's own genesis.
+    ///
+    /// The accounts must produce the header's state root, the body must match
+    /// the header's transactions root, and any block after genesis must carry
+    /// a finality justification signed by the snapshot's authorities. An
+    /// authority set that has not changed since genesis must be the genesis set.
+    pub fn verify(&self, genesis_hash: &Hash, genesis_authorities: &AuthoritySet, threshold: u32) -> CoreResult<()> {
+        if self.version != SNAPSHOT_VERSION {
+            return Err(CoreError::ValidationFailed(format!("Unsupported snapshot version {}", self.version)));
+        }
+        if &self.genesis_hash != genesis_hash {
+            return Err(CoreError::ValidationFailed("Snapshot belongs to another chain".to_string()));
+        }
+
+        let header = &self.block.header;
+        if StateTrie::from_accounts(&self.accounts).root_hash() != header.state_root {
+            return Err(CoreError::ValidationFailed("Snapshot accounts do not match the state root".to_string()));
+        }
+        if crypto::hash_to_hex(&merkle_root(&self.block.body.leaves())) != header.transactions_root {
+            return Err(CoreError::ValidationFailed("Transactions root mismatch".to_string()));
+        }
+
+        if header.number == 0 {
+            if &self.block.hash() != genesis_hash {
+                return Err(CoreError::ValidationFailed("Snapshot genesis block does not match".to_string()));
+            }
+            return Ok(());
+        }
+        // The justification of such a block is signed by the outgoing set, which the snapshot does not hold
+        if header.authority_change.is_some() {
+            return Err(CoreError::ValidationFailed("Snapshot block changes the authority set".to_string()));
+        }
+        if self.authorities.set_id == 0 && &self.authorities != genesis_authorities {
+            return Err(CoreError::ValidationFailed("Snapshot authorities differ from the genesis set".to_string()));
+        }
+        let justification = finality_justification(&self.block)
+            .ok_or_else(|| CoreError::ValidationFailed("Snapshot block is not justified".to_string()))?;
+        FinalityGadget::new(self.authorities.clone(), threshold)
+            .verify_justification(justification, &self.block.hash(), header.number)
+    }
+}
@@ -0,0 +1,391 @@
+// Authenticated account state
+// This module implements a compact sparse Merkle trie over account states.
+// Its root is committed in every block header as `state_root`.
+
+use crate::core::blockchain::AccountState;
+use crate::core::codec::Encode;
+use crate::core::merkle::{leaf_hash, node_hash};
+use crate::primitives::*;
+use crate::utils::crypto::{hash_from_hex, hash_to_hex, sha256};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Hash of an empty subtree
+const EMPTY_NODE: [u8; 32] = [0u8; 32];
+
+/// Trie node.
+///
+/// A subtree holding a single account is represented by that account's leaf,
+/// whatever its depth, so paths are only as long as needed to separate keys.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TrieNode {
+    /// Inner node
+    Branch { left: [u8; 32], right: [u8; 32] },
+    /// Account leaf
+    Leaf {
+        key: [u8; 32],
+        account_id: AccountId,
+        account: AccountState,
+    },
+}
+
+impl TrieNode {
+    /// Node hash
+    pub fn hash(&self) -> [u8; 32] {
+        match self {
+            TrieNode::Branch { left, right } => node_hash(left, right),
+            TrieNode::Leaf { key, account, .. } => account_leaf_hash(key, account),
+        }
+    }
+}
+
+/// Leaf met at the end of a non-inclusion proof
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProofLeaf {
+    /// Trie key of the other account
+    pub key: Hash,
+    /// Hash of the other account's state
+    pub value_hash: Hash,
+}
+
+/// Proof of an account's state (or absence) under a state root
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AccountProof {
+    /// Account being proven
+    pub account_id: AccountId,
+    /// Account state, `None` for a proof of absence
+    pub account: Option<AccountState>,
+    /// Sibling hashes from the root down to the account's leaf
+    pub siblings: Vec<Hash>,
+    /// Different leaf occupying the account's path, for proofs of absence
+    pub other_leaf: Option<ProofLeaf>,
+}
+
+impl AccountProof {
+    /// Check the proof against a state root from a trusted block header
+    pub fn verify(&self, state_root: &Hash) -> bool {
+        let root = match hash_from_hex(state_root) {
+            Some(root) => root,
+            None => return false,
+        };
+        let key = trie_key(&self.account_id);
+        let depth = self.siblings.len();
+        if depth > 256 {
+            return false;
+        }
+
+        let mut current = match (&self.account, &self.other_leaf) {
+            (Some(account), None) => account_leaf_hash(&key, account),
+            (None, None) => EMPTY_NODE,
+            (None, Some(other)) => {
+                let (other_key, value_hash) = match (hash_from_hex(&other.key), hash_from_hex(&other.value_hash)) {
+                    (Some(k), Some(v)) => (k, v),
+                    _ => return false,
+                };
+                // The other leaf must sit on this account's path and not be the account itself
+                if other_key == key || (0..depth).any(|d| bit(&other_key, d) != bit(&key, d)) {
+                    return false;
+                }
+                leaf_node_hash(&other_key, &value_hash)
+            }
+            (Some(_), Some(_)) => return false,
+        };
+
+        for d in (0..depth).rev() {
+            let sibling = match hash_from_hex(&self.siblings[d]) {
+                Some(sibling) => sibling,
+                None => return false,
+            };
+            current = if bit(&key, d) {
+                node_hash(&sibling, &current)
+            } else {
+                node_hash(&current, &sibling)
+            };
+        }
+        current == root
+    }
+}
+
+/// Sparse Merkle trie over account states.
+///
+/// Nodes are content-addressed and never overwritten, so every root produced
+/// since the trie was built stays provable until pruned.
+#[derive(Debug, Clone, Default)]
+pub struct StateTrie {
+    /// Nodes by hash
+    pub nodes: HashMap<[u8; 32], TrieNode>,
+    /// Current root
+    pub root: [u8; 32],
+}
+
+impl StateTrie {
+    /// Create an empty trie
+    pub fn new() -> Self {
+        Self {
+            nodes: HashMap::new(),
+            root: EMPTY_NODE,
+        }
+    }
+
+    /// Build a trie from a full account set
+    pub fn from_accounts<'a, I>(accounts: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a AccountId, &'a AccountState)>,
+    {
+        let mut trie = Self::new();
+        for (account_id, account) in accounts {
+            trie.update(account_id, account);
+        }
+        trie
+    }
+
+    /// Current root as a hex hash
+    pub fn root_hash(&self) -> Hash {
+        hash_to_hex(&self.root)
+    }
+
+    /// Insert or replace an account, moving the root forward
+    pub fn update(&mut self, account_id: &AccountId, account: &AccountState) {
+        let key = trie_key(account_id);
+        let leaf = TrieNode::Leaf {
+            key,
+            account_id: account_id.clone(),
+            account: account.clone(),
+        };
+        self.root = self.insert(self.root, 0, &key, leaf);
+    }
+
+    /// Move the root back to an earlier state root.
+    ///
+    /// If the nodes of `root` are no longer held (for example after a restart),
+    /// the trie is rebuilt from `accounts`, which must be the state at that root.
+    /// Returns `false` if `accounts` do not produce `root`.
+    pub fn reset_to<'a, I>(&mut self, root: [u8; 32], accounts: I) -> bool
+    where
+        I: IntoIterator<Item = (&'a AccountId, &'a AccountState)>,
+    {
+        if root == EMPTY_NODE || self.nodes.contains_key(&root) {
+            self.root = root;
+            return true;
+        }
+
+        let rebuilt = Self::from_accounts(accounts);
+        self.nodes.extend(rebuilt.nodes);
+        if rebuilt.root != root {
+            return false;
+        }
+        self.root = root;
+        true
+    }
+
+    /// Look up an account under `root`
+    pub fn get(&self, root: &[u8; 32], account_id: &AccountId) -> Option<AccountState> {
+        let key = trie_key(account_id);
+        let mut current = *root;
+        let mut depth = 0;
+        loop {
+            match self.nodes.get(&current)? {
+                TrieNode::Leaf { key: leaf_key, account, .. } => {
+                    return (leaf_key == &key).then(|| account.clone());
+                }
+                TrieNode::Branch { left, right } => {
+                    current = if bit(&key, depth) { *right } else { *left };
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// Drop every node not reachable from `roots`, returning how many were removed.
+    ///
+    /// Older state roots can no longer be read or proven afterwards.
+    pub fn retain_roots(&mut self, roots: &[[u8; 32]]) -> usize {
+        let mut live = HashSet::new();
+        let mut pending: Vec<[u8; 32]> = roots.iter().copied().chain([self.root]).collect();
+        while let Some(node) = pending.pop() {
+            if node == EMPTY_NODE || !live.insert(node) {
+                continue;
+            }
+            if let Some(TrieNode::Branch { left, right }) = self.nodes.get(&node) {
+                pending.extend([*left, *right]);
+            }
+        }
+        let before = self.nodes.len();
+        self.nodes.retain(|hash, _| live.contains(hash));
+        before - self.nodes.len()
+    }
+
+    /// All accounts under `root`. Returns `None` if any node of the root is not held.
+    pub fn accounts_at(&self, root: &[u8; 32]) -> Option<BTreeMap<AccountId, AccountState>> {
+        let mut accounts = BTreeMap::new();
+        let mut pending = vec![*root];
+        while let Some(node) = pending.pop() {
+            if node == EMPTY_NODE {
+                continue;
+            }
+            match self.nodes.get(&node)? {
+                TrieNode::Leaf { account_id, account, .. } => {
+                    accounts.insert(account_id.clone(), account.clone());
+                }
+                TrieNode::Branch { left, right } => pending.extend([*left, *right]),
+            }
+        }
+        Some(accounts)
+    }
+
+    /// Prove an account (or its absence) under `root`. Returns `None` if the root is unknown.
+    pub fn prove(&self, root: &[u8; 32], account_id: &AccountId) -> Option<AccountProof> {
+        let key = trie_key(account_id);
+        let mut siblings = Vec::new();
+        let mut current = *root;
+        let mut depth = 0;
+
+        loop {
+            if current == EMPTY_NODE {
+                return Some(AccountProof {
+                    account_id: account_id.clone(),
+                    account: None,
+                    siblings,
+                    other_leaf: None,
+                });
+            }
+
+            match self.nodes.get(&current)? {
+                TrieNode::Leaf { key: leaf_key, account, .. } => {
+                    let (account, other_leaf) = if leaf_key == &key {
+                        (Some(account.clone()), None)
+                    } else {
+                        let other = ProofLeaf {
+                            key: hash_to_hex(leaf_key),
+                            value_hash: hash_to_hex(&sha256(&account.encode())),
+                        };
+                        (None, Some(other))
+                    };
+                    return Some(AccountProof {
+                        account_id: account_id.clone(),
+                        account,
+                        siblings,
+                        other_leaf,
+                    });
+                }
+                TrieNode::Branch { left, right } => {
+                    let (next, sibling) = if bit(&key, depth) { (*right, *left) } else { (*left, *right) };
+                    siblings.push(hash_to_hex(&sibling));
+                    current = next;
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// Insert `leaf` below `node` at `depth`, returning the new subtree hash
+    fn insert(&mut self, node: [u8; 32], depth: usize, key: &[u8; 32], leaf: TrieNode) -> [u8; 32] {
+        let existing = if node == EMPTY_NODE { None } else { self.nodes.get(&node).cloned() };
+
+        match existing {
+            None => self.store(leaf),
+            Some(TrieNode::Leaf { key: existing_key, .. }) if &existing_key == key => self.store(leaf),
+            Some(TrieNode::Leaf { key: existing_key, .. }) => {
+                // Push both leaves down until their paths diverge
+                let existing_bit = bit(&existing_key, depth);
+                let new_bit = bit(key, depth);
+                let (left, right) = if existing_bit == new_bit {
+                    let child = self.insert(node, depth + 1, key, leaf);
+                    if new_bit { (EMPTY_NODE, child) } else { (child, EMPTY_NODE) }
+                } else {
+                    let new_leaf = self.store(leaf);
+                    if new_bit { (node, new_leaf) } else { (new_leaf, node) }
+                };
+                self.store(TrieNode::Branch { left, right })
+            }
+            Some(TrieNode::Branch { left, right }) => {
+                let (left, right) = if bit(key, depth) {
+                    (left, self.insert(right, depth + 1, key, leaf))
+                } else {
+                    (self.insert(left, depth + 1, key, leaf), right)
+                };
+                self.store(TrieNode::Branch { left, right })
+            }
+        }
+    }
+
+    fn store(&mut self, node: TrieNode) -> [u8; 32] {
+        let hash = node.hash();
+        self.nodes.entry(hash).or_insert(node);
+        hash
+    }
+}
+
+/// Trie key of an account: SHA-256 of its ID
+pub fn trie_key(account_id: &AccountId) -> [u8; 32] {
+    sha256(account_id.as_bytes())
+}
+
+fn account_leaf_hash(key: &[u8; 32], account: &AccountState) -> [u8; 32] {
+    leaf_node_hash(key, &sha256(&account.encode()))
+}
+
+fn leaf_node_hash(key: &[u8; 32], value_hash: &[u8; 32]) -> [u8; 32] {
+    let mut bytes = Vec::with_capacity(64);
+    bytes.extend_from_slice(key);
+    bytes.extend_from_slice(value_hash);
+    leaf_hash(&bytes)
+}
+
+/// Bit of `key` at `depth`, most significant first
+fn bit(key: &[u8; 32], depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(balance: Balance) -> AccountState {
+        AccountState {
+            balance,
+            ..AccountState::default()
+        }
+    }
+
+    #[test]
+    fn test_root_is_independent_of_insertion_order() {
+        let ids: Vec<AccountId> = (0..20).map(|i| format!("account_{}", i)).collect();
+        let empty = AccountState::default();
+        let forward = StateTrie::from_accounts(ids.iter().map(|id| (id, &empty)));
+        let mut backward = StateTrie::new();
+        for id in ids.iter().rev() {
+            backward.update(id, &empty);
+        }
+        assert_eq!(forward.root, backward.root);
+    }
+
+    #[test]
+    fn test_inclusion_and_absence_proofs() {
+        let mut trie = StateTrie::new();
+        for i in 0..50 {
+            trie.update(&format!("account_{}", i), &account(i));
+        }
+        let old_root = trie.root_hash();
+        trie.update(&"account_7".to_string(), &account(700));
+        let root = trie.root_hash();
+
+        let proof = trie.prove(&trie.root, &"account_7".to_string()).unwrap();
+        assert_eq!(proof.account, Some(account(700)));
+        assert!(proof.verify(&root));
+        assert!(!proof.verify(&old_root));
+
+        // Earlier roots remain provable
+        let old = trie.prove(&hash_from_hex(&old_root).unwrap(), &"account_7".to_string()).unwrap();
+        assert_eq!(old.account, Some(account(7)));
+        assert!(old.verify(&old_root));
+
+        let absent = trie.prove(&trie.root, &"nobody".to_string()).unwrap();
+        assert_eq!(absent.account, None);
+        assert!(absent.verify(&root));
+
+        let mut forged = proof.clone();
+        forged.account = Some(account(1_000_000));
+        assert!(!forged.verify(&root));
+    }
+}
@@ -0,0 +1,409 @@
+// Persistent storage for the core blockchain
+// This module provides the key/value backends that keep blocks, transactions
+// and account state across node restarts
+
+use crate::primitives::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the write-ahead journal inside the storage directory
+const JOURNAL_FILE: &str = "JOURNAL";
+
+/// Meta key for the best block hash
+pub const META_BEST_BLOCK: &str = "best_block";
+
+/// Meta key for the finalized block hash
+pub const META_FINALIZED_BLOCK: &str = "finalized_block";
+
+/// Meta key for the genesis block hash
+pub const META_GENESIS_BLOCK: &str = "genesis_block";
+
+/// Meta key for the current finality authority set
+pub const META_AUTHORITY_SET: &str = "authority_set";
+
+/// Receipts key of the receipts of a block's calls, in execution order
+pub fn block_receipts_key(block_hash: &Hash) -> String {
+    format!("block/{}", block_hash)
+}
+
+/// Receipts key of the hash of the block a call's receipt is stored with
+pub fn receipt_block_key(transaction_hash: &Hash) -> String {
+    format!("call/{}", transaction_hash)
+}
+
+/// Storage columns
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Column {
+    /// Blocks by hash
+    Blocks,
+    /// Transactions by hash
+    Transactions,
+    /// Account states by account ID
+    Accounts,
+    /// Block undo records by block hash
+    Undo,
+    /// Chain metadata (best/finalized block pointers)
+    Meta,
+    /// Call receipts by block hash, and the including block by call hash
+    Receipts,
+}
+
+impl Column {
+    /// All columns
+    pub const ALL: [Column; 6] = [
+        Column::Blocks,
+        Column::Transactions,
+        Column::Accounts,
+        Column::Undo,
+        Column::Meta,
+        Column::Receipts,
+    ];
+
+    /// Directory name of the column
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Blocks => "blocks",
+            Column::Transactions => "transactions",
+            Column::Accounts => "accounts",
+            Column::Undo => "undo",
+            Column::Meta => "meta",
+            Column::Receipts => "receipts",
+        }
+    }
+}
+
+/// Single storage write operation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum WriteOp {
+    /// Insert or replace a value
+    Put {
+        column: Column,
+        key: String,
+        value: Vec<u8>,
+    },
+    /// Remove a value
+    Delete {
+        column: Column,
+        key: String,
+    },
+}
+
+/// Batch of write operations applied atomically
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct WriteBatch {
+    /// Operations in application order
+    pub ops: Vec<WriteOp>,
+}
+
+impl WriteBatch {
+    /// Create an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a serialized value
+    pub fn put<T: Serialize>(&mut self, column: Column, key: &str, value: &T) -> CoreResult<()> {
+        let value = serde_json::to_vec(value)
+            .map_err(|e| CoreError::StorageError(format!("Failed to encode {}: {}", key, e)))?;
+        self.put_raw(column, key, value);
+        Ok(())
+    }
+
+    /// Queue raw bytes
+    pub fn put_raw(&mut self, column: Column, key: &str, value: Vec<u8>) {
+        self.ops.push(WriteOp::Put {
+            column,
+            key: key.to_string(),
+            value,
+        });
+    }
+
+    /// Queue a removal
+    pub fn delete(&mut self, column: Column, key: &str) {
+        self.ops.push(WriteOp::Delete {
+            column,
+            key: key.to_string(),
+        });
+    }
+
+    /// Number of queued operations
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether the batch has no operations
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Key/value storage backend
+pub trait StorageBackend: Send {
+    /// Read a raw value
+    fn get(&self, column: Column, key: &str) -> CoreResult<Option<Vec<u8>>>;
+
+    /// List all keys of a column
+    fn keys(&self, column: Column) -> CoreResult<Vec<String>>;
+
+    /// Apply a batch atomically: either every operation is visible afterwards or none is
+    fn write(&mut self, batch: WriteBatch) -> CoreResult<()>;
+}
+
+/// Read and decode a value from a backend
+pub fn read_value<T: DeserializeOwned>(
+    backend: &dyn StorageBackend,
+    column: Column,
+    key: &str,
+) -> CoreResult<Option<T>> {
+    match backend.get(column, key)? {
+        Some(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| CoreError::StorageError(format!("Failed to decode {}/{}: {}", column.name(), key, e))),
+        None => Ok(None),
+    }
+}
+
+/// Read and decode every value of a column
+pub fn read_column<T: DeserializeOwned>(
+    backend: &dyn StorageBackend,
+    column: Column,
+) -> CoreResult<Vec<(String, T)>> {
+    let mut values = Vec::new();
+    for key in backend.keys(column)? {
+        if let Some(value) = read_value(backend, column, &key)? {
+            values.push((key, value));
+        }
+    }
+    Ok(values)
+}
+
+/// In-memory backend, used when no data directory is configured
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    /// Column data
+    pub columns: HashMap<Column, BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    /// Create empty in-memory storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn get(&self, column: Column, key: &str) -> CoreResult<Option<Vec<u8>>> {
+        Ok(self.columns.get(&column).and_then(|c| c.get(key)).cloned())
+    }
+
+    fn keys(&self, column: Column) -> CoreResult<Vec<String>> {
+        Ok(self.columns.get(&column).map(|c| c.keys().cloned().collect()).unwrap_or_default())
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> CoreResult<()> {
+        for op in batch.ops {
+            match op {
+                WriteOp::Put { column, key, value } => {
+                    self.columns.entry(column).or_default().insert(key, value);
+                }
+                WriteOp::Delete { column, key } => {
+                    if let Some(c) = self.columns.get_mut(&column) {
+                        c.remove(&key);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// File-system backend rooted at the node's data directory.
+///
+/// Every value lives in its own file under a per-column directory. A batch is
+/// first made durable in a journal file and only then applied, so a crash
+/// partway through a write is completed on the next `open`.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    /// Root directory
+    pub root: PathBuf,
+}
+
+impl FileStorage {
+    /// Open (or create) storage under `root`, replaying any unfinished batch
+    pub fn open<P: AsRef<Path>>(root: P) -> CoreResult<Self> {
+        let root = root.as_ref().to_path_buf();
+        for column in Column::ALL {
+            fs::create_dir_all(root.join(column.name())).map_err(io_error)?;
+        }
+
+        let storage = Self { root };
+        storage.recover()?;
+        Ok(storage)
+    }
+
+    /// Finish a batch left behind by a crash
+    fn recover(&self) -> CoreResult<()> {
+        let journal = self.root.join(JOURNAL_FILE);
+        if !journal.exists() {
+            return Ok(());
+        }
+
+        let bytes = fs::read(&journal).map_err(io_error)?;
+        let batch: WriteBatch = serde_json::from_slice(&bytes)
+            .map_err(|e| CoreError::StorageError(format!("Corrupted journal: {}", e)))?;
+        self.apply(&batch)?;
+        fs::remove_file(&journal).map_err(io_error)?;
+        Ok(())
+    }
+
+    /// Apply batch operations to the column files
+    fn apply(&self, batch: &WriteBatch) -> CoreResult<()> {
+        for op in &batch.ops {
+            match op {
+                WriteOp::Put { column, key, value } => {
+                    write_atomic(&self.value_path(*column, key), value)?;
+                }
+                WriteOp::Delete { column, key } => {
+                    let path = self.value_path(*column, key);
+                    if path.exists() {
+                        fs::remove_file(path).map_err(io_error)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Path of the file holding `key`
+    fn value_path(&self, column: Column, key: &str) -> PathBuf {
+        self.root.join(column.name()).join(encode_key(key))
+    }
+}
+
+impl StorageBackend for FileStorage {
+    fn get(&self, column: Column, key: &str) -> CoreResult<Option<Vec<u8>>> {
+        let path = self.value_path(column, key);
+        if !path.exists() {
+            return Ok(None);
+        }
+        fs::read(path).map(Some).map_err(io_error)
+    }
+
+    fn keys(&self, column: Column) -> CoreResult<Vec<String>> {
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(self.root.join(column.name())).map_err(io_error)? {
+            let name = entry.map_err(io_error)?.file_name();
+            // Skip leftover temporary files
+            if let Some(key) = name.to_str().and_then(decode_key) {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> CoreResult<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let journal = self.root.join(JOURNAL_FILE);
+        let bytes = serde_json::to_vec(&batch)
+            .map_err(|e| CoreError::StorageError(format!("Failed to encode batch: {}", e)))?;
+        write_atomic(&journal, &bytes)?;
+
+        self.apply(&batch)?;
+        fs::remove_file(&journal).map_err(io_error)?;
+        Ok(())
+    }
+}
+
+/// Write a file via a synced temporary file and rename
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> CoreResult<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = fs::File::create(&tmp).map_err(io_error)?;
+    file.write_all(bytes).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    fs::rename(&tmp, path).map_err(io_error)?;
+
+    // Persist the rename itself
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Encode a key as a file name (hex of the key bytes)
+fn encode_key(key: &str) -> String {
+    key.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a file name back into a key
+fn decode_key(name: &str) -> Option<String> {
+    if !name.len().is_multiple_of(2) || !name.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let bytes = (0..name.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&name[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .ok()?;
+    String::from_utf8(bytes).ok()
+}
+
+fn io_error(error: std::io::Error) -> CoreError {
+    CoreError::StorageError(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("ledger-storage-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_file_storage_roundtrip() {
+        let dir = temp_dir();
+        let mut storage = FileStorage::open(&dir).unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch.put(Column::Meta, META_BEST_BLOCK, &"0xabc".to_string()).unwrap();
+        batch.put(Column::Accounts, "alice/../bob", &42u64).unwrap();
+        storage.write(batch).unwrap();
+
+        let reopened = FileStorage::open(&dir).unwrap();
+        let best: Option<String> = read_value(&reopened, Column::Meta, META_BEST_BLOCK).unwrap();
+        assert_eq!(best, Some("0xabc".to_string()));
+        assert_eq!(reopened.keys(Column::Accounts).unwrap(), vec!["alice/../bob".to_string()]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_storage_replays_journal() {
+        let dir = temp_dir();
+        let storage = FileStorage::open(&dir).unwrap();
+
+        // Simulate a crash after the journal was written but before it was applied
+        let mut batch = WriteBatch::new();
+        batch.put(Column::Blocks, "0x01", &"block".to_string()).unwrap();
+        batch.delete(Column::Blocks, "0x02");
+        write_atomic(&dir.join("blocks").join(encode_key("0x02")), b"\"stale\"").unwrap();
+        write_atomic(&dir.join(JOURNAL_FILE), &serde_json::to_vec(&batch).unwrap()).unwrap();
+        drop(storage);
+
+        let recovered = FileStorage::open(&dir).unwrap();
+        assert!(!dir.join(JOURNAL_FILE).exists());
+        assert_eq!(recovered.keys(Column::Blocks).unwrap(), vec!["0x01".to_string()]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
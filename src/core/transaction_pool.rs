@@ -0,0 +1,414 @@
+// Transaction pool
+// This module holds signed transactions waiting for inclusion, ordered by fee
+// and arrival, with per-sender nonce queues and size limits
+
+use crate::core::blockchain::{AccountState, Transaction};
+use crate::primitives::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+
+/// Pool limits
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PoolConfig {
+    /// Maximum number of pooled transactions (ready and future)
+    pub max_transactions: usize,
+    /// Maximum number of pooled transactions per sender
+    pub max_per_account: usize,
+    /// Maximum number of transactions taken into one block
+    pub max_block_transactions: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_transactions: constants::MAX_POOL_TRANSACTIONS as usize,
+            max_per_account: constants::MAX_POOL_TRANSACTIONS_PER_ACCOUNT as usize,
+            max_block_transactions: constants::MAX_TRANSACTIONS_PER_BLOCK as usize,
+        }
+    }
+}
+
+/// Transaction with its arrival order in the pool
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PooledTransaction {
+    /// Pooled transaction
+    pub transaction: Transaction,
+    /// Arrival sequence number, lower arrived earlier
+    pub arrival: u64,
+}
+
+/// Pool statistics
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PoolStats {
+    /// Transactions ready for inclusion
+    pub ready: usize,
+    /// Transactions waiting for a nonce gap to be filled or for funds
+    pub future: usize,
+    /// Distinct senders with pooled transactions
+    pub senders: usize,
+    /// Sum of the fees of all pooled transactions
+    pub total_fees: Balance,
+    /// Lowest pooled fee
+    pub min_fee: Option<Balance>,
+    /// Highest pooled fee
+    pub max_fee: Option<Balance>,
+    /// Transactions evicted to make room for higher fees
+    pub evicted: u64,
+    /// Transactions dropped because their nonce was already used
+    pub dropped: u64,
+}
+
+/// Fee-prioritised transaction pool.
+///
+/// Each sender has a ready queue, holding the transactions that follow on from
+/// the account's nonce and that its balance covers, and a future queue for the
+/// rest. Blocks are filled from the ready queues by fee, then arrival, without
+/// ever reordering a sender's nonces.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransactionPool {
+    /// Pool limits
+    pub config: PoolConfig,
+    /// Ready transactions by sender and nonce
+    ready: HashMap<AccountId, BTreeMap<u64, PooledTransaction>>,
+    /// Future transactions by sender and nonce
+    future: HashMap<AccountId, BTreeMap<u64, PooledTransaction>>,
+    /// Next arrival sequence number
+    next_arrival: u64,
+    /// Eviction counter
+    evicted: u64,
+    /// Stale transaction counter
+    dropped: u64,
+}
+
+impl TransactionPool {
+    /// Create an empty pool
+    pub fn new(config: PoolConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Admit a transaction whose signature has already been checked.
+    ///
+    /// `account` is the sender's current on-chain state.
+    pub fn submit(&mut self, transaction: Transaction, account: &AccountState) -> CoreResult<()> {
+        let sender = transaction.from.clone();
+        let next_nonce = self.next_nonce(&sender, account.nonce);
+        if transaction.nonce < next_nonce || self.contains_nonce(&sender, transaction.nonce) {
+            return Err(CoreError::InvalidNonce { expected: next_nonce, got: transaction.nonce });
+        }
+
+        if transaction.nonce == next_nonce {
+            let cost = self.ready_cost(&sender).saturating_add(transaction_cost(&transaction));
+            if cost > account.balance {
+                return Err(CoreError::InsufficientBalance);
+            }
+        }
+
+        if self.sender_count(&sender) >= self.config.max_per_account {
+            return Err(CoreError::ValidationFailed("Too many pooled transactions for this account".to_string()));
+        }
+        self.make_room(transaction.fee)?;
+
+        let pooled = PooledTransaction {
+            transaction,
+            arrival: self.next_arrival,
+        };
+        self.next_arrival += 1;
+        self.future.entry(sender.clone()).or_default().insert(pooled.transaction.nonce, pooled);
+        self.rebuild_sender(&sender, account);
+        Ok(())
+    }
+
+    /// Next nonce the sender should use, counting its ready transactions
+    pub fn next_nonce(&self, account_id: &AccountId, account_nonce: u64) -> u64 {
+        self.ready.get(account_id)
+            .and_then(|queue| queue.keys().next_back())
+            .map(|nonce| nonce + 1)
+            .unwrap_or(account_nonce)
+            .max(account_nonce)
+    }
+
+    /// Pick transactions for the next block by fee, then arrival, keeping each sender's nonce order
+    pub fn select_for_block(&self) -> Vec<Transaction> {
+        let mut heap = BinaryHeap::new();
+        for (sender, queue) in &self.ready {
+            if let Some((nonce, pooled)) = queue.iter().next() {
+                heap.push((pooled.transaction.fee, Reverse(pooled.arrival), sender, *nonce));
+            }
+        }
+
+        let mut selected = Vec::new();
+        while selected.len() < self.config.max_block_transactions {
+            let Some((_, _, sender, nonce)) = heap.pop() else {
+                break;
+            };
+            let queue = &self.ready[sender];
+            selected.push(queue[&nonce].transaction.clone());
+            if let Some(next) = queue.get(&(nonce + 1)) {
+                heap.push((next.transaction.fee, Reverse(next.arrival), sender, nonce + 1));
+            }
+        }
+        selected
+    }
+
+    /// Re-check every sender against post-block state.
+    ///
+    /// Transactions whose nonce was used are dropped, and ready transactions
+    /// the sender can no longer pay for move back to the future queue.
+    pub fn revalidate(&mut self, accounts: &HashMap<AccountId, AccountState>) {
+        let mut senders: Vec<AccountId> = self.ready.keys().chain(self.future.keys()).cloned().collect();
+        senders.sort();
+        senders.dedup();
+
+        let empty = AccountState::default();
+        for sender in senders {
+            let account = accounts.get(&sender).unwrap_or(&empty);
+            self.rebuild_sender(&sender, account);
+        }
+    }
+
+    /// Ready transactions of a sender, in nonce order
+    pub fn ready_transactions(&self, account_id: &AccountId) -> Vec<&Transaction> {
+        self.ready.get(account_id)
+            .map(|queue| queue.values().map(|p| &p.transaction).collect())
+            .unwrap_or_default()
+    }
+
+    /// Future transactions of a sender, in nonce order
+    pub fn future_transactions(&self, account_id: &AccountId) -> Vec<&Transaction> {
+        self.future.get(account_id)
+            .map(|queue| queue.values().map(|p| &p.transaction).collect())
+            .unwrap_or_default()
+    }
+
+    /// Find a pooled transaction by hash
+    pub fn get(&self, hash: &Hash) -> Option<&Transaction> {
+        self.iter().find(|p| &p.transaction.hash == hash).map(|p| &p.transaction)
+    }
+
+    /// Number of pooled transactions
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Whether the pool is empty
+    pub fn is_empty(&self) -> bool {
+        self.ready.is_empty() && self.future.is_empty()
+    }
+
+    /// Current pool statistics
+    pub fn stats(&self) -> PoolStats {
+        let fees: Vec<Balance> = self.iter().map(|p| p.transaction.fee).collect();
+        let mut senders: Vec<&AccountId> = self.ready.keys().chain(self.future.keys()).collect();
+        senders.sort();
+        senders.dedup();
+
+        PoolStats {
+            ready: self.ready.values().map(BTreeMap::len).sum(),
+            future: self.future.values().map(BTreeMap::len).sum(),
+            senders: senders.len(),
+            total_fees: fees.iter().sum(),
+            min_fee: fees.iter().min().copied(),
+            max_fee: fees.iter().max().copied(),
+            evicted: self.evicted,
+            dropped: self.dropped,
+        }
+    }
+
+    /// All pooled transactions
+    fn iter(&self) -> impl Iterator<Item = &PooledTransaction> {
+        self.ready.values().chain(self.future.values()).flat_map(BTreeMap::values)
+    }
+
+    fn contains_nonce(&self, account_id: &AccountId, nonce: u64) -> bool {
+        [&self.ready, &self.future]
+            .iter()
+            .any(|queues| queues.get(account_id).is_some_and(|q| q.contains_key(&nonce)))
+    }
+
+    fn sender_count(&self, account_id: &AccountId) -> usize {
+        [&self.ready, &self.future]
+            .iter()
+            .map(|queues| queues.get(account_id).map(BTreeMap::len).unwrap_or(0))
+            .sum()
+    }
+
+    fn ready_cost(&self, account_id: &AccountId) -> Balance {
+        self.ready.get(account_id)
+            .map(|queue| queue.values().map(|p| transaction_cost(&p.transaction)).fold(0, Balance::saturating_add))
+            .unwrap_or(0)
+    }
+
+    /// Evict the cheapest transaction if the pool is full and `fee` outbids it.
+    ///
+    /// Only the last transaction of a sender's queue is a candidate, so eviction
+    /// never leaves a nonce gap behind a ready transaction. Future transactions
+    /// and later arrivals go first on equal fees.
+    fn make_room(&mut self, fee: Balance) -> CoreResult<()> {
+        if self.len() < self.config.max_transactions {
+            return Ok(());
+        }
+
+        let victim = [(false, &self.future), (true, &self.ready)]
+            .into_iter()
+            .flat_map(|(is_ready, queues)| {
+                queues.iter().filter_map(move |(sender, queue)| {
+                    queue.iter().next_back().map(|(nonce, p)| {
+                        ((p.transaction.fee, is_ready, Reverse(p.arrival)), sender.clone(), *nonce)
+                    })
+                })
+            })
+            .min();
+
+        match victim {
+            Some(((victim_fee, is_ready, _), sender, nonce)) if victim_fee < fee => {
+                let queues = if is_ready { &mut self.ready } else { &mut self.future };
+                if let Some(queue) = queues.get_mut(&sender) {
+                    queue.remove(&nonce);
+                    if queue.is_empty() {
+                        queues.remove(&sender);
+                    }
+                }
+                self.evicted += 1;
+                Ok(())
+            }
+            _ => Err(CoreError::ValidationFailed("Transaction pool is full".to_string())),
+        }
+    }
+
+    /// Re-split a sender's transactions into ready and future queues against its account state
+    fn rebuild_sender(&mut self, account_id: &AccountId, account: &AccountState) {
+        let mut pending = self.ready.remove(account_id).unwrap_or_default();
+        pending.append(&mut self.future.remove(account_id).unwrap_or_default());
+
+        let stale: Vec<u64> = pending.range(..account.nonce).map(|(nonce, _)| *nonce).collect();
+        for nonce in stale {
+            pending.remove(&nonce);
+            self.dropped += 1;
+        }
+
+        let mut ready = BTreeMap::new();
+        let mut next_nonce = account.nonce;
+        let mut spent: Balance = 0;
+        while let Some(pooled) = pending.remove(&next_nonce) {
+            let cost = spent.saturating_add(transaction_cost(&pooled.transaction));
+            if cost > account.balance {
+                pending.insert(next_nonce, pooled);
+                break;
+            }
+            spent = cost;
+            ready.insert(next_nonce, pooled);
+            next_nonce += 1;
+        }
+
+        if !ready.is_empty() {
+            self.ready.insert(account_id.clone(), ready);
+        }
+        if !pending.is_empty() {
+            self.future.insert(account_id.clone(), pending);
+        }
+    }
+}
+
+/// Total amount a transaction takes from the sender
+fn transaction_cost(transaction: &Transaction) -> Balance {
+    transaction.amount.saturating_add(transaction.fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::blockchain::TransactionType;
+
+    fn tx(from: &str, nonce: u64, fee: Balance) -> Transaction {
+        Transaction {
+            hash: format!("0x{}{}", from, nonce),
+            from: from.to_string(),
+            to: "receiver".to_string(),
+            amount: 10,
+            transaction_type: TransactionType::Transfer,
+            data: Vec::new(),
+            fee,
+            nonce,
+            block_number: None,
+            timestamp: 0,
+            signature: None,
+        }
+    }
+
+    fn funded(balance: Balance) -> AccountState {
+        AccountState {
+            balance,
+            ..AccountState::default()
+        }
+    }
+
+    #[test]
+    fn test_block_selection_by_fee_then_arrival() {
+        let mut pool = TransactionPool::new(PoolConfig {
+            max_block_transactions: 4,
+            ..PoolConfig::default()
+        });
+        let account = funded(1_000);
+        pool.submit(tx("alice", 0, 1), &account).unwrap();
+        pool.submit(tx("alice", 1, 50), &account).unwrap();
+        pool.submit(tx("bob", 0, 5), &account).unwrap();
+        pool.submit(tx("carol", 0, 5), &account).unwrap();
+        pool.submit(tx("dave", 0, 2), &account).unwrap();
+
+        let order: Vec<(String, u64)> = pool.select_for_block().iter().map(|t| (t.from.clone(), t.nonce)).collect();
+        // Alice's high fee cannot jump ahead of her own nonce 0; Bob arrived before Carol
+        assert_eq!(order, vec![
+            ("bob".to_string(), 0),
+            ("carol".to_string(), 0),
+            ("dave".to_string(), 0),
+            ("alice".to_string(), 0),
+        ]);
+    }
+
+    #[test]
+    fn test_limits_and_eviction() {
+        let mut pool = TransactionPool::new(PoolConfig {
+            max_transactions: 3,
+            max_per_account: 2,
+            ..PoolConfig::default()
+        });
+        let account = funded(1_000);
+        pool.submit(tx("alice", 0, 1), &account).unwrap();
+        pool.submit(tx("alice", 1, 1), &account).unwrap();
+        assert!(matches!(pool.submit(tx("alice", 2, 9), &account), Err(CoreError::ValidationFailed(_))));
+
+        pool.submit(tx("bob", 0, 3), &account).unwrap();
+        // Full: an equal fee does not get in, a higher fee evicts Alice's last transaction
+        assert!(pool.submit(tx("carol", 0, 1), &account).is_err());
+        pool.submit(tx("carol", 0, 2), &account).unwrap();
+
+        assert_eq!(pool.len(), 3);
+        assert_eq!(pool.ready_transactions(&"alice".to_string()).len(), 1);
+        let stats = pool.stats();
+        assert_eq!((stats.ready, stats.senders, stats.evicted), (3, 3, 1));
+        assert_eq!((stats.min_fee, stats.max_fee, stats.total_fees), (Some(1), Some(3), 6));
+    }
+
+    #[test]
+    fn test_revalidate_after_block() {
+        let mut pool = TransactionPool::default();
+        let account = funded(100);
+        for nonce in 0..4 {
+            pool.submit(tx("alice", nonce, 1), &account).unwrap();
+        }
+        assert_eq!(pool.stats().ready, 4);
+
+        // Nonce 0 was included and Alice spent most of her balance elsewhere
+        let mut accounts = HashMap::new();
+        accounts.insert("alice".to_string(), AccountState { balance: 25, nonce: 1, ..AccountState::default() });
+        pool.revalidate(&accounts);
+
+        let stats = pool.stats();
+        assert_eq!((stats.ready, stats.future, stats.dropped), (2, 1, 1));
+        assert_eq!(pool.next_nonce(&"alice".to_string(), 1), 3);
+    }
+}
@@ -2,6 +2,7 @@
 // Handles SEC, ERC, and PDPA compliance requirements
 
 use serde::{Deserialize, Serialize};
+use crate::primitives::Energy;
 use std::collections::HashMap;
 
 /// Main compliance management system
@@ -719,7 +720,7 @@ pub struct RenewableCertificate {
     pub certificate_id: String,
     pub energy_source: EnergySource,
     pub generation_facility: String,
-    pub energy_amount: f64,
+    pub energy_amount: Energy,
     pub generation_period: GenerationPeriod,
     pub certificate_status: CertificateStatus,
 }
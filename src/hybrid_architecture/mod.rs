@@ -17,6 +17,7 @@ pub use integration::HybridArchitecture;
 pub use compliance::ComplianceManager;
 
 use serde::{Deserialize, Serialize};
+use crate::primitives::{Amount, Energy, Price};
 use crate::token_system::TokenSystem;
 use crate::energy_trading::EnergyMarket;
 
@@ -156,14 +157,17 @@ impl HybridSystem {
         // 1. Validate compliance
         self.compliance.validate_energy_transaction(&transaction_data)?;
         
-        // 2. Convert to EnergyTrade format
+        // 2. Convert to EnergyTrade format, which still takes floats
+        let total_cost = transaction_data.price_per_kwh
+            .checked_cost(transaction_data.energy_amount)
+            .ok_or("Energy transaction cost overflows")?;
         let energy_trade = crate::energy_trading::EnergyTrade {
             trade_id: transaction_data.transaction_id.clone(),
             buyer: transaction_data.buyer,
             seller: transaction_data.seller,
-            energy_amount: transaction_data.energy_amount,
-            price_per_kwh: transaction_data.price_per_kwh,
-            total_cost: transaction_data.energy_amount * transaction_data.price_per_kwh,
+            energy_amount: transaction_data.energy_amount.to_f64(),
+            price_per_kwh: transaction_data.price_per_kwh.to_f64(),
+            total_cost: total_cost.to_f64(),
             grid_fee: 0.0, // Calculate based on system rules
             timestamp: transaction_data.timestamp,
         };
@@ -182,8 +186,8 @@ impl HybridSystem {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnergyTransactionData {
     pub transaction_id: String,
-    pub energy_amount: f64,
-    pub price_per_kwh: f64,
+    pub energy_amount: Energy,
+    pub price_per_kwh: Price,
     pub buyer: String,
     pub seller: String,
     pub timestamp: chrono::DateTime<chrono::Utc>,
@@ -193,7 +197,7 @@ pub struct EnergyTransactionData {
 pub struct GridIntegrationData {
     pub grid_connection_point: String,
     pub grid_load_factor: f64,
-    pub wheeling_charges: f64,
+    pub wheeling_charges: Amount,
     pub congestion_factor: f64,
 }
 
@@ -1,7 +1,9 @@
 // Public Chain Implementation for Governance and Investment Layer
 // Handles transparency, governance, and public participation
 
+use crate::core::offences::OffenceKind;
 use crate::token_system::{GridToken, GovernanceProposal};
+use crate::primitives::Energy;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -85,7 +87,7 @@ pub enum PublicTransactionType {
 pub struct EnergyCertificate {
     pub certificate_id: String,
     pub energy_source: EnergySource,
-    pub energy_amount: f64,
+    pub energy_amount: Energy,
     pub generation_date: chrono::DateTime<chrono::Utc>,
     pub issuer: String,
     pub carbon_offset: f64,
@@ -408,7 +410,7 @@ impl PublicChain {
     }
 
     /// Issue energy certificate
-    pub fn issue_certificate(&mut self, energy_amount: f64, energy_source: EnergySource, issuer: &str) -> Result<String, String> {
+    pub fn issue_certificate(&mut self, energy_amount: Energy, energy_source: EnergySource, issuer: &str) -> Result<String, String> {
         let certificate_id = format!("cert_{}", uuid::Uuid::new_v4());
         
         let certificate = EnergyCertificate {
@@ -417,7 +419,7 @@ impl PublicChain {
             energy_amount,
             generation_date: chrono::Utc::now(),
             issuer: issuer.to_string(),
-            carbon_offset: energy_amount * 0.7, // Simplified calculation
+            carbon_offset: energy_amount.to_f64() * 0.7, // Simplified calculation
         };
 
         self.transparency_layer.energy_certificates.push(certificate);
@@ -561,7 +563,7 @@ pub struct NominationPool {
     pub nominators: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SlashingRules {
     pub offline_penalty: f64,
     pub misbehavior_penalty: f64,
@@ -584,6 +586,21 @@ impl SlashingRules {
             max_slash_percentage: 0.10,
         }
     }
+
+    /// Fraction of stake slashed for an offence, capped at `max_slash_percentage`
+    pub fn penalty_for(&self, kind: OffenceKind) -> f64 {
+        let penalty = match kind {
+            OffenceKind::Equivocation => self.misbehavior_penalty,
+            OffenceKind::Unresponsiveness => self.offline_penalty,
+        };
+        penalty.clamp(0.0, self.max_slash_percentage)
+    }
+}
+
+impl Default for SlashingRules {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Default for PublicChain {
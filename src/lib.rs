@@ -51,12 +51,15 @@ pub struct ThaiEnergyTradingSystem {
 }
 
 impl ThaiEnergyTradingSystem {
-    /// Create new system instance
+    /// Create new system instance on the dev chain
     pub fn new() -> Self {
-        let config = load_system_config();
-        let runtime = Runtime::new(config);
-        
-        Self { runtime }
+        Self::with_config(SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() })
+    }
+
+    /// Create new system instance from the environment; fails if `CHAIN_SPEC`
+    /// names neither a preset nor a readable chain spec
+    pub fn try_new() -> CoreResult<Self> {
+        Ok(Self::with_config(load_system_config()?))
     }
 
     /// Create system with custom configuration
@@ -111,7 +114,7 @@ pub fn create_system() -> ThaiEnergyTradingSystem {
 
 /// Helper function to create system with genesis initialization
 pub fn create_and_initialize_system() -> CoreResult<ThaiEnergyTradingSystem> {
-    let mut system = ThaiEnergyTradingSystem::new();
+    let mut system = ThaiEnergyTradingSystem::try_new()?;
     system.initialize()?;
     Ok(system)
 }
@@ -9,7 +9,147 @@ use blockchain::Blockchain;
 use energy_trading::{EnergyMarket, EnergyOrder, OrderType, Prosumer, create_energy_trade_transaction, create_grid_fee_transaction};
 use token_system::TokenSystem;
 
+/// Options of the node and its subcommands that take a value
+const VALUE_FLAGS: [&str; 8] = ["--chain", "--format", "--base-path", "--name", "--port", "--rpc-port", "--ws-port", "--dev-seed"];
+
+/// Split arguments into flags and their values, accepting `--flag value` and `--flag=value`
+fn parse_flags(args: &[String]) -> ledger_core::CoreResult<Vec<(String, String)>> {
+    let mut flags = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), value.to_string()),
+            None if VALUE_FLAGS.contains(&arg.as_str()) => {
+                let value = args.next().cloned()
+                    .ok_or_else(|| ledger_core::CoreError::InvalidInput(format!("{} needs a value", arg)))?;
+                (arg.clone(), value)
+            }
+            None => (arg.clone(), String::new()),
+        };
+        flags.push((flag, value));
+    }
+    Ok(flags)
+}
+
+/// Print the raw chain spec of a preset or spec file:
+/// `ledger build-spec [--chain <preset|path>] [--format json|toml]`
+fn build_spec(args: &[String]) -> ledger_core::CoreResult<()> {
+    use ledger_core::core::SpecFormat;
+    use ledger_core::{ChainSpec, CoreError};
+
+    let mut chain = "local-testnet".to_string();
+    let mut format = SpecFormat::Json;
+    for (flag, value) in parse_flags(args)? {
+        match flag.as_str() {
+            "--chain" => chain = value,
+            "--format" => format = SpecFormat::from_name(&value)?,
+            _ => return Err(CoreError::InvalidInput(format!("Unknown build-spec argument {}", flag))),
+        }
+    }
+
+    let spec = ChainSpec::from_name_or_path(&chain)?;
+    println!("{}", format.encode(&spec.to_raw())?);
+    Ok(())
+}
+
+/// Re-execute a node's chain from genesis and report the first divergence:
+/// `ledger verify-chain [--chain <preset|path>] [--base-path <dir>]`
+fn verify_chain(args: &[String]) -> ledger_core::CoreResult<()> {
+    use ledger_core::utils::config;
+    use ledger_core::{ChainSpec, CoreError, Runtime};
+
+    let mut config = config::load_system_config()?;
+    for (flag, value) in parse_flags(args)? {
+        match flag.as_str() {
+            "--chain" => config.chain_spec = ChainSpec::from_name_or_path(&value)?,
+            "--base-path" => config.node_config.data_dir = value,
+            _ => return Err(CoreError::InvalidInput(format!("Unknown verify-chain argument {}", flag))),
+        }
+    }
+
+    let report = Runtime::open(config)?.verify_chain()?;
+    match report.divergence {
+        Some(divergence) => Err(CoreError::ValidationFailed(divergence.to_string())),
+        None => {
+            println!("Re-executed {} blocks without divergence", report.verified_blocks);
+            Ok(())
+        }
+    }
+}
+
+/// Run as a validator authoring blocks every `block_time` seconds until SIGINT or SIGTERM.
+///
+/// Configuration comes from the environment, overridden by `--chain`,
+/// `--base-path`, `--name` and the port options. The authority key is read
+/// from `AUTHORITY_KEY`, or derived from a public seed with `--dev-seed`.
+/// Progress is logged to stderr at the level `RUST_LOG` sets, `info` by default.
+fn run_node(args: &[String]) -> ledger_core::CoreResult<()> {
+    use ledger_core::utils::{config, crypto};
+    use ledger_core::{BlockProducer, ChainSpec, CoreError, Runtime, SystemClock};
+
+    let port = |flag: &str, value: &str| value.parse::<u16>()
+        .map_err(|_| CoreError::InvalidInput(format!("{} expects a port, got {}", flag, value)));
+    let mut config = config::load_system_config()?;
+    let mut authority_key = std::env::var("AUTHORITY_KEY").ok();
+    for (flag, value) in parse_flags(args)? {
+        match flag.as_str() {
+            "--chain" => {
+                config.chain_spec = ChainSpec::from_name_or_path(&value)?;
+                config.chain_spec.boot_nodes.extend(config::load_boot_nodes());
+            }
+            "--base-path" => config.node_config.data_dir = value,
+            "--name" => config.node_config.name = value,
+            "--port" => config.node_config.port = port(&flag, &value)?,
+            "--rpc-port" => config.node_config.rpc_port = port(&flag, &value)?,
+            "--ws-port" => config.node_config.ws_port = port(&flag, &value)?,
+            "--dev-seed" => authority_key = Some(crypto::keypair_from_seed(&value).0),
+            // Every node authors blocks; the flag is accepted for deployment scripts
+            "--validator" => {}
+            _ => log::warn!("Ignoring unsupported option {}", flag),
+        }
+    }
+
+    let authority_key = authority_key
+        .ok_or_else(|| CoreError::InvalidInput("No authority key: set AUTHORITY_KEY or pass --dev-seed".to_string()))?;
+    let author = crypto::public_key_from_private(&authority_key)
+        .ok_or_else(|| CoreError::InvalidInput("Malformed authority key".to_string()))?;
+    let chain_name = config.chain_spec.name.clone();
+    let mut runtime = Runtime::start(config)?;
+    runtime.blockchain.set_authority_key(authority_key);
+
+    let mut producer = BlockProducer::new(runtime, author.clone(), SystemClock);
+    let shutdown = producer.shutdown_handle();
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register(signal, shutdown.clone())
+            .map_err(|e| CoreError::SystemError(format!("Failed to install signal handler: {}", e)))?;
+    }
+
+    log::info!("Authoring {} blocks as {} from block #{}", chain_name, author, producer.runtime.blockchain.get_block_height());
+    producer.run();
+    log::info!("Shut down at block #{}", producer.runtime.blockchain.get_block_height());
+    Ok(())
+}
+
 fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("build-spec") => build_spec(&args[1..]),
+        Some("verify-chain") => verify_chain(&args[1..]),
+        Some("demo") => {
+            run_demo();
+            Ok(())
+        }
+        _ => run_node(&args),
+    };
+    if let Err(e) = result {
+        eprintln!("ledger: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Walk through peer-to-peer energy trading on the legacy ledger
+fn run_demo() {
     println!("🌟 Decentralized Energy Trading Ecosystem - Demo 🌟");
     println!("=====================================================");
 
@@ -19,42 +19,95 @@ pub type Hash = String;
 /// Timestamp type
 pub type Timestamp = u64;
 
-/// Energy amount in kWh
-pub type EnergyAmount = f64;
+pub use ledger_arithmetic::{Amount, Energy, ParseFixedError, Price, Rounding};
 
-/// Price in Thai Baht (THB)
-pub type Price = f64;
+/// Execution cost of a call, in abstract units of work
+pub type Weight = u64;
 
 /// Core system constants
 pub mod constants {
     use super::*;
 
     /// One kWh equals one token (1:1 ratio)
-    pub const KWH_TO_TOKEN_RATIO: f64 = 1.0;
+    pub const KWH_TO_TOKEN_RATIO: Price = Price::from_raw(Price::SCALE);
 
-    /// Minimum energy trade amount (kWh)
-    pub const MIN_ENERGY_TRADE: EnergyAmount = 0.1;
+    /// Minimum energy trade amount (0.1 kWh)
+    pub const MIN_ENERGY_TRADE: Energy = Energy::from_raw(100);
 
-    /// Maximum energy trade amount (kWh)
-    pub const MAX_ENERGY_TRADE: EnergyAmount = 10_000.0;
+    /// Maximum energy trade amount (10,000 kWh)
+    pub const MAX_ENERGY_TRADE: Energy = Energy::from_raw(10_000_000);
 
-    /// Grid fee percentage (1%)
-    pub const GRID_FEE_PERCENTAGE: f64 = 0.01;
+    /// Grid fee in basis points (1%)
+    pub const GRID_FEE_BPS: u32 = 100;
 
     /// Minimum stake amount for validators
     pub const MIN_VALIDATOR_STAKE: Balance = 10_000;
 
+    /// Size of the active validator set elected each era
+    pub const VALIDATOR_COUNT: u32 = 21;
+
+    /// Era length in blocks (one hour of six-second blocks)
+    pub const ERA_LENGTH: BlockNumber = 600;
+
+    /// Maximum validator candidates one nominator may back
+    pub const MAX_NOMINATIONS: u32 = 16;
+
+    /// Slots a validator may miss per era before it is slashed for downtime
+    pub const MAX_MISSED_SLOTS: u64 = 60;
+
+    /// Blocks a slash waits before it is applied, during which governance may cancel it
+    pub const SLASH_DEFER_BLOCKS: BlockNumber = 1_200;
+
+    /// Blocks whose runtime events stay queryable (one week of six-second blocks)
+    pub const EVENT_HISTORY_BLOCKS: BlockNumber = 100_800;
+
+    /// Weight of the calls one block may hold
+    pub const MAX_BLOCK_WEIGHT: Weight = 1_000_000;
+
+    /// Share of `MAX_BLOCK_WEIGHT`, in parts per million, at which call fees stay constant
+    pub const TARGET_BLOCK_FULLNESS_PPM: u128 = 250_000;
+
+    /// Weight of scheduled calls dispatched when a block is initialized
+    pub const MAX_SCHEDULED_WEIGHT: Weight = MAX_BLOCK_WEIGHT / 5;
+
+    /// Scheduled calls one account may have waiting
+    pub const MAX_SCHEDULED_PER_ACCOUNT: usize = 16;
+
+    /// Maximum calls in one batch call
+    pub const MAX_BATCH_CALLS: usize = 64;
+
+    /// Flat fee every call pays, 0.01 of the fee token
+    pub const BASE_CALL_FEE: Balance = 10_u128.pow(TOKEN_DECIMALS as u32) / 100;
+
+    /// Fee per unit of call weight at a multiplier of one, 0.00001 of the fee token
+    pub const CALL_FEE_PER_WEIGHT: Balance = 10_u128.pow(TOKEN_DECIMALS as u32) / 100_000;
+
     /// Block time in seconds
     pub const BLOCK_TIME: u64 = 6;
 
     /// Maximum transactions per block
     pub const MAX_TRANSACTIONS_PER_BLOCK: u32 = 1000;
 
+    /// Maximum transactions held in the transaction pool
+    pub const MAX_POOL_TRANSACTIONS: u32 = 8192;
+
+    /// Maximum pooled transactions per sender
+    pub const MAX_POOL_TRANSACTIONS_PER_ACCOUNT: u32 = 64;
+
+    /// PBFT ticks without progress before replicas change the primary
+    pub const PBFT_VIEW_TIMEOUT: u64 = 20;
+
+    /// Blocks between PBFT checkpoints
+    pub const PBFT_CHECKPOINT_INTERVAL: u64 = 10;
+
+    /// Sequence numbers a PBFT replica accepts above its stable checkpoint
+    pub const PBFT_LOG_WINDOW: u64 = 100;
+
     /// Token decimal places
-    pub const TOKEN_DECIMALS: u8 = 18;
+    pub const TOKEN_DECIMALS: u8 = Amount::DECIMALS as u8;
 
-    /// Default energy price (THB per kWh)
-    pub const DEFAULT_ENERGY_PRICE: Price = 4.50;
+    /// Default energy price (4.50 THB per kWh)
+    pub const DEFAULT_ENERGY_PRICE: Price = Price::from_raw(45_000);
 }
 
 /// Core error types
@@ -76,6 +129,12 @@ pub enum CoreError {
     ValidationFailed(String),
     /// System error
     SystemError(String),
+    /// Storage error
+    StorageError(String),
+    /// Missing or invalid signature
+    InvalidSignature,
+    /// Transaction nonce does not follow the sender's nonce
+    InvalidNonce { expected: u64, got: u64 },
 }
 
 impl std::fmt::Display for CoreError {
@@ -87,8 +146,13 @@ impl std::fmt::Display for CoreError {
             CoreError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
             CoreError::TransactionNotFound => write!(f, "Transaction not found"),
             CoreError::BlockNotFound => write!(f, "Block not found"),
+            CoreError::InvalidSignature => write!(f, "Invalid signature"),
+            CoreError::InvalidNonce { expected, got } => {
+                write!(f, "Invalid nonce: expected {}, got {}", expected, got)
+            }
             CoreError::ValidationFailed(msg) => write!(f, "Validation failed: {}", msg),
             CoreError::SystemError(msg) => write!(f, "System error: {}", msg),
+            CoreError::StorageError(msg) => write!(f, "Storage error: {}", msg),
         }
     }
 }
@@ -128,6 +192,7 @@ pub struct ChainSpec {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenesisConfig {
     /// Initial accounts with balances
+    #[serde(with = "balance_map")]
     pub accounts: HashMap<AccountId, Balance>,
     /// Initial validators
     pub validators: Vec<AccountId>,
@@ -135,17 +200,96 @@ pub struct GenesisConfig {
     pub energy_market: EnergyMarketConfig,
 }
 
+/// Serde layout of genesis balances.
+///
+/// Balances are written as decimal strings, sorted by account, because TOML
+/// integers stop at `i64`; plain integers are accepted on input.
+pub mod balance_map {
+    use super::{AccountId, Balance};
+    use serde::de::{self, Deserializer, MapAccess, Visitor};
+    use serde::ser::{SerializeMap, Serializer};
+    use std::collections::{BTreeMap, HashMap};
+    use std::fmt;
+
+    /// Write balances as a sorted map of decimal strings
+    pub fn serialize<S: Serializer>(accounts: &HashMap<AccountId, Balance>, serializer: S) -> Result<S::Ok, S::Error> {
+        let sorted: BTreeMap<_, _> = accounts.iter().collect();
+        let mut map = serializer.serialize_map(Some(sorted.len()))?;
+        for (account, balance) in sorted {
+            map.serialize_entry(account, &balance.to_string())?;
+        }
+        map.end()
+    }
+
+    /// Read balances given as decimal strings or integers
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<AccountId, Balance>, D::Error> {
+        deserializer.deserialize_map(AccountsVisitor)
+    }
+
+    struct AccountsVisitor;
+
+    impl<'de> Visitor<'de> for AccountsVisitor {
+        type Value = HashMap<AccountId, Balance>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a map of accounts to balances")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut accounts = HashMap::new();
+            while let Some((account, BalanceValue(balance))) = map.next_entry::<AccountId, BalanceValue>()? {
+                accounts.insert(account, balance);
+            }
+            Ok(accounts)
+        }
+    }
+
+    struct BalanceValue(Balance);
+
+    impl<'de> de::Deserialize<'de> for BalanceValue {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(BalanceVisitor).map(BalanceValue)
+        }
+    }
+
+    struct BalanceVisitor;
+
+    impl Visitor<'_> for BalanceVisitor {
+        type Value = Balance;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a non-negative balance as an integer or decimal string")
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<Balance, E> {
+            Ok(value as Balance)
+        }
+
+        fn visit_u128<E: de::Error>(self, value: u128) -> Result<Balance, E> {
+            Ok(value)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Balance, E> {
+            Balance::try_from(value).map_err(|_| E::custom("negative balance"))
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Balance, E> {
+            value.parse().map_err(|_| E::custom(format!("invalid balance {:?}", value)))
+        }
+    }
+}
+
 /// Energy market configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnergyMarketConfig {
     /// Market opening hours
     pub opening_hours: (u8, u8), // (start_hour, end_hour)
-    /// Trading fee percentage
-    pub trading_fee: f64,
+    /// Trading fee in basis points
+    pub trading_fee_bps: u32,
     /// Minimum order size
-    pub min_order_size: EnergyAmount,
+    pub min_order_size: Energy,
     /// Maximum order size
-    pub max_order_size: EnergyAmount,
+    pub max_order_size: Energy,
 }
 
 /// Node configuration
@@ -161,6 +305,35 @@ pub struct NodeConfig {
     pub ws_port: u16,
     /// Data directory
     pub data_dir: String,
+    /// What the node keeps of finalized history
+    #[serde(default)]
+    pub pruning: PruningMode,
+}
+
+/// History a node keeps once blocks are final.
+///
+/// Unfinalized blocks, the finalized block and the current account state are
+/// kept in every mode.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PruningMode {
+    /// Keep every block body, transaction and historical state
+    #[default]
+    Archive,
+    /// Keep full bodies of the last N blocks; older finalized blocks keep only their headers
+    KeepBlocks(u64),
+    /// Keep only headers of blocks below the finalized block
+    HeadersOnly,
+}
+
+impl PruningMode {
+    /// Parse `archive`, `headers` or a number of blocks to keep
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "archive" => Some(PruningMode::Archive),
+            "headers" => Some(PruningMode::HeadersOnly),
+            blocks => blocks.parse().ok().map(PruningMode::KeepBlocks),
+        }
+    }
 }
 
 /// Consensus configuration
@@ -170,8 +343,34 @@ pub struct ConsensusConfig {
     pub algorithm: ConsensusAlgorithm,
     /// Block time in seconds
     pub block_time: u64,
-    /// Finality threshold
+    /// Share of validator stake, in percent, that must be exceeded by finality votes
     pub finality_threshold: u32,
+    /// Whether the block producer authors blocks without calls or transactions
+    #[serde(default)]
+    pub empty_blocks: EmptyBlocks,
+}
+
+/// How a block producer treats slots with nothing to include
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EmptyBlocks {
+    /// Author a block in every slot
+    #[default]
+    Produce,
+    /// Author only when there is something to include
+    Skip,
+    /// Skip empty slots, but author after this many skipped slots in a row
+    KeepAlive(u32),
+}
+
+impl EmptyBlocks {
+    /// Parse `produce`, `skip` or a number of empty slots to skip at most
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "produce" => Some(EmptyBlocks::Produce),
+            "skip" => Some(EmptyBlocks::Skip),
+            slots => slots.parse().ok().map(EmptyBlocks::KeepAlive),
+        }
+    }
 }
 
 /// Consensus algorithm types
@@ -222,7 +421,7 @@ impl Default for EnergyMarketConfig {
     fn default() -> Self {
         Self {
             opening_hours: (6, 22), // 6 AM to 10 PM
-            trading_fee: constants::GRID_FEE_PERCENTAGE,
+            trading_fee_bps: constants::GRID_FEE_BPS,
             min_order_size: constants::MIN_ENERGY_TRADE,
             max_order_size: constants::MAX_ENERGY_TRADE,
         }
@@ -237,6 +436,7 @@ impl Default for NodeConfig {
             rpc_port: 9933,
             ws_port: 9944,
             data_dir: "./data".to_string(),
+            pruning: PruningMode::Archive,
         }
     }
 }
@@ -246,7 +446,8 @@ impl Default for ConsensusConfig {
         Self {
             algorithm: ConsensusAlgorithm::NPoS,
             block_time: constants::BLOCK_TIME,
-            finality_threshold: 12,
+            finality_threshold: 66,
+            empty_blocks: EmptyBlocks::Produce,
         }
     }
 }
@@ -0,0 +1,226 @@
+// Batch calls
+// This module dispatches the calls of a batch in order and rolls back the
+// service state a failed call, or a failed all-or-nothing batch, left behind
+
+use super::{Call, ReceiptStatus, Runtime, RuntimeStats, SystemEvent};
+use crate::primitives::*;
+#[cfg(feature = "hybrid-multi-signature")]
+use pallet_hybrid_architecture::multisig::MultisigRegistry;
+use crate::services::{EnergyTradingService, SchedulerService, SlashingService, StakingService, TokenService};
+
+/// State calls change, saved to restore it when a call fails.
+///
+/// Calls do not touch the blockchain: they reach it as the one extrinsic
+/// recorded for the top-level call once it has a receipt, so the blockchain
+/// itself needs no saving.
+struct Checkpoint {
+    /// Balances, stakes, proposals and votes
+    token_service: TokenService,
+    /// Order book, trades and participants
+    energy_trading_service: EnergyTradingService,
+    /// Candidacies and nominations
+    staking: StakingService,
+    /// Pending slashes
+    slashing: SlashingService,
+    /// Scheduled calls
+    scheduler: SchedulerService,
+    /// Pending multisig calls
+    #[cfg(feature = "hybrid-multi-signature")]
+    multisig: MultisigRegistry,
+    /// Runtime statistics
+    stats: RuntimeStats,
+}
+
+impl Runtime {
+    /// Dispatch the calls in order, stopping at the first that fails.
+    ///
+    /// The failed call is rolled back; the calls before it keep their effect
+    /// and events. The batch itself succeeds, and its items show where it
+    /// was interrupted. The calls take no checkpoint of their own: when one
+    /// fails, the state from before the batch is restored and the calls
+    /// before it are dispatched again, so only an interrupted batch pays for
+    /// its rollback.
+    pub(super) fn dispatch_batch(
+        &mut self,
+        from: &AccountId,
+        calls: Vec<Call>,
+        block_number: BlockNumber,
+        events: &mut Vec<SystemEvent>,
+        items: &mut Vec<ReceiptStatus>,
+    ) -> CoreResult<()> {
+        check_batch_size(&calls)?;
+        let checkpoint = self.checkpoint();
+        let mut done = 0;
+        for call in &calls {
+            let mut call_events = Vec::new();
+            match self.dispatch(from, call.clone(), block_number, &mut call_events, &mut Vec::new()) {
+                Ok(()) => {
+                    events.append(&mut call_events);
+                    items.push(ReceiptStatus::Success);
+                    done += 1;
+                }
+                Err(error) => {
+                    items.push(ReceiptStatus::Failed { error: error.to_string() });
+                    break;
+                }
+            }
+        }
+        if done < calls.len() {
+            self.restore_checkpoint(checkpoint);
+            for call in calls.into_iter().take(done) {
+                self.dispatch(from, call, block_number, &mut Vec::new(), &mut Vec::new())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Dispatch the calls in order, failing if one fails.
+    ///
+    /// Every state change of the batch, token, trading and the other
+    /// services alike, is rolled back by the call, scheduled call or batch
+    /// the batch failed in, so it takes no checkpoint of its own. The
+    /// blockchain has nothing to roll back: calls reach it only as the
+    /// extrinsic recorded with the receipt of a top-level call. The items
+    /// list the outcome of each call up to the one that failed.
+    pub(super) fn dispatch_batch_all(
+        &mut self,
+        from: &AccountId,
+        calls: Vec<Call>,
+        block_number: BlockNumber,
+        events: &mut Vec<SystemEvent>,
+        items: &mut Vec<ReceiptStatus>,
+    ) -> CoreResult<()> {
+        check_batch_size(&calls)?;
+        for (index, call) in calls.into_iter().enumerate() {
+            if let Err(error) = self.dispatch(from, call, block_number, events, &mut Vec::new()) {
+                items.push(ReceiptStatus::Failed { error: error.to_string() });
+                return Err(CoreError::ValidationFailed(format!("Batch call {} failed: {}", index, error)));
+            }
+            items.push(ReceiptStatus::Success);
+        }
+        Ok(())
+    }
+
+    /// Run a top-level call, restoring the state calls change if it fails.
+    ///
+    /// This is the one checkpoint a call takes; nested calls fail through to it.
+    pub(super) fn transactional(&mut self, f: impl FnOnce(&mut Self) -> CoreResult<()>) -> CoreResult<()> {
+        let checkpoint = self.checkpoint();
+        let result = f(self);
+        if result.is_err() {
+            self.restore_checkpoint(checkpoint);
+        }
+        result
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            token_service: self.token_service.clone(),
+            energy_trading_service: self.energy_trading_service.clone(),
+            staking: self.staking.clone(),
+            slashing: self.slashing.clone(),
+            scheduler: self.scheduler.clone(),
+            #[cfg(feature = "hybrid-multi-signature")]
+            multisig: self.multisig.clone(),
+            stats: self.state.stats.clone(),
+        }
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.token_service = checkpoint.token_service;
+        self.energy_trading_service = checkpoint.energy_trading_service;
+        self.staking = checkpoint.staking;
+        self.slashing = checkpoint.slashing;
+        self.scheduler = checkpoint.scheduler;
+        #[cfg(feature = "hybrid-multi-signature")]
+        {
+            self.multisig = checkpoint.multisig;
+        }
+        self.state.stats = checkpoint.stats;
+    }
+}
+
+/// Refuse batches longer than [`constants::MAX_BATCH_CALLS`]
+fn check_batch_size(calls: &[Call]) -> CoreResult<()> {
+    if calls.len() > constants::MAX_BATCH_CALLS {
+        return Err(CoreError::InvalidInput(format!(
+            "Batch of {} calls exceeds the limit of {}", calls.len(), constants::MAX_BATCH_CALLS
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::crypto;
+
+    #[test]
+    fn test_batch_all_rolls_back_every_call() {
+        let config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        let mut runtime = Runtime::new(config);
+        runtime.initialize_genesis().unwrap();
+        let (alice, bob) = ["alice", "bob"].map(crypto::dev_account_id).into();
+        let transfer = |amount| Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount };
+        let watt = |runtime: &Runtime, account| runtime.token_service.get_balance(account, &"WATT".to_string());
+        let (alice_watt, bob_watt) = (watt(&runtime, &alice), watt(&runtime, &bob));
+
+        // The second transfer overdraws, so neither takes effect
+        let calls = vec![transfer(100), transfer(alice_watt)];
+        let receipt = runtime.execute_call(alice.clone(), Call::BatchAll { calls: calls.clone() }).unwrap();
+        assert!(!receipt.is_success());
+        assert!(receipt.events.is_empty());
+        assert_eq!(receipt.items.len(), 2);
+        assert_eq!(receipt.items[0], ReceiptStatus::Success);
+        assert!(matches!(receipt.items[1], ReceiptStatus::Failed { .. }));
+        assert_eq!((watt(&runtime, &alice), watt(&runtime, &bob)), (alice_watt, bob_watt));
+        assert_eq!(runtime.state.stats.total_token_transfers, 0);
+
+        // A plain batch keeps the transfer before the failure and skips the rest
+        let calls = vec![transfer(100), transfer(alice_watt), transfer(5)];
+        let receipt = runtime.execute_call(alice.clone(), Call::Batch { calls }).unwrap();
+        assert!(receipt.is_success());
+        assert_eq!(receipt.items.len(), 2);
+        assert_eq!(receipt.events.len(), 1);
+        assert_eq!(watt(&runtime, &bob), bob_watt + 100);
+
+        // Each batch is recorded as one call and re-executes identically
+        runtime.produce_block_at(alice.clone(), 1_000).unwrap();
+        assert_eq!(runtime.blockchain.get_block_by_number(1).unwrap().body.calls.len(), 2);
+        assert!(runtime.verify_chain().unwrap().is_valid());
+
+        let oversized = vec![transfer(1); constants::MAX_BATCH_CALLS + 1];
+        assert!(!runtime.execute_call(alice, Call::BatchAll { calls: oversized }).unwrap().is_success());
+    }
+
+    #[test]
+    fn test_batch_rolls_back_only_the_failed_call() {
+        let config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        let mut runtime = Runtime::new(config);
+        runtime.initialize_genesis().unwrap();
+        let (alice, bob) = ["alice", "bob"].map(crypto::dev_account_id).into();
+        let transfer = |amount| Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount };
+        let watt = |runtime: &Runtime, account| runtime.token_service.get_balance(account, &"WATT".to_string());
+        let (alice_watt, bob_watt) = (watt(&runtime, &alice), watt(&runtime, &bob));
+
+        // The nested all-or-nothing batch transfers 10 before it fails; only that is undone
+        let failing = Call::BatchAll { calls: vec![transfer(10), transfer(alice_watt)] };
+        let calls = vec![transfer(100), transfer(20), failing, transfer(5)];
+        let receipt = runtime.execute_call(alice.clone(), Call::Batch { calls }).unwrap();
+        assert!(receipt.is_success());
+        assert_eq!(receipt.items.len(), 3);
+        assert!(matches!(receipt.items[2], ReceiptStatus::Failed { .. }));
+        assert_eq!(receipt.events.len(), 2);
+        assert_eq!(watt(&runtime, &bob), bob_watt + 120);
+        assert_eq!(watt(&runtime, &alice), alice_watt - 120);
+        assert_eq!(runtime.state.stats.total_token_transfers, 2);
+
+        // A batch whose first call fails keeps nothing but the fee
+        let receipt = runtime.execute_call(alice.clone(), Call::Batch { calls: vec![transfer(alice_watt), transfer(1)] }).unwrap();
+        assert!(receipt.is_success() && receipt.events.is_empty());
+        assert_eq!(watt(&runtime, &bob), bob_watt + 120);
+
+        runtime.produce_block_at(alice, 1_000).unwrap();
+        assert!(runtime.verify_chain().unwrap().is_valid());
+    }
+}
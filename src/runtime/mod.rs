@@ -6,6 +6,22 @@ use crate::core::*;
 use crate::services::*;
 use serde::{Deserialize, Serialize};
 use serde_json;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "hybrid-multi-signature")]
+use pallet_hybrid_architecture::multisig::{MultisigConfig, MultisigRegistry};
+
+mod batch;
+#[cfg(feature = "hybrid-multi-signature")]
+mod multisig;
+mod node;
+mod snapshot;
+mod verifier;
+
+#[cfg(feature = "hybrid-multi-signature")]
+pub use multisig::multisig_account_id;
+pub use node::*;
+pub use snapshot::*;
+pub use verifier::*;
 
 /// Main runtime for the Thai Energy Trading System
 pub struct Runtime {
@@ -15,6 +31,17 @@ pub struct Runtime {
     pub token_service: TokenService,
     /// Energy trading service
     pub energy_trading_service: EnergyTradingService,
+    /// Validator candidacies, nominations and era elections
+    pub staking: StakingService,
+    /// Deferred slashes of misbehaving validators
+    pub slashing: SlashingService,
+    /// Call fees and block weight
+    pub fees: FeeService,
+    /// Calls scheduled for later blocks
+    pub scheduler: SchedulerService,
+    /// Multisig calls waiting for approvals
+    #[cfg(feature = "hybrid-multi-signature")]
+    pub multisig: MultisigRegistry,
     /// System configuration
     pub config: SystemConfig,
     /// Runtime state
@@ -30,6 +57,14 @@ pub struct RuntimeState {
     pub current_block: BlockNumber,
     /// System events
     pub events: Vec<SystemEvent>,
+    /// Events of recent blocks by block number
+    pub block_events: BTreeMap<BlockNumber, Vec<SystemEvent>>,
+    /// Receipts of calls executed for the next block, in execution order
+    pub pending_receipts: Vec<TransactionReceipt>,
+    /// Receipts of recent blocks by block number
+    pub receipts: BTreeMap<BlockNumber, Vec<TransactionReceipt>>,
+    /// Block number and index of recent receipts by transaction hash
+    pub receipt_index: HashMap<Hash, (BlockNumber, u32)>,
     /// Runtime statistics
     pub stats: RuntimeStats,
 }
@@ -59,7 +94,7 @@ pub struct ApiVersion {
 }
 
 /// System event
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SystemEvent {
     /// Event type
     pub event_type: EventType,
@@ -86,8 +121,14 @@ pub enum EventType {
     ProposalCreated,
     /// Vote cast
     VoteCast,
+    /// Offence reported; its slash waits for the deferral window
+    OffenceReported,
     /// Validator slashed
     ValidatorSlashed,
+    /// Validator set elected for a new era
+    ValidatorsElected,
+    /// Scheduled call dispatched
+    ScheduledCallDispatched,
     /// System error
     SystemError,
 }
@@ -109,21 +150,49 @@ pub struct RuntimeStats {
     pub uptime: u64,
 }
 
-/// Transaction execution result
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ExecutionResult {
-    /// Success flag
-    pub success: bool,
-    /// Transaction hash
+/// Outcome of a call
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ReceiptStatus {
+    /// The call took effect
+    Success,
+    /// The call was rejected
+    Failed {
+        /// Why the call was rejected
+        error: String,
+    },
+}
+
+/// Record of a call executed in a block
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransactionReceipt {
+    /// Hash of the call, derived from its sender, content and position
     pub transaction_hash: Hash,
-    /// Block number
+    /// Calling account
+    pub sender: AccountId,
+    /// Number of the block the call is part of
     pub block_number: BlockNumber,
-    /// Gas used
-    pub gas_used: u64,
-    /// Events emitted
+    /// Hash of that block, `None` until the block is produced
+    pub block_hash: Option<Hash>,
+    /// Position among the calls of the block
+    pub index: u32,
+    /// Whether the call took effect
+    pub status: ReceiptStatus,
+    /// Weight the call was charged for
+    pub weight: Weight,
+    /// Fee charged to the sender
+    pub fee: FeeBreakdown,
+    /// Events the call emitted
     pub events: Vec<SystemEvent>,
-    /// Error message if failed
-    pub error: Option<String>,
+    /// Outcome of each call of a batch, in order; empty for other calls
+    #[serde(default)]
+    pub items: Vec<ReceiptStatus>,
+}
+
+impl TransactionReceipt {
+    /// Whether the call took effect
+    pub fn is_success(&self) -> bool {
+        self.status == ReceiptStatus::Success
+    }
 }
 
 /// Dispatch call types
@@ -157,6 +226,49 @@ pub enum Call {
     Unstake {
         amount: Balance,
     },
+    /// Declare validator candidacy with the caller's staked GRID
+    Validate,
+    /// Back validator candidates with the caller's staked GRID
+    Nominate {
+        targets: Vec<AccountId>,
+    },
+    /// Cancel a deferred slash; governance only
+    CancelSlash {
+        slash_id: u64,
+    },
+    /// Dispatch a call as the caller at a later block or time, optionally repeating
+    Schedule {
+        when: DispatchTime,
+        repeat: Option<Repeat>,
+        call: Box<Call>,
+    },
+    /// Cancel a call the caller scheduled
+    CancelScheduled {
+        id: u64,
+    },
+    /// Approve a call of the multisig account of `signatories` and `threshold` as one of its signatories;
+    /// the approval reaching the threshold dispatches the call as the multisig account
+    #[cfg(feature = "hybrid-multi-signature")]
+    AsMulti {
+        signatories: Vec<AccountId>,
+        threshold: u16,
+        call: Box<Call>,
+    },
+    /// Withdraw a pending multisig call; only the signatory who approved it first
+    #[cfg(feature = "hybrid-multi-signature")]
+    CancelAsMulti {
+        signatories: Vec<AccountId>,
+        threshold: u16,
+        call_hash: Hash,
+    },
+    /// Dispatch calls in order, stopping at the first that fails; the calls before it keep their effect
+    Batch {
+        calls: Vec<Call>,
+    },
+    /// Dispatch calls in order, all or none: if one fails, the whole batch is rolled back
+    BatchAll {
+        calls: Vec<Call>,
+    },
 }
 
 impl Runtime {
@@ -165,11 +277,19 @@ impl Runtime {
         let blockchain = Blockchain::new(config.clone());
         let token_service = TokenService::new();
         let energy_trading_service = EnergyTradingService::new(config.chain_spec.genesis.energy_market.clone());
+        let staking = StakingService::new(StakingConfig::default());
+        let slashing = SlashingService::new(SlashingConfig::default());
+        let fees = FeeService::new(FeeConfig::default());
+        let scheduler = SchedulerService::new(SchedulerConfig::default());
 
         let state = RuntimeState {
             version: RuntimeVersion::default(),
             current_block: 0,
             events: Vec::new(),
+            block_events: BTreeMap::new(),
+            pending_receipts: Vec::new(),
+            receipts: BTreeMap::new(),
+            receipt_index: HashMap::new(),
             stats: RuntimeStats::default(),
         };
 
@@ -177,43 +297,156 @@ impl Runtime {
             blockchain,
             token_service,
             energy_trading_service,
+            staking,
+            slashing,
+            fees,
+            scheduler,
+            #[cfg(feature = "hybrid-multi-signature")]
+            multisig: MultisigRegistry::new(MultisigConfig::default()),
             config,
             state,
         }
     }
 
+    /// Create runtime backed by persistent storage under `node_config.data_dir`
+    pub fn open(config: SystemConfig) -> CoreResult<Self> {
+        let mut runtime = Self::new(config.clone());
+        runtime.blockchain = Blockchain::open(config)?;
+        runtime.state.current_block = runtime.blockchain.get_block_height();
+        runtime.state.stats.total_blocks = runtime.state.current_block;
+        Ok(runtime)
+    }
+
     /// Initialize runtime with genesis accounts
     pub fn initialize_genesis(&mut self) -> CoreResult<()> {
+        self.sync_service_time();
         // Initialize genesis accounts
         for (account, balance) in &self.config.chain_spec.genesis.accounts {
             self.token_service.mint(account, "GRID", *balance)?;
             self.token_service.mint(account, "WATT", *balance)?;
         }
 
-        // Initialize validators
+        // Initialize validators; they form the active set of the first era
         for validator in &self.config.chain_spec.genesis.validators {
             self.token_service.stake(validator, constants::MIN_VALIDATOR_STAKE, None)?;
+            self.staking.declare_candidacy(validator, &self.token_service)?;
         }
+        self.staking.active = self.staking.elect(&self.token_service);
 
         // Emit genesis event
         self.emit_event(SystemEvent {
             event_type: EventType::BlockFinalized,
             data: serde_json::json!({"block_number": 0, "genesis": true}),
             block_number: 0,
-            timestamp: chrono::Utc::now().timestamp() as u64,
+            timestamp: self.chain_time(),
         });
 
         Ok(())
     }
 
-    /// Execute a call
-    pub fn execute_call(&mut self, from: AccountId, call: Call) -> CoreResult<ExecutionResult> {
-        let transaction_hash = format!("0x{:x}", fastrand::u64(..));
+    /// Execute a call as part of the next block and record its receipt.
+    ///
+    /// The sender pays the fee for the call's weight up front. A call that is
+    /// rejected afterwards still pays and gets a receipt, with the error as
+    /// its status and no events; whatever it changed before failing is
+    /// rolled back. A call that cannot pay, or does not fit in
+    /// the block, fails without a receipt. Calls with a receipt are recorded
+    /// in the next block, so that the block can be re-executed.
+    pub fn execute_call(&mut self, from: AccountId, call: Call) -> CoreResult<TransactionReceipt> {
+        self.sync_service_time();
+        let block_number = self.state.current_block + 1;
+        let index = self.state.pending_receipts.len() as u32;
+        let encoded = serde_json::to_vec(&(&from, &call, block_number, index))
+            .map_err(|e| CoreError::InvalidInput(format!("Failed to encode call: {}", e)))?;
+        let transaction_hash = crate::utils::crypto::sha256_hash(&encoded);
+        let weight = self.call_weight(&call, encoded.len());
+        let extrinsic = Extrinsic {
+            sender: from.clone(),
+            call: serde_json::to_vec(&call)
+                .map_err(|e| CoreError::InvalidInput(format!("Failed to encode call: {}", e)))?,
+        };
+        let fee = self.fees.charge(&from, weight, &mut self.token_service)?;
+
         let mut events = Vec::new();
+        let mut items = Vec::new();
+        let dispatched = self.transactional(|runtime| runtime.dispatch(&from, call, block_number, &mut events, &mut items));
+        let status = match dispatched {
+            Ok(()) => ReceiptStatus::Success,
+            Err(error) => {
+                events.clear();
+                ReceiptStatus::Failed { error: error.to_string() }
+            }
+        };
+        for event in &events {
+            self.emit_event(event.clone());
+        }
+
+        let receipt = TransactionReceipt {
+            transaction_hash,
+            sender: from,
+            block_number,
+            block_hash: None,
+            index,
+            status,
+            weight,
+            fee,
+            events,
+            items,
+        };
+        self.state.pending_receipts.push(receipt.clone());
+        self.blockchain.record_call(extrinsic);
+        Ok(receipt)
+    }
 
-        let result = match call {
+    /// Weight of a call: the work its variant does plus a cost per encoded byte
+    pub fn call_weight(&self, call: &Call, encoded_len: usize) -> Weight {
+        let work = match call {
+            Call::TokenTransfer { .. } => 200,
+            Call::EnergyTrade { .. } => {
+                // Matching walks every resting order
+                let book = &self.energy_trading_service.order_book;
+                let resting: usize = book.buy_orders.values().chain(book.sell_orders.values()).map(Vec::len).sum();
+                500 + 20 * resting as Weight
+            }
+            Call::CreateProposal { .. } => 400,
+            Call::Vote { .. } => 150,
+            Call::Stake { validator, .. } => if validator.is_some() { 500 } else { 300 },
+            Call::Unstake { .. } => 300,
+            Call::Validate => 300,
+            Call::Nominate { targets } => 200 + 100 * targets.len() as Weight,
+            Call::CancelSlash { .. } => 100,
+            // Every dispatch is paid for when scheduling
+            Call::Schedule { repeat, call, .. } => {
+                let runs = repeat.map_or(1, |repeat| repeat.times as Weight);
+                200 + self.call_weight(call, 0).saturating_mul(runs)
+            }
+            Call::CancelScheduled { .. } => 100,
+            #[cfg(feature = "hybrid-multi-signature")]
+            Call::AsMulti { signatories, call, .. } => {
+                300 + 10 * signatories.len() as Weight + self.call_weight(call, 0)
+            }
+            #[cfg(feature = "hybrid-multi-signature")]
+            Call::CancelAsMulti { signatories, .. } => 150 + 10 * signatories.len() as Weight,
+            Call::Batch { calls } | Call::BatchAll { calls } => {
+                100 + calls.iter().map(|call| self.call_weight(call, 0)).sum::<Weight>()
+            }
+        };
+        work + encoded_len as Weight
+    }
+
+    /// Apply a call to the services, collecting the events it emits and, for a batch, the outcome of each of its calls
+    fn dispatch(
+        &mut self,
+        from: &AccountId,
+        call: Call,
+        block_number: BlockNumber,
+        events: &mut Vec<SystemEvent>,
+        items: &mut Vec<ReceiptStatus>,
+    ) -> CoreResult<()> {
+        let now = self.chain_time();
+        match call {
             Call::TokenTransfer { to, token, amount } => {
-                self.token_service.transfer(&from, &to, &token, amount)?;
+                self.token_service.transfer(from, &to, &token, amount)?;
                 events.push(SystemEvent {
                     event_type: EventType::TokenTransferred,
                     data: serde_json::json!({
@@ -222,11 +455,11 @@ impl Runtime {
                         "token": token,
                         "amount": amount
                     }),
-                    block_number: self.state.current_block,
-                    timestamp: chrono::Utc::now().timestamp() as u64,
+                    block_number,
+                    timestamp: now,
                 });
                 self.state.stats.total_token_transfers += 1;
-                Ok(()) as CoreResult<()>
+                Ok(())
             }
             Call::EnergyTrade { order } => {
                 let order_id = self.energy_trading_service.place_order(order)?;
@@ -236,15 +469,15 @@ impl Runtime {
                         "order_id": order_id,
                         "account": from
                     }),
-                    block_number: self.state.current_block,
-                    timestamp: chrono::Utc::now().timestamp() as u64,
+                    block_number,
+                    timestamp: now,
                 });
                 self.state.stats.total_energy_trades += 1;
-                Ok(()) as CoreResult<()>
+                Ok(())
             }
             Call::CreateProposal { proposal } => {
                 let proposal_id = self.token_service.create_proposal(
-                    &from,
+                    from,
                     proposal.title.clone(),
                     proposal.description.clone(),
                     proposal.voting_period,
@@ -255,30 +488,31 @@ impl Runtime {
                         "proposal_id": proposal_id,
                         "proposer": from
                     }),
-                    block_number: self.state.current_block,
-                    timestamp: chrono::Utc::now().timestamp() as u64,
+                    block_number,
+                    timestamp: now,
                 });
                 self.state.stats.active_proposals += 1;
-                Ok(()) as CoreResult<()>
+                Ok(())
             }
             Call::Vote { proposal_id, vote } => {
-                // Clone the vote to avoid move issues
-                let vote_clone = vote.clone();
-                self.token_service.vote_on_proposal(&from, &proposal_id, vote)?;
+                self.token_service.vote_on_proposal(from, &proposal_id, vote.clone())?;
                 events.push(SystemEvent {
                     event_type: EventType::VoteCast,
                     data: serde_json::json!({
                         "voter": from,
                         "proposal_id": proposal_id,
-                        "vote": vote_clone
+                        "vote": vote
                     }),
-                    block_number: self.state.current_block,
-                    timestamp: chrono::Utc::now().timestamp() as u64,
+                    block_number,
+                    timestamp: now,
                 });
                 Ok(())
             }
             Call::Stake { amount, validator } => {
-                self.token_service.stake(&from, amount, validator.as_ref())?;
+                self.token_service.stake(from, amount, validator.as_ref())?;
+                if let Some(validator) = &validator {
+                    self.staking.nominate(from, std::slice::from_ref(validator), &self.token_service)?;
+                }
                 events.push(SystemEvent {
                     event_type: EventType::TokenTransferred,
                     data: serde_json::json!({
@@ -286,13 +520,13 @@ impl Runtime {
                         "amount": amount,
                         "action": "stake"
                     }),
-                    block_number: self.state.current_block,
-                    timestamp: chrono::Utc::now().timestamp() as u64,
+                    block_number,
+                    timestamp: now,
                 });
                 Ok(())
             }
             Call::Unstake { amount } => {
-                self.token_service.unstake(&from, amount)?;
+                self.token_service.unstake(from, amount)?;
                 events.push(SystemEvent {
                     event_type: EventType::TokenTransferred,
                     data: serde_json::json!({
@@ -300,47 +534,156 @@ impl Runtime {
                         "amount": amount,
                         "action": "unstake"
                     }),
-                    block_number: self.state.current_block,
-                    timestamp: chrono::Utc::now().timestamp() as u64,
+                    block_number,
+                    timestamp: now,
                 });
                 Ok(())
             }
-        };
+            Call::Validate => {
+                self.staking.declare_candidacy(from, &self.token_service)?;
+                events.push(SystemEvent {
+                    event_type: EventType::TransactionExecuted,
+                    data: serde_json::json!({
+                        "account": from,
+                        "action": "validate"
+                    }),
+                    block_number,
+                    timestamp: now,
+                });
+                Ok(())
+            }
+            Call::Nominate { targets } => {
+                self.staking.nominate(from, &targets, &self.token_service)?;
+                events.push(SystemEvent {
+                    event_type: EventType::TransactionExecuted,
+                    data: serde_json::json!({
+                        "account": from,
+                        "targets": targets,
+                        "action": "nominate"
+                    }),
+                    block_number,
+                    timestamp: now,
+                });
+                Ok(())
+            }
+            Call::CancelSlash { slash_id } => {
+                let slash = self.slashing.cancel(from, slash_id, &mut self.token_service)?;
+                events.push(SystemEvent {
+                    event_type: EventType::TransactionExecuted,
+                    data: serde_json::json!({
+                        "account": from,
+                        "slash_id": slash_id,
+                        "offender": slash.offender,
+                        "action": "cancel_slash"
+                    }),
+                    block_number,
+                    timestamp: now,
+                });
+                Ok(())
+            }
+            Call::Schedule { when, repeat, call } => {
+                let encoded = serde_json::to_vec(&call)
+                    .map_err(|e| CoreError::InvalidInput(format!("Failed to encode call: {}", e)))?;
+                let weight = self.call_weight(&call, encoded.len());
+                let id = self.scheduler.schedule(from, when, repeat, encoded, weight, block_number)?;
+                events.push(SystemEvent {
+                    event_type: EventType::TransactionExecuted,
+                    data: serde_json::json!({
+                        "account": from,
+                        "id": id,
+                        "when": when,
+                        "repeat": repeat,
+                        "action": "schedule"
+                    }),
+                    block_number,
+                    timestamp: now,
+                });
+                Ok(())
+            }
+            Call::CancelScheduled { id } => {
+                self.scheduler.cancel(from, id)?;
+                events.push(SystemEvent {
+                    event_type: EventType::TransactionExecuted,
+                    data: serde_json::json!({
+                        "account": from,
+                        "id": id,
+                        "action": "cancel_scheduled"
+                    }),
+                    block_number,
+                    timestamp: now,
+                });
+                Ok(())
+            }
+            #[cfg(feature = "hybrid-multi-signature")]
+            Call::AsMulti { signatories, threshold, call } => {
+                let multisig = multisig::multisig(signatories, threshold)?;
+                self.dispatch_as_multi(from, multisig, *call, block_number, events, items)
+            }
+            #[cfg(feature = "hybrid-multi-signature")]
+            Call::CancelAsMulti { signatories, threshold, call_hash } => {
+                let multisig = multisig::multisig(signatories, threshold)?;
+                self.cancel_as_multi(from, multisig, &call_hash, block_number, events)
+            }
+            Call::Batch { calls } => self.dispatch_batch(from, calls, block_number, events, items),
+            Call::BatchAll { calls } => self.dispatch_batch_all(from, calls, block_number, events, items),
+        }
+    }
 
-        // Emit events
-        for event in &events {
-            self.emit_event(event.clone());
+    /// Produce new block at the current time
+    pub fn produce_block(&mut self, author: AccountId) -> CoreResult<Block> {
+        self.produce_block_at(author, crate::utils::time::current_timestamp())
+    }
+
+    /// Produce new block with the given timestamp.
+    ///
+    /// Offences are processed and, on the last block of an era, the next
+    /// validators elected before the block is built, so that the block can
+    /// announce the new finality authorities.
+    pub fn produce_block_at(&mut self, author: AccountId, timestamp: Timestamp) -> CoreResult<Block> {
+        let election = self.initialize_block()?;
+        if let Some(authorities) = self.elected_authorities(election.as_ref()) {
+            self.blockchain.schedule_authority_change(authorities);
         }
+        let receipts = &self.state.pending_receipts;
+        let block = self
+            .blockchain
+            .mine_block_with(author, timestamp, |block, batch| stage_receipts(receipts, block, batch))?;
+        self.finalize_block(&block, election);
+        Ok(block)
+    }
 
-        // Create execution result
-        match result {
-            Ok(()) => Ok(ExecutionResult {
-                success: true,
-                transaction_hash,
-                block_number: self.state.current_block,
-                gas_used: 1000, // Simplified gas calculation
-                events,
-                error: None,
-            }),
-            Err(error) => Ok(ExecutionResult {
-                success: false,
-                transaction_hash,
-                block_number: self.state.current_block,
-                gas_used: 500, // Less gas for failed transactions
-                events,
-                error: Some(error.to_string()),
-            }),
+    /// Work done before the next block is built: the state checkpoint, offences, due scheduled calls, lapsed multisig approvals, and the election on the last block of an era
+    fn initialize_block(&mut self) -> CoreResult<Option<ElectionResult>> {
+        let number = self.blockchain.get_block_height() + 1;
+        self.save_state_checkpoint()?;
+        self.sync_service_time();
+        self.process_offences(number)?;
+        self.dispatch_scheduled(number);
+        #[cfg(feature = "hybrid-multi-signature")]
+        self.multisig.expire(number);
+        Ok(self.staking.end_block(number, &self.token_service))
+    }
+
+    /// Finality authorities an election hands over to; only NPoS chains follow elections
+    fn elected_authorities(&self, election: Option<&ElectionResult>) -> Option<Vec<Authority>> {
+        if self.config.consensus_config.algorithm != ConsensusAlgorithm::NPoS {
+            return None;
         }
+        election.map(|election| {
+            election.validators.iter()
+                .map(|id| Authority { id: id.clone(), weight: election.exposures[id].total })
+                .collect()
+        })
     }
 
-    /// Produce new block
-    pub fn produce_block(&mut self, author: AccountId) -> CoreResult<Block> {
-        let block = self.blockchain.mine_block(author)?;
-        
+    /// Work done once `block` is on the chain: receipts, fee multiplier and block events
+    fn finalize_block(&mut self, block: &Block, election: Option<ElectionResult>) {
         // Update runtime state
         self.state.current_block = block.header.number;
         self.state.stats.total_blocks = block.header.number;
         self.state.stats.total_transactions += block.body.transactions.len() as u64;
+        self.store_receipts(block);
+        self.fees.end_block();
 
         // Emit block finalized event
         self.emit_event(SystemEvent {
@@ -350,14 +693,205 @@ impl Runtime {
                 "transaction_count": block.body.transactions.len()
             }),
             block_number: block.header.number,
-            timestamp: chrono::Utc::now().timestamp() as u64,
+            timestamp: block.header.timestamp,
         });
 
-        Ok(block)
+        if let Some(election) = election {
+            self.emit_event(SystemEvent {
+                event_type: EventType::ValidatorsElected,
+                data: serde_json::json!({
+                    "era": election.era,
+                    "validators": election.validators,
+                    "exposures": election.exposures
+                }),
+                block_number: block.header.number,
+                timestamp: block.header.timestamp,
+            });
+        }
+    }
+
+    /// Time as the chain sees it: the timestamp of the best block.
+    ///
+    /// Execution must not read the wall clock, or re-executing a block on
+    /// another node or later would give a different result.
+    fn chain_time(&self) -> Timestamp {
+        self.blockchain.state.best_block.header.timestamp
+    }
+
+    /// Hand the chain time to the services that stamp their records with it
+    fn sync_service_time(&mut self) {
+        let now = self.chain_time();
+        self.token_service.now = now;
+        self.energy_trading_service.now = now;
+        self.scheduler.now = now;
+    }
+
+    /// Report offences detected up to block `number` and apply the slashes that are due.
+    ///
+    /// Downtime is judged on the last block of each era. Reported validators
+    /// are chilled so they are not elected again.
+    fn process_offences(&mut self, number: BlockNumber) -> CoreResult<()> {
+        let era = self.staking.era_of(number);
+        let now = self.chain_time();
+        if self.staking.era_of(number + 1) != era {
+            self.blockchain.note_unresponsive(self.slashing.config.max_missed_slots);
+        }
+
+        for offence in self.blockchain.take_offences() {
+            let offender = offence.offender().clone();
+            let exposure = self.staking.active.exposures.get(&offender).cloned().unwrap_or_else(|| {
+                let own = self.token_service.bonded(&offender);
+                Exposure { total: own, own, others: Vec::new() }
+            });
+            self.staking.chill(&offender);
+            let Some(slash) = self.slashing.report(&offence, &exposure, era, number, &mut self.token_service) else {
+                continue;
+            };
+            let data = serde_json::json!({
+                "slash_id": slash.id,
+                "offender": offender,
+                "kind": slash.kind,
+                "amount": slash.total(),
+                "apply_at": slash.apply_at,
+                "offence": offence
+            });
+            self.emit_event(SystemEvent {
+                event_type: EventType::OffenceReported,
+                data,
+                block_number: number,
+                timestamp: now,
+            });
+        }
+
+        for (slash, amount) in self.slashing.apply_due(number, &mut self.token_service)? {
+            self.emit_event(SystemEvent {
+                event_type: EventType::ValidatorSlashed,
+                data: serde_json::json!({
+                    "slash_id": slash.id,
+                    "offender": slash.offender,
+                    "kind": slash.kind,
+                    "amount": amount,
+                    "nominators": slash.others,
+                    "treasury": self.slashing.config.treasury
+                }),
+                block_number: number,
+                timestamp: now,
+            });
+        }
+        Ok(())
+    }
+
+    /// Dispatch the scheduled calls due at block `number`, each as its owner.
+    ///
+    /// A call that fails is rolled back; the outcome of every call is
+    /// reported in a `ScheduledCallDispatched` event.
+    fn dispatch_scheduled(&mut self, number: BlockNumber) {
+        let now = self.chain_time();
+        for task in self.scheduler.take_due(number) {
+            let mut events = Vec::new();
+            let result = serde_json::from_slice::<Call>(&task.call)
+                .map_err(|e| CoreError::InvalidInput(format!("Failed to decode scheduled call: {}", e)))
+                .and_then(|call| self.transactional(|runtime| {
+                    runtime.dispatch(&task.owner, call, number, &mut events, &mut Vec::new())
+                }));
+            let status = match result {
+                Ok(()) => ReceiptStatus::Success,
+                Err(error) => {
+                    events.clear();
+                    ReceiptStatus::Failed { error: error.to_string() }
+                }
+            };
+            for event in events {
+                self.emit_event(event);
+            }
+            self.emit_event(SystemEvent {
+                event_type: EventType::ScheduledCallDispatched,
+                data: serde_json::json!({
+                    "id": task.id,
+                    "owner": task.owner,
+                    "status": status
+                }),
+                block_number: number,
+                timestamp: now,
+            });
+        }
+    }
+
+    /// Attach the pending receipts to a produced block, dropping receipts of old blocks
+    fn store_receipts(&mut self, block: &Block) {
+        let number = block.header.number;
+        let mut receipts = std::mem::take(&mut self.state.pending_receipts);
+        for receipt in &mut receipts {
+            receipt.block_hash = Some(block.hash());
+            self.state.receipt_index.insert(receipt.transaction_hash.clone(), (number, receipt.index));
+        }
+        self.state.receipts.insert(number, receipts);
+
+        let oldest = number.saturating_sub(constants::EVENT_HISTORY_BLOCKS);
+        while let Some(entry) = self.state.receipts.first_entry() {
+            if *entry.key() >= oldest {
+                break;
+            }
+            for receipt in entry.remove() {
+                self.state.receipt_index.remove(&receipt.transaction_hash);
+            }
+        }
+    }
+
+    /// Receipt of a call included in a best-chain block.
+    ///
+    /// Receipts of recent blocks are served from memory, older ones from the
+    /// block store, so they outlive a restart.
+    pub fn get_receipt(&self, transaction_hash: &Hash) -> CoreResult<Option<TransactionReceipt>> {
+        if let Some((number, index)) = self.state.receipt_index.get(transaction_hash) {
+            return Ok(self.state.receipts.get(number).and_then(|receipts| receipts.get(*index as usize)).cloned());
+        }
+        let storage = self.blockchain.storage.as_ref();
+        let Some(block_hash) = read_value::<Hash>(storage, Column::Receipts, &receipt_block_key(transaction_hash))? else {
+            return Ok(None);
+        };
+        let receipts: Vec<TransactionReceipt> =
+            read_value(storage, Column::Receipts, &block_receipts_key(&block_hash))?.unwrap_or_default();
+        Ok(receipts.into_iter().find(|receipt| {
+            &receipt.transaction_hash == transaction_hash
+                && self.blockchain.get_block_by_number(receipt.block_number).map(Block::hash) == Some(block_hash.clone())
+        }))
+    }
+
+    /// Receipts of the calls in a best-chain block, in execution order
+    pub fn get_receipts_by_block(&self, number: BlockNumber) -> CoreResult<Vec<TransactionReceipt>> {
+        if let Some(receipts) = self.state.receipts.get(&number) {
+            return Ok(receipts.clone());
+        }
+        let Some(block) = self.blockchain.get_block_by_number(number) else {
+            return Ok(Vec::new());
+        };
+        let key = block_receipts_key(&block.hash());
+        Ok(read_value(self.blockchain.storage.as_ref(), Column::Receipts, &key)?.unwrap_or_default())
+    }
+
+    /// Validator set of the current era with each validator's exposure
+    pub fn get_validator_set(&self) -> &ElectionResult {
+        &self.staking.active
+    }
+
+    /// Events emitted in a block, oldest first
+    pub fn get_events_by_block(&self, number: BlockNumber, page: &PageRequest) -> Page<&SystemEvent> {
+        Page::paginate(self.state.block_events.get(&number).into_iter().flatten(), page)
     }
 
     /// Emit system event
     fn emit_event(&mut self, event: SystemEvent) {
+        self.state.block_events.entry(event.block_number).or_default().push(event.clone());
+        // Events of old blocks are only kept in the recent history
+        let oldest = event.block_number.saturating_sub(constants::EVENT_HISTORY_BLOCKS);
+        while let Some(entry) = self.state.block_events.first_entry() {
+            if *entry.key() >= oldest {
+                break;
+            }
+            entry.remove();
+        }
+
         self.state.events.push(event);
         
         // Keep only last 1000 events
@@ -488,3 +1022,269 @@ impl Default for RuntimeStats {
         }
     }
 }
+
+/// Queue a block's receipts, and the block they belong to by call hash, for the block's write batch
+fn stage_receipts(receipts: &[TransactionReceipt], block: &Block, batch: &mut WriteBatch) -> CoreResult<()> {
+    if receipts.is_empty() {
+        return Ok(());
+    }
+    let block_hash = block.hash();
+    let receipts: Vec<TransactionReceipt> = receipts
+        .iter()
+        .map(|receipt| TransactionReceipt { block_hash: Some(block_hash.clone()), ..receipt.clone() })
+        .collect();
+    for receipt in &receipts {
+        batch.put(Column::Receipts, &receipt_block_key(&receipt.transaction_hash), &block_hash)?;
+    }
+    batch.put(Column::Receipts, &block_receipts_key(&block_hash), &receipts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_era_election_replaces_finality_authorities() {
+        let mut config = SystemConfig::default();
+        let (alice, bob, dave) = ["alice", "bob", "dave"].map(crate::utils::crypto::dev_account_id).into();
+        for account in [&alice, &bob, &dave] {
+            config.chain_spec.genesis.accounts.insert(account.clone(), 100_000 * Amount::SCALE);
+        }
+        config.chain_spec.genesis.validators = vec![alice.clone(), bob.clone()];
+
+        let mut runtime = Runtime::new(config);
+        runtime.staking.config.era_length = 2;
+        runtime.initialize_genesis().unwrap();
+        assert_eq!(runtime.get_validator_set().validators.len(), 2);
+
+        // Dave backs bob with 50_000 GRID; alice leaves
+        runtime.execute_call(dave.clone(), Call::Stake { amount: 50_000, validator: Some(bob.clone()) }).unwrap();
+        runtime.staking.chill(&alice);
+
+        // Block 1 closes era 0 (blocks 0 and 1)
+        let block = runtime.produce_block(alice).unwrap();
+
+        let election = runtime.get_validator_set();
+        assert_eq!(election.era, 1);
+        assert_eq!(election.validators, vec![bob.clone()]);
+        assert_eq!(election.exposures[&bob].total, constants::MIN_VALIDATOR_STAKE + 50_000);
+
+        // The block announces the new set, which takes over once the old set finalizes it
+        assert_eq!(block.header.authority_change.as_ref().map(|set| set.set_id), Some(1));
+        assert_eq!(runtime.blockchain.finality.authorities.set_id, 0);
+        for seed in ["alice", "bob"] {
+            let (private_key, public_key) = crate::utils::crypto::keypair_from_seed(seed);
+            let vote = sign_finality_vote(&block.hash(), 1, 0, &private_key, &public_key).unwrap();
+            runtime.blockchain.submit_finality_vote(&block.hash(), vote).unwrap();
+        }
+        assert_eq!(runtime.blockchain.finality.authorities.set_id, 1);
+        assert_eq!(runtime.blockchain.finality.authorities.weight_of(&bob), Some(60_000));
+        let block_events = runtime.get_events_by_block(1, &PageRequest::default());
+        assert!(block_events.items.iter().any(|e| e.event_type == EventType::ValidatorsElected));
+    }
+
+    #[test]
+    fn test_failed_nomination_unstakes_again() {
+        let config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        let mut runtime = Runtime::new(config);
+        runtime.initialize_genesis().unwrap();
+        let (alice, bob) = ["alice", "bob"].map(crate::utils::crypto::dev_account_id).into();
+        let grid = |runtime: &Runtime| runtime.token_service.get_balance(&bob, &"GRID".to_string());
+        let before = grid(&runtime);
+
+        // Bob is not a candidate, so nominating him fails after the stake was taken
+        let stake = Call::Stake { amount: 1_000, validator: Some(bob.clone()) };
+        let receipt = runtime.execute_call(bob.clone(), stake).unwrap();
+        assert!(matches!(receipt.status, ReceiptStatus::Failed { .. }));
+        assert_eq!(runtime.token_service.bonded(&bob), 0);
+        assert_eq!(grid(&runtime), before - receipt.fee.total());
+        assert!(!runtime.staking.nominations.contains_key(&bob));
+
+        // Alice, a candidate, cannot nominate either
+        let stake = Call::Stake { amount: 1_000, validator: Some(alice.clone()) };
+        let bonded = runtime.token_service.bonded(&alice);
+        assert!(!runtime.execute_call(alice.clone(), stake).unwrap().is_success());
+        assert_eq!(runtime.token_service.bonded(&alice), bonded);
+    }
+
+    #[test]
+    fn test_receipts_are_stored_with_their_block() {
+        let mut config = SystemConfig::default();
+        let (alice, bob) = ["alice", "bob"].map(crate::utils::crypto::dev_account_id).into();
+        config.chain_spec.genesis.accounts.insert(alice.clone(), 10 * Amount::SCALE);
+        let mut runtime = Runtime::new(config);
+        runtime.initialize_genesis().unwrap();
+
+        let transfer = Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount: 4 * Amount::SCALE };
+        let first = runtime.execute_call(alice.clone(), transfer.clone()).unwrap();
+        let second = runtime.execute_call(alice.clone(), transfer.clone()).unwrap();
+        let rejected = runtime.execute_call(alice.clone(), transfer).unwrap();
+        assert!(first.is_success() && second.is_success());
+        assert_ne!(first.transaction_hash, second.transaction_hash);
+        assert!(matches!(rejected.status, ReceiptStatus::Failed { .. }));
+        assert!(rejected.events.is_empty());
+        // Rejected calls still pay for their weight
+        let fees: Balance = [&first, &second, &rejected].iter().map(|r| r.fee.total()).sum();
+        assert_eq!(rejected.fee.total(), constants::BASE_CALL_FEE + rejected.weight as Balance * constants::CALL_FEE_PER_WEIGHT);
+        let grid = "GRID".to_string();
+        assert_eq!(runtime.token_service.get_balance(&alice, &grid), 10 * Amount::SCALE - fees);
+        assert_eq!(runtime.token_service.get_balance(&runtime.fees.config.collector, &grid), fees);
+        assert!(runtime.get_receipt(&first.transaction_hash).unwrap().is_none());
+
+        let block = runtime.produce_block(alice).unwrap();
+        let receipt = runtime.get_receipt(&second.transaction_hash).unwrap().unwrap();
+        assert_eq!(receipt.block_hash, Some(block.hash()));
+        assert_eq!((receipt.block_number, receipt.index), (1, 1));
+        assert_eq!(receipt.events[0].event_type, EventType::TokenTransferred);
+        assert_eq!(runtime.get_receipts_by_block(1).unwrap().len(), 3);
+        assert_eq!(runtime.token_service.get_balance(&bob, &"WATT".to_string()), 8 * Amount::SCALE);
+    }
+
+    #[test]
+    fn test_failed_calls_fill_the_block_and_full_blocks_refuse_calls() {
+        let mut config = SystemConfig::default();
+        let (alice, bob) = ["alice", "bob"].map(crate::utils::crypto::dev_account_id).into();
+        config.chain_spec.genesis.accounts.insert(alice.clone(), 10 * Amount::SCALE);
+        let mut runtime = Runtime::new(config);
+        runtime.initialize_genesis().unwrap();
+        let grid = |runtime: &Runtime| runtime.token_service.get_balance(&alice, &"GRID".to_string());
+
+        // The failed transfer is rolled back but its weight and fee stay
+        let transfer = Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount: 100 * Amount::SCALE };
+        let failed = runtime.execute_call(alice.clone(), transfer.clone()).unwrap();
+        assert!(!failed.is_success());
+        assert_eq!(runtime.fees.block_weight, failed.weight);
+        assert_eq!(grid(&runtime), 10 * Amount::SCALE - failed.fee.total());
+        assert_eq!(runtime.token_service.get_balance(&bob, &"WATT".to_string()), 0);
+
+        // A call that does not fit is refused before it is charged or recorded
+        runtime.fees.block_weight = constants::MAX_BLOCK_WEIGHT;
+        let before = grid(&runtime);
+        assert!(runtime.execute_call(alice.clone(), transfer).is_err());
+        assert_eq!(grid(&runtime), before);
+        assert_eq!(runtime.state.pending_receipts.len(), 1);
+
+        // The next block starts empty with a higher multiplier
+        runtime.produce_block(alice.clone()).unwrap();
+        assert_eq!(runtime.fees.block_weight, 0);
+        assert!(runtime.fees.multiplier_ppm > runtime.fees.config.min_multiplier_ppm);
+        let transfer = Call::TokenTransfer { to: bob, token: "WATT".to_string(), amount: Amount::SCALE };
+        let receipt = runtime.execute_call(alice, transfer).unwrap();
+        assert!(receipt.is_success());
+        assert!(receipt.fee.weight_fee > receipt.weight as Balance * constants::CALL_FEE_PER_WEIGHT);
+    }
+
+    #[test]
+    fn test_scheduled_calls_run_at_block_initialization() {
+        let config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        let mut runtime = Runtime::new(config);
+        runtime.initialize_genesis().unwrap();
+        let (alice, bob) = ["alice", "bob"].map(crate::utils::crypto::dev_account_id).into();
+        let transfer = |amount| Box::new(Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount });
+        let watt = |runtime: &Runtime| runtime.token_service.get_balance(&bob, &"WATT".to_string());
+        let before = watt(&runtime);
+
+        let periodic = Call::Schedule {
+            when: DispatchTime::AtBlock(2),
+            repeat: Some(Repeat { every: 1, times: 3 }),
+            call: transfer(100),
+        };
+        let timed = Call::Schedule { when: DispatchTime::AtTimestamp(1_010), repeat: None, call: transfer(7) };
+        assert!(runtime.execute_call(alice.clone(), periodic).unwrap().is_success());
+        assert!(runtime.execute_call(alice.clone(), timed).unwrap().is_success());
+        let past = Call::Schedule { when: DispatchTime::AtBlock(0), repeat: None, call: transfer(1) };
+        assert!(!runtime.execute_call(alice.clone(), past).unwrap().is_success());
+        assert!(!runtime.execute_call(bob.clone(), Call::CancelScheduled { id: 0 }).unwrap().is_success());
+
+        runtime.produce_block_at(alice.clone(), 1_000).unwrap();
+        assert_eq!(watt(&runtime), before);
+        runtime.produce_block_at(alice.clone(), 1_006).unwrap();
+        runtime.produce_block_at(alice.clone(), 1_012).unwrap();
+        assert_eq!(watt(&runtime), before + 200);
+
+        // Cancelled before its third run; the timed call is due once block 3 is the chain time
+        assert!(runtime.execute_call(alice.clone(), Call::CancelScheduled { id: 0 }).unwrap().is_success());
+        runtime.produce_block_at(alice.clone(), 1_018).unwrap();
+        assert_eq!(watt(&runtime), before + 207);
+        assert!(runtime.scheduler.tasks.is_empty());
+
+        let dispatched = (1..=4)
+            .flat_map(|number| runtime.get_events_by_block(number, &PageRequest::default()).items)
+            .filter(|event| event.event_type == EventType::ScheduledCallDispatched)
+            .count();
+        assert_eq!(dispatched, 3);
+        assert!(runtime.verify_chain().unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_snapshot_backup_and_restore() {
+        let mut config = SystemConfig::default();
+        let (alice, bob) = ["alice", "bob"].map(crate::utils::crypto::dev_account_id).into();
+        for account in [&alice, &bob] {
+            config.chain_spec.genesis.accounts.insert(account.clone(), 100_000 * Amount::SCALE);
+        }
+        config.chain_spec.genesis.validators = vec![alice.clone(), bob.clone()];
+
+        let mut runtime = Runtime::new(config.clone());
+        runtime.initialize_genesis().unwrap();
+        let transfer = Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount: 250 };
+        runtime.execute_call(alice.clone(), transfer.clone()).unwrap();
+        let scheduled = Call::Schedule { when: DispatchTime::AtBlock(5), repeat: None, call: Box::new(transfer) };
+        assert!(runtime.execute_call(alice.clone(), scheduled).unwrap().is_success());
+        #[cfg(feature = "hybrid-multi-signature")]
+        {
+            let as_multi = Call::AsMulti {
+                signatories: vec![alice.clone(), bob.clone()],
+                threshold: 2,
+                call: Box::new(Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount: 10 }),
+            };
+            assert!(runtime.execute_call(alice.clone(), as_multi).unwrap().is_success());
+        }
+        let block = runtime.produce_block(alice.clone()).unwrap();
+        assert!(runtime.export_snapshot().is_err());
+        for seed in ["alice", "bob"] {
+            let (private_key, public_key) = crate::utils::crypto::keypair_from_seed(seed);
+            let vote = sign_finality_vote(&block.hash(), 1, 0, &private_key, &public_key).unwrap();
+            runtime.blockchain.submit_finality_vote(&block.hash(), vote).unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!("ledger-snapshot-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("snapshot.json");
+        runtime.export_snapshot().unwrap().save(&path).unwrap();
+
+        let mut snapshot = RuntimeSnapshot::load(&path).unwrap();
+        snapshot.tokens.balances[0].2.balance += 1;
+        config.node_config.data_dir = dir.join("restored").to_string_lossy().to_string();
+        assert!(Runtime::restore(config.clone(), snapshot).is_err());
+
+        let mut restored = Runtime::restore(config.clone(), RuntimeSnapshot::load(&path).unwrap()).unwrap();
+        assert_eq!(restored.state.current_block, 1);
+        assert_eq!(restored.token_service.get_balance(&bob, &"WATT".to_string()), 100_000 * Amount::SCALE + 250);
+        assert_eq!(restored.get_validator_set(), runtime.get_validator_set());
+
+        // The pending transfer is still due at block 5
+        assert_eq!(restored.scheduler.tasks, runtime.scheduler.tasks);
+        assert!(restored.scheduler.take_due(4).is_empty());
+        assert_eq!(restored.scheduler.take_due(5).len(), 1);
+
+        // So is the multisig call that has one of its two approvals
+        #[cfg(feature = "hybrid-multi-signature")]
+        {
+            assert_eq!(restored.multisig.pending.len(), 1);
+            assert_eq!(restored.multisig.pending, runtime.multisig.pending);
+        }
+
+
+        // A restart rebuilds the services on top of the snapshot it was restored from
+        restored.produce_block(alice.clone()).unwrap();
+        let expected = restored.token_service.get_balance(&bob, &"WATT".to_string());
+        drop(restored);
+        let restarted = Runtime::start(config).unwrap();
+        assert_eq!(restarted.blockchain.get_block_height(), 2);
+        assert_eq!(restarted.token_service.get_balance(&bob, &"WATT".to_string()), expected);
+        assert_eq!(restarted.scheduler.tasks, runtime.scheduler.tasks);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+// Multi-signature calls
+// This module collects the approvals of a multisig account's signatories over
+// several calls and dispatches the approved call as the multisig account
+
+use super::{Call, EventType, ReceiptStatus, Runtime, SystemEvent};
+use crate::primitives::*;
+use crate::utils::crypto;
+use pallet_hybrid_architecture::multisig::{Approval, Multisig};
+use pallet_hybrid_architecture::HybridArchitectureError;
+
+/// Account id of the multisig of `signatories`, in any order, requiring `threshold` approvals
+pub fn multisig_account_id(signatories: Vec<AccountId>, threshold: u16) -> CoreResult<AccountId> {
+    Ok(crypto::module_account_id(&multisig(signatories, threshold)?.seed()))
+}
+
+/// Signatory set and threshold of a multisig account
+pub(super) fn multisig(signatories: Vec<AccountId>, threshold: u16) -> CoreResult<Multisig> {
+    Multisig::new(signatories, threshold).map_err(multisig_error)
+}
+
+fn multisig_error(error: HybridArchitectureError) -> CoreError {
+    CoreError::ValidationFailed(error.to_string())
+}
+
+impl Runtime {
+    /// Approve `call` as signatory `from`, dispatching it as the multisig account once the threshold is reached.
+    ///
+    /// If the approved call fails, the approval is rolled back with it by
+    /// the call this one was dispatched in, and the call stays pending.
+    pub(super) fn dispatch_as_multi(
+        &mut self,
+        from: &AccountId,
+        multisig: Multisig,
+        call: Call,
+        block_number: BlockNumber,
+        events: &mut Vec<SystemEvent>,
+        items: &mut Vec<ReceiptStatus>,
+    ) -> CoreResult<()> {
+        let account = crypto::module_account_id(&multisig.seed());
+        let encoded = serde_json::to_vec(&call)
+            .map_err(|e| CoreError::InvalidInput(format!("Failed to encode call: {}", e)))?;
+        let call_hash = crypto::sha256_hash(&encoded);
+        let now = self.chain_time();
+
+        let approval = self.multisig
+            .approve(&account, multisig, from, call_hash.clone(), encoded, block_number)
+            .map_err(multisig_error)?;
+        let (approvals, executed) = match &approval {
+            Approval::Pending { approvals } => (*approvals, false),
+            Approval::Ready(pending) => (pending.approvals.len(), true),
+        };
+        events.push(SystemEvent {
+            event_type: EventType::TransactionExecuted,
+            data: serde_json::json!({
+                "account": from,
+                "multisig": account,
+                "call_hash": call_hash,
+                "approvals": approvals,
+                "executed": executed,
+                "action": "approve_as_multi"
+            }),
+            block_number,
+            timestamp: now,
+        });
+        match approval {
+            Approval::Pending { .. } => Ok(()),
+            Approval::Ready(_) => self.dispatch(&account, call, block_number, events, items),
+        }
+    }
+
+    /// Withdraw a pending call of a multisig account on behalf of the signatory who approved it first
+    pub(super) fn cancel_as_multi(
+        &mut self,
+        from: &AccountId,
+        multisig: Multisig,
+        call_hash: &Hash,
+        block_number: BlockNumber,
+        events: &mut Vec<SystemEvent>,
+    ) -> CoreResult<()> {
+        let account = crypto::module_account_id(&multisig.seed());
+        self.multisig.cancel(&account, call_hash, from).map_err(multisig_error)?;
+        events.push(SystemEvent {
+            event_type: EventType::TransactionExecuted,
+            data: serde_json::json!({
+                "account": from,
+                "multisig": account,
+                "call_hash": call_hash,
+                "action": "cancel_as_multi"
+            }),
+            block_number,
+            timestamp: self.chain_time(),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multisig_dispatches_at_threshold() {
+        let config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        let mut runtime = Runtime::new(config);
+        runtime.initialize_genesis().unwrap();
+        let (alice, bob, charlie) = ["alice", "bob", "charlie"].map(crypto::dev_account_id).into();
+        let signatories = vec![charlie.clone(), bob.clone(), alice.clone()];
+        let account = multisig_account_id(signatories.clone(), 2).unwrap();
+        assert_eq!(account, multisig_account_id(vec![alice.clone(), bob.clone(), charlie.clone()], 2).unwrap());
+        assert!(multisig_account_id(signatories.clone(), 1).is_err());
+
+        let watt = "WATT".to_string();
+        let fund = Call::TokenTransfer { to: account.clone(), token: watt.clone(), amount: 1_000 };
+        runtime.execute_call(alice.clone(), fund).unwrap();
+        let as_multi = |amount| Call::AsMulti {
+            signatories: signatories.clone(),
+            threshold: 2,
+            call: Box::new(Call::TokenTransfer { to: charlie.clone(), token: "WATT".to_string(), amount }),
+        };
+
+        // One approval moves nothing; the second dispatches the transfer as the multisig account
+        assert!(runtime.execute_call(bob.clone(), as_multi(300)).unwrap().is_success());
+        assert_eq!(runtime.token_service.get_balance(&charlie, &watt), 0);
+        assert!(!runtime.execute_call(bob.clone(), as_multi(300)).unwrap().is_success());
+        let receipt = runtime.execute_call(alice.clone(), as_multi(300)).unwrap();
+        assert!(receipt.is_success());
+        assert_eq!(receipt.events[1].event_type, EventType::TokenTransferred);
+        assert_eq!(runtime.token_service.get_balance(&charlie, &watt), 300);
+        assert_eq!(runtime.token_service.get_balance(&account, &watt), 700);
+
+        // An approved call that fails keeps waiting with the approvals it had
+        runtime.execute_call(bob.clone(), as_multi(5_000)).unwrap();
+        assert!(!runtime.execute_call(alice.clone(), as_multi(5_000)).unwrap().is_success());
+        let pending = runtime.multisig.pending[&account].values().next().unwrap().clone();
+        assert_eq!(pending.approvals.len(), 1);
+        let cancel = |call_hash: &Hash| Call::CancelAsMulti { signatories: signatories.clone(), threshold: 2, call_hash: call_hash.clone() };
+        assert!(!runtime.execute_call(alice.clone(), cancel(&pending.call_hash)).unwrap().is_success());
+        assert!(runtime.execute_call(bob.clone(), cancel(&pending.call_hash)).unwrap().is_success());
+        assert!(runtime.multisig.pending.is_empty());
+
+        runtime.produce_block_at(alice, 1_000).unwrap();
+        assert!(runtime.verify_chain().unwrap().is_valid());
+    }
+}
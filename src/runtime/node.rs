@@ -0,0 +1,501 @@
+// Block production service
+// This module runs the runtime as a long-lived node that authors a block every
+// `block_time` seconds on an injectable clock, until it is asked to shut down
+
+use super::{Divergence, Runtime, RuntimeSnapshot};
+use super::verifier::replayable;
+use crate::core::{Blockchain, Block, MemoryStorage};
+use crate::primitives::*;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Longest the producer sleeps before checking for a shutdown request, in milliseconds
+const SHUTDOWN_POLL_MILLIS: u64 = 100;
+
+impl Runtime {
+    /// Open the node under `node_config.data_dir` and rebuild its service state.
+    ///
+    /// A new data dir gets its genesis state. Otherwise the service state is
+    /// loaded from the snapshot saved at the last finalized block, or built
+    /// from genesis if there is none yet, and only the blocks after it are
+    /// replayed.
+    pub fn start(config: SystemConfig) -> CoreResult<Self> {
+        let stored = Self::open(config.clone())?;
+        let (mut runtime, base) = Self::rebuild(&config, &stored.blockchain, stored.blockchain.get_block_height())?;
+        runtime.blockchain = stored.blockchain;
+        runtime.blockchain.set_state_checkpoint(base);
+        runtime.state.current_block = runtime.blockchain.get_block_height();
+        runtime.state.stats.total_blocks = runtime.state.current_block;
+        Ok(runtime)
+    }
+
+    /// Save the service state at the finalized block if finality moved past the last saved state.
+    ///
+    /// The state is rebuilt by replaying the blocks since the last snapshot,
+    /// which pruning kept for that reason. A finalized block that hands over
+    /// to new authorities cannot be snapshotted, so the next one is waited
+    /// for. Runtimes that do not save their state, i.e. ones not started
+    /// with [`Runtime::start`], are left alone.
+    pub(super) fn save_state_checkpoint(&mut self) -> CoreResult<()> {
+        let Some(saved) = self.blockchain.state_checkpoint() else {
+            return Ok(());
+        };
+        let finalized = &self.blockchain.state.finalized_block.header;
+        if finalized.number <= saved || finalized.authority_change.is_some() {
+            return Ok(());
+        }
+        let number = finalized.number;
+        let (replay, _) = Self::rebuild(&self.config, &self.blockchain, number)?;
+        replay.export_snapshot()?.save(&RuntimeSnapshot::base_path(&self.config))?;
+        self.blockchain.set_state_checkpoint(number);
+        Ok(())
+    }
+
+    /// Runtime holding the service state at block `to` of `chain`, backed by an in-memory chain.
+    ///
+    /// Starts from the saved snapshot, or from genesis, and replays the
+    /// blocks of `chain` after it. Returns the runtime and the number of the
+    /// block it started from.
+    fn rebuild(config: &SystemConfig, chain: &Blockchain, to: BlockNumber) -> CoreResult<(Self, BlockNumber)> {
+        let mut runtime = Self::new(config.clone());
+        let path = RuntimeSnapshot::base_path(config);
+        if path.exists() {
+            let snapshot = RuntimeSnapshot::load(&path)?;
+            runtime.restore_services(&snapshot)?;
+            runtime.blockchain = Blockchain::from_snapshot(config.clone(), Box::new(MemoryStorage::new()), snapshot.chain)?;
+            runtime.state.current_block = runtime.blockchain.get_block_height();
+        } else {
+            runtime.initialize_genesis()?;
+        }
+
+        let base = runtime.blockchain.get_block_height();
+        for number in base + 1..=to {
+            let block = replayable(chain.get_block_by_number(number))?;
+            runtime.replay_block(block).map_err(|kind| {
+                let divergence = Divergence { block_number: number, block_hash: block.hash(), kind, accounts: Vec::new() };
+                CoreError::ValidationFailed(format!("Cannot rebuild the runtime: {}", divergence))
+            })?;
+        }
+        Ok((runtime, base))
+    }
+}
+
+/// Source of time for the block producer
+pub trait Clock {
+    /// Milliseconds since the Unix epoch
+    fn now_millis(&self) -> u64;
+
+    /// Wait for `millis` milliseconds
+    fn sleep(&self, millis: u64);
+}
+
+/// Wall clock time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0)
+    }
+
+    fn sleep(&self, millis: u64) {
+        std::thread::sleep(std::time::Duration::from_millis(millis));
+    }
+}
+
+/// Clock that only moves when slept on or advanced; clones share the same time
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    /// Current time in milliseconds
+    now: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Clock starting at `millis`
+    pub fn new(millis: u64) -> Self {
+        Self { now: Arc::new(AtomicU64::new(millis)) }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, millis: u64) {
+        self.now.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+
+    fn sleep(&self, millis: u64) {
+        self.advance(millis);
+    }
+}
+
+/// Work run around every block the producer authors
+pub trait BlockHook {
+    /// Called before block `number` is authored
+    fn on_initialize(&mut self, _runtime: &mut Runtime, _number: BlockNumber) -> CoreResult<()> {
+        Ok(())
+    }
+
+    /// Called after a block was authored
+    fn on_finalize(&mut self, _runtime: &mut Runtime, _block: &Block) -> CoreResult<()> {
+        Ok(())
+    }
+}
+
+/// What the producer did in a slot
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlotOutcome {
+    /// A block was authored
+    Authored(Box<Block>),
+    /// Nothing to include and the empty block policy skipped the slot
+    SkippedEmpty,
+    /// The slot belongs to another authority
+    NotOurSlot,
+    /// Shutdown was requested while waiting for the slot
+    Shutdown,
+}
+
+/// Authors blocks on the runtime every `block_time` seconds
+pub struct BlockProducer<C: Clock> {
+    /// Runtime blocks are authored on
+    pub runtime: Runtime,
+    /// Account blocks are authored as
+    pub author: AccountId,
+    /// Time source
+    clock: C,
+    /// Hooks run around every authored block, in registration order
+    hooks: Vec<Box<dyn BlockHook>>,
+    /// Set to stop the producer after the current slot
+    shutdown: Arc<AtomicBool>,
+    /// Last slot the producer handled
+    last_slot: Option<u64>,
+    /// Empty slots skipped since the last authored block
+    skipped: u32,
+}
+
+impl<C: Clock> BlockProducer<C> {
+    /// Create a producer authoring as `author`
+    pub fn new(runtime: Runtime, author: AccountId, clock: C) -> Self {
+        Self {
+            runtime,
+            author,
+            clock,
+            hooks: Vec::new(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            last_slot: None,
+            skipped: 0,
+        }
+    }
+
+    /// Register a hook run around every authored block
+    pub fn add_hook(&mut self, hook: Box<dyn BlockHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Flag that stops the producer once set, e.g. from a signal handler
+    pub fn shutdown_handle(&self) -> Arc<AtomicBool> {
+        self.shutdown.clone()
+    }
+
+    /// Block time in milliseconds
+    fn slot_millis(&self) -> u64 {
+        self.runtime.config.consensus_config.block_time.max(1) * 1_000
+    }
+
+    /// Wait for the next slot and author a block in it if the schedule allows.
+    ///
+    /// A block whose hooks or production fail is not retried; the error is
+    /// returned and the next call waits for the following slot.
+    pub fn step(&mut self) -> CoreResult<SlotOutcome> {
+        let slot_millis = self.slot_millis();
+        let next_slot = match self.last_slot {
+            Some(slot) => slot + 1,
+            None => self.clock.now_millis().div_ceil(slot_millis),
+        };
+        loop {
+            if self.shutdown.load(Ordering::SeqCst) {
+                return Ok(SlotOutcome::Shutdown);
+            }
+            let now = self.clock.now_millis();
+            let start = next_slot * slot_millis;
+            if now >= start {
+                break;
+            }
+            self.clock.sleep((start - now).min(SHUTDOWN_POLL_MILLIS));
+        }
+        // Slots missed while busy are not made up for
+        let slot = self.clock.now_millis() / slot_millis;
+        self.last_slot = Some(slot);
+        let timestamp = slot * slot_millis / 1_000;
+
+        if let Some(authorship) = &self.runtime.blockchain.authorship {
+            if authorship.expected_author(authorship.slot_at(timestamp)) != Some(&self.author) {
+                return Ok(SlotOutcome::NotOurSlot);
+            }
+        }
+        if !self.has_work() {
+            let skip = match self.runtime.config.consensus_config.empty_blocks {
+                EmptyBlocks::Produce => false,
+                EmptyBlocks::Skip => true,
+                EmptyBlocks::KeepAlive(slots) => self.skipped < slots,
+            };
+            if skip {
+                self.skipped += 1;
+                return Ok(SlotOutcome::SkippedEmpty);
+            }
+        }
+        self.skipped = 0;
+
+        let number = self.runtime.blockchain.get_block_height() + 1;
+        for hook in &mut self.hooks {
+            hook.on_initialize(&mut self.runtime, number)?;
+        }
+        let block = self.runtime.produce_block_at(self.author.clone(), timestamp)?;
+        for hook in &mut self.hooks {
+            hook.on_finalize(&mut self.runtime, &block)?;
+        }
+        Ok(SlotOutcome::Authored(Box::new(block)))
+    }
+
+    /// Author blocks until shutdown is requested.
+    ///
+    /// Failed slots are reported and skipped, so one bad block does not stop
+    /// the node. A block being authored when the signal arrives is finished.
+    pub fn run(&mut self) {
+        loop {
+            match self.step() {
+                Ok(SlotOutcome::Shutdown) => return,
+                Ok(SlotOutcome::Authored(block)) => {
+                    log::info!(
+                        "Authored block #{} {} with {} transactions and {} calls",
+                        block.header.number, block.hash(), block.body.transactions.len(), block.body.calls.len()
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Block production failed: {}", e),
+            }
+        }
+    }
+
+    /// Whether there are calls or transactions waiting for a block
+    fn has_work(&self) -> bool {
+        !self.runtime.state.pending_receipts.is_empty() || self.runtime.blockchain.pool_stats().ready > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::sign_finality_vote;
+    use crate::runtime::{Call, RuntimeSnapshot};
+    use crate::services::DispatchTime;
+    use crate::utils::crypto;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl BlockHook for Recorder {
+        fn on_initialize(&mut self, _runtime: &mut Runtime, number: BlockNumber) -> CoreResult<()> {
+            self.0.borrow_mut().push(format!("init {}", number));
+            Ok(())
+        }
+
+        fn on_finalize(&mut self, _runtime: &mut Runtime, block: &Block) -> CoreResult<()> {
+            self.0.borrow_mut().push(format!("final {}", block.header.number));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_block_producer_follows_block_time() {
+        let mut config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        config.consensus_config.empty_blocks = EmptyBlocks::KeepAlive(2);
+        let mut runtime = Runtime::new(config);
+        runtime.initialize_genesis().unwrap();
+
+        let alice = crypto::dev_account_id("alice");
+        let clock = ManualClock::new(1_000_500);
+        let mut producer = BlockProducer::new(runtime, alice.clone(), clock.clone());
+        let log = Rc::new(RefCell::new(Vec::new()));
+        producer.add_hook(Box::new(Recorder(log.clone())));
+
+        // Two empty slots are skipped, the third keeps the chain alive
+        assert_eq!(producer.step(), Ok(SlotOutcome::SkippedEmpty));
+        assert_eq!(clock.now_millis(), 1_002_000);
+        assert_eq!(producer.step(), Ok(SlotOutcome::SkippedEmpty));
+        let SlotOutcome::Authored(block) = producer.step().unwrap() else { panic!("expected a block") };
+        assert_eq!((block.header.number, block.header.timestamp), (1, 1_014));
+
+        // A pending call is included in the very next slot
+        producer.runtime.execute_call(alice.clone(), Call::TokenTransfer {
+            to: crypto::dev_account_id("bob"),
+            token: "GRID".to_string(),
+            amount: 100,
+        }).unwrap();
+        let SlotOutcome::Authored(block) = producer.step().unwrap() else { panic!("expected a block") };
+        assert_eq!((block.header.number, block.header.timestamp), (2, 1_020));
+        assert_eq!(producer.runtime.get_receipts_by_block(2).unwrap().len(), 1);
+        assert_eq!(*log.borrow(), ["init 1", "final 1", "init 2", "final 2"]);
+
+        producer.shutdown_handle().store(true, Ordering::SeqCst);
+        assert_eq!(producer.step(), Ok(SlotOutcome::Shutdown));
+        producer.run();
+        assert_eq!(producer.runtime.blockchain.get_block_height(), 2);
+    }
+
+    #[test]
+    fn test_restarted_node_rebuilds_service_state() {
+        let dir = std::env::temp_dir().join(format!("ledger-node-{}", uuid::Uuid::new_v4()));
+        let mut config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        config.node_config.data_dir = dir.to_string_lossy().to_string();
+        let (alice, bob) = ["alice", "bob"].map(crypto::dev_account_id).into();
+        let watt = |runtime: &Runtime| runtime.token_service.get_balance(&bob, &"WATT".to_string());
+        let transfer = |amount| Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount };
+
+        let mut runtime = Runtime::start(config.clone()).unwrap();
+        let genesis = watt(&runtime);
+        runtime.execute_call(alice.clone(), transfer(250)).unwrap();
+        runtime.produce_block(alice.clone()).unwrap();
+        let scheduled = Call::Schedule { when: DispatchTime::AtBlock(3), repeat: None, call: Box::new(transfer(50)) };
+        runtime.execute_call(alice.clone(), scheduled).unwrap();
+        runtime.produce_block(alice.clone()).unwrap();
+        drop(runtime);
+
+        // Genesis is not minted again and the scheduled transfer is still pending
+        let mut restarted = Runtime::start(config).unwrap();
+        assert_eq!(restarted.blockchain.get_block_height(), 2);
+        assert_eq!(watt(&restarted), genesis + 250);
+        assert_eq!(restarted.scheduler.tasks.len(), 1);
+
+        restarted.produce_block(alice.clone()).unwrap();
+        assert_eq!(watt(&restarted), genesis + 300);
+        assert!(restarted.scheduler.tasks.is_empty());
+        assert!(restarted.verify_chain().unwrap().is_valid());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pruned_node_restarts_from_its_finalized_state() {
+        let dir = std::env::temp_dir().join(format!("ledger-node-{}", uuid::Uuid::new_v4()));
+        let mut config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        config.node_config.data_dir = dir.to_string_lossy().to_string();
+        config.node_config.pruning = PruningMode::HeadersOnly;
+        let (alice, bob) = ["alice", "bob"].map(crypto::dev_account_id).into();
+        let watt = |runtime: &Runtime| runtime.token_service.get_balance(&bob, &"WATT".to_string());
+        let transfer = Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount: 100 };
+
+        let mut runtime = Runtime::start(config.clone()).unwrap();
+        assert_eq!(runtime.blockchain.state_checkpoint(), Some(0));
+        for _ in 0..4 {
+            runtime.execute_call(alice.clone(), transfer.clone()).unwrap();
+            runtime.produce_block(alice.clone()).unwrap();
+        }
+        // Alice, the only dev validator, finalizes block 3 while block 4 is already built on it
+        let hash = runtime.blockchain.get_block_by_number(3).unwrap().hash();
+        let (private_key, public_key) = crypto::keypair_from_seed("alice");
+        let vote = sign_finality_vote(&hash, 3, 0, &private_key, &public_key).unwrap();
+        assert!(runtime.blockchain.submit_finality_vote(&hash, vote).unwrap());
+
+        // The next block saves the service state at the finalized block
+        runtime.execute_call(alice.clone(), transfer).unwrap();
+        runtime.produce_block(alice.clone()).unwrap();
+        assert_eq!(runtime.blockchain.state_checkpoint(), Some(3));
+        let saved = RuntimeSnapshot::load(&RuntimeSnapshot::base_path(&config)).unwrap();
+        assert_eq!(saved.chain.block.hash(), hash);
+        let expected = watt(&runtime);
+        drop(runtime);
+
+        // Only blocks 4 and 5 are replayed on top of the saved state
+        let restarted = Runtime::start(config.clone()).unwrap();
+        assert_eq!(restarted.blockchain.get_block_height(), 5);
+        assert_eq!(restarted.blockchain.state_checkpoint(), Some(3));
+        assert_eq!(watt(&restarted), expected);
+
+        // Blocks below it lost their calls, so the chain can no longer be re-executed
+        assert!(restarted.blockchain.get_block_by_number(2).unwrap().is_pruned());
+        assert!(!restarted.blockchain.get_block_by_number(3).unwrap().is_pruned());
+        drop(restarted);
+        config.node_config.pruning = PruningMode::Archive;
+        let error = Runtime::open(config).unwrap().verify_chain().unwrap_err();
+        assert!(error.to_string().contains("History pruned: block 1 "), "{}", error);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_receipts_are_found_after_a_restart() {
+        let dir = std::env::temp_dir().join(format!("ledger-node-{}", uuid::Uuid::new_v4()));
+        let mut config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        config.node_config.data_dir = dir.to_string_lossy().to_string();
+        let (alice, bob) = ["alice", "bob"].map(crypto::dev_account_id).into();
+        let transfer = |amount| Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount };
+
+        let mut runtime = Runtime::start(config.clone()).unwrap();
+        let included = runtime.execute_call(alice.clone(), transfer(100)).unwrap();
+        let rejected = runtime.execute_call(alice.clone(), transfer(Balance::MAX)).unwrap();
+        let block = runtime.produce_block(alice.clone()).unwrap();
+        let (private_key, public_key) = crypto::keypair_from_seed("alice");
+        let vote = sign_finality_vote(&block.hash(), 1, 0, &private_key, &public_key).unwrap();
+        assert!(runtime.blockchain.submit_finality_vote(&block.hash(), vote).unwrap());
+        let later = runtime.execute_call(alice.clone(), transfer(100)).unwrap();
+        runtime.produce_block(alice.clone()).unwrap();
+        assert_eq!(runtime.blockchain.state_checkpoint(), Some(1));
+        drop(runtime);
+
+        // Block 1 is below the saved state and not replayed, so its receipts come from the block store
+        let restarted = Runtime::start(config).unwrap();
+        assert!(!restarted.state.receipts.contains_key(&1));
+        let receipt = restarted.get_receipt(&included.transaction_hash).unwrap().unwrap();
+        assert!(receipt.is_success());
+        assert_eq!(receipt.block_hash, Some(block.hash()));
+        assert_eq!(receipt.events, included.events);
+        let receipt = restarted.get_receipt(&rejected.transaction_hash).unwrap().unwrap();
+        assert_eq!((receipt.status, receipt.index), (rejected.status, 1));
+        assert_eq!(restarted.get_receipts_by_block(1).unwrap().len(), 2);
+        assert!(restarted.get_receipt(&later.transaction_hash).unwrap().is_some());
+        assert!(restarted.get_receipt(&Hash::default()).unwrap().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_node_refuses_a_damaged_snapshot() {
+        let dir = std::env::temp_dir().join(format!("ledger-node-{}", uuid::Uuid::new_v4()));
+        let mut config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        config.node_config.data_dir = dir.to_string_lossy().to_string();
+        let alice = crypto::dev_account_id("alice");
+
+        let mut runtime = Runtime::start(config.clone()).unwrap();
+        let block = runtime.produce_block(alice.clone()).unwrap();
+        let (private_key, public_key) = crypto::keypair_from_seed("alice");
+        let vote = sign_finality_vote(&block.hash(), 1, 0, &private_key, &public_key).unwrap();
+        assert!(runtime.blockchain.submit_finality_vote(&block.hash(), vote).unwrap());
+        runtime.produce_block(alice).unwrap();
+        assert_eq!(runtime.blockchain.state_checkpoint(), Some(1));
+        drop(runtime);
+
+        // An edited service state no longer matches its commitment
+        let path = RuntimeSnapshot::base_path(&config);
+        let mut snapshot = RuntimeSnapshot::load(&path).unwrap();
+        snapshot.fees.multiplier_ppm += 1;
+        snapshot.save(&path).unwrap();
+        let Err(error) = Runtime::start(config.clone()) else { panic!("expected an error") };
+        assert!(error.to_string().contains("does not match its commitment"), "{}", error);
+
+        // A truncated file is not read at all
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        let Err(error) = Runtime::start(config) else { panic!("expected an error") };
+        assert!(error.to_string().contains("Malformed snapshot"), "{}", error);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
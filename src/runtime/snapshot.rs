@@ -0,0 +1,210 @@
+// Runtime state snapshots
+// This module bundles the chain snapshot with the service state the runtime
+// keeps next to it, for node bootstrap and operator backup and restore
+
+use super::Runtime;
+use crate::core::*;
+use crate::primitives::*;
+use crate::services::*;
+use crate::utils::crypto;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(feature = "hybrid-multi-signature")]
+use pallet_hybrid_architecture::multisig::MultisigRegistry;
+use std::path::{Path, PathBuf};
+
+/// File under `node_config.data_dir`, next to the block store, keeping the
+/// snapshot the node rebuilds its service state from on restart
+const RUNTIME_SNAPSHOT_FILE: &str = "runtime-snapshot.json";
+
+/// Token service state in a deterministic layout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenSnapshot {
+    /// Token metadata by token ID
+    pub tokens: BTreeMap<TokenId, TokenMetadata>,
+    /// Balances by account and token
+    pub balances: Vec<(AccountId, TokenId, TokenBalance)>,
+    /// Governance proposals by ID
+    pub proposals: BTreeMap<String, GovernanceProposal>,
+    /// Votes by voter and proposal
+    pub votes: Vec<(AccountId, String, Vote)>,
+    /// Staking records by account
+    pub staking: BTreeMap<AccountId, Vec<StakingInfo>>,
+    /// Allowances by owner, spender and token
+    pub allowances: Vec<(AccountId, AccountId, TokenId, TokenAmount)>,
+}
+
+/// Energy market state in a deterministic layout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnergyMarketSnapshot {
+    /// Open orders
+    pub order_book: OrderBook,
+    /// Trades by ID
+    pub active_trades: BTreeMap<String, EnergyTrade>,
+    /// Participants by account
+    pub participants: BTreeMap<AccountId, Participant>,
+}
+
+/// Ledger state at a finalized block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeSnapshot {
+    /// Accounts, block header and authorities, checked against the header's state root
+    pub chain: ChainSnapshot,
+    /// Token balances and governance proposals
+    pub tokens: TokenSnapshot,
+    /// Order book and trades
+    pub energy_market: EnergyMarketSnapshot,
+    /// Candidacies, nominations and the active validator set
+    pub staking: StakingService,
+    /// Pending slashes
+    pub slashing: SlashingService,
+    /// Fee multiplier
+    pub fees: FeeService,
+    /// Scheduled and periodic calls
+    pub scheduler: SchedulerService,
+    /// Multisig calls waiting for approvals
+    #[cfg(feature = "hybrid-multi-signature")]
+    pub multisig: MultisigRegistry,
+    /// Commitment to the service state above.
+    ///
+    /// Service state is not part of the block state root, so this only
+    /// detects a damaged or edited file, not a dishonest snapshot producer.
+    pub services_root: Hash,
+}
+
+impl RuntimeSnapshot {
+    /// Commitment to the service state of the snapshot
+    pub fn compute_services_root(&self) -> CoreResult<Hash> {
+        let services = (&self.tokens, &self.energy_market, &self.staking, &self.slashing, &self.fees, &self.scheduler);
+        #[cfg(feature = "hybrid-multi-signature")]
+        let services = (services, &self.multisig);
+        let encoded = serde_json::to_vec(&services)
+            .map_err(|e| CoreError::InvalidInput(format!("Failed to encode snapshot: {}", e)))?;
+        Ok(crypto::hash_to_hex(&crypto::sha256(&encoded)))
+    }
+
+    /// Write the snapshot to a JSON file
+    pub fn save(&self, path: &Path) -> CoreResult<()> {
+        let bytes = serde_json::to_vec(self)
+            .map_err(|e| CoreError::InvalidInput(format!("Failed to encode snapshot: {}", e)))?;
+        write_atomic(path, &bytes)
+    }
+
+    /// Read a snapshot from a JSON file
+    pub fn load(path: &Path) -> CoreResult<Self> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| CoreError::StorageError(format!("Failed to read {}: {}", path.display(), e)))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| CoreError::InvalidInput(format!("Malformed snapshot {}: {}", path.display(), e)))
+    }
+
+    /// Where the node under `node_config.data_dir` keeps the snapshot it rebuilds its service state from
+    pub fn base_path(config: &SystemConfig) -> PathBuf {
+        Path::new(&config.node_config.data_dir).join(RUNTIME_SNAPSHOT_FILE)
+    }
+}
+
+impl Runtime {
+    /// Snapshot the ledger at the finalized block.
+    ///
+    /// Services only hold the state of the best block, so the best block must
+    /// be final.
+    pub fn export_snapshot(&self) -> CoreResult<RuntimeSnapshot> {
+        if self.blockchain.state.best_block.hash() != self.blockchain.state.finalized_block.hash() {
+            return Err(CoreError::ValidationFailed("Best block is not finalized yet".to_string()));
+        }
+
+        let tokens = &self.token_service;
+        let mut balances: Vec<_> = tokens.balances.iter()
+            .map(|((account, token), balance)| (account.clone(), token.clone(), balance.clone()))
+            .collect();
+        balances.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        let mut votes: Vec<_> = tokens.votes.iter()
+            .map(|((voter, proposal), vote)| (voter.clone(), proposal.clone(), vote.clone()))
+            .collect();
+        votes.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        let mut allowances: Vec<_> = tokens.allowances.iter()
+            .map(|((owner, spender, token), amount)| (owner.clone(), spender.clone(), token.clone(), *amount))
+            .collect();
+        allowances.sort_by(|a, b| (&a.0, &a.1, &a.2).cmp(&(&b.0, &b.1, &b.2)));
+
+        let market = &self.energy_trading_service;
+        let mut snapshot = RuntimeSnapshot {
+            chain: self.blockchain.export_snapshot()?,
+            tokens: TokenSnapshot {
+                tokens: tokens.tokens.clone().into_iter().collect(),
+                balances,
+                proposals: tokens.proposals.clone().into_iter().collect(),
+                votes,
+                staking: tokens.staking.clone().into_iter().collect(),
+                allowances,
+            },
+            energy_market: EnergyMarketSnapshot {
+                order_book: market.order_book.clone(),
+                active_trades: market.active_trades.clone().into_iter().collect(),
+                participants: market.participants.clone().into_iter().collect(),
+            },
+            staking: self.staking.clone(),
+            slashing: self.slashing.clone(),
+            fees: self.fees.clone(),
+            scheduler: self.scheduler.clone(),
+            #[cfg(feature = "hybrid-multi-signature")]
+            multisig: self.multisig.clone(),
+            services_root: Hash::new(),
+        };
+        snapshot.services_root = snapshot.compute_services_root()?;
+        Ok(snapshot)
+    }
+
+    /// Restore a runtime from a snapshot into the empty `node_config.data_dir`.
+    ///
+    /// The snapshot is kept next to the chain, since a restarted node rebuilds
+    /// its service state from it.
+    pub fn restore(config: SystemConfig, snapshot: RuntimeSnapshot) -> CoreResult<Self> {
+        let mut runtime = Self::new(config.clone());
+        runtime.restore_services(&snapshot)?;
+        runtime.blockchain = Blockchain::restore(config.clone(), snapshot.chain.clone())?;
+        snapshot.save(&RuntimeSnapshot::base_path(&config))?;
+        runtime.blockchain.set_state_checkpoint(snapshot.chain.block.header.number);
+        runtime.state.current_block = runtime.blockchain.get_block_height();
+        runtime.state.stats.total_blocks = runtime.state.current_block;
+        Ok(runtime)
+    }
+
+    /// Replace the service state with that of a snapshot, after checking it against its commitment
+    pub(super) fn restore_services(&mut self, snapshot: &RuntimeSnapshot) -> CoreResult<()> {
+        if snapshot.compute_services_root()? != snapshot.services_root {
+            return Err(CoreError::ValidationFailed("Snapshot service state does not match its commitment".to_string()));
+        }
+
+        let tokens = snapshot.tokens.clone();
+        let service = &mut self.token_service;
+        service.tokens = tokens.tokens.into_iter().collect();
+        service.balances = tokens.balances.into_iter()
+            .map(|(account, token, balance)| ((account, token), balance))
+            .collect();
+        service.proposals = tokens.proposals.into_iter().collect();
+        service.votes = tokens.votes.into_iter()
+            .map(|(voter, proposal, vote)| ((voter, proposal), vote))
+            .collect();
+        service.staking = tokens.staking.into_iter().collect();
+        service.allowances = tokens.allowances.into_iter()
+            .map(|(owner, spender, token, amount)| ((owner, spender, token), amount))
+            .collect();
+
+        let market = &mut self.energy_trading_service;
+        market.order_book = snapshot.energy_market.order_book.clone();
+        market.active_trades = snapshot.energy_market.active_trades.clone().into_iter().collect();
+        market.participants = snapshot.energy_market.participants.clone().into_iter().collect();
+
+        self.staking = snapshot.staking.clone();
+        self.slashing = snapshot.slashing.clone();
+        self.fees = snapshot.fees.clone();
+        self.scheduler = snapshot.scheduler.clone();
+        #[cfg(feature = "hybrid-multi-signature")]
+        {
+            self.multisig = snapshot.multisig.clone();
+        }
+        Ok(())
+    }
+}
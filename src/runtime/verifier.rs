@@ -0,0 +1,309 @@
+// Chain re-execution verifier
+// This module replays the canonical chain from genesis in a fresh runtime and
+// reports the first block whose state, receipts or authorities come out differently
+
+use super::{Call, Runtime, TransactionReceipt};
+use crate::core::{AccountState, Authority, Block};
+use crate::primitives::*;
+use crate::utils::crypto;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Account whose state differs between the chain and its re-execution
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AccountDiff {
+    /// Account
+    pub account: AccountId,
+    /// State committed by the chain, `None` if the account did not exist
+    pub expected: Option<AccountState>,
+    /// State produced by the re-execution, `None` if the account does not exist
+    pub actual: Option<AccountState>,
+}
+
+/// What came out differently when a block was re-executed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DivergenceKind {
+    /// The block or one of its calls could not be re-executed
+    Rejected {
+        /// Why re-execution failed
+        error: String,
+    },
+    /// The block announces other finality authorities than the re-executed election
+    AuthorityChange {
+        /// Authorities announced by the block
+        expected: Option<Vec<Authority>>,
+        /// Authorities elected by the re-execution
+        actual: Option<Vec<Authority>>,
+    },
+    /// The block's transactions produce another state root
+    StateRoot {
+        /// State root committed by the block
+        expected: Hash,
+        /// State root produced by the re-execution
+        actual: Hash,
+    },
+    /// A call's receipt differs; `None` where one side has no receipt at that position
+    Receipt {
+        /// Position of the call in the block
+        index: usize,
+        /// Receipt kept by the chain's runtime
+        expected: Option<Box<TransactionReceipt>>,
+        /// Receipt produced by the re-execution
+        actual: Option<Box<TransactionReceipt>>,
+    },
+}
+
+/// First block whose re-execution does not match the chain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Divergence {
+    /// Block number
+    pub block_number: BlockNumber,
+    /// Block hash
+    pub block_hash: Hash,
+    /// What differs
+    pub kind: DivergenceKind,
+    /// Accounts whose state differs, for state root divergences
+    pub accounts: Vec<AccountDiff>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "block #{} {} diverges: ", self.block_number, self.block_hash)?;
+        match &self.kind {
+            DivergenceKind::Rejected { error } => write!(f, "re-execution failed: {}", error)?,
+            DivergenceKind::AuthorityChange { .. } => write!(f, "other finality authorities elected")?,
+            DivergenceKind::StateRoot { expected, actual } => {
+                write!(f, "state root {} expected, {} produced", expected, actual)?
+            }
+            DivergenceKind::Receipt { index, .. } => write!(f, "receipt of call {} differs", index)?,
+        }
+        for diff in &self.accounts {
+            write!(f, "\n  {}: {:?} expected, {:?} produced", diff.account, diff.expected, diff.actual)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of re-executing the chain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VerificationReport {
+    /// Blocks after genesis re-executed without divergence
+    pub verified_blocks: BlockNumber,
+    /// Blocks whose stored receipts were compared
+    pub receipts_checked: BlockNumber,
+    /// First divergence, `None` if the chain re-executes identically
+    pub divergence: Option<Divergence>,
+}
+
+impl VerificationReport {
+    /// Whether every block re-executed identically
+    pub fn is_valid(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+impl Runtime {
+    /// Re-execute the canonical chain from genesis in a fresh runtime and stop at the first divergence.
+    ///
+    /// Each block's recorded calls are replayed and its transactions
+    /// executed as its author did. The state root and announced authorities
+    /// are compared for every block, receipts for every block they were
+    /// stored with, so that service state changes show up through the events
+    /// of the calls that made them. Service state changed outside calls is
+    /// not replayed and shows up as a divergence. Needs an archive node,
+    /// since pruned blocks have no bodies left to replay, and fails on blocks
+    /// pruned before the node became one.
+    pub fn verify_chain(&self) -> CoreResult<VerificationReport> {
+        if self.config.node_config.pruning != PruningMode::Archive {
+            return Err(CoreError::InvalidInput("Re-executing the chain needs an archive node".to_string()));
+        }
+        let mut replay = Runtime::new(self.config.clone());
+        replay.initialize_genesis()?;
+
+        let mut report = VerificationReport { verified_blocks: 0, receipts_checked: 0, divergence: None };
+        for number in 1..=self.blockchain.get_block_height() {
+            let block = replayable(self.blockchain.get_block_by_number(number))?;
+            let expected = self.get_receipts_by_block(number)?;
+            let result = replay.replay_block(block).and_then(|()| {
+                if expected.is_empty() {
+                    return Ok(());
+                }
+                report.receipts_checked += 1;
+                let actual = replay.state.receipts.get(&number).map(Vec::as_slice).unwrap_or_default();
+                receipt_divergence(&expected, actual).map_or(Ok(()), Err)
+            });
+            match result {
+                Ok(()) => report.verified_blocks = number,
+                Err(kind) => {
+                    let accounts = match &kind {
+                        DivergenceKind::StateRoot { expected, .. } => self.account_diff(&mut replay, block, expected),
+                        _ => Vec::new(),
+                    };
+                    report.divergence = Some(Divergence { block_number: number, block_hash: block.hash(), kind, accounts });
+                    break;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Execute `block` on top of this runtime's best block as its author did, then import it
+    pub(super) fn replay_block(&mut self, block: &Block) -> Result<(), DivergenceKind> {
+        let rejected = |error: String| DivergenceKind::Rejected { error };
+        for (index, extrinsic) in block.body.calls.iter().enumerate() {
+            let call: Call = serde_json::from_slice(&extrinsic.call)
+                .map_err(|e| rejected(format!("Call {} does not decode: {}", index, e)))?;
+            self.execute_call(extrinsic.sender.clone(), call)
+                .map_err(|e| rejected(format!("Call {} failed: {}", index, e)))?;
+        }
+        // The calls are in the block already
+        self.blockchain.take_pending_calls();
+
+        let election = self.initialize_block().map_err(|e| rejected(e.to_string()))?;
+        let expected = block.header.authority_change.as_ref().map(|set| set.authorities.clone());
+        let actual = self.elected_authorities(election.as_ref());
+        if expected != actual {
+            return Err(DivergenceKind::AuthorityChange { expected, actual });
+        }
+
+        let (root, _) = self.blockchain.dry_run_block(block).map_err(|e| rejected(e.to_string()))?;
+        if root != block.header.state_root {
+            return Err(DivergenceKind::StateRoot { expected: block.header.state_root.clone(), actual: root });
+        }
+        self.blockchain.import_block(block.clone()).map_err(|e| rejected(e.to_string()))?;
+        self.finalize_block(block, election);
+        Ok(())
+    }
+
+    /// Accounts the replay of `block` leaves in another state than the chain committed under `root`.
+    ///
+    /// Empty if this node no longer holds the state under `root`.
+    fn account_diff(&self, replay: &mut Runtime, block: &Block, root: &Hash) -> Vec<AccountDiff> {
+        let Some(committed) = crypto::hash_from_hex(root).and_then(|root| self.blockchain.state_trie.accounts_at(&root)) else {
+            return Vec::new();
+        };
+        let Ok((_, touched)) = replay.blockchain.dry_run_block(block) else {
+            return Vec::new();
+        };
+        touched.into_iter()
+            .map(|(account, actual)| AccountDiff { expected: committed.get(&account).cloned(), actual, account })
+            .filter(|diff| diff.expected != diff.actual)
+            .collect()
+    }
+}
+
+/// Block to replay, failing if it is missing or pruning dropped what it executed
+pub(super) fn replayable(block: Option<&Block>) -> CoreResult<&Block> {
+    match block {
+        Some(block) if block.is_pruned() => Err(CoreError::InvalidInput(format!(
+            "History pruned: block {} no longer holds its transactions and calls",
+            block.header.number
+        ))),
+        Some(block) => Ok(block),
+        None => Err(CoreError::BlockNotFound),
+    }
+}
+
+/// First receipt that differs between the chain and its re-execution
+fn receipt_divergence(expected: &[TransactionReceipt], actual: &[TransactionReceipt]) -> Option<DivergenceKind> {
+    (0..expected.len().max(actual.len()))
+        .find(|&index| expected.get(index) != actual.get(index))
+        .map(|index| DivergenceKind::Receipt {
+            index,
+            expected: expected.get(index).cloned().map(Box::new),
+            actual: actual.get(index).cloned().map(Box::new),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{block_receipts_key, Column, Transaction, TransactionType, WriteBatch};
+
+    #[test]
+    fn test_verify_chain_reports_first_divergence() {
+        let config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        let mut runtime = Runtime::new(config);
+        runtime.initialize_genesis().unwrap();
+        let (alice, bob) = ["alice", "bob"].map(crypto::dev_account_id).into();
+        let transfer = Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount: 250 };
+
+        runtime.execute_call(alice.clone(), transfer.clone()).unwrap();
+        let mut transaction = Transaction {
+            hash: String::new(),
+            from: alice.clone(),
+            to: bob.clone(),
+            amount: 500,
+            transaction_type: TransactionType::Transfer,
+            data: Vec::new(),
+            fee: 1,
+            nonce: 0,
+            block_number: None,
+            timestamp: 0,
+            signature: None,
+        };
+        transaction.sign(&crypto::keypair_from_seed("alice").0).unwrap();
+        runtime.blockchain.add_transaction(transaction).unwrap();
+        let first = runtime.produce_block_at(alice.clone(), 1_000).unwrap();
+        runtime.execute_call(bob.clone(), transfer).unwrap();
+        runtime.produce_block_at(alice.clone(), 1_006).unwrap();
+
+        let report = runtime.verify_chain().unwrap();
+        assert!(report.is_valid(), "{:?}", report.divergence);
+        assert_eq!((report.verified_blocks, report.receipts_checked), (2, 2));
+
+        // A receipt whose event differs from the re-executed one is reported
+        let receipts = runtime.state.receipts.clone();
+        runtime.state.receipts.get_mut(&2).unwrap()[0].events[0].timestamp += 1;
+        let divergence = runtime.verify_chain().unwrap().divergence.unwrap();
+        assert_eq!(divergence.block_number, 2);
+        assert!(matches!(divergence.kind, DivergenceKind::Receipt { index: 0, .. }));
+        runtime.state.receipts = receipts;
+
+        // A block whose transactions do not produce its state root names the accounts that differ
+        runtime.blockchain.state.blocks.get_mut(&first.hash()).unwrap().body.transactions[0].amount += 1;
+        let divergence = runtime.verify_chain().unwrap().divergence.unwrap();
+        assert_eq!(divergence.block_number, 1);
+        assert!(matches!(divergence.kind, DivergenceKind::StateRoot { .. }));
+        let accounts: Vec<_> = divergence.accounts.iter().map(|diff| &diff.account).collect();
+        assert_eq!(accounts.len(), 2);
+        assert!(accounts.contains(&&alice) && accounts.contains(&&bob));
+    }
+
+    #[test]
+    fn test_reopened_chain_is_verified_against_its_stored_receipts() {
+        let dir = std::env::temp_dir().join(format!("ledger-node-{}", uuid::Uuid::new_v4()));
+        let mut config = SystemConfig { chain_spec: ChainSpec::dev(), ..SystemConfig::default() };
+        config.node_config.data_dir = dir.to_string_lossy().to_string();
+        let (alice, bob) = ["alice", "bob"].map(crypto::dev_account_id).into();
+        let transfer = Call::TokenTransfer { to: bob.clone(), token: "WATT".to_string(), amount: 250 };
+
+        let mut runtime = Runtime::start(config.clone()).unwrap();
+        runtime.execute_call(alice.clone(), transfer.clone()).unwrap();
+        runtime.produce_block_at(alice.clone(), 1_000).unwrap();
+        runtime.produce_block_at(alice.clone(), 1_006).unwrap();
+        runtime.execute_call(bob.clone(), transfer).unwrap();
+        let last = runtime.produce_block_at(alice.clone(), 1_012).unwrap();
+        drop(runtime);
+
+        // The reopened chain holds no service state, only the receipts stored with its blocks
+        let mut opened = Runtime::open(config.clone()).unwrap();
+        assert!(opened.state.receipts.is_empty());
+        let report = opened.verify_chain().unwrap();
+        assert!(report.is_valid(), "{:?}", report.divergence);
+        assert_eq!((report.verified_blocks, report.receipts_checked), (3, 2));
+
+        // A stored receipt the calls do not reproduce is reported
+        let mut receipts = opened.get_receipts_by_block(3).unwrap();
+        receipts[0].events.clear();
+        let mut batch = WriteBatch::new();
+        batch.put(Column::Receipts, &block_receipts_key(&last.hash()), &receipts).unwrap();
+        opened.blockchain.storage.write(batch).unwrap();
+        drop(opened);
+        let divergence = Runtime::open(config).unwrap().verify_chain().unwrap().divergence.unwrap();
+        assert_eq!(divergence.block_number, 3);
+        assert!(matches!(divergence.kind, DivergenceKind::Receipt { index: 0, .. }));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, BTreeMap};
 
 /// Energy market service
+#[derive(Clone)]
 pub struct EnergyTradingService {
     /// Order book
     pub order_book: OrderBook,
@@ -15,6 +16,8 @@ pub struct EnergyTradingService {
     pub participants: HashMap<AccountId, Participant>,
     /// Market configuration
     pub config: EnergyMarketConfig,
+    /// Time trades are stamped with; the runtime keeps it at the chain time
+    pub now: Timestamp,
 }
 
 /// Order book for energy trading
@@ -29,8 +32,8 @@ pub struct OrderBook {
 /// Order key for sorting
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct OrderKey {
-    /// Price in smallest unit
-    pub price: u64,
+    /// Price per kWh
+    pub price: Price,
     /// Timestamp for FIFO ordering
     pub timestamp: Timestamp,
 }
@@ -45,11 +48,11 @@ pub struct EnergyOrder {
     /// Order type
     pub order_type: OrderType,
     /// Energy amount (kWh)
-    pub energy_amount: EnergyAmount,
+    pub energy_amount: Energy,
     /// Price per kWh
     pub price_per_kwh: Price,
     /// Total price
-    pub total_price: Price,
+    pub total_price: Amount,
     /// Order status
     pub status: OrderStatus,
     /// Creation timestamp
@@ -57,7 +60,7 @@ pub struct EnergyOrder {
     /// Expiration timestamp
     pub expires_at: Timestamp,
     /// Filled amount
-    pub filled_amount: EnergyAmount,
+    pub filled_amount: Energy,
 }
 
 /// Order types
@@ -98,13 +101,13 @@ pub struct EnergyTrade {
     /// Seller account
     pub seller: AccountId,
     /// Energy amount
-    pub energy_amount: EnergyAmount,
+    pub energy_amount: Energy,
     /// Price per kWh
     pub price_per_kwh: Price,
     /// Total cost
-    pub total_cost: Price,
+    pub total_cost: Amount,
     /// Grid fee
-    pub grid_fee: Price,
+    pub grid_fee: Amount,
     /// Trade timestamp
     pub timestamp: Timestamp,
     /// Trade status
@@ -134,7 +137,7 @@ pub struct Participant {
     /// Active status
     pub is_active: bool,
     /// Energy balance
-    pub energy_balance: EnergyAmount,
+    pub energy_balance: Energy,
 }
 
 /// Participant type
@@ -142,18 +145,18 @@ pub struct Participant {
 pub enum ParticipantType {
     /// Energy producer
     Producer { 
-        production_capacity: EnergyAmount,
+        production_capacity: Energy,
         energy_source: EnergySource,
     },
     /// Energy consumer
     Consumer { 
-        consumption_capacity: EnergyAmount,
+        consumption_capacity: Energy,
         consumer_type: ConsumerType,
     },
     /// Prosumer (producer + consumer)
     Prosumer { 
-        production_capacity: EnergyAmount,
-        consumption_capacity: EnergyAmount,
+        production_capacity: Energy,
+        consumption_capacity: Energy,
         energy_source: EnergySource,
     },
     /// Energy aggregator
@@ -198,6 +201,7 @@ impl EnergyTradingService {
             active_trades: HashMap::new(),
             participants: HashMap::new(),
             config,
+            now: crate::utils::time::current_timestamp(),
         }
     }
 
@@ -238,7 +242,7 @@ impl EnergyTradingService {
         }
 
         // Check price validity
-        if order.price_per_kwh <= 0.0 {
+        if order.price_per_kwh.is_zero() {
             return Err(CoreError::ValidationFailed("Invalid price".to_string()));
         }
 
@@ -278,19 +282,21 @@ impl EnergyTradingService {
     /// Execute trade between matched orders
     fn execute_trade(&mut self, buy_order: &EnergyOrder, sell_order: &EnergyOrder) -> CoreResult<()> {
         let trade_amount = buy_order.energy_amount.min(sell_order.energy_amount);
-        let trade_price = (buy_order.price_per_kwh + sell_order.price_per_kwh) / 2.0;
-        let total_cost = trade_amount * trade_price;
-        let grid_fee = total_cost * self.config.trading_fee;
+        // Halfway between bid and ask; an odd last digit rounds in the buyer's favour
+        let trade_price = buy_order.price_per_kwh.midpoint(sell_order.price_per_kwh, Rounding::Down);
+        let total_cost = trade_price.checked_cost(trade_amount).ok_or(CoreError::InvalidAmount)?;
+        let grid_fee = crate::utils::calculate_grid_fee(total_cost, self.config.trading_fee_bps)?;
 
         let trade = EnergyTrade {
-            id: format!("trade_{}", chrono::Utc::now().timestamp()),
+            // A pair of orders trades at most once, since the smaller one is filled
+            id: format!("trade_{}_{}", buy_order.id, sell_order.id),
             buyer: buy_order.account.clone(),
             seller: sell_order.account.clone(),
             energy_amount: trade_amount,
             price_per_kwh: trade_price,
             total_cost,
             grid_fee,
-            timestamp: chrono::Utc::now().timestamp() as u64,
+            timestamp: self.now,
             status: TradeStatus::Pending,
         };
 
@@ -329,20 +335,20 @@ impl EnergyTradingService {
         }
     }
 
-    /// Calculate average price
+    /// Calculate average price, rounded down
     fn calculate_average_price(&self) -> Price {
-        let trades: Vec<&EnergyTrade> = self.active_trades.values().collect();
-        if trades.is_empty() {
-            return 0.0;
+        if self.active_trades.is_empty() {
+            return Price::ZERO;
         }
 
-        let total_price: Price = trades.iter().map(|t| t.price_per_kwh).sum();
-        total_price / trades.len() as Price
+        // The mean of u64 prices fits a u64, and their sum fits a u128
+        let total_price: u128 = self.active_trades.values().map(|t| t.price_per_kwh.raw() as u128).sum();
+        Price::from_raw((total_price / self.active_trades.len() as u128) as u64)
     }
 
     /// Calculate total volume
-    fn calculate_total_volume(&self) -> EnergyAmount {
-        self.active_trades.values().map(|t| t.energy_amount).sum()
+    fn calculate_total_volume(&self) -> Energy {
+        self.active_trades.values().map(|t| t.energy_amount).fold(Energy::ZERO, Energy::saturating_add)
     }
 }
 
@@ -360,7 +366,7 @@ pub struct MarketStats {
     /// Average price
     pub average_price: Price,
     /// Total volume
-    pub total_volume: EnergyAmount,
+    pub total_volume: Energy,
 }
 
 impl OrderBook {
@@ -375,7 +381,7 @@ impl OrderBook {
     /// Add order to book
     pub fn add_order(&mut self, order: EnergyOrder) {
         let key = OrderKey {
-            price: (order.price_per_kwh * 1000000.0) as u64, // Convert to smallest unit
+            price: order.price_per_kwh,
             timestamp: order.created_at,
         };
 
@@ -400,7 +406,7 @@ impl OrderBook {
     }
 
     /// Update orders after trade
-    pub fn update_orders_after_trade(&mut self, _buy_order: &EnergyOrder, _sell_order: &EnergyOrder, _trade_amount: EnergyAmount) {
+    pub fn update_orders_after_trade(&mut self, _buy_order: &EnergyOrder, _sell_order: &EnergyOrder, _trade_amount: Energy) {
         // Implementation would update or remove orders based on fill amount
         // This is a simplified version
     }
@@ -0,0 +1,209 @@
+// Call fee service
+// This module prices calls by weight, scales the price with how full recent
+// blocks were and collects the fees into a configurable account
+
+use crate::primitives::*;
+use crate::services::token::{TokenId, TokenService};
+use crate::utils::crypto;
+use serde::{Deserialize, Serialize};
+
+/// Parts per million, the precision of the fee multiplier
+const PPM: u128 = 1_000_000;
+
+/// Fee configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FeeConfig {
+    /// Token fees are paid in
+    pub token: TokenId,
+    /// Account receiving the fees
+    pub collector: AccountId,
+    /// Flat fee per call
+    pub base_fee: Balance,
+    /// Fee per unit of weight at a multiplier of one
+    pub fee_per_weight: Balance,
+    /// Weight one block may hold
+    pub max_block_weight: Weight,
+    /// Block fullness in parts per million at which the multiplier stays put
+    pub target_fullness_ppm: u128,
+    /// How fast the multiplier follows the fullness, in parts per million
+    pub adjustment_ppm: u128,
+    /// Lowest multiplier in parts per million
+    pub min_multiplier_ppm: u128,
+    /// Highest multiplier in parts per million
+    pub max_multiplier_ppm: u128,
+}
+
+impl Default for FeeConfig {
+    fn default() -> Self {
+        Self {
+            token: "GRID".to_string(),
+            collector: crypto::module_account_id("treasury"),
+            base_fee: constants::BASE_CALL_FEE,
+            fee_per_weight: constants::CALL_FEE_PER_WEIGHT,
+            max_block_weight: constants::MAX_BLOCK_WEIGHT,
+            target_fullness_ppm: constants::TARGET_BLOCK_FULLNESS_PPM,
+            adjustment_ppm: 250_000,
+            min_multiplier_ppm: PPM,
+            max_multiplier_ppm: 100 * PPM,
+        }
+    }
+}
+
+/// Fee charged for one call
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FeeBreakdown {
+    /// Token the fee is paid in
+    pub token: TokenId,
+    /// Flat part of the fee
+    pub base_fee: Balance,
+    /// Part of the fee for the call's weight, after the multiplier
+    pub weight_fee: Balance,
+}
+
+impl FeeBreakdown {
+    /// Total fee
+    pub fn total(&self) -> Balance {
+        self.base_fee + self.weight_fee
+    }
+}
+
+/// Fee multiplier and the weight used in the block being built
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeService {
+    /// Fee configuration
+    pub config: FeeConfig,
+    /// Current weight fee multiplier in parts per million
+    pub multiplier_ppm: u128,
+    /// Weight of the calls executed for the next block
+    pub block_weight: Weight,
+}
+
+impl Default for FeeService {
+    fn default() -> Self {
+        Self::new(FeeConfig::default())
+    }
+}
+
+impl FeeService {
+    /// Create a fee service at the lowest multiplier
+    pub fn new(config: FeeConfig) -> Self {
+        Self {
+            multiplier_ppm: config.min_multiplier_ppm,
+            block_weight: 0,
+            config,
+        }
+    }
+
+    /// Fee of a call with the given weight at the current multiplier
+    pub fn fee_for(&self, weight: Weight) -> FeeBreakdown {
+        let weight_fee = (weight as u128)
+            .saturating_mul(self.config.fee_per_weight)
+            .saturating_mul(self.multiplier_ppm) / PPM;
+        FeeBreakdown {
+            token: self.config.token.clone(),
+            base_fee: self.config.base_fee,
+            weight_fee,
+        }
+    }
+
+    /// Take the fee for a call of `weight` from `payer`, reserving room in the block.
+    ///
+    /// Fails without charging if the block has no room left or the payer
+    /// cannot cover the fee.
+    pub fn charge(&mut self, payer: &AccountId, weight: Weight, tokens: &mut TokenService) -> CoreResult<FeeBreakdown> {
+        let block_weight = self.block_weight.saturating_add(weight);
+        if block_weight > self.config.max_block_weight {
+            return Err(CoreError::ValidationFailed("Block weight limit reached".to_string()));
+        }
+
+        let fee = self.fee_for(weight);
+        if fee.total() > 0 {
+            if tokens.get_balance(payer, &fee.token) < fee.total() {
+                return Err(CoreError::InsufficientBalance);
+            }
+            tokens.transfer(payer, &self.config.collector, &fee.token, fee.total())?;
+        }
+        self.block_weight = block_weight;
+        Ok(fee)
+    }
+
+    /// Close the block: move the multiplier towards the fullness of the block and reset its weight.
+    ///
+    /// The multiplier grows when blocks are fuller than the target and
+    /// shrinks when they are emptier, in proportion to the difference.
+    pub fn end_block(&mut self) -> u128 {
+        let config = &self.config;
+        let fullness = (self.block_weight as u128 * PPM / config.max_block_weight.max(1) as u128).min(PPM);
+        let step = |difference: u128| self.multiplier_ppm * config.adjustment_ppm / PPM * difference / PPM;
+        let next = if fullness >= config.target_fullness_ppm {
+            self.multiplier_ppm.saturating_add(step(fullness - config.target_fullness_ppm))
+        } else {
+            self.multiplier_ppm.saturating_sub(step(config.target_fullness_ppm - fullness))
+        };
+
+        self.multiplier_ppm = next.clamp(config.min_multiplier_ppm, config.max_multiplier_ppm);
+        self.block_weight = 0;
+        self.multiplier_ppm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fees_follow_block_fullness() {
+        let mut service = FeeService::new(FeeConfig { max_block_weight: 1_000, ..FeeConfig::default() });
+        let mut tokens = TokenService::new();
+        let payer = "payer".to_string();
+        tokens.mint(&payer, "GRID", 10 * Amount::SCALE).unwrap();
+
+        // 0.01 GRID flat plus 0.00001 GRID per unit of weight
+        let fee = service.charge(&payer, 900, &mut tokens).unwrap();
+        assert_eq!(fee.total(), Amount::SCALE * 19 / 1_000);
+        assert!(service.charge(&payer, 200, &mut tokens).is_err());
+        let collector = service.config.collector.clone();
+        assert_eq!(tokens.get_balance(&collector, &"GRID".to_string()), fee.total());
+
+        // 90% full against a 25% target raises the multiplier by 0.25 * 0.65
+        assert_eq!(service.end_block(), 1_162_500);
+        assert_eq!(service.fee_for(100).weight_fee, constants::CALL_FEE_PER_WEIGHT * 11_625 / 100);
+
+        // Empty blocks bring it back down, but not below the floor
+        for _ in 0..10 {
+            service.end_block();
+        }
+        assert_eq!(service.multiplier_ppm, PPM);
+
+        tokens.mint(&"poor".to_string(), "GRID", constants::BASE_CALL_FEE).unwrap();
+        assert_eq!(service.charge(&"poor".to_string(), 1, &mut tokens), Err(CoreError::InsufficientBalance));
+        assert_eq!(service.block_weight, 0);
+    }
+
+    #[test]
+    fn test_full_blocks_raise_the_multiplier_to_its_cap() {
+        let mut service = FeeService::new(FeeConfig { max_block_weight: 1_000, ..FeeConfig::default() });
+        let mut tokens = TokenService::new();
+        let payer = "payer".to_string();
+        tokens.mint(&payer, "GRID", 1_000 * Amount::SCALE).unwrap();
+
+        // A call may fill the block exactly, but nothing fits after it
+        service.charge(&payer, 1_000, &mut tokens).unwrap();
+        let balance = tokens.get_balance(&payer, &"GRID".to_string());
+        assert!(service.charge(&payer, 1, &mut tokens).is_err());
+        assert_eq!(tokens.get_balance(&payer, &"GRID".to_string()), balance);
+        assert_eq!(service.block_weight, 1_000);
+
+        // A full block against a 25% target raises the multiplier by 0.25 * 0.75
+        assert_eq!(service.end_block(), 1_187_500);
+        assert_eq!(service.block_weight, 0);
+
+        // Back-to-back full blocks stop at the ceiling
+        for _ in 0..50 {
+            service.block_weight = service.config.max_block_weight;
+            service.end_block();
+        }
+        assert_eq!(service.multiplier_ppm, service.config.max_multiplier_ppm);
+        assert_eq!(service.fee_for(1).weight_fee, constants::CALL_FEE_PER_WEIGHT * 100);
+    }
+}
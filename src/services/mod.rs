@@ -2,8 +2,16 @@
 // This module contains all the business logic services
 
 pub mod energy_trading;
+pub mod fees;
+pub mod scheduler;
+pub mod slashing;
+pub mod staking;
 pub mod token;
 
 // Re-export service types
 pub use energy_trading::*;
+pub use fees::*;
+pub use scheduler::*;
+pub use slashing::*;
+pub use staking::*;
 pub use token::*;
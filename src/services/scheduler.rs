@@ -0,0 +1,210 @@
+// Call scheduler service
+// This module keeps calls scheduled for a later block or time, optionally
+// repeating, and hands out the due ones within a weight budget per block
+
+use crate::primitives::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Scheduler configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SchedulerConfig {
+    /// Weight of scheduled calls dispatched per block
+    pub max_weight_per_block: Weight,
+    /// Tasks one account may have scheduled at a time
+    pub max_tasks_per_account: usize,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_weight_per_block: constants::MAX_SCHEDULED_WEIGHT,
+            max_tasks_per_account: constants::MAX_SCHEDULED_PER_ACCOUNT,
+        }
+    }
+}
+
+/// When a scheduled call is due
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DispatchTime {
+    /// At the initialization of a block
+    AtBlock(BlockNumber),
+    /// At the first block initialized once the chain time has reached a timestamp
+    AtTimestamp(Timestamp),
+}
+
+impl DispatchTime {
+    /// Time `every` blocks or seconds later
+    fn after(self, every: u64) -> Self {
+        match self {
+            DispatchTime::AtBlock(number) => DispatchTime::AtBlock(number.saturating_add(every)),
+            DispatchTime::AtTimestamp(timestamp) => DispatchTime::AtTimestamp(timestamp.saturating_add(every)),
+        }
+    }
+}
+
+/// Periodic repetition of a scheduled call
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Repeat {
+    /// Blocks or seconds between dispatches, in the unit of the dispatch time
+    pub every: u64,
+    /// Dispatches left, including the next one
+    pub times: u32,
+}
+
+/// Call waiting for its dispatch time
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScheduledTask {
+    /// Task identifier, used to cancel it
+    pub id: u64,
+    /// Account that scheduled the call and that it is dispatched as
+    pub owner: AccountId,
+    /// Next dispatch time
+    pub when: DispatchTime,
+    /// Repetition, `None` for a single dispatch
+    pub repeat: Option<Repeat>,
+    /// Encoded call
+    pub call: Vec<u8>,
+    /// Weight of one dispatch
+    pub weight: Weight,
+}
+
+/// Scheduled calls ordered by dispatch time
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SchedulerService {
+    /// Scheduler configuration
+    pub config: SchedulerConfig,
+    /// Tasks by identifier
+    pub tasks: BTreeMap<u64, ScheduledTask>,
+    /// Dispatch time and identifier of every task
+    agenda: BTreeSet<(DispatchTime, u64)>,
+    /// Identifier of the next task
+    next_id: u64,
+    /// Chain time timestamp targets are compared with; the runtime keeps it current
+    pub now: Timestamp,
+}
+
+impl SchedulerService {
+    /// Create a scheduler with no tasks
+    pub fn new(config: SchedulerConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Schedule an encoded call of `weight` for `owner`.
+    ///
+    /// `number` is the block being built; a call may be due in that very
+    /// block, but not at a time already passed.
+    pub fn schedule(
+        &mut self,
+        owner: &AccountId,
+        when: DispatchTime,
+        repeat: Option<Repeat>,
+        call: Vec<u8>,
+        weight: Weight,
+        number: BlockNumber,
+    ) -> CoreResult<u64> {
+        let passed = match when {
+            DispatchTime::AtBlock(target) => target < number,
+            DispatchTime::AtTimestamp(target) => target <= self.now,
+        };
+        if passed {
+            return Err(CoreError::InvalidInput("Dispatch time has already passed".to_string()));
+        }
+        if repeat.is_some_and(|repeat| repeat.every == 0 || repeat.times == 0) {
+            return Err(CoreError::InvalidInput("Repetition needs a period and at least one dispatch".to_string()));
+        }
+        if weight > self.config.max_weight_per_block {
+            return Err(CoreError::ValidationFailed("Call is too heavy to be scheduled".to_string()));
+        }
+        if self.tasks_of(owner).count() >= self.config.max_tasks_per_account {
+            return Err(CoreError::ValidationFailed("Too many scheduled calls".to_string()));
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.agenda.insert((when, id));
+        self.tasks.insert(id, ScheduledTask { id, owner: owner.clone(), when, repeat, call, weight });
+        Ok(id)
+    }
+
+    /// Cancel a task on behalf of its owner
+    pub fn cancel(&mut self, origin: &AccountId, id: u64) -> CoreResult<ScheduledTask> {
+        let task = self.tasks.get(&id).ok_or_else(|| CoreError::InvalidInput(format!("No scheduled call {}", id)))?;
+        if &task.owner != origin {
+            return Err(CoreError::ValidationFailed("Only the owner can cancel a scheduled call".to_string()));
+        }
+        self.agenda.remove(&(task.when, id));
+        Ok(self.tasks.remove(&id).expect("task exists"))
+    }
+
+    /// Tasks scheduled by `owner`
+    pub fn tasks_of<'a>(&'a self, owner: &'a AccountId) -> impl Iterator<Item = &'a ScheduledTask> + 'a {
+        self.tasks.values().filter(move |task| &task.owner == owner)
+    }
+
+    /// Take the tasks due at block `number`, as many as the weight budget allows.
+    ///
+    /// Block targets come before timestamp targets, each earliest first.
+    /// Tasks beyond the budget stay due for the following blocks. A
+    /// repeating task is scheduled again one period after its dispatch time.
+    pub fn take_due(&mut self, number: BlockNumber) -> Vec<ScheduledTask> {
+        let by_block = self.agenda.range(..=(DispatchTime::AtBlock(number), u64::MAX));
+        let by_time = self.agenda.range((DispatchTime::AtTimestamp(0), 0)..=(DispatchTime::AtTimestamp(self.now), u64::MAX));
+        let due: Vec<(DispatchTime, u64)> = by_block.chain(by_time).copied().collect();
+
+        let mut used: Weight = 0;
+        let mut taken = Vec::new();
+        for entry in due {
+            let weight = self.tasks[&entry.1].weight;
+            if used + weight > self.config.max_weight_per_block {
+                break;
+            }
+            used += weight;
+            self.agenda.remove(&entry);
+            let task = self.tasks.remove(&entry.1).expect("scheduled task exists");
+            if let Some(repeat) = task.repeat.filter(|repeat| repeat.times > 1) {
+                let next = ScheduledTask {
+                    when: task.when.after(repeat.every),
+                    repeat: Some(Repeat { times: repeat.times - 1, ..repeat }),
+                    ..task.clone()
+                };
+                self.agenda.insert((next.when, next.id));
+                self.tasks.insert(next.id, next);
+            }
+            taken.push(task);
+        }
+        taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due_tasks_follow_budget_and_repeat() {
+        let mut scheduler = SchedulerService::new(SchedulerConfig { max_weight_per_block: 100, max_tasks_per_account: 4 });
+        let owner = "alice".to_string();
+        let repeat = Some(Repeat { every: 10, times: 2 });
+        let periodic = scheduler.schedule(&owner, DispatchTime::AtBlock(5), repeat, vec![1], 60, 1).unwrap();
+        let timed = scheduler.schedule(&owner, DispatchTime::AtTimestamp(1_000), None, vec![2], 60, 1).unwrap();
+        assert!(scheduler.schedule(&owner, DispatchTime::AtBlock(0), None, vec![3], 10, 1).is_err());
+        assert!(scheduler.schedule(&owner, DispatchTime::AtBlock(5), None, vec![3], 101, 1).is_err());
+        assert!(scheduler.cancel(&"bob".to_string(), timed).is_err());
+
+        scheduler.now = 2_000;
+        assert!(scheduler.take_due(4).iter().map(|task| task.id).eq([timed]));
+        // Both due, but only one fits the budget of a block
+        scheduler.schedule(&owner, DispatchTime::AtBlock(5), None, vec![3], 60, 1).unwrap();
+        assert!(scheduler.take_due(5).iter().map(|task| task.id).eq([periodic]));
+        assert_eq!(scheduler.take_due(6).len(), 1);
+        assert!(scheduler.take_due(14).is_empty());
+
+        // The second and last run of the periodic task
+        assert!(scheduler.take_due(15).iter().map(|task| task.id).eq([periodic]));
+        assert!(scheduler.tasks.is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+// Validator slashing service
+// This module turns reported offences into deferred slashes of the offender's
+// and its nominators' stake, which governance may cancel before they apply
+
+use crate::core::offences::{Offence, OffenceKind};
+use crate::hybrid_architecture::public_chain::SlashingRules;
+use crate::primitives::*;
+use crate::services::staking::{Exposure, IndividualExposure, STAKING_TOKEN};
+use crate::services::token::TokenService;
+use crate::utils::crypto;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Parts per million, the precision slash fractions are applied with
+const PPM: Balance = 1_000_000;
+
+/// Slashing configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SlashingConfig {
+    /// Penalties per offence kind
+    pub rules: SlashingRules,
+    /// Slots a validator may miss per era before it is slashed
+    pub max_missed_slots: u64,
+    /// Blocks between reporting a slash and applying it
+    pub defer_blocks: BlockNumber,
+    /// Account receiving slashed funds
+    pub treasury: AccountId,
+    /// Account allowed to cancel deferred slashes
+    pub governance: AccountId,
+}
+
+impl Default for SlashingConfig {
+    fn default() -> Self {
+        Self {
+            rules: SlashingRules::default(),
+            max_missed_slots: constants::MAX_MISSED_SLOTS,
+            defer_blocks: constants::SLASH_DEFER_BLOCKS,
+            treasury: crypto::module_account_id("treasury"),
+            governance: crypto::module_account_id("governance"),
+        }
+    }
+}
+
+/// Slash waiting for its deferral window to pass
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UnappliedSlash {
+    /// Slash identifier, used to cancel it
+    pub id: u64,
+    /// Offending validator
+    pub offender: AccountId,
+    /// Kind of the offence
+    pub kind: OffenceKind,
+    /// Amount taken from the validator's own stake
+    pub own: Balance,
+    /// Amounts taken from its nominators
+    pub others: Vec<IndividualExposure>,
+    /// Block the offence was reported in
+    pub reported_at: BlockNumber,
+    /// First block the slash may be applied in
+    pub apply_at: BlockNumber,
+}
+
+impl UnappliedSlash {
+    /// Total amount the slash takes
+    pub fn total(&self) -> Balance {
+        self.own + self.others.iter().map(|o| o.value).sum::<Balance>()
+    }
+}
+
+/// Deferred slashes and the offences already punished
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlashingService {
+    /// Slashing configuration
+    pub config: SlashingConfig,
+    /// Slashes not applied yet by identifier
+    pub pending: BTreeMap<u64, UnappliedSlash>,
+    /// Identifier of the next slash
+    next_id: u64,
+    /// Offender and offence kind already slashed in an era
+    reported: BTreeSet<(u64, AccountId, OffenceKind)>,
+}
+
+impl SlashingService {
+    /// Create a slashing service with no pending slashes
+    pub fn new(config: SlashingConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Schedule the slash for an offence against the stake exposed to the offender.
+    ///
+    /// The stake the slash takes is locked until it is applied or cancelled,
+    /// so neither the offender nor its nominators can unstake it during the
+    /// deferral window; stake unbonded before the report is no longer slashed.
+    /// A validator is slashed at most once per offence kind and era, so the
+    /// same misbehaviour seen on several blocks is only punished once. Returns
+    /// `None` if the offence was already punished or nothing is at stake.
+    pub fn report(
+        &mut self,
+        offence: &Offence,
+        exposure: &Exposure,
+        era: u64,
+        now: BlockNumber,
+        tokens: &mut TokenService,
+    ) -> Option<&UnappliedSlash> {
+        let key = (era, offence.offender().clone(), offence.kind());
+        self.reported.retain(|(reported_era, _, _)| *reported_era >= era);
+        if self.reported.contains(&key) {
+            return None;
+        }
+
+        let ppm = (self.config.rules.penalty_for(offence.kind()) * PPM as f64).round() as Balance;
+        let token = STAKING_TOKEN.to_string();
+        let mut slash = |who: &AccountId, value: Balance| tokens.lock_staked(who, &token, value.saturating_mul(ppm) / PPM);
+        let id = self.next_id;
+        let unapplied = UnappliedSlash {
+            id,
+            offender: offence.offender().clone(),
+            kind: offence.kind(),
+            own: slash(offence.offender(), exposure.own),
+            others: exposure.others.iter()
+                .map(|o| IndividualExposure { who: o.who.clone(), value: slash(&o.who, o.value) })
+                .filter(|o| o.value > 0)
+                .collect(),
+            reported_at: now,
+            apply_at: now + self.config.defer_blocks,
+        };
+        if unapplied.total() == 0 {
+            return None;
+        }
+
+        self.reported.insert(key);
+        self.next_id += 1;
+        Some(self.pending.entry(id).or_insert(unapplied))
+    }
+
+    /// Cancel a deferred slash on behalf of governance, unlocking the stake it would have taken
+    pub fn cancel(&mut self, origin: &AccountId, id: u64, tokens: &mut TokenService) -> CoreResult<UnappliedSlash> {
+        if origin != &self.config.governance {
+            return Err(CoreError::ValidationFailed("Only governance can cancel slashes".to_string()));
+        }
+        let slash = self.pending.remove(&id)
+            .ok_or_else(|| CoreError::InvalidInput(format!("No pending slash {}", id)))?;
+        Self::unlock(&slash, tokens);
+        Ok(slash)
+    }
+
+    /// Apply every slash whose deferral window has passed, moving the stake to the treasury.
+    ///
+    /// Returns the applied slashes with the amount taken; the stake was
+    /// locked when the slash was reported, so all of it is there.
+    pub fn apply_due(&mut self, now: BlockNumber, tokens: &mut TokenService) -> CoreResult<Vec<(UnappliedSlash, Balance)>> {
+        let due: Vec<u64> = self.pending.values()
+            .filter(|slash| slash.apply_at <= now)
+            .map(|slash| slash.id)
+            .collect();
+
+        let token = STAKING_TOKEN.to_string();
+        let mut applied = Vec::new();
+        for id in due {
+            let slash = self.pending.remove(&id).expect("due slash is pending");
+            Self::unlock(&slash, tokens);
+            let mut taken = tokens.slash_staked(&slash.offender, &token, slash.own, &self.config.treasury)?;
+            for nominator in &slash.others {
+                taken += tokens.slash_staked(&nominator.who, &token, nominator.value, &self.config.treasury)?;
+            }
+            applied.push((slash, taken));
+        }
+        Ok(applied)
+    }
+
+    /// Release the stake locked for a slash
+    fn unlock(slash: &UnappliedSlash, tokens: &mut TokenService) {
+        let token = STAKING_TOKEN.to_string();
+        tokens.unlock_staked(&slash.offender, &token, slash.own);
+        for nominator in &slash.others {
+            tokens.unlock_staked(&nominator.who, &token, nominator.value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::staking::StakeLedger;
+
+    fn exposure() -> Exposure {
+        Exposure {
+            total: 150_000,
+            own: 100_000,
+            others: vec![IndividualExposure { who: "nominator".to_string(), value: 50_000 }],
+        }
+    }
+
+    fn offence(kind: OffenceKind) -> Offence {
+        let vote = crate::core::ValidatorSignature {
+            validator: "validator".to_string(),
+            signature: String::new(),
+            timestamp: 0,
+        };
+        match kind {
+            OffenceKind::Equivocation => Offence::VoteEquivocation {
+                offender: "validator".to_string(),
+                set_id: 0,
+                number: 1,
+                first: ("0xa".to_string(), vote.clone()),
+                second: ("0xb".to_string(), vote),
+            },
+            OffenceKind::Unresponsiveness => Offence::Unresponsive { offender: "validator".to_string(), missed_slots: 100 },
+        }
+    }
+
+    fn staked_tokens() -> TokenService {
+        let mut tokens = TokenService::new();
+        for (who, amount) in [("validator", 100_000), ("nominator", 50_000)] {
+            tokens.mint(&who.to_string(), STAKING_TOKEN, amount).unwrap();
+            tokens.stake(&who.to_string(), amount, None).unwrap();
+        }
+        tokens
+    }
+
+    #[test]
+    fn test_deferred_slash_moves_stake_to_treasury() {
+        let mut service = SlashingService::new(SlashingConfig { defer_blocks: 10, ..SlashingConfig::default() });
+        let mut tokens = staked_tokens();
+
+        let slash = service.report(&offence(OffenceKind::Equivocation), &exposure(), 0, 5, &mut tokens).unwrap().clone();
+        assert_eq!(slash.own, 5_000);
+        assert_eq!(slash.others[0].value, 2_500);
+        assert_eq!(slash.apply_at, 15);
+        // The same offence kind is punished once per era
+        assert!(service.report(&offence(OffenceKind::Equivocation), &exposure(), 0, 6, &mut tokens).is_none());
+
+        assert!(service.apply_due(14, &mut tokens).unwrap().is_empty());
+        let applied = service.apply_due(15, &mut tokens).unwrap();
+        assert_eq!(applied[0].1, 7_500);
+
+        let treasury = service.config.treasury.clone();
+        assert_eq!(tokens.get_balance(&treasury, &STAKING_TOKEN.to_string()), 7_500);
+        assert_eq!(tokens.balances[&("validator".to_string(), STAKING_TOKEN.to_string())].staked, 95_000);
+        assert_eq!(tokens.balances[&("nominator".to_string(), STAKING_TOKEN.to_string())].staked, 47_500);
+    }
+
+    #[test]
+    fn test_governance_cancels_pending_slash() {
+        let mut service = SlashingService::new(SlashingConfig::default());
+        let mut tokens = staked_tokens();
+        let id = service.report(&offence(OffenceKind::Unresponsiveness), &exposure(), 0, 1, &mut tokens).unwrap().id;
+
+        assert!(service.cancel(&"validator".to_string(), id, &mut tokens).is_err());
+        let governance = service.config.governance.clone();
+        assert_eq!(service.cancel(&governance, id, &mut tokens).unwrap().own, 1_000);
+
+        assert!(service.apply_due(constants::SLASH_DEFER_BLOCKS + 1, &mut tokens).unwrap().is_empty());
+        assert_eq!(tokens.balances[&("validator".to_string(), STAKING_TOKEN.to_string())].staked, 100_000);
+    }
+
+    #[test]
+    fn test_reported_stake_cannot_be_unstaked() {
+        let mut service = SlashingService::new(SlashingConfig { defer_blocks: 10, ..SlashingConfig::default() });
+        let mut tokens = staked_tokens();
+        let (validator, nominator) = ("validator".to_string(), "nominator".to_string());
+        service.report(&offence(OffenceKind::Equivocation), &exposure(), 0, 5, &mut tokens).unwrap();
+
+        // Only the stake the slash does not take can leave during the deferral window
+        assert!(tokens.unstake(&validator, 100_000).is_err());
+        assert!(tokens.unstake(&nominator, 50_000).is_err());
+        tokens.unstake(&validator, 95_000).unwrap();
+        tokens.unstake(&nominator, 47_500).unwrap();
+
+        let applied = service.apply_due(15, &mut tokens).unwrap();
+        assert_eq!(applied[0].1, 7_500);
+        let treasury = service.config.treasury.clone();
+        assert_eq!(tokens.get_balance(&treasury, &STAKING_TOKEN.to_string()), 7_500);
+        assert_eq!(tokens.bonded(&validator) + tokens.bonded(&nominator), 0);
+
+        // A cancelled slash releases its lock
+        tokens.stake(&validator, 10_000, None).unwrap();
+        let id = service.report(&offence(OffenceKind::Unresponsiveness), &exposure(), 0, 20, &mut tokens).unwrap().id;
+        assert!(tokens.unstake(&validator, 10_000).is_err());
+        let governance = service.config.governance.clone();
+        service.cancel(&governance, id, &mut tokens).unwrap();
+        tokens.unstake(&validator, 10_000).unwrap();
+    }
+}
@@ -0,0 +1,401 @@
+// Staking and validator election service
+// This module tracks validator candidates and nominations and elects the
+// active validator set at each era boundary with sequential Phragmén
+
+use crate::primitives::*;
+use crate::services::token::TokenService;
+use ledger_arithmetic::mul_div;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Token whose staked balance backs validators and nominators
+pub const STAKING_TOKEN: &str = "GRID";
+
+/// Source of the stake bonded by an account
+pub trait StakeLedger {
+    /// Amount an account has staked
+    fn bonded(&self, who: &AccountId) -> Balance;
+}
+
+impl StakeLedger for TokenService {
+    fn bonded(&self, who: &AccountId) -> Balance {
+        self.balances.get(&(who.clone(), STAKING_TOKEN.to_string()))
+            .map(|balance| balance.staked)
+            .unwrap_or(0)
+    }
+}
+
+impl StakeLedger for pallet_token_system::TokenSystem {
+    fn bonded(&self, who: &AccountId) -> Balance {
+        self.get_stake_info(who).map(|stake| stake.amount as Balance).unwrap_or(0)
+    }
+}
+
+/// Staking configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StakingConfig {
+    /// Size of the active validator set
+    pub validator_count: usize,
+    /// Era length in blocks
+    pub era_length: BlockNumber,
+    /// Stake a candidate needs to be elected
+    pub min_validator_stake: Balance,
+    /// Most candidates one nominator may back
+    pub max_nominations: usize,
+}
+
+impl Default for StakingConfig {
+    fn default() -> Self {
+        Self {
+            validator_count: constants::VALIDATOR_COUNT as usize,
+            era_length: constants::ERA_LENGTH,
+            min_validator_stake: constants::MIN_VALIDATOR_STAKE,
+            max_nominations: constants::MAX_NOMINATIONS as usize,
+        }
+    }
+}
+
+/// Stake one nominator assigned to a validator
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IndividualExposure {
+    /// Nominator
+    pub who: AccountId,
+    /// Stake assigned to the validator
+    pub value: Balance,
+}
+
+/// Stake backing an elected validator
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Exposure {
+    /// Own and nominated stake together
+    pub total: Balance,
+    /// Validator's own stake
+    pub own: Balance,
+    /// Stake of the nominators
+    pub others: Vec<IndividualExposure>,
+}
+
+/// Outcome of an era election
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ElectionResult {
+    /// Era the set is active in
+    pub era: u64,
+    /// Elected validators in election order
+    pub validators: Vec<AccountId>,
+    /// Backing of each elected validator
+    pub exposures: BTreeMap<AccountId, Exposure>,
+}
+
+/// Validator candidacies, nominations and the active set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StakingService {
+    /// Staking configuration
+    pub config: StakingConfig,
+    /// Accounts that declared validator candidacy
+    pub candidates: BTreeSet<AccountId>,
+    /// Candidates backed by each nominator
+    pub nominations: BTreeMap<AccountId, Vec<AccountId>>,
+    /// Validator set of the current era
+    pub active: ElectionResult,
+}
+
+impl StakingService {
+    /// Create a staking service with no candidates
+    pub fn new(config: StakingConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Declare validator candidacy; the account must have bonded enough stake
+    pub fn declare_candidacy(&mut self, who: &AccountId, ledger: &dyn StakeLedger) -> CoreResult<()> {
+        if ledger.bonded(who) < self.config.min_validator_stake {
+            return Err(CoreError::InvalidInput(format!(
+                "Validator stake below minimum of {}",
+                self.config.min_validator_stake
+            )));
+        }
+        if self.nominations.contains_key(who) {
+            return Err(CoreError::InvalidInput("Nominators cannot be validator candidates".to_string()));
+        }
+        self.candidates.insert(who.clone());
+        Ok(())
+    }
+
+    /// Stop being a candidate or a nominator from the next election on
+    pub fn chill(&mut self, who: &AccountId) {
+        self.candidates.remove(who);
+        self.nominations.remove(who);
+    }
+
+    /// Back candidates with the nominator's bonded stake, adding to earlier nominations
+    pub fn nominate(&mut self, who: &AccountId, targets: &[AccountId], ledger: &dyn StakeLedger) -> CoreResult<()> {
+        if ledger.bonded(who) == 0 {
+            return Err(CoreError::InvalidInput("Nominator has no bonded stake".to_string()));
+        }
+        if self.candidates.contains(who) {
+            return Err(CoreError::InvalidInput("Validator candidates cannot nominate".to_string()));
+        }
+        if let Some(target) = targets.iter().find(|t| !self.candidates.contains(*t)) {
+            return Err(CoreError::InvalidInput(format!("{} is not a validator candidate", target)));
+        }
+
+        let mut nominated = self.nominations.get(who).cloned().unwrap_or_default();
+        for target in targets {
+            if !nominated.contains(target) {
+                nominated.push(target.clone());
+            }
+        }
+        if nominated.is_empty() || nominated.len() > self.config.max_nominations {
+            return Err(CoreError::InvalidInput(format!(
+                "Nominate between 1 and {} candidates",
+                self.config.max_nominations
+            )));
+        }
+        self.nominations.insert(who.clone(), nominated);
+        Ok(())
+    }
+
+    /// Era a block belongs to
+    pub fn era_of(&self, number: BlockNumber) -> u64 {
+        number / self.config.era_length.max(1)
+    }
+
+    /// Run the election if `number` is the last block of an era.
+    ///
+    /// Returns the new active set. If no candidate is eligible the current
+    /// set stays active.
+    pub fn end_block(&mut self, number: BlockNumber, ledger: &dyn StakeLedger) -> Option<ElectionResult> {
+        if self.era_of(number + 1) == self.era_of(number) {
+            return None;
+        }
+        let mut result = self.elect(ledger);
+        if result.validators.is_empty() {
+            return None;
+        }
+        result.era = self.era_of(number + 1);
+        self.active = result.clone();
+        Some(result)
+    }
+
+    /// Elect validators from the current candidacies, nominations and stake
+    pub fn elect(&self, ledger: &dyn StakeLedger) -> ElectionResult {
+        let candidates: Vec<(AccountId, Balance)> = self.candidates.iter()
+            .map(|c| (c.clone(), ledger.bonded(c)))
+            .filter(|(_, stake)| *stake >= self.config.min_validator_stake)
+            .collect();
+        let eligible: BTreeSet<&AccountId> = candidates.iter().map(|(c, _)| c).collect();
+
+        // Candidates vote for themselves with their own stake
+        let mut voters: Vec<(AccountId, Balance, Vec<AccountId>)> = candidates.iter()
+            .map(|(c, stake)| (c.clone(), *stake, vec![c.clone()]))
+            .collect();
+        for (nominator, targets) in &self.nominations {
+            let targets: Vec<AccountId> = targets.iter().filter(|t| eligible.contains(t)).cloned().collect();
+            let stake = ledger.bonded(nominator);
+            if stake > 0 && !targets.is_empty() {
+                voters.push((nominator.clone(), stake, targets));
+            }
+        }
+
+        let (validators, exposures) = seq_phragmen(self.config.validator_count, &candidates, &voters);
+        ElectionResult {
+            era: self.active.era,
+            validators,
+            exposures,
+        }
+    }
+}
+
+/// Resolution of the voters' stake shares: parts per billion of the total voter stake
+const SHARE_SCALE: u128 = 1_000_000_000;
+
+/// A load of one over a single share; loads are integers in these units
+const LOAD_ONE: u128 = SHARE_SCALE * SHARE_SCALE * SHARE_SCALE;
+
+/// Sequential Phragmén election.
+///
+/// Each round elects the candidate whose election raises the maximum voter
+/// load the least, which spreads the voters' stake evenly over the winners.
+/// A voter backing itself (the candidate's own stake) is reported as `own`
+/// exposure. Stakes enter as parts per billion of the total voter stake and
+/// loads are fixed point, so every node elects the same set; each voter's
+/// stake is split exactly, with rounding left to its last winner.
+pub fn seq_phragmen(
+    to_elect: usize,
+    candidates: &[(AccountId, Balance)],
+    voters: &[(AccountId, Balance, Vec<AccountId>)],
+) -> (Vec<AccountId>, BTreeMap<AccountId, Exposure>) {
+    let index: BTreeMap<&AccountId, usize> = candidates.iter().enumerate().map(|(i, (c, _))| (c, i)).collect();
+    let total = voters.iter().fold(0u128, |total, (_, stake, _)| total.saturating_add(*stake));
+    let shares: Vec<u128> = voters.iter().map(|(_, stake, _)| stake_share(*stake, total)).collect();
+    let mut approval = vec![0u128; candidates.len()];
+    for (v, (_, _, targets)) in voters.iter().enumerate() {
+        for target in targets {
+            if let Some(&i) = index.get(target) {
+                approval[i] += shares[v];
+            }
+        }
+    }
+
+    let mut elected: Vec<usize> = Vec::new();
+    let mut voter_load = vec![0u128; voters.len()];
+    // Load each voter carried for each of its targets when that target was elected
+    let mut edge_load: Vec<BTreeMap<usize, u128>> = vec![BTreeMap::new(); voters.len()];
+
+    while elected.len() < to_elect.min(candidates.len()) {
+        let mut scores = vec![LOAD_ONE; candidates.len()];
+        for (v, (_, _, targets)) in voters.iter().enumerate() {
+            for target in targets {
+                if let Some(&i) = index.get(target) {
+                    scores[i] = scores[i].saturating_add(shares[v].saturating_mul(voter_load[v]));
+                }
+            }
+        }
+
+        // Lowest score wins; ties go to the smaller account id
+        let winner = (0..candidates.len())
+            .filter(|i| !elected.contains(i) && approval[*i] > 0)
+            .map(|i| (i, scores[i] / approval[i]))
+            .min_by(|(a, sa), (b, sb)| sa.cmp(sb).then(candidates[*a].0.cmp(&candidates[*b].0)));
+        let Some((winner, score)) = winner else {
+            break;
+        };
+        elected.push(winner);
+        for (v, (_, _, targets)) in voters.iter().enumerate() {
+            if targets.iter().any(|t| index.get(t) == Some(&winner)) {
+                edge_load[v].insert(winner, score - voter_load[v]);
+                voter_load[v] = score;
+            }
+        }
+    }
+
+    let mut exposures: BTreeMap<AccountId, Exposure> = elected.iter()
+        .map(|&i| (candidates[i].0.clone(), Exposure::default()))
+        .collect();
+    for (v, (voter, stake, _)) in voters.iter().enumerate() {
+        let edges: Vec<(usize, u128)> = edge_load[v].iter().map(|(i, load)| (*i, *load)).collect();
+        let mut remaining = *stake;
+        for (n, (i, load)) in edges.iter().enumerate() {
+            let value = if n + 1 == edges.len() {
+                remaining
+            } else {
+                let parts = mul_div(*load, SHARE_SCALE, voter_load[v], Rounding::Down).unwrap_or(SHARE_SCALE);
+                (stake / SHARE_SCALE * parts + stake % SHARE_SCALE * parts / SHARE_SCALE).min(remaining)
+            };
+            remaining -= value;
+
+            let exposure = exposures.get_mut(&candidates[*i].0).expect("edges only point at elected candidates");
+            exposure.total += value;
+            if voter == &candidates[*i].0 {
+                exposure.own += value;
+            } else if value > 0 {
+                exposure.others.push(IndividualExposure { who: voter.clone(), value });
+            }
+        }
+    }
+
+    let validators = elected.into_iter().map(|i| candidates[i].0.clone()).collect();
+    (validators, exposures)
+}
+
+/// `stake` as parts per billion of `total`, rounded down
+fn stake_share(stake: Balance, total: Balance) -> u128 {
+    mul_div(stake, SHARE_SCALE, total, Rounding::Down).unwrap_or_else(|| match total / SHARE_SCALE {
+        0 => 0,
+        unit => (stake / unit).min(SHARE_SCALE),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct Stakes(HashMap<AccountId, Balance>);
+
+    impl StakeLedger for Stakes {
+        fn bonded(&self, who: &AccountId) -> Balance {
+            self.0.get(who).copied().unwrap_or(0)
+        }
+    }
+
+    fn id(name: &str) -> AccountId {
+        name.to_string()
+    }
+
+    #[test]
+    fn test_phragmen_balances_nominator_stake() {
+        // Two nominators back "a"; one also backs "b", so its stake moves to "b"
+        let candidates = vec![(id("a"), 10), (id("b"), 10), (id("c"), 10)];
+        let voters = vec![
+            (id("a"), 10, vec![id("a")]),
+            (id("b"), 10, vec![id("b")]),
+            (id("c"), 10, vec![id("c")]),
+            (id("x"), 30, vec![id("a")]),
+            (id("y"), 20, vec![id("a"), id("b")]),
+        ];
+        let (validators, exposures) = seq_phragmen(2, &candidates, &voters);
+
+        assert_eq!(validators, vec![id("a"), id("b")]);
+        let (a, b) = (&exposures[&id("a")], &exposures[&id("b")]);
+        assert_eq!((a.own, b.own), (10, 10));
+        assert_eq!(a.total + b.total, 70);
+        assert!(b.others.iter().any(|e| e.who == id("y") && e.value > 0));
+        assert_eq!(a.others.iter().filter(|e| e.who == id("y")).count(), 1);
+    }
+
+    #[test]
+    fn test_phragmen_splits_large_stakes_exactly() {
+        // Stakes far beyond a float's 53-bit mantissa, one unit apart
+        let tokens = 1_000_000_000_000 * Amount::SCALE;
+        let candidates = vec![(id("a"), tokens + 1), (id("b"), tokens), (id("c"), tokens)];
+        let voters = vec![
+            (id("a"), tokens + 1, vec![id("a")]),
+            (id("b"), tokens, vec![id("b")]),
+            (id("c"), tokens, vec![id("c")]),
+            (id("x"), 3 * tokens + 7, vec![id("a"), id("b"), id("c")]),
+        ];
+        let (validators, exposures) = seq_phragmen(2, &candidates, &voters);
+
+        // "b" and "c" tie and the smaller account id wins
+        let tie = if id("b") < id("c") { id("b") } else { id("c") };
+        assert_eq!(validators, vec![id("a"), tie.clone()]);
+        let total: Balance = exposures.values().map(|exposure| exposure.total).sum();
+        assert_eq!(total, 5 * tokens + 8);
+        assert_eq!(exposures[&id("a")].own, tokens + 1);
+        assert_eq!(seq_phragmen(2, &candidates, &voters), (validators, exposures));
+    }
+
+    #[test]
+    fn test_era_election_requires_minimum_stake() {
+        let config = StakingConfig { validator_count: 2, era_length: 10, min_validator_stake: 100, max_nominations: 4 };
+        let mut staking = StakingService::new(config);
+        let mut stakes = Stakes(HashMap::new());
+        stakes.0.insert(id("a"), 100);
+        stakes.0.insert(id("b"), 150);
+        stakes.0.insert(id("c"), 50);
+        stakes.0.insert(id("n"), 500);
+
+        staking.declare_candidacy(&id("a"), &stakes).unwrap();
+        staking.declare_candidacy(&id("b"), &stakes).unwrap();
+        assert!(staking.declare_candidacy(&id("c"), &stakes).is_err());
+        assert!(staking.nominate(&id("n"), &[id("c")], &stakes).is_err());
+        staking.nominate(&id("n"), &[id("a")], &stakes).unwrap();
+
+        // Nothing happens until the era's last block
+        assert_eq!(staking.end_block(8, &stakes), None);
+        let result = staking.end_block(9, &stakes).unwrap();
+        assert_eq!(result.era, 1);
+        assert_eq!(result.validators, vec![id("a"), id("b")]);
+        assert_eq!(result.exposures[&id("a")].total, 600);
+        assert_eq!(staking.active, result);
+
+        // A candidate whose stake dropped below the minimum is not elected
+        stakes.0.insert(id("b"), 10);
+        let result = staking.end_block(19, &stakes).unwrap();
+        assert_eq!(result.era, 2);
+        assert_eq!(result.validators, vec![id("a")]);
+    }
+}
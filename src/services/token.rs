@@ -86,6 +86,7 @@ pub struct StakingInfo {
 }
 
 /// Token service for managing tokens and governance
+#[derive(Clone)]
 pub struct TokenService {
     /// Token metadata storage
     pub tokens: HashMap<TokenId, TokenMetadata>,
@@ -99,18 +100,36 @@ pub struct TokenService {
     pub staking: HashMap<AccountId, Vec<StakingInfo>>,
     /// Token allowances
     pub allowances: HashMap<(AccountId, AccountId, TokenId), TokenAmount>,
+    /// Time proposals and stakes are stamped with; the runtime keeps it at the chain time
+    pub now: Timestamp,
 }
 
 impl TokenService {
     /// Create a new token service
     pub fn new() -> Self {
+        let mut tokens = HashMap::new();
+        for (symbol, name, description) in [
+            ("GRID", "Grid Token", "Utility and governance token, staked to back validators"),
+            ("WATT", "Watt Token", "Stable token used to settle energy trades"),
+        ] {
+            tokens.insert(symbol.to_string(), TokenMetadata {
+                name: name.to_string(),
+                symbol: symbol.to_string(),
+                decimals: constants::TOKEN_DECIMALS,
+                total_supply: 0,
+                description: description.to_string(),
+                icon_url: None,
+            });
+        }
+
         Self {
-            tokens: HashMap::new(),
+            tokens,
             balances: HashMap::new(),
             proposals: HashMap::new(),
             votes: HashMap::new(),
             staking: HashMap::new(),
             allowances: HashMap::new(),
+            now: crate::utils::time::current_timestamp(),
         }
     }
 
@@ -197,7 +216,7 @@ impl TokenService {
         voting_period: u64,
     ) -> CoreResult<String> {
         let proposal_id = format!("proposal_{}", self.proposals.len() + 1);
-        let now = chrono::Utc::now().timestamp() as u64;
+        let now = self.now;
 
         let proposal = GovernanceProposal {
             id: proposal_id.clone(),
@@ -228,8 +247,7 @@ impl TokenService {
             .ok_or_else(|| CoreError::InvalidInput("Proposal not found".to_string()))?;
 
         // Check if voting period is still active
-        let now = chrono::Utc::now().timestamp() as u64;
-        if now > proposal.voting_ends_at {
+        if self.now > proposal.voting_ends_at {
             return Err(CoreError::InvalidInput("Voting period has ended".to_string()));
         }
 
@@ -273,15 +291,16 @@ impl TokenService {
         self.transfer_tokens(&token_id.to_string(), from, to, amount)
     }
 
-    /// Stake tokens (simplified interface)
+    /// Stake GRID tokens (simplified interface).
+    ///
+    /// Backing a validator is recorded by the staking service, not here.
     pub fn stake(&mut self, account: &AccountId, amount: TokenAmount, _validator: Option<&AccountId>) -> CoreResult<()> {
-        // Use a default governance token for staking
-        self.stake_tokens(account, &"governance_token".to_string(), amount)
+        self.stake_tokens(account, &"GRID".to_string(), amount)
     }
 
-    /// Unstake tokens (simplified interface)
+    /// Unstake GRID tokens (simplified interface)
     pub fn unstake(&mut self, account: &AccountId, amount: TokenAmount) -> CoreResult<()> {
-        self.unstake_tokens(account, &"governance_token".to_string(), amount)
+        self.unstake_tokens(account, &"GRID".to_string(), amount)
     }
 
     /// Stake tokens
@@ -306,7 +325,7 @@ impl TokenService {
             staker: account.clone(),
             amount,
             reward_rate: 0.05, // 5% annual reward
-            staked_at: chrono::Utc::now().timestamp() as u64,
+            staked_at: self.now,
             lock_period: 30 * 24 * 60 * 60, // 30 days
             rewards_earned: 0,
         };
@@ -328,6 +347,9 @@ impl TokenService {
         if balance.staked < amount {
             return Err(CoreError::InvalidInput("Insufficient staked tokens".to_string()));
         }
+        if balance.staked - balance.locked < amount {
+            return Err(CoreError::InvalidInput("Staked tokens are locked by a pending slash".to_string()));
+        }
 
         balance.staked -= amount;
         balance.balance += amount;
@@ -340,6 +362,58 @@ impl TokenService {
         Ok(())
     }
 
+    /// Lock up to `amount` of an account's staked tokens so they cannot be unstaked.
+    ///
+    /// Returns the amount actually locked, which is less than `amount` if the
+    /// account has less unlocked stake.
+    pub fn lock_staked(&mut self, account: &AccountId, token_id: &TokenId, amount: TokenAmount) -> TokenAmount {
+        match self.balances.get_mut(&(account.clone(), token_id.clone())) {
+            Some(balance) => {
+                let locked = amount.min(balance.staked - balance.locked);
+                balance.locked += locked;
+                locked
+            }
+            None => 0,
+        }
+    }
+
+    /// Release staked tokens locked with [`Self::lock_staked`]
+    pub fn unlock_staked(&mut self, account: &AccountId, token_id: &TokenId, amount: TokenAmount) {
+        if let Some(balance) = self.balances.get_mut(&(account.clone(), token_id.clone())) {
+            balance.locked = balance.locked.saturating_sub(amount);
+        }
+    }
+
+    /// Move up to `amount` of an account's staked tokens to `beneficiary`.
+    ///
+    /// Returns the amount actually moved, which is less than `amount` if the
+    /// account has since unstaked.
+    pub fn slash_staked(
+        &mut self,
+        account: &AccountId,
+        token_id: &TokenId,
+        amount: TokenAmount,
+        beneficiary: &AccountId,
+    ) -> CoreResult<TokenAmount> {
+        if !self.tokens.contains_key(token_id) {
+            return Err(CoreError::InvalidInput("Token does not exist".to_string()));
+        }
+
+        let slashed = match self.balances.get_mut(&(account.clone(), token_id.clone())) {
+            Some(balance) => {
+                let slashed = amount.min(balance.staked);
+                balance.staked -= slashed;
+                balance.locked = balance.locked.min(balance.staked);
+                slashed
+            }
+            None => 0,
+        };
+        if slashed > 0 {
+            self.mint_tokens(token_id, beneficiary, slashed)?;
+        }
+        Ok(slashed)
+    }
+
     /// Get proposal details
     pub fn get_proposal(&self, proposal_id: &str) -> Option<&GovernanceProposal> {
         self.proposals.get(proposal_id)
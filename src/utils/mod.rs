@@ -3,7 +3,6 @@
 
 use crate::primitives::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
 /// Cryptographic utilities
 pub mod crypto {
@@ -29,18 +28,117 @@ pub mod crypto {
         format!("0x{:x}", hasher.finish())
     }
 
-    /// Verify signature (simplified)
-    pub fn verify_signature(message: &str, signature: &str, public_key: &str) -> bool {
-        // Simplified signature verification
-        // In a real implementation, this would use proper cryptographic verification
-        !message.is_empty() && !signature.is_empty() && !public_key.is_empty()
+    /// SHA-256 digest of raw bytes
+    pub fn sha256(data: &[u8]) -> [u8; 32] {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(data).into()
     }
 
-    /// Generate keypair (simplified)
+    /// SHA-256 digest of raw bytes as a `0x`-prefixed hex hash
+    pub fn sha256_hash(data: &[u8]) -> Hash {
+        hash_to_hex(&sha256(data))
+    }
+
+    /// Format a 32-byte digest as a `0x`-prefixed hex hash
+    pub fn hash_to_hex(digest: &[u8; 32]) -> Hash {
+        format!("0x{}", to_hex(digest))
+    }
+
+    /// Parse a `0x`-prefixed hex hash into a 32-byte digest
+    pub fn hash_from_hex(hash: &str) -> Option<[u8; 32]> {
+        from_hex(hash.strip_prefix("0x").unwrap_or(hash))?.try_into().ok()
+    }
+
+    /// Lowercase hex encoding of raw bytes
+    pub fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Decode hex into raw bytes
+    pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+        if !hex.len().is_multiple_of(2) {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+
+    /// Sign a message with a hex-encoded Ed25519 private key, returning the hex signature
+    pub fn sign_message(message: &[u8], private_key: &str) -> CoreResult<String> {
+        use ed25519_dalek::Signer;
+
+        let signing_key = signing_key_from_hex(private_key)
+            .ok_or_else(|| CoreError::InvalidInput("Malformed private key".to_string()))?;
+        Ok(to_hex(&signing_key.sign(message).to_bytes()))
+    }
+
+    /// Verify a hex-encoded Ed25519 signature over `message`
+    pub fn verify_signature(message: &[u8], signature: &str, public_key: &str) -> bool {
+        use ed25519_dalek::{Signature, VerifyingKey};
+
+        let key_bytes: [u8; 32] = match from_hex(public_key).and_then(|b| b.try_into().ok()) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+        let signature_bytes: [u8; 64] = match from_hex(signature).and_then(|b| b.try_into().ok()) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+        match VerifyingKey::from_bytes(&key_bytes) {
+            Ok(key) => key.verify_strict(message, &Signature::from_bytes(&signature_bytes)).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Generate a random Ed25519 keypair as hex `(private_key, public_key)`
     pub fn generate_keypair() -> (String, String) {
-        let private_key = format!("priv_{:x}", fastrand::u64(..));
-        let public_key = format!("pub_{:x}", fastrand::u64(..));
-        (private_key, public_key)
+        let mut secret = [0u8; 32];
+        getrandom::getrandom(&mut secret).expect("operating system RNG unavailable");
+        keypair_from_secret(&secret)
+    }
+
+    /// Derive a deterministic keypair from a seed phrase (development accounts only)
+    pub fn keypair_from_seed(seed: &str) -> (String, String) {
+        keypair_from_secret(&sha256(seed.as_bytes()))
+    }
+
+    /// Account ID owned by a public key.
+    ///
+    /// The account ID is the hex encoding of the Ed25519 public key itself, so a
+    /// signature can be checked against the sender without any key registry.
+    pub fn account_id_from_public_key(public_key: &str) -> Option<AccountId> {
+        let bytes: [u8; 32] = from_hex(public_key)?.try_into().ok()?;
+        ed25519_dalek::VerifyingKey::from_bytes(&bytes).ok()?;
+        Some(to_hex(&bytes))
+    }
+
+    /// Hex public key belonging to a hex-encoded private key
+    pub fn public_key_from_private(private_key: &str) -> Option<String> {
+        signing_key_from_hex(private_key).map(|key| to_hex(key.verifying_key().as_bytes()))
+    }
+
+    /// Account ID of a dev account derived with [`keypair_from_seed`]
+    pub fn dev_account_id(seed: &str) -> AccountId {
+        keypair_from_seed(seed).1
+    }
+
+    /// Keyless account ID of a runtime module such as the treasury.
+    ///
+    /// Nobody holds a private key for it, so funds only move by runtime logic.
+    pub fn module_account_id(name: &str) -> AccountId {
+        to_hex(&sha256(format!("modl/{}", name).as_bytes()))
+    }
+
+    fn keypair_from_secret(secret: &[u8; 32]) -> (String, String) {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(secret);
+        (to_hex(secret), to_hex(signing_key.verifying_key().as_bytes()))
+    }
+
+    fn signing_key_from_hex(private_key: &str) -> Option<ed25519_dalek::SigningKey> {
+        let secret: [u8; 32] = from_hex(private_key)?.try_into().ok()?;
+        Some(ed25519_dalek::SigningKey::from_bytes(&secret))
     }
 }
 
@@ -90,38 +188,38 @@ pub mod math {
         }
     }
 
-    /// Calculate grid fee
-    pub fn calculate_grid_fee(amount: f64, fee_percentage: f64) -> f64 {
-        amount * fee_percentage
+    /// Calculate grid fee from a rate in basis points, rounding up
+    pub fn calculate_grid_fee(amount: Amount, fee_bps: u32) -> CoreResult<Amount> {
+        amount.checked_mul_ratio(fee_bps as u128, 10_000, Rounding::Up).ok_or(CoreError::InvalidAmount)
     }
 
     /// Convert kWh to tokens (1:1 ratio)
-    pub fn kwh_to_tokens(kwh: EnergyAmount) -> Balance {
-        (kwh * constants::KWH_TO_TOKEN_RATIO * 10_f64.powi(constants::TOKEN_DECIMALS as i32)) as Balance
+    pub fn kwh_to_tokens(kwh: Energy) -> CoreResult<Balance> {
+        constants::KWH_TO_TOKEN_RATIO.checked_cost(kwh).map(Amount::raw).ok_or(CoreError::InvalidAmount)
     }
 
-    /// Convert tokens to kWh
-    pub fn tokens_to_kwh(tokens: Balance) -> EnergyAmount {
-        (tokens as f64) / (constants::KWH_TO_TOKEN_RATIO * 10_f64.powi(constants::TOKEN_DECIMALS as i32))
+    /// Convert tokens to kWh, rounding down to whole Wh
+    pub fn tokens_to_kwh(tokens: Balance) -> CoreResult<Energy> {
+        constants::KWH_TO_TOKEN_RATIO.checked_energy(Amount::from_raw(tokens), Rounding::Down).ok_or(CoreError::InvalidAmount)
     }
 
     /// Calculate energy trade settlement
     pub fn calculate_trade_settlement(
-        energy_amount: EnergyAmount,
+        energy_amount: Energy,
         price_per_kwh: Price,
-        grid_fee_percentage: f64,
-    ) -> TradeSettlement {
-        let total_cost = energy_amount * price_per_kwh;
-        let grid_fee = calculate_grid_fee(total_cost, grid_fee_percentage);
-        let net_amount = total_cost - grid_fee;
+        grid_fee_bps: u32,
+    ) -> CoreResult<TradeSettlement> {
+        let total_cost = price_per_kwh.checked_cost(energy_amount).ok_or(CoreError::InvalidAmount)?;
+        let grid_fee = calculate_grid_fee(total_cost, grid_fee_bps)?;
+        let net_amount = total_cost.saturating_sub(grid_fee);
 
-        TradeSettlement {
+        Ok(TradeSettlement {
             energy_amount,
             price_per_kwh,
             total_cost,
             grid_fee,
             net_amount,
-        }
+        })
     }
 
     /// Calculate compound interest for staking rewards
@@ -140,15 +238,15 @@ pub mod math {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TradeSettlement {
     /// Energy amount
-    pub energy_amount: EnergyAmount,
+    pub energy_amount: Energy,
     /// Price per kWh
     pub price_per_kwh: Price,
     /// Total cost
-    pub total_cost: Price,
+    pub total_cost: Amount,
     /// Grid fee
-    pub grid_fee: Price,
+    pub grid_fee: Amount,
     /// Net amount after fees
-    pub net_amount: Price,
+    pub net_amount: Amount,
 }
 
 /// Validation utilities
@@ -161,7 +259,7 @@ pub mod validation {
     }
 
     /// Validate energy amount
-    pub fn validate_energy_amount(amount: EnergyAmount) -> CoreResult<()> {
+    pub fn validate_energy_amount(amount: Energy) -> CoreResult<()> {
         if amount < constants::MIN_ENERGY_TRADE {
             return Err(CoreError::ValidationFailed("Energy amount too small".to_string()));
         }
@@ -173,10 +271,10 @@ pub mod validation {
 
     /// Validate price
     pub fn validate_price(price: Price) -> CoreResult<()> {
-        if price <= 0.0 {
+        if price.is_zero() {
             return Err(CoreError::ValidationFailed("Price must be positive".to_string()));
         }
-        if price > 1000.0 {
+        if price > Price::from_raw(1000 * Price::SCALE) {
             return Err(CoreError::ValidationFailed("Price too high".to_string()));
         }
         Ok(())
@@ -220,13 +318,13 @@ pub mod format {
     }
 
     /// Format energy amount
-    pub fn format_energy_amount(amount: EnergyAmount) -> String {
-        format!("{:.2} kWh", amount)
+    pub fn format_energy_amount(amount: Energy) -> String {
+        format!("{} kWh", amount)
     }
 
     /// Format price
     pub fn format_price(price: Price) -> String {
-        format!("{:.2} THB", price)
+        format!("{} THB", price)
     }
 
     /// Format percentage
@@ -253,64 +351,25 @@ pub mod format {
 pub mod config {
     use super::*;
 
-    /// Load system configuration from environment or defaults
-    pub fn load_system_config() -> SystemConfig {
-        SystemConfig {
+    /// Load system configuration from environment or defaults.
+    ///
+    /// Fails if `CHAIN_SPEC` names neither a preset nor a readable spec file.
+    pub fn load_system_config() -> CoreResult<SystemConfig> {
+        Ok(SystemConfig {
             network_id: std::env::var("NETWORK_ID").unwrap_or_else(|_| "thai-energy-trading".to_string()),
-            chain_spec: load_chain_spec(),
+            chain_spec: load_chain_spec()?,
             node_config: load_node_config(),
             consensus_config: load_consensus_config(),
-        }
-    }
-
-    /// Load chain specification
-    fn load_chain_spec() -> ChainSpec {
-        ChainSpec {
-            name: std::env::var("CHAIN_NAME").unwrap_or_else(|_| "Thai Energy Trading Network".to_string()),
-            id: std::env::var("CHAIN_ID").unwrap_or_else(|_| "thai-energy-trading".to_string()),
-            genesis: load_genesis_config(),
-            boot_nodes: load_boot_nodes(),
-        }
-    }
-
-    /// Load genesis configuration
-    fn load_genesis_config() -> GenesisConfig {
-        GenesisConfig {
-            accounts: load_genesis_accounts(),
-            validators: load_genesis_validators(),
-            energy_market: load_energy_market_config(),
-        }
-    }
-
-    /// Load genesis accounts
-    fn load_genesis_accounts() -> HashMap<AccountId, Balance> {
-        let mut accounts = HashMap::new();
-        
-        // Add some default accounts for testing
-        accounts.insert("alice".to_string(), 1_000_000 * 10_u128.pow(constants::TOKEN_DECIMALS as u32));
-        accounts.insert("bob".to_string(), 1_000_000 * 10_u128.pow(constants::TOKEN_DECIMALS as u32));
-        accounts.insert("charlie".to_string(), 1_000_000 * 10_u128.pow(constants::TOKEN_DECIMALS as u32));
-        
-        accounts
+        })
     }
 
-    /// Load genesis validators
-    fn load_genesis_validators() -> Vec<AccountId> {
-        vec![
-            "alice".to_string(),
-            "bob".to_string(),
-            "charlie".to_string(),
-        ]
-    }
-
-    /// Load energy market configuration
-    fn load_energy_market_config() -> EnergyMarketConfig {
-        EnergyMarketConfig {
-            opening_hours: (6, 22), // 6 AM to 10 PM
-            trading_fee: constants::GRID_FEE_PERCENTAGE,
-            min_order_size: constants::MIN_ENERGY_TRADE,
-            max_order_size: constants::MAX_ENERGY_TRADE,
-        }
+    /// Load the chain spec named by `CHAIN_SPEC`, a preset or a JSON or TOML
+    /// file, `local-testnet` by default; `BOOT_NODES` adds boot nodes
+    pub fn load_chain_spec() -> CoreResult<ChainSpec> {
+        let chain = std::env::var("CHAIN_SPEC").unwrap_or_else(|_| "local-testnet".to_string());
+        let mut spec = ChainSpec::from_name_or_path(&chain)?;
+        spec.boot_nodes.extend(load_boot_nodes());
+        Ok(spec)
     }
 
     /// Load node configuration
@@ -321,6 +380,9 @@ pub mod config {
             rpc_port: std::env::var("RPC_PORT").unwrap_or_else(|_| "9933".to_string()).parse().unwrap_or(9933),
             ws_port: std::env::var("WS_PORT").unwrap_or_else(|_| "9944".to_string()).parse().unwrap_or(9944),
             data_dir: std::env::var("DATA_DIR").unwrap_or_else(|_| "./data".to_string()),
+            pruning: std::env::var("PRUNING").ok()
+                .and_then(|mode| PruningMode::parse(&mode))
+                .unwrap_or_default(),
         }
     }
 
@@ -333,12 +395,15 @@ pub mod config {
                 _ => ConsensusAlgorithm::NPoS,
             },
             block_time: std::env::var("BLOCK_TIME").unwrap_or_else(|_| "6".to_string()).parse().unwrap_or(6),
-            finality_threshold: std::env::var("FINALITY_THRESHOLD").unwrap_or_else(|_| "12".to_string()).parse().unwrap_or(12),
+            finality_threshold: std::env::var("FINALITY_THRESHOLD").unwrap_or_else(|_| "66".to_string()).parse().unwrap_or(66),
+            empty_blocks: std::env::var("EMPTY_BLOCKS").ok()
+                .and_then(|policy| EmptyBlocks::parse(&policy))
+                .unwrap_or_default(),
         }
     }
 
-    /// Load boot nodes
-    fn load_boot_nodes() -> Vec<String> {
+    /// Boot nodes listed in `BOOT_NODES`, separated by commas
+    pub fn load_boot_nodes() -> Vec<String> {
         std::env::var("BOOT_NODES")
             .unwrap_or_default()
             .split(',')
@@ -359,34 +424,39 @@ pub mod testing {
     }
 
     /// Create test energy order
-    pub fn create_test_energy_order(account: &str, order_type: crate::services::OrderType, amount: EnergyAmount, price: Price) -> crate::services::EnergyOrder {
+    pub fn create_test_energy_order(account: &str, order_type: crate::services::OrderType, amount: Energy, price: Price) -> crate::services::EnergyOrder {
         crate::services::EnergyOrder {
             id: format!("order_{}", fastrand::u64(..)),
             account: account.to_string(),
             order_type,
             energy_amount: amount,
             price_per_kwh: price,
-            total_price: amount * price,
+            total_price: price.checked_cost(amount).unwrap(),
             status: crate::services::OrderStatus::Pending,
             created_at: time::current_timestamp(),
             expires_at: time::add_days(time::current_timestamp(), 1),
-            filled_amount: 0.0,
+            filled_amount: Energy::ZERO,
         }
     }
 
     /// Create test governance proposal
     pub fn create_test_proposal(proposer: &str, title: &str, description: &str) -> crate::services::GovernanceProposal {
+        let now = time::current_timestamp();
+        let voting_period = 7 * 24 * 60 * 60;
         crate::services::GovernanceProposal {
             id: format!("proposal_{}", fastrand::u64(..)),
             title: title.to_string(),
             description: description.to_string(),
             proposer: proposer.to_string(),
-            proposal_type: crate::services::ProposalType::ParameterChange,
-            voting_period: (time::current_timestamp(), time::add_days(time::current_timestamp(), 7)),
+            voting_period,
+            execution_period: voting_period,
+            threshold: 1000,
             votes_for: 0,
             votes_against: 0,
-            status: crate::services::ProposalStatus::Voting,
-            execution_time: None,
+            votes_abstain: 0,
+            status: crate::services::ProposalStatus::Active,
+            created_at: now,
+            voting_ends_at: now + voting_period,
         }
     }
 